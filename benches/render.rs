@@ -0,0 +1,89 @@
+//! Rendering benchmarks for hypr-notch
+//!
+//! Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use hypr_notch::draw::{self, Canvas, Corners, Fill, NotchStyle};
+use hypr_notch::module::Rect;
+use std::hint::black_box;
+
+/// Expanded notch at normal scale and at the doubled scale of a 4K output
+const SIZES: [(&str, u32, u32); 2] = [("800x400", 800, 400), ("800x400@2x", 1600, 800)];
+
+const CORNERS: Corners = Corners {
+    top_left: false,
+    top_right: false,
+    bottom_left: true,
+    bottom_right: true,
+};
+
+fn notch_style(fill: Fill) -> NotchStyle {
+    NotchStyle {
+        rounded: true,
+        corner_radius: 24,
+        corners: CORNERS,
+        fill,
+        shadow: None,
+        border: None,
+    }
+}
+
+fn bench_draw_notch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("draw_notch");
+    let stops = vec![[40, 20, 20, 255], [120, 60, 30, 255]];
+    for (name, width, height) in SIZES {
+        let mut buffer = vec![0; (width * height * 4) as usize];
+        let notch = Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        let solid = notch_style(Fill::Solid([0, 0, 0, 255]));
+        group.bench_function(BenchmarkId::new("solid", name), |b| {
+            b.iter(|| draw::draw_notch(black_box(&mut buffer), width, height, notch, &solid))
+        });
+
+        // Shadow and border around a gradient, inset to leave room for the shadow
+        let styled = NotchStyle {
+            shadow: Some((16, [0, 0, 0, 160])),
+            border: Some((2, [255, 255, 255, 80])),
+            ..notch_style(Fill::Linear {
+                stops: stops.clone(),
+                angle: 90.0,
+            })
+        };
+        let inset = Rect {
+            x: 16,
+            y: 0,
+            width: width - 32,
+            height: height - 16,
+        };
+        group.bench_function(BenchmarkId::new("styled", name), |b| {
+            b.iter(|| draw::draw_notch(black_box(&mut buffer), width, height, inset, &styled))
+        });
+    }
+    group.finish();
+}
+
+fn bench_fill_rect(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fill_rect");
+    for (name, width, height) in SIZES {
+        let mut buffer = vec![0; (width * height * 4) as usize];
+        for (label, color) in [
+            ("opaque", [60, 60, 60, 255]),
+            ("translucent", [60, 60, 60, 128]),
+        ] {
+            group.bench_function(BenchmarkId::new(label, name), |b| {
+                b.iter(|| {
+                    let mut canvas = Canvas::new(black_box(&mut buffer), width, height);
+                    canvas.fill_rect(0, 0, width, height, color);
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_draw_notch, bench_fill_rect);
+criterion_main!(benches);
@@ -1,10 +1,15 @@
 // filepath: src/app.rs
 //! Main application logic for hypr-notch
 
-use crate::config::NotchConfig;
-use crate::draw;
-use crate::module::{ModuleEvent, ModuleRegistry};
+use crate::config::{
+    self, Background, FullscreenBehavior, HotZone, Margins, NotchAnchor, NotchConfig,
+};
+use crate::draw::{self, Fill, NotchStyle};
+use crate::image::{self, RgbaImage};
+use crate::module::{ModuleEvent, ModuleRegistry, Rect};
 use crate::modules::ClockModule;
+use crate::theme;
+use crate::toplevel::{HyprlandFullscreen, ToplevelTracker};
 use log::{debug, info, warn};
 use smithay_client_toolkit::{
     compositor::CompositorState,
@@ -13,15 +18,16 @@ use smithay_client_toolkit::{
     registry::RegistryState,
     seat::SeatState,
     shell::{
-        wlr_layer::{Anchor, KeyboardInteractivity, LayerSurface},
+        wlr_layer::{KeyboardInteractivity, LayerSurface},
         WaylandSurface,
     },
     shm::{slot::SlotPool, Shm},
 };
 use std::time::{Duration, Instant};
-use wayland_client::protocol::{wl_pointer, wl_shm};
+use wayland_client::protocol::{wl_output, wl_pointer, wl_shm};
 use wayland_client::Connection;
 use wayland_client::Proxy;
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1;
 
 pub struct AppData {
     registry_state: RegistryState,
@@ -35,15 +41,30 @@ pub struct AppData {
     pub(crate) height: u32,
     configured: bool,
     pub(crate) expanded: bool,
+    pub(crate) hovered: bool,
     pointer: Option<wl_pointer::WlPointer>,
     config: NotchConfig,
     last_draw: Option<Instant>,
     module_registry: ModuleRegistry,
     input_region: Option<Region>,
-    pub(crate) buffer_drawn: bool,
+    /// Rectangles making up the current input region
+    input_rects: Option<Vec<Rect>>,
+    pub buffer_drawn: bool,
+    /// Output the notch was last shown on
+    output: Option<wl_output::WlOutput>,
+    toplevel_manager: Option<ZwlrForeignToplevelManagerV1>,
+    pub(crate) toplevels: ToplevelTracker,
+    hyprland_fullscreen: HyprlandFullscreen,
+    /// A fullscreen window covers the notch's output
+    fullscreen: bool,
+    /// The surface is unmapped because of `FullscreenBehavior::Hide`
+    hidden: bool,
+    /// Decoded `background.style = "image"` file
+    background_image: Option<RgbaImage>,
 }
 
 impl AppData {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         registry_state: RegistryState,
         output_state: OutputState,
@@ -56,14 +77,45 @@ impl AppData {
         _connection: &Connection,
     ) -> Self {
         info!("Configuring layer surface");
-        layer_surface.set_anchor(Anchor::TOP);
+        layer_surface.set_anchor(config.anchor.to_anchor());
         layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
-        layer_surface.set_size(config.collapsed_width, config.collapsed_height);
-        layer_surface.set_exclusive_zone(-1);
-        layer_surface.set_margin(0, 0, 0, 0);
+        let (width, height) = surface_size(
+            padded(
+                (config.collapsed_width, config.collapsed_height),
+                &shadow_padding(&config),
+            ),
+            config.hot_zone,
+        );
+        layer_surface.set_size(width, height);
+        if config.exclusive_zone {
+            let zone = if config.anchor.is_vertical() {
+                config.collapsed_width
+            } else {
+                config.collapsed_height
+            };
+            layer_surface.set_exclusive_zone(zone as i32);
+        } else {
+            layer_surface.set_exclusive_zone(-1);
+        }
+        let margin = &config.margin;
+        layer_surface.set_margin(margin.top, margin.right, margin.bottom, margin.left);
         info!("Committing layer surface configuration");
         layer_surface.wl_surface().commit();
 
+        let background_image = match &config.background {
+            Background::Image { path, .. } => {
+                let path = config::resolve_path(path);
+                match image::load_png(&path) {
+                    Ok(image) => Some(image),
+                    Err(e) => {
+                        warn!("Failed to load background image {}: {}", path.display(), e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
         let mut module_registry = ModuleRegistry::new();
         if let Err(err) = module_registry.load_modules_from_config(&config) {
             log::error!("Failed to load modules from config: {}", err);
@@ -81,16 +133,25 @@ impl AppData {
             shm_state,
             layer_surface: Some(layer_surface),
             pool,
-            width: config.collapsed_width,
-            height: config.collapsed_height,
+            width,
+            height,
             configured: false,
             expanded: false,
+            hovered: false,
             pointer: None,
             config,
             last_draw: None,
             module_registry,
             input_region: None,
+            input_rects: None,
             buffer_drawn: false,
+            output: None,
+            toplevel_manager: None,
+            toplevels: ToplevelTracker::default(),
+            hyprland_fullscreen: HyprlandFullscreen::default(),
+            fullscreen: false,
+            hidden: false,
+            background_image,
         }
     }
 
@@ -126,6 +187,8 @@ impl AppData {
         let width = self.width;
         let height = self.height;
         let stride = width * 4;
+        let shrunk = self.is_shrunk();
+        let notch = self.notch_rect();
 
         let (buffer, canvas) = self.pool.create_buffer(
             width as i32,
@@ -134,24 +197,56 @@ impl AppData {
             wl_shm::Format::Argb8888,
         )?;
 
-        let expanded = self.expanded;
-        let corner_radius = self.config.corner_radius;
-        let color = self.config.background_color;
+        let theme = theme::current();
+        let background = self.config.background_color.resolve(&theme);
+        let resolve_all = |colors: &[theme::ThemeColor]| -> Vec<[u8; 4]> {
+            colors.iter().map(|color| color.resolve(&theme)).collect()
+        };
+        let fill = match (&self.config.background, &self.background_image) {
+            (Background::Linear { colors, angle }, _) if !colors.is_empty() => Fill::Linear {
+                stops: resolve_all(colors),
+                angle: *angle,
+            },
+            (Background::Radial { colors }, _) if !colors.is_empty() => Fill::Radial {
+                stops: resolve_all(colors),
+            },
+            (Background::Image { fit, .. }, Some(image)) => Fill::Image {
+                image,
+                fit: *fit,
+                backdrop: background,
+            },
+            _ => Fill::Solid(background),
+        };
+        let style = NotchStyle {
+            rounded: self.expanded,
+            corner_radius: self.config.corner_radius,
+            corners: draw::Corners::for_anchor(self.config.anchor, &self.config.margin),
+            fill,
+            shadow: self
+                .config
+                .shadow
+                .as_ref()
+                .map(|shadow| (shadow.size, shadow.color.resolve(&theme))),
+            border: self
+                .config
+                .border
+                .as_ref()
+                .map(|border| (border.width, border.color.resolve(&theme))),
+        };
 
-        draw::fill_canvas_with_rounded_corners(
-            canvas,
-            width,
-            height,
-            expanded,
-            corner_radius,
-            color,
-        );
+        draw::draw_notch(canvas, width, height, notch, &style);
 
+        let mut canvas_wrapper = draw::Canvas::new(canvas, width, height);
         if self.expanded {
-            let mut canvas_wrapper = draw::Canvas::new(canvas, width, height);
-            self.module_registry.draw(&mut canvas_wrapper);
+            self.module_registry.draw(&mut canvas_wrapper, notch);
+        } else if !shrunk {
+            self.module_registry
+                .draw_collapsed(&mut canvas_wrapper, notch);
         }
 
+        // Module areas are only known after layout, which happens while drawing
+        self.update_input_region();
+
         if let Some(layer_surface) = &self.layer_surface {
             buffer
                 .attach_to(layer_surface.wl_surface())
@@ -166,50 +261,212 @@ impl AppData {
     }
 
     pub fn resize(&mut self, expand: bool) {
-        if self.expanded == expand {
+        // A hidden notch stays unmapped until fullscreen ends
+        if self.expanded == expand || self.hidden {
             return;
         }
 
         self.expanded = expand;
+        self.apply_size();
+    }
 
+    fn apply_size(&mut self) {
         if let Some(layer_surface) = &self.layer_surface {
-            if expand {
-                self.width = self.config.expanded_width;
-                self.height = self.config.expanded_height;
+            if self.expanded {
+                (self.width, self.height) = padded(
+                    (self.config.expanded_width, self.config.expanded_height),
+                    &shadow_padding(&self.config),
+                );
                 layer_surface.set_size(self.width, self.height);
-                self.module_registry
-                    .calculate_layout(self.width, self.height);
+                let notch = self.notch_rect();
+                self.module_registry.calculate_layout(notch);
             } else {
-                self.width = self.config.collapsed_width;
-                self.height = self.config.collapsed_height;
+                (self.width, self.height) = self.collapsed_surface_size();
                 layer_surface.set_size(self.width, self.height);
             }
             layer_surface.wl_surface().commit();
-            self.set_full_input_region(); // <-- Add this line
         }
     }
 
-    pub fn set_full_input_region(&mut self) {
+    /// Size of the visible collapsed notch
+    fn collapsed_notch_size(&self) -> (u32, u32) {
+        let (mut width, mut height) = (self.config.collapsed_width, self.config.collapsed_height);
+        // The strip keeps hugging the edge the notch is attached to
+        if self.is_shrunk() {
+            if self.config.anchor.is_vertical() {
+                width = self.config.fullscreen_height;
+            } else {
+                height = self.config.fullscreen_height;
+            }
+        }
+        (width, height)
+    }
+
+    /// Size of the surface holding the collapsed notch, its shadow and its
+    /// hot zone
+    fn collapsed_surface_size(&self) -> (u32, u32) {
+        surface_size(
+            padded(self.collapsed_notch_size(), &shadow_padding(&self.config)),
+            self.config.hot_zone,
+        )
+    }
+
+    /// Area of the surface covered by the visible notch
+    fn notch_rect(&self) -> Rect {
+        let size = if self.expanded {
+            (self.config.expanded_width, self.config.expanded_height)
+        } else {
+            self.collapsed_notch_size()
+        };
+        let padding = shadow_padding(&self.config);
+        let frame = align_to_anchor(
+            self.config.anchor,
+            (self.width, self.height),
+            padded(size, &padding),
+        );
+        Rect {
+            x: frame.x + padding.left,
+            y: frame.y + padding.top,
+            width: size.0.min(self.width),
+            height: size.1.min(self.height),
+        }
+    }
+
+    /// Whether the collapsed notch is reduced to a strip for a fullscreen window
+    fn is_shrunk(&self) -> bool {
+        self.fullscreen && self.config.fullscreen_behavior == FullscreenBehavior::Shrink
+    }
+
+    pub fn set_toplevel_manager(&mut self, manager: ZwlrForeignToplevelManagerV1) {
+        self.toplevel_manager = Some(manager);
+    }
+
+    /// Remember which output the notch is shown on
+    pub fn set_output(&mut self, output: wl_output::WlOutput) {
+        self.output = Some(output);
+        self.update_fullscreen();
+    }
+
+    /// Re-check the known toplevels, or Hyprland's reports without foreign
+    /// toplevel management, for a fullscreen window on our output
+    pub fn update_fullscreen(&mut self) {
+        let fullscreen = if self.toplevel_manager.is_some() {
+            self.toplevels.fullscreen_on(self.output.as_ref())
+        } else {
+            let monitor = self
+                .output
+                .as_ref()
+                .and_then(|output| self.output_state.info(output))
+                .and_then(|info| info.name);
+            self.hyprland_fullscreen.fullscreen_on(monitor.as_deref())
+        };
+        self.set_fullscreen(fullscreen);
+    }
+
+    /// Fullscreen state reported by Hyprland's `fullscreen` event for the
+    /// focused monitor
+    pub fn set_hyprland_fullscreen(&mut self, fullscreen: bool) {
+        self.hyprland_fullscreen.set(fullscreen);
+        self.update_fullscreen();
+    }
+
+    /// Monitor named by Hyprland's `focusedmon` event
+    pub fn set_hyprland_monitor(&mut self, monitor: &str) {
+        self.hyprland_fullscreen.focus(monitor);
+    }
+
+    fn set_fullscreen(&mut self, fullscreen: bool) {
+        if self.fullscreen == fullscreen {
+            return;
+        }
+        self.fullscreen = fullscreen;
+        info!(
+            "Fullscreen window {}, behavior {:?}",
+            if fullscreen { "active" } else { "gone" },
+            self.config.fullscreen_behavior
+        );
+
+        match self.config.fullscreen_behavior {
+            FullscreenBehavior::Hide if fullscreen => self.hide_surface(),
+            FullscreenBehavior::Hide => self.show_surface(),
+            FullscreenBehavior::Shrink if !self.expanded => {
+                self.apply_size();
+                let _ = self.draw();
+            }
+            FullscreenBehavior::Shrink | FullscreenBehavior::Keep => {}
+        }
+    }
+
+    /// Unmap the surface by committing without a buffer
+    fn hide_surface(&mut self) {
+        if let Some(layer_surface) = &self.layer_surface {
+            let surface = layer_surface.wl_surface();
+            surface.attach(None, 0, 0);
+            surface.commit();
+        }
+        self.hidden = true;
+        self.expanded = false;
+        self.hovered = false;
+        self.configured = false;
+        self.buffer_drawn = false;
+    }
+
+    /// Map the surface again; the first frame is drawn once the compositor
+    /// sends a fresh configure
+    fn show_surface(&mut self) {
+        if !self.hidden {
+            return;
+        }
+        self.hidden = false;
+        if let Some(layer_surface) = &self.layer_surface {
+            (self.width, self.height) = self.collapsed_surface_size();
+            layer_surface.set_size(self.width, self.height);
+            layer_surface.wl_surface().commit();
+        }
+    }
+
+    /// Rectangles of the surface that should receive pointer input
+    fn input_rects(&self) -> Vec<Rect> {
+        shaped_input_rects(
+            &self.config,
+            (self.width, self.height),
+            self.notch_rect(),
+            self.expanded,
+            || self.module_registry.interactive_areas(self.expanded),
+        )
+    }
+
+    /// Shape the input region to the visible notch, so clicks on transparent
+    /// corners (or anywhere but interactive modules in click-through mode)
+    /// reach the windows beneath. Takes effect with the next commit.
+    pub fn update_input_region(&mut self) {
+        let rects = self.input_rects();
+        if self.input_rects.as_ref() == Some(&rects) {
+            return;
+        }
+
         if let Some(layer_surface) = &self.layer_surface {
             let surface = layer_surface.wl_surface();
             match Region::new(&self.compositor_state) {
                 Ok(region) => {
-                    region.add(0, 0, self.width as i32, self.height as i32);
+                    for rect in &rects {
+                        region.add(rect.x, rect.y, rect.width as i32, rect.height as i32);
+                    }
                     surface.set_input_region(Some(region.wl_region()));
                     self.input_region = Some(region);
                     info!(
-                        "Set input region to (0, 0, {}, {}) for surface {:?}",
-                        self.width,
-                        self.height,
+                        "Set input region to {} rects for surface {:?}",
+                        rects.len(),
                         surface.id()
                     );
+                    self.input_rects = Some(rects);
                 }
                 Err(e) => {
                     warn!("Failed to create input region for notch surface: {e}");
                 }
             }
         } else {
-            warn!("set_full_input_region called but no layer_surface present");
+            warn!("update_input_region called but no layer_surface present");
         }
     }
 
@@ -223,6 +480,28 @@ impl AppData {
             self.module_registry
                 .handle_event(&ModuleEvent::UpdateCollapsed);
         }
+
+        // Let modules expand the notch on their own (e.g. warnings), and
+        // collapse it again once they are done and the pointer is elsewhere
+        let wants_expand = self.module_registry.wants_expand();
+        if wants_expand && !self.expanded {
+            info!("Expanding notch on module request");
+            self.resize(true);
+        } else if !wants_expand && self.expanded && !self.hovered {
+            info!("Collapsing notch after module request ended");
+            self.resize(false);
+        }
+    }
+
+    /// Forward an input event to the module under the pointer
+    /// Returns true if a module handled it and the notch should be redrawn
+    pub fn handle_module_event(&mut self, event: &ModuleEvent) -> bool {
+        self.module_registry.handle_event(event)
+    }
+
+    /// Whether the notch should stay expanded without the pointer inside it
+    pub fn modules_want_expand(&self) -> bool {
+        self.module_registry.wants_expand()
     }
 
     pub fn registry_state(&mut self) -> &mut RegistryState {
@@ -251,3 +530,219 @@ impl AppData {
         info!("Layer surface closed");
     }
 }
+
+/// Rectangles of a `surface` sized surface that take pointer input: the
+/// notch at `notch`, with its corners cut away when expanded, and the hot
+/// zone of a collapsed notch. In click-through mode only the areas
+/// `interactive` returns do.
+fn shaped_input_rects(
+    config: &NotchConfig,
+    surface: (u32, u32),
+    notch: Rect,
+    expanded: bool,
+    interactive: impl FnOnce() -> Vec<Rect>,
+) -> Vec<Rect> {
+    if config.click_through {
+        return interactive();
+    }
+
+    if expanded {
+        let corners = draw::Corners::for_anchor(config.anchor, &config.margin);
+        return draw::shape_rects(notch.width, notch.height, config.corner_radius, corners)
+            .into_iter()
+            .map(|rect| Rect {
+                x: rect.x + notch.x,
+                y: rect.y + notch.y,
+                ..rect
+            })
+            .collect();
+    }
+
+    let mut rects = vec![notch];
+    if let Some(hot_zone) = config.hot_zone {
+        rects.push(align_to_anchor(
+            config.anchor,
+            surface,
+            (hot_zone.width, hot_zone.height),
+        ));
+    }
+    rects
+}
+
+/// Transparent space around the notch for its shadow, on every side away
+/// from the anchored edges
+fn shadow_padding(config: &NotchConfig) -> Margins {
+    let size = config
+        .shadow
+        .as_ref()
+        .map_or(0, |shadow| shadow.size as i32);
+    let anchor = config.anchor;
+    let side = |anchored: bool| if anchored { 0 } else { size };
+    Margins {
+        top: side(anchor.top()),
+        right: side(anchor.right()),
+        bottom: side(anchor.bottom()),
+        left: side(anchor.left()),
+    }
+}
+
+/// `size` grown by `padding`
+fn padded(size: (u32, u32), padding: &Margins) -> (u32, u32) {
+    (
+        size.0 + (padding.left + padding.right) as u32,
+        size.1 + (padding.top + padding.bottom) as u32,
+    )
+}
+
+/// Size of the surface holding a collapsed notch (with its shadow) of
+/// `notch` size together with its hot zone
+fn surface_size(notch: (u32, u32), hot_zone: Option<HotZone>) -> (u32, u32) {
+    match hot_zone {
+        Some(zone) => (notch.0.max(zone.width), notch.1.max(zone.height)),
+        None => notch,
+    }
+}
+
+/// Place a `size` rectangle inside `surface`, flush with the anchored edges
+/// and centered along the others
+fn align_to_anchor(anchor: NotchAnchor, surface: (u32, u32), size: (u32, u32)) -> Rect {
+    let (width, height) = (size.0.min(surface.0), size.1.min(surface.1));
+    let align = |start: bool, end: bool, outer: u32, inner: u32| {
+        if start {
+            0
+        } else if end {
+            (outer - inner) as i32
+        } else {
+            ((outer - inner) / 2) as i32
+        }
+    };
+    Rect {
+        x: align(anchor.left(), anchor.right(), surface.0, width),
+        y: align(anchor.top(), anchor.bottom(), surface.1, height),
+        width,
+        height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether any of `rects` covers the pixel at `(x, y)`
+    fn covered(rects: &[Rect], x: i32, y: i32) -> bool {
+        rects.iter().any(|rect| rect.contains(x as f64, y as f64))
+    }
+
+    #[test]
+    fn expanded_input_matches_the_drawn_shape() {
+        let (width, height) = (120, 60);
+        let notch = Rect {
+            x: 10,
+            y: 0,
+            width: 100,
+            height: 50,
+        };
+        // Attached to the top with two rounded corners, and floating with four
+        let floating = Margins {
+            top: 8,
+            ..Default::default()
+        };
+        for margin in [Margins::default(), floating] {
+            let config = NotchConfig {
+                corner_radius: 16,
+                margin,
+                ..Default::default()
+            };
+            let style = NotchStyle {
+                rounded: true,
+                corner_radius: config.corner_radius,
+                corners: draw::Corners::for_anchor(config.anchor, &config.margin),
+                fill: Fill::Solid([0, 0, 0, 255]),
+                shadow: None,
+                border: None,
+            };
+            let mut buffer = vec![0; (width * height * 4) as usize];
+            draw::draw_notch(&mut buffer, width, height, notch, &style);
+            let rects = shaped_input_rects(&config, (width, height), notch, true, Vec::new);
+
+            for y in 0..height as i32 {
+                for x in 0..width as i32 {
+                    let drawn = buffer[(y * width as i32 + x) as usize * 4 + 3] != 0;
+                    assert_eq!(covered(&rects, x, y), drawn, "({x}, {y}) {margin:?}");
+                }
+            }
+            // The arcs are followed row by row, not by one rectangle
+            assert!(rects.len() > 3);
+        }
+    }
+
+    #[test]
+    fn hot_zone_reaches_past_the_collapsed_notch() {
+        let config = NotchConfig {
+            hot_zone: Some(HotZone {
+                width: 400,
+                height: 8,
+            }),
+            ..Default::default()
+        };
+        let surface = surface_size((200, 30), config.hot_zone);
+        assert_eq!(surface, (400, 30));
+        let notch = align_to_anchor(config.anchor, surface, (200, 30));
+        let rects = shaped_input_rects(&config, surface, notch, false, Vec::new);
+
+        assert_eq!(rects[0], notch);
+        let zone = rects[1];
+        // Flush with the top edge and wider than the notch on both sides
+        assert_eq!((zone.x, zone.y, zone.width, zone.height), (0, 0, 400, 8));
+        assert!(covered(&rects, 5, 2) && covered(&rects, 395, 2));
+        // but no deeper than its own height beside the notch
+        assert!(!covered(&rects, 5, 20));
+        assert!(covered(&rects, 200, 20));
+
+        // Without a hot zone only the notch takes input
+        let config = NotchConfig::default();
+        assert_eq!(
+            shaped_input_rects(&config, (200, 30), notch, false, Vec::new),
+            [notch]
+        );
+    }
+
+    #[test]
+    fn click_through_leaves_only_interactive_modules() {
+        let config = NotchConfig {
+            click_through: true,
+            hot_zone: Some(HotZone {
+                width: 400,
+                height: 8,
+            }),
+            ..Default::default()
+        };
+        let notch = Rect {
+            x: 0,
+            y: 0,
+            width: 300,
+            height: 100,
+        };
+        let buttons = vec![
+            Rect {
+                x: 10,
+                y: 10,
+                width: 40,
+                height: 20,
+            },
+            Rect {
+                x: 200,
+                y: 50,
+                width: 60,
+                height: 30,
+            },
+        ];
+        for expanded in [false, true] {
+            let rects =
+                shaped_input_rects(&config, (400, 100), notch, expanded, || buttons.clone());
+            assert_eq!(rects, buttons);
+        }
+        let rects = shaped_input_rects(&config, (400, 100), notch, true, Vec::new);
+        assert!(rects.is_empty());
+    }
+}
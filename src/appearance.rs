@@ -0,0 +1,106 @@
+//! System colour scheme for hypr-notch
+//!
+//! Follows the `color-scheme` setting in the `org.freedesktop.appearance`
+//! namespace of the XDG desktop portal, so the notch can switch between a
+//! light and a dark theme together with the rest of the desktop.
+
+use log::{info, warn};
+use std::thread;
+use std::time::Duration;
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{OwnedValue, Value};
+
+pub const PORTAL_NAME: &str = "org.freedesktop.portal.Desktop";
+pub const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+pub const SETTINGS_INTERFACE: &str = "org.freedesktop.portal.Settings";
+pub const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+pub const COLOR_SCHEME_KEY: &str = "color-scheme";
+
+/// Seconds to wait before reconnecting after the portal went away
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScheme {
+    NoPreference,
+    Dark,
+    Light,
+}
+
+impl ColorScheme {
+    /// Map the portal's `color-scheme` value; unknown values mean no preference
+    pub fn from_portal(value: u32) -> Self {
+        match value {
+            1 => Self::Dark,
+            2 => Self::Light,
+            _ => Self::NoPreference,
+        }
+    }
+}
+
+/// Unwrap a `u32`, looking through the extra variant `Read` wraps it in
+fn value_to_u32(value: &Value) -> Option<u32> {
+    match value {
+        Value::U32(value) => Some(*value),
+        Value::Value(inner) => value_to_u32(inner),
+        _ => None,
+    }
+}
+
+fn settings_proxy(conn: &Connection) -> zbus::Result<Proxy<'static>> {
+    Proxy::new(conn, PORTAL_NAME, PORTAL_PATH, SETTINGS_INTERFACE)
+}
+
+/// Ask the portal for the current colour scheme
+pub fn read_color_scheme(proxy: &Proxy) -> zbus::Result<ColorScheme> {
+    let key = (APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY);
+    // ReadOne is only available from version 2 of the interface
+    let value: OwnedValue = match proxy.call("ReadOne", &key) {
+        Ok(value) => value,
+        Err(_) => proxy.call("Read", &key)?,
+    };
+    let value = value_to_u32(&value)
+        .ok_or_else(|| zbus::Error::Failure("color-scheme is not a u32".to_string()))?;
+    Ok(ColorScheme::from_portal(value))
+}
+
+/// Report the current colour scheme and every change of it until the
+/// connection to the portal is lost
+pub fn watch(conn: &Connection, on_change: &impl Fn(ColorScheme)) -> zbus::Result<()> {
+    let proxy = settings_proxy(conn)?;
+
+    // Subscribe before reading so a change in between isn't missed
+    let changes = proxy.receive_signal("SettingChanged")?;
+    on_change(read_color_scheme(&proxy)?);
+
+    for message in changes {
+        let (namespace, key, value): (String, String, OwnedValue) = message.body().deserialize()?;
+        if namespace != APPEARANCE_NAMESPACE || key != COLOR_SCHEME_KEY {
+            continue;
+        }
+        match value_to_u32(&value) {
+            Some(value) => on_change(ColorScheme::from_portal(value)),
+            None => warn!(
+                "Ignoring color-scheme change with unexpected value {:?}",
+                value
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Follow the portal's colour scheme on a background thread, calling
+/// `on_change` with the initial value and after every change
+pub fn spawn_listener(on_change: impl Fn(ColorScheme) + Send + 'static) {
+    let spawned = thread::Builder::new()
+        .name("color-scheme".to_string())
+        .spawn(move || loop {
+            info!("Following the desktop portal colour scheme");
+            if let Err(e) = Connection::session().and_then(|conn| watch(&conn, &on_change)) {
+                warn!("Desktop portal colour scheme unavailable: {}", e);
+            }
+            thread::sleep(RETRY_INTERVAL);
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start colour scheme listener: {}", e);
+    }
+}
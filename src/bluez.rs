@@ -0,0 +1,358 @@
+//! BlueZ client for hypr-notch
+//!
+//! Reads Bluetooth adapters and devices from `org.bluez` through its
+//! ObjectManager, and keeps them current from the `InterfacesAdded`,
+//! `InterfacesRemoved` and `PropertiesChanged` signals. Devices are
+//! connected and disconnected through `org.bluez.Device1`, and battery
+//! levels come from `org.bluez.Battery1`.
+
+use log::{debug, info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use zbus::blocking::fdo::DBusProxy;
+use zbus::blocking::{Connection, MessageIterator};
+use zbus::message::Type as MessageType;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use zbus::MatchRule;
+
+pub const BLUEZ_NAME: &str = "org.bluez";
+pub const ADAPTER_INTERFACE: &str = "org.bluez.Adapter1";
+pub const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+pub const BATTERY_INTERFACE: &str = "org.bluez.Battery1";
+const OBJECT_MANAGER_INTERFACE: &str = "org.freedesktop.DBus.ObjectManager";
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+type Properties = HashMap<String, OwnedValue>;
+/// The interfaces of one object and their properties
+type Interfaces = HashMap<String, Properties>;
+
+fn property<T: TryFrom<OwnedValue>>(properties: &Properties, name: &str) -> Option<T> {
+    let value = properties.get(name)?.try_clone().ok()?;
+    T::try_from(value).ok()
+}
+
+/// A Bluetooth controller
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Adapter {
+    pub path: String,
+    pub name: String,
+    pub powered: bool,
+}
+
+impl Adapter {
+    fn from_interfaces(path: &str, interfaces: &Interfaces) -> Option<Self> {
+        let properties = interfaces.get(ADAPTER_INTERFACE)?;
+        Some(Self {
+            path: path.to_string(),
+            name: property(properties, "Alias")
+                .or_else(|| property(properties, "Name"))
+                .unwrap_or_default(),
+            powered: property(properties, "Powered").unwrap_or(false),
+        })
+    }
+}
+
+/// A Bluetooth device an adapter knows about
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Device {
+    pub path: String,
+    /// Object path of the adapter the device belongs to
+    pub adapter: String,
+    pub address: String,
+    /// The user's alias for the device, or else the name it reports
+    pub name: String,
+    /// Freedesktop icon name, such as `audio-headset` or `input-mouse`
+    pub icon: String,
+    pub paired: bool,
+    pub connected: bool,
+    /// Battery level in percent, for devices that report one
+    pub battery: Option<u8>,
+}
+
+impl Device {
+    fn from_interfaces(path: &str, interfaces: &Interfaces) -> Option<Self> {
+        let properties = interfaces.get(DEVICE_INTERFACE)?;
+        let address: String = property(properties, "Address").unwrap_or_default();
+        let name = property(properties, "Alias")
+            .or_else(|| property(properties, "Name"))
+            .unwrap_or_else(|| address.clone());
+        Some(Self {
+            path: path.to_string(),
+            adapter: property::<OwnedObjectPath>(properties, "Adapter")
+                .map(|path| path.to_string())
+                .unwrap_or_default(),
+            address,
+            name,
+            icon: property(properties, "Icon").unwrap_or_default(),
+            paired: property(properties, "Paired").unwrap_or(false),
+            connected: property(properties, "Connected").unwrap_or(false),
+            battery: interfaces
+                .get(BATTERY_INTERFACE)
+                .and_then(|battery| property::<u8>(battery, "Percentage"))
+                .map(|percent| percent.min(100)),
+        })
+    }
+
+    /// Whether the device plays or records sound: headsets, headphones
+    /// and speakers
+    pub fn is_audio(&self) -> bool {
+        self.icon.starts_with("audio-")
+    }
+}
+
+struct Shared {
+    connection: Connection,
+    /// Every object `org.bluez` manages, by path
+    objects: Mutex<BTreeMap<String, Interfaces>>,
+    /// Bumped on every change to `objects`
+    generation: AtomicU64,
+    on_change: Box<dyn Fn() + Send + Sync>,
+}
+
+impl Shared {
+    fn changed(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        (self.on_change)();
+    }
+
+    /// Read every object from scratch
+    fn load(&self) -> zbus::Result<()> {
+        let reply = self.connection.call_method(
+            Some(BLUEZ_NAME),
+            "/",
+            Some(OBJECT_MANAGER_INTERFACE),
+            "GetManagedObjects",
+            &(),
+        )?;
+        let objects: HashMap<OwnedObjectPath, Interfaces> = reply.body().deserialize()?;
+        *self.objects.lock().unwrap() = objects
+            .into_iter()
+            .map(|(path, interfaces)| (path.to_string(), interfaces))
+            .collect();
+        self.changed();
+        Ok(())
+    }
+
+    fn clear(&self) {
+        let mut objects = self.objects.lock().unwrap();
+        let had_objects = !objects.is_empty();
+        objects.clear();
+        drop(objects);
+        if had_objects {
+            self.changed();
+        }
+    }
+
+    fn interfaces_added(&self, path: String, added: Interfaces) {
+        self.objects
+            .lock()
+            .unwrap()
+            .entry(path)
+            .or_default()
+            .extend(added);
+        self.changed();
+    }
+
+    fn interfaces_removed(&self, path: &str, removed: &[String]) {
+        let mut objects = self.objects.lock().unwrap();
+        let Some(interfaces) = objects.get_mut(path) else {
+            return;
+        };
+        interfaces.retain(|name, _| !removed.contains(name));
+        if interfaces.is_empty() {
+            objects.remove(path);
+        }
+        drop(objects);
+        self.changed();
+    }
+
+    fn properties_changed(
+        &self,
+        path: &str,
+        interface: &str,
+        changed: Properties,
+        invalidated: &[String],
+    ) {
+        let mut objects = self.objects.lock().unwrap();
+        // Properties of objects or interfaces that haven't been added yet
+        // arrive with InterfacesAdded
+        let Some(properties) = objects
+            .get_mut(path)
+            .and_then(|interfaces| interfaces.get_mut(interface))
+        else {
+            return;
+        };
+        properties.retain(|name, _| !invalidated.contains(name));
+        properties.extend(changed);
+        drop(objects);
+        self.changed();
+    }
+
+    fn handle_signal(&self, message: &zbus::Message) {
+        let header = message.header();
+        let (Some(interface), Some(member)) = (header.interface(), header.member()) else {
+            return;
+        };
+        let body = message.body();
+
+        match (interface.as_str(), member.as_str()) {
+            (OBJECT_MANAGER_INTERFACE, "InterfacesAdded") => {
+                if let Ok((path, added)) = body.deserialize::<(OwnedObjectPath, Interfaces)>() {
+                    self.interfaces_added(path.to_string(), added);
+                }
+            }
+            (OBJECT_MANAGER_INTERFACE, "InterfacesRemoved") => {
+                if let Ok((path, removed)) = body.deserialize::<(OwnedObjectPath, Vec<String>)>() {
+                    self.interfaces_removed(path.as_str(), &removed);
+                }
+            }
+            (PROPERTIES_INTERFACE, "PropertiesChanged") => {
+                let Some(path) = header.path() else {
+                    return;
+                };
+                if let Ok((interface, changed, invalidated)) =
+                    body.deserialize::<(String, Properties, Vec<String>)>()
+                {
+                    self.properties_changed(path.as_str(), &interface, changed, &invalidated);
+                }
+            }
+            ("org.freedesktop.DBus", "NameOwnerChanged") => {
+                let Ok((name, _, new_owner)) = body.deserialize::<(String, String, String)>()
+                else {
+                    return;
+                };
+                if name != BLUEZ_NAME {
+                    return;
+                }
+                if new_owner.is_empty() {
+                    info!("{} left the bus", BLUEZ_NAME);
+                    self.clear();
+                } else if let Err(e) = self.load() {
+                    warn!("Failed to read {}: {}", BLUEZ_NAME, e);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Adapters and devices of `org.bluez`, kept current by a background thread
+pub struct Client {
+    shared: Arc<Shared>,
+}
+
+impl Client {
+    /// Start following `org.bluez` on `connection`, which is normally the
+    /// system bus. It's fine for BlueZ not to be running yet. `on_change`
+    /// is called from the background thread whenever anything changes.
+    pub fn start(
+        connection: Connection,
+        on_change: impl Fn() + Send + Sync + 'static,
+    ) -> zbus::Result<Self> {
+        let shared = Arc::new(Shared {
+            connection,
+            objects: Mutex::new(BTreeMap::new()),
+            generation: AtomicU64::new(0),
+            on_change: Box::new(on_change),
+        });
+
+        // Listen before reading the objects so no change slips through
+        let proxy = DBusProxy::new(&shared.connection)?;
+        let rules = [
+            MatchRule::builder()
+                .msg_type(MessageType::Signal)
+                .sender(BLUEZ_NAME)?
+                .interface(OBJECT_MANAGER_INTERFACE)?
+                .build(),
+            MatchRule::builder()
+                .msg_type(MessageType::Signal)
+                .sender(BLUEZ_NAME)?
+                .interface(PROPERTIES_INTERFACE)?
+                .member("PropertiesChanged")?
+                .build(),
+            MatchRule::builder()
+                .msg_type(MessageType::Signal)
+                .sender("org.freedesktop.DBus")?
+                .interface("org.freedesktop.DBus")?
+                .member("NameOwnerChanged")?
+                .arg(0, BLUEZ_NAME)?
+                .build(),
+        ];
+        for rule in rules {
+            proxy.add_match_rule(rule)?;
+        }
+        let messages = MessageIterator::from(&shared.connection);
+        let listener = shared.clone();
+        thread::Builder::new()
+            .name("bluetooth".to_string())
+            .spawn(move || {
+                for message in messages {
+                    match message {
+                        Ok(message) if message.message_type() == MessageType::Signal => {
+                            listener.handle_signal(&message)
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!("Bluetooth connection error: {}", e),
+                    }
+                }
+            })
+            .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+
+        if let Err(e) = shared.load() {
+            debug!("{} is not available yet: {}", BLUEZ_NAME, e);
+        }
+        Ok(Self { shared })
+    }
+
+    /// Incremented whenever an adapter or device changes
+    pub fn generation(&self) -> u64 {
+        self.shared.generation.load(Ordering::SeqCst)
+    }
+
+    pub fn adapters(&self) -> Vec<Adapter> {
+        self.shared
+            .objects
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(path, interfaces)| Adapter::from_interfaces(path, interfaces))
+            .collect()
+    }
+
+    /// All known devices, paired or not, sorted by name
+    pub fn devices(&self) -> Vec<Device> {
+        let mut devices: Vec<Device> = self
+            .shared
+            .objects
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(path, interfaces)| Device::from_interfaces(path, interfaces))
+            .collect();
+        devices.sort_by_cached_key(|device| (device.name.to_lowercase(), device.path.clone()));
+        devices
+    }
+
+    fn call_device(&self, device: &Device, method: &str) -> zbus::Result<()> {
+        self.shared.connection.call_method(
+            Some(BLUEZ_NAME),
+            device.path.as_str(),
+            Some(DEVICE_INTERFACE),
+            method,
+            &(),
+        )?;
+        Ok(())
+    }
+
+    /// Connect every profile the device offers. Returns once BlueZ has
+    /// connected it or given up, which can take several seconds.
+    pub fn connect(&self, device: &Device) -> zbus::Result<()> {
+        self.call_device(device, "Connect")
+    }
+
+    pub fn disconnect(&self, device: &Device) -> zbus::Result<()> {
+        self.call_device(device, "Disconnect")
+    }
+}
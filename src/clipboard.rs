@@ -0,0 +1,823 @@
+//! Clipboard history for hypr-notch
+//!
+//! Watches the clipboard through `zwlr_data_control_manager_v1` on a
+//! Wayland connection of its own, reading every new text or image
+//! selection into a bounded [`History`], and can make an earlier entry
+//! the current selection again by serving it as a data source. The
+//! history can be kept on disk between runs.
+
+use crate::image::{decode_png, RgbaImage};
+use calloop::channel::{self, Channel, Sender};
+use calloop::EventLoop;
+use calloop_wayland_source::WaylandSource;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, Cursor, PipeReader, Read, Write};
+use std::os::fd::AsFd;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wayland_client::backend::ObjectId;
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::wl_registry::{self, WlRegistry};
+use wayland_client::protocol::wl_seat::{self, WlSeat};
+use wayland_client::{event_created_child, Connection, Dispatch, Proxy, QueueHandle};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::{self, ZwlrDataControlManagerV1},
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+    zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+
+/// MIME type text entries are stored as
+pub const TEXT_MIME_TYPE: &str = "text/plain;charset=utf-8";
+
+/// Text types, most preferred first. A restored text entry is offered as
+/// all of them, since applications differ in which they ask for.
+const TEXT_MIME_TYPES: [&str; 5] = [
+    TEXT_MIME_TYPE,
+    "text/plain",
+    "UTF8_STRING",
+    "STRING",
+    "TEXT",
+];
+
+/// Name of the history's index in its directory
+const INDEX_FILE: &str = "history.toml";
+
+/// Largest size of an image entry's thumbnail
+pub const THUMBNAIL_WIDTH: u32 = 48;
+pub const THUMBNAIL_HEIGHT: u32 = 24;
+
+/// MIME types whose selections are never recorded, whatever else is
+/// configured: password managers offer this one with copied passwords
+pub const IGNORED_MIME_TYPES: [&str; 1] = ["x-kde-passwordManagerHint"];
+
+/// Whether `name` is a data file `History::save` writes: an entry id in
+/// hex. Nothing else in the history directory is touched.
+fn is_entry_file(name: &str) -> bool {
+    name.len() == 16 && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// FNV-1a hash of the MIME type, a zero byte and the data. Unlike
+/// `DefaultHasher` it is the same in every build, so ids can name files.
+pub fn entry_id(mime_type: &str, data: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    mime_type
+        .as_bytes()
+        .iter()
+        .chain(&[0])
+        .chain(data)
+        .fold(OFFSET_BASIS, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(PRIME)
+        })
+}
+
+/// A PNG entry scaled down to fit [`THUMBNAIL_WIDTH`] by
+/// [`THUMBNAIL_HEIGHT`]
+#[derive(Debug, Clone)]
+pub struct Thumbnail {
+    pub image: Arc<RgbaImage>,
+    /// Full size of the copied image
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Thumbnail {
+    fn decode(data: &[u8]) -> Option<Self> {
+        let image = decode_png(Cursor::new(data))
+            .map_err(|e| warn!("Undecodable clipboard image: {}", e))
+            .ok()?;
+        let scale = (THUMBNAIL_WIDTH as f32 / image.width.max(1) as f32)
+            .min(THUMBNAIL_HEIGHT as f32 / image.height.max(1) as f32)
+            .min(1.0);
+        let width = ((image.width as f32 * scale).round() as u32).max(1);
+        let height = ((image.height as f32 * scale).round() as u32).max(1);
+        Some(Self {
+            width: image.width,
+            height: image.height,
+            image: Arc::new(image.scaled(width, height)),
+        })
+    }
+}
+
+/// One copied text or image
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// [`entry_id`] of the MIME type and data, which identifies the entry
+    pub id: u64,
+    /// [`TEXT_MIME_TYPE`] for text, or the image type it was copied as
+    pub mime_type: String,
+    pub data: Vec<u8>,
+    /// Unix time in seconds when it was copied
+    pub copied: i64,
+    /// Made along with the entry, off the UI thread, for PNGs that decode
+    pub thumbnail: Option<Thumbnail>,
+}
+
+impl Entry {
+    pub fn new(mime_type: String, data: Vec<u8>, copied: i64) -> Self {
+        let thumbnail = (mime_type == "image/png")
+            .then(|| Thumbnail::decode(&data))
+            .flatten();
+        Self {
+            id: entry_id(&mime_type, &data),
+            mime_type,
+            data,
+            copied,
+            thumbnail,
+        }
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.mime_type.starts_with("image/")
+    }
+
+    /// The first non-blank line of a text entry with its whitespace
+    /// collapsed, for showing in a single row
+    pub fn preview(&self) -> Option<String> {
+        if self.is_image() {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&self.data);
+        let line = text.lines().find(|line| !line.trim().is_empty())?;
+        Some(line.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+/// Choose which of an offer's MIME types to read: text first, then PNG,
+/// then any other image type
+pub fn preferred_mime_type(mime_types: &[String]) -> Option<&str> {
+    let offered = |wanted: &str| mime_types.iter().find(|mime| mime.as_str() == wanted);
+    TEXT_MIME_TYPES
+        .into_iter()
+        .chain(["image/png"])
+        .find_map(offered)
+        .or_else(|| mime_types.iter().find(|mime| mime.starts_with("image/")))
+        .map(String::as_str)
+}
+
+/// Entries on disk: the index lists them newest first, and each entry's
+/// data is in a file of its own
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    #[serde(default)]
+    entries: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    mime_type: String,
+    copied: i64,
+    file: String,
+}
+
+/// Copied entries, newest first
+#[derive(Debug, Default)]
+pub struct History {
+    entries: VecDeque<Entry>,
+    max_entries: usize,
+    /// Bumped on every change
+    version: u64,
+}
+
+impl History {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            max_entries: max_entries.max(1),
+            version: 0,
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Add a newly copied entry at the front. Copying something already in
+    /// the history moves it to the front instead of adding it twice.
+    /// Returns false if it already was the newest entry.
+    pub fn push(&mut self, entry: Entry) -> bool {
+        if self
+            .entries
+            .front()
+            .is_some_and(|first| first.id == entry.id)
+        {
+            return false;
+        }
+        self.entries.retain(|existing| existing.id != entry.id);
+        self.entries.push_front(entry);
+        self.entries.truncate(self.max_entries);
+        self.version += 1;
+        true
+    }
+
+    pub fn remove(&mut self, id: u64) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        let removed = self.entries.len() != before;
+        if removed {
+            self.version += 1;
+        }
+        removed
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.version += 1;
+    }
+
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    /// Read a history saved in `dir`, keeping at most `max_entries`.
+    /// Entries whose data is missing are skipped.
+    pub fn load(dir: &Path, max_entries: usize) -> Self {
+        let mut history = Self::new(max_entries);
+        let Ok(text) = fs::read_to_string(dir.join(INDEX_FILE)) else {
+            return history;
+        };
+        let index: Index = match toml::from_str(&text) {
+            Ok(index) => index,
+            Err(e) => {
+                warn!("Ignoring clipboard history {}: {}", dir.display(), e);
+                return history;
+            }
+        };
+        for saved in index.entries.into_iter().take(history.max_entries) {
+            if !is_entry_file(&saved.file) {
+                debug!("Ignoring clipboard entry {:?}", saved.file);
+                continue;
+            }
+            match fs::read(dir.join(&saved.file)) {
+                Ok(data) => {
+                    history
+                        .entries
+                        .push_back(Entry::new(saved.mime_type, data, saved.copied))
+                }
+                Err(e) => debug!("Missing clipboard entry {}: {}", saved.file, e),
+            }
+        }
+        history
+    }
+
+    /// Save the history to `dir`, which only the user may read. Data files
+    /// are written once and removed when their entry goes; other files in
+    /// `dir` are left alone.
+    pub fn save(&self, dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        let private_file = |path: &Path| {
+            fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(path)
+        };
+
+        let mut index = Index::default();
+        for entry in &self.entries {
+            let file = format!("{:016x}", entry.id);
+            let path = dir.join(&file);
+            if !path.exists() {
+                private_file(&path)?.write_all(&entry.data)?;
+            }
+            index.entries.push(IndexEntry {
+                mime_type: entry.mime_type.clone(),
+                copied: entry.copied,
+                file,
+            });
+        }
+        private_file(&dir.join(INDEX_FILE))?.write_all(toml::to_string(&index)?.as_bytes())?;
+
+        let kept: HashSet<&str> = index
+            .entries
+            .iter()
+            .map(|saved| saved.file.as_str())
+            .collect();
+        for dir_entry in fs::read_dir(dir)?.flatten() {
+            let name = dir_entry.file_name();
+            let name = name.to_string_lossy();
+            if is_entry_file(&name) && !kept.contains(name.as_ref()) {
+                let _ = fs::remove_file(dir_entry.path());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What is recorded and where the history is kept
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Selections larger than this many bytes are not recorded
+    pub max_entry_size: usize,
+    /// Selections offering any of these MIME types, or of
+    /// [`IGNORED_MIME_TYPES`], are not recorded
+    pub ignore_mime_types: Vec<String>,
+    /// Directory to save the history in, if it is kept
+    pub history_dir: Option<PathBuf>,
+}
+
+/// Requests for the clipboard thread
+#[derive(Debug, Clone, Copy)]
+pub enum Command {
+    /// Make the entry the current selection again
+    Restore(u64),
+    Remove(u64),
+    Clear,
+}
+
+struct State {
+    history: Arc<Mutex<History>>,
+    options: Options,
+    on_change: Box<dyn Fn() + Send + Sync>,
+    queue_handle: QueueHandle<State>,
+    manager: ZwlrDataControlManagerV1,
+    device: ZwlrDataControlDeviceV1,
+    /// MIME types of the offers announced so far
+    offers: HashMap<ObjectId, Vec<String>>,
+    /// The selection we serve, and the entry it serves
+    source: Option<(ZwlrDataControlSourceV1, Entry)>,
+    /// Where the threads reading selections send what they read
+    selections: Sender<Entry>,
+}
+
+impl State {
+    /// Take the history saved on disk in place of the empty one the
+    /// module starts with
+    fn load_history(&self) {
+        let Some(dir) = &self.options.history_dir else {
+            return;
+        };
+        let max_entries = self.history.lock().unwrap().max_entries();
+        let mut loaded = History::load(dir, max_entries);
+        let mut history = self.history.lock().unwrap();
+        loaded.version = history.version + 1;
+        *history = loaded;
+        drop(history);
+        (self.on_change)();
+    }
+
+    fn changed(&self) {
+        let history = self.history.lock().unwrap();
+        if let Some(dir) = &self.options.history_dir {
+            if let Err(e) = history.save(dir) {
+                warn!("Failed to save clipboard history: {}", e);
+            }
+        }
+        drop(history);
+        (self.on_change)();
+    }
+
+    /// Read a new selection on another thread, unless it should be ignored
+    fn read_selection(&self, offer: &ZwlrDataControlOfferV1, mime_types: &[String]) {
+        if let Some(ignored) = mime_types.iter().find(|mime| {
+            IGNORED_MIME_TYPES.contains(&mime.as_str())
+                || self.options.ignore_mime_types.contains(mime)
+        }) {
+            debug!("Not recording a selection offering {}", ignored);
+            return;
+        }
+        let Some(mime_type) = preferred_mime_type(mime_types) else {
+            return;
+        };
+        let (reader, writer) = match io::pipe() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                warn!("Failed to read the selection: {}", e);
+                return;
+            }
+        };
+        offer.receive(mime_type.to_string(), writer.as_fd());
+        // Our end of the pipe has been duplicated into the request, and
+        // must close so the reader sees the end of the data
+        drop(writer);
+
+        let is_text = TEXT_MIME_TYPES.contains(&mime_type);
+        let mime_type = if is_text { TEXT_MIME_TYPE } else { mime_type }.to_string();
+        let max_size = self.options.max_entry_size;
+        let selections = self.selections.clone();
+        let spawned = thread::Builder::new()
+            .name("clipboard-read".to_string())
+            .spawn(move || {
+                let Some(data) = read_limited(reader, max_size) else {
+                    return;
+                };
+                if is_text && String::from_utf8_lossy(&data).trim().is_empty() {
+                    return;
+                }
+                let _ = selections.send(Entry::new(mime_type, data, now_secs()));
+            });
+        if let Err(e) = spawned {
+            warn!("Failed to start reading the selection: {}", e);
+        }
+    }
+
+    fn record(&mut self, entry: Entry) {
+        let pushed = self.history.lock().unwrap().push(entry);
+        if pushed {
+            self.changed();
+        }
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::Restore(id) => {
+                let Some(entry) = self.history.lock().unwrap().get(id).cloned() else {
+                    return;
+                };
+                let source = self.manager.create_data_source(&self.queue_handle, ());
+                if entry.is_image() {
+                    source.offer(entry.mime_type.clone());
+                } else {
+                    for mime_type in TEXT_MIME_TYPES {
+                        source.offer(mime_type.to_string());
+                    }
+                }
+                self.device.set_selection(Some(&source));
+                if let Some((old, _)) = self.source.replace((source, entry.clone())) {
+                    old.destroy();
+                }
+                // Our own selection comes back to be recorded, but show it
+                // at the front right away
+                self.record(Entry {
+                    copied: now_secs(),
+                    ..entry
+                });
+            }
+            Command::Remove(id) => {
+                let removed = self.history.lock().unwrap().remove(id);
+                if removed {
+                    self.changed();
+                }
+            }
+            Command::Clear => {
+                self.history.lock().unwrap().clear();
+                self.changed();
+            }
+        }
+    }
+}
+
+/// Read all of `reader`, or None if it is empty, fails or holds more than
+/// `max_size` bytes
+fn read_limited(reader: PipeReader, max_size: usize) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    if let Err(e) = reader.take(max_size as u64 + 1).read_to_end(&mut data) {
+        debug!("Failed to read the selection: {}", e);
+        return None;
+    }
+    if data.len() > max_size {
+        debug!("Not recording a selection of over {} bytes", max_size);
+        return None;
+    }
+    (!data.is_empty()).then_some(data)
+}
+
+/// Start watching the clipboard of the first seat. `history` is updated
+/// from a background thread, which first loads the history saved in
+/// `options.history_dir`, if any, and calls `on_change` after every
+/// change. Returns where to send commands, or an error if the compositor
+/// doesn't support data control.
+pub fn spawn(
+    history: Arc<Mutex<History>>,
+    options: Options,
+    on_change: impl Fn() + Send + Sync + 'static,
+) -> Result<Sender<Command>, Box<dyn std::error::Error>> {
+    spawn_on(Connection::connect_to_env()?, history, options, on_change)
+}
+
+/// [`spawn`] on an existing Wayland connection
+pub fn spawn_on(
+    connection: Connection,
+    history: Arc<Mutex<History>>,
+    options: Options,
+    on_change: impl Fn() + Send + Sync + 'static,
+) -> Result<Sender<Command>, Box<dyn std::error::Error>> {
+    let (globals, queue) = registry_queue_init::<State>(&connection)?;
+    let queue_handle = queue.handle();
+    let manager = globals.bind::<ZwlrDataControlManagerV1, _, _>(&queue_handle, 1..=1, ())?;
+    let seat = globals.bind::<WlSeat, _, _>(&queue_handle, 1..=1, ())?;
+    let device = manager.get_data_device(&seat, &queue_handle, ());
+
+    let (selections, selection_channel) = channel::channel();
+    let (commands, command_channel) = channel::channel();
+    let state = State {
+        history,
+        options,
+        on_change: Box::new(on_change),
+        queue_handle,
+        manager,
+        device,
+        offers: HashMap::new(),
+        source: None,
+        selections,
+    };
+    thread::Builder::new()
+        .name("clipboard".to_string())
+        .spawn(move || {
+            state.load_history();
+            if let Err(e) = run(connection, queue, state, selection_channel, command_channel) {
+                warn!("Clipboard thread stopped: {}", e);
+            }
+        })?;
+    Ok(commands)
+}
+
+fn run(
+    connection: Connection,
+    queue: wayland_client::EventQueue<State>,
+    mut state: State,
+    selections: Channel<Entry>,
+    commands: Channel<Command>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut event_loop = EventLoop::<State>::try_new()?;
+    let handle = event_loop.handle();
+    handle.insert_source(WaylandSource::new(connection, queue), |_, queue, state| {
+        queue.dispatch_pending(state)
+    })?;
+    handle.insert_source(selections, |event, _, state| {
+        if let channel::Event::Msg(entry) = event {
+            state.record(entry);
+        }
+    })?;
+    handle.insert_source(commands, |event, _, state| {
+        if let channel::Event::Msg(command) = event {
+            state.handle_command(command);
+        }
+    })?;
+    info!("Watching the clipboard");
+    event_loop.run(None, &mut state, |_| {})?;
+    Ok(())
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _state: &mut Self,
+        _registry: &WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlSeat, ()> for State {
+    fn event(
+        _state: &mut Self,
+        _seat: &WlSeat,
+        _event: wl_seat::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrDataControlManagerV1, ()> for State {
+    fn event(
+        _state: &mut Self,
+        _manager: &ZwlrDataControlManagerV1,
+        _event: zwlr_data_control_manager_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _device: &ZwlrDataControlDeviceV1,
+        event: zwlr_data_control_device_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_data_control_device_v1::Event::DataOffer { id } => {
+                state.offers.insert(id.id(), Vec::new());
+            }
+            zwlr_data_control_device_v1::Event::Selection { id } => {
+                // None when the selection was cleared, which leaves the
+                // history as it is
+                let Some(offer) = id else {
+                    return;
+                };
+                let mime_types = state.offers.remove(&offer.id()).unwrap_or_default();
+                state.read_selection(&offer, &mime_types);
+                offer.destroy();
+            }
+            zwlr_data_control_device_v1::Event::Finished => {
+                warn!("Clipboard data device finished");
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(State, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ())
+    ]);
+}
+
+impl Dispatch<ZwlrDataControlOfferV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        offer: &ZwlrDataControlOfferV1,
+        event: zwlr_data_control_offer_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let zwlr_data_control_offer_v1::Event::Offer { mime_type } = event {
+            state.offers.entry(offer.id()).or_default().push(mime_type);
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlSourceV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        source: &ZwlrDataControlSourceV1,
+        event: zwlr_data_control_source_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_data_control_source_v1::Event::Send { mime_type, fd } => {
+                let Some((_, entry)) = state
+                    .source
+                    .as_ref()
+                    .filter(|(serving, _)| serving == source)
+                else {
+                    return;
+                };
+                debug!("Sending the restored selection as {}", mime_type);
+                // A slow reader must not hold up the clipboard thread
+                let data = entry.data.clone();
+                let spawned = thread::Builder::new()
+                    .name("clipboard-write".to_string())
+                    .spawn(move || {
+                        if let Err(e) = File::from(fd).write_all(&data) {
+                            debug!("Failed to send the selection: {}", e);
+                        }
+                    });
+                if let Err(e) = spawned {
+                    warn!("Failed to start sending the selection: {}", e);
+                }
+            }
+            zwlr_data_control_source_v1::Event::Cancelled => {
+                // Someone else set the selection
+                if state
+                    .source
+                    .as_ref()
+                    .is_some_and(|(serving, _)| serving == source)
+                {
+                    state.source = None;
+                }
+                source.destroy();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(data: &str, copied: i64) -> Entry {
+        Entry::new(TEXT_MIME_TYPE.to_string(), data.as_bytes().to_vec(), copied)
+    }
+
+    #[test]
+    fn saving_only_removes_its_own_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), "mine").unwrap();
+        fs::write(dir.path().join("0123456789ABCDEF"), "mine too").unwrap();
+
+        let mut history = History::new(10);
+        history.push(text("first", 1));
+        history.push(text("second", 2));
+        history.save(dir.path()).unwrap();
+        let first = history.entries().nth(1).unwrap().id;
+        history.remove(first);
+        history.save(dir.path()).unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        let second = format!("{:016x}", history.entries().next().unwrap().id);
+        let mut expected = vec![
+            "0123456789ABCDEF".to_string(),
+            INDEX_FILE.to_string(),
+            "notes.txt".to_string(),
+            second,
+        ];
+        expected.sort();
+        assert_eq!(names, expected);
+
+        let loaded = History::load(dir.path(), 10);
+        let previews: Vec<String> = loaded.entries().filter_map(Entry::preview).collect();
+        assert_eq!(previews, ["second"]);
+    }
+
+    #[test]
+    fn index_entries_outside_the_directory_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("secret"), "not history").unwrap();
+        let history_dir = dir.path().join("history");
+        fs::create_dir(&history_dir).unwrap();
+        fs::write(
+            history_dir.join(INDEX_FILE),
+            "[[entries]]\nmime_type = \"text/plain\"\ncopied = 1\nfile = \"../secret\"\n",
+        )
+        .unwrap();
+        assert_eq!(History::load(&history_dir, 10).entries().count(), 0);
+    }
+
+    /// A `width` by `height` PNG
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&vec![0x80; (width * height * 4) as usize])
+            .unwrap();
+        writer.finish().unwrap();
+        data
+    }
+
+    #[test]
+    fn ids_are_the_same_in_every_build() {
+        // Saved histories name their files after these
+        assert_eq!(text("hello", 1).id, 0x6cd2_490d_6771_879b,);
+        assert_eq!(text("hello", 2).id, text("hello", 1).id);
+        assert_ne!(
+            Entry::new("text/html".to_string(), b"hello".to_vec(), 1).id,
+            text("hello", 1).id
+        );
+    }
+
+    #[test]
+    fn png_entries_get_thumbnails() {
+        let entry = Entry::new("image/png".to_string(), png(96, 16), 1);
+        let thumbnail = entry.thumbnail.unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (96, 16));
+        assert_eq!((thumbnail.image.width, thumbnail.image.height), (48, 8));
+
+        // Small images aren't scaled up
+        let entry = Entry::new("image/png".to_string(), png(10, 30), 1);
+        let thumbnail = entry.thumbnail.unwrap();
+        assert_eq!((thumbnail.image.width, thumbnail.image.height), (8, 24));
+        let entry = Entry::new("image/png".to_string(), png(4, 4), 1);
+        let thumbnail = entry.thumbnail.unwrap();
+        assert_eq!((thumbnail.image.width, thumbnail.image.height), (4, 4));
+
+        // Only PNGs are decoded
+        let broken = Entry::new("image/png".to_string(), b"not a png".to_vec(), 1);
+        assert!(broken.thumbnail.is_none());
+        let jpeg = Entry::new("image/jpeg".to_string(), png(4, 4), 1);
+        assert!(jpeg.thumbnail.is_none());
+        assert!(text("hello", 1).thumbnail.is_none());
+    }
+
+    #[test]
+    fn entry_file_names() {
+        assert!(is_entry_file("00000000deadbeef"));
+        assert!(!is_entry_file("00000000DEADBEEF"));
+        assert!(!is_entry_file("deadbeef"));
+        assert!(!is_entry_file(INDEX_FILE));
+    }
+}
@@ -0,0 +1,208 @@
+//! Pixel colours for hypr-notch
+//!
+//! Configuration, themes and modules describe colours with straight alpha
+//! as `[b, g, r, a]` arrays. The `Argb8888` buffer the compositor reads
+//! holds premultiplied pixels, so every write goes through [`PremulColor`],
+//! which does the premultiplication, compositing and byte order in one
+//! place.
+
+/// A colour with premultiplied alpha, as stored in the buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PremulColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+/// `a * b / 255`, rounded to the nearest integer
+fn mul_div_255(a: u8, b: u8) -> u8 {
+    let product = a as u32 * b as u32 + 128;
+    ((product + (product >> 8)) >> 8) as u8
+}
+
+/// [`mul_div_255`] applied to all four channels of a packed pixel at once,
+/// two channels per 16-bit lane
+fn scale_packed(pixel: u32, factor: u8) -> u32 {
+    const LANES: u32 = 0x00ff_00ff;
+    let scale = |lanes: u32| {
+        let product = lanes * factor as u32 + 0x0080_0080;
+        ((product + ((product >> 8) & LANES)) >> 8) & LANES
+    };
+    scale(pixel & LANES) | (scale((pixel >> 8) & LANES) << 8)
+}
+
+impl PremulColor {
+    pub const TRANSPARENT: Self = Self {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 0,
+    };
+
+    /// Premultiply a straight-alpha colour given in `[b, g, r, a]` order
+    pub fn from_bgra([b, g, r, a]: [u8; 4]) -> Self {
+        Self {
+            r: mul_div_255(r, a),
+            g: mul_div_255(g, a),
+            b: mul_div_255(b, a),
+            a,
+        }
+    }
+
+    /// Premultiply a straight-alpha colour given in `[r, g, b, a]` order,
+    /// as decoded images are
+    pub fn from_rgba([r, g, b, a]: [u8; 4]) -> Self {
+        Self::from_bgra([b, g, r, a])
+    }
+
+    /// Scale the colour by `coverage` (0 to 1), for anti-aliased edges
+    pub fn with_coverage(self, coverage: f32) -> Self {
+        let coverage = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+        self.scaled(coverage)
+    }
+
+    /// Multiply every channel by `factor / 255`
+    pub fn scaled(self, factor: u8) -> Self {
+        Self::from_argb(scale_packed(self.to_argb(), factor))
+    }
+
+    /// Porter-Duff source-over: this colour composited on top of `below`.
+    /// A premultiplied channel never exceeds its alpha, so the sums can't
+    /// carry into the next channel.
+    pub fn over(self, below: Self) -> Self {
+        Self::from_argb(self.over_argb(below.to_argb()))
+    }
+
+    /// [`over`](Self::over) for a native `0xAARRGGBB` pixel, so runs of
+    /// buffer pixels can be composited without unpacking each one
+    pub fn over_argb(self, below: u32) -> u32 {
+        self.to_argb()
+            .wrapping_add(scale_packed(below, 255 - self.a))
+    }
+
+    /// The pixel as a native `0xAARRGGBB` value
+    pub fn to_argb(self) -> u32 {
+        u32::from_be_bytes([self.a, self.r, self.g, self.b])
+    }
+
+    /// Read a native `0xAARRGGBB` value
+    pub fn from_argb(pixel: u32) -> Self {
+        let [a, r, g, b] = pixel.to_be_bytes();
+        Self { r, g, b, a }
+    }
+
+    /// Bytes of the pixel in buffer memory. `wl_shm` formats are always
+    /// little-endian, whatever the byte order of the host.
+    pub fn to_bytes(self) -> [u8; 4] {
+        self.to_argb().to_le_bytes()
+    }
+
+    /// Read a pixel from buffer memory
+    pub fn from_bytes(bytes: [u8; 4]) -> Self {
+        Self::from_argb(u32::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_div_255_rounds_to_nearest() {
+        for a in 0..=255u8 {
+            for b in 0..=255u8 {
+                let exact = (a as f64 * b as f64 / 255.0).round() as u8;
+                assert_eq!(mul_div_255(a, b), exact, "{} * {} / 255", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn packed_scaling_matches_each_channel() {
+        let pixel = u32::from_be_bytes([255, 200, 128, 1]);
+        for factor in 0..=255u8 {
+            let expected = pixel
+                .to_be_bytes()
+                .map(|channel| mul_div_255(channel, factor));
+            assert_eq!(scale_packed(pixel, factor).to_be_bytes(), expected);
+        }
+    }
+
+    #[test]
+    fn straight_alpha_is_premultiplied() {
+        let color = PremulColor::from_bgra([255, 128, 0, 128]);
+        assert_eq!(
+            color,
+            PremulColor {
+                r: 0,
+                g: 64,
+                b: 128,
+                a: 128
+            }
+        );
+        // Buffer bytes are B, G, R, A
+        assert_eq!(color.to_bytes(), [128, 64, 0, 128]);
+        assert_eq!(PremulColor::from_bytes(color.to_bytes()), color);
+        assert_eq!(PremulColor::from_rgba([0, 128, 255, 128]), color);
+
+        assert_eq!(
+            PremulColor::from_bgra([1, 2, 3, 255]).to_bytes(),
+            [1, 2, 3, 255]
+        );
+        assert_eq!(
+            PremulColor::from_bgra([1, 2, 3, 0]),
+            PremulColor::TRANSPARENT
+        );
+    }
+
+    #[test]
+    fn source_over() {
+        let blue = PremulColor::from_bgra([255, 0, 0, 255]);
+        let red = PremulColor::from_bgra([0, 0, 255, 255]);
+
+        // Opaque covers, transparent leaves alone
+        assert_eq!(red.over(blue), red);
+        assert_eq!(PremulColor::TRANSPARENT.over(blue), blue);
+        assert_eq!(
+            PremulColor::TRANSPARENT.over(PremulColor::TRANSPARENT),
+            PremulColor::TRANSPARENT
+        );
+
+        // Half red over blue keeps half of the blue and stays opaque
+        let half_red = PremulColor::from_bgra([0, 0, 255, 128]);
+        assert_eq!(
+            half_red.over(blue),
+            PremulColor {
+                r: 128,
+                g: 0,
+                b: 127,
+                a: 255
+            }
+        );
+        // Over nothing it is unchanged
+        assert_eq!(half_red.over(PremulColor::TRANSPARENT), half_red);
+        // Two halves make three quarters
+        assert_eq!(half_red.over(half_red).a, 128 + 64);
+        assert_eq!(
+            half_red.over_argb(blue.to_argb()),
+            half_red.over(blue).to_argb()
+        );
+    }
+
+    #[test]
+    fn coverage_scales_every_channel() {
+        let color = PremulColor::from_bgra([200, 100, 50, 255]);
+        assert_eq!(color.with_coverage(1.0), color);
+        assert_eq!(color.with_coverage(0.0), PremulColor::TRANSPARENT);
+        assert_eq!(
+            color.with_coverage(0.5),
+            PremulColor {
+                r: 25,
+                g: 50,
+                b: 100,
+                a: 128
+            }
+        );
+    }
+}
@@ -4,7 +4,10 @@
 //! functionality to load and save configuration from/to files.
 //! The NotchConfig struct contains all configurable parameters.
 
+use crate::theme::{ThemeColor, ThemeConfig};
+use log::warn;
 use serde::{Deserialize, Serialize};
+use smithay_client_toolkit::shell::wlr_layer::{Anchor, Layer};
 use std::{
     fs,
     io::ErrorKind,
@@ -19,13 +22,236 @@ pub struct NotchConfig {
     pub expanded_width: u32,
     pub expanded_height: u32,
     pub corner_radius: u32,
-    pub background_color: [u8; 4], // BGRA format
+    /// Colour string, theme role or palette name, or a legacy BGRA array
+    #[serde(default = "default_background_color")]
+    pub background_color: ThemeColor,
+
+    /// Solid colour, gradient or image behind the modules
+    #[serde(default)]
+    pub background: Background,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<Shadow>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub border: Option<Border>,
+
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    /// Freedesktop icon theme, the GTK icon theme by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon_theme: Option<String>,
+
+    /// Screen edge or corner the notch is attached to
+    #[serde(default)]
+    pub anchor: NotchAnchor,
+    #[serde(default)]
+    pub margin: Margins,
+    #[serde(default)]
+    pub layer: NotchLayer,
+    /// Reserve space for the collapsed notch so windows don't go beneath it
+    #[serde(default)]
+    pub exclusive_zone: bool,
+    /// Layer shell namespace, for compositor rules such as `layerrule`
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+
+    /// Invisible strip along the anchored edge that also expands the
+    /// collapsed notch on hover
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hot_zone: Option<HotZone>,
+    /// Only accept input over interactive modules and let every other
+    /// click through to the windows beneath
+    #[serde(default)]
+    pub click_through: bool,
+
+    /// What to do while a fullscreen window covers the notch's output
+    #[serde(default)]
+    pub fullscreen_behavior: FullscreenBehavior,
+    /// Height of the strip left over when `fullscreen_behavior = "shrink"`
+    #[serde(default = "default_fullscreen_height")]
+    pub fullscreen_height: u32,
 
     // New modules field with default
     #[serde(default)]
     pub modules: ModulesConfig,
 }
 
+/// How the notch background is painted
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "style", rename_all = "lowercase")]
+pub enum Background {
+    /// `background_color` only
+    #[default]
+    Solid,
+    /// Evenly spaced colour stops along `angle`, in degrees clockwise from
+    /// left-to-right (90 runs top to bottom)
+    Linear {
+        colors: Vec<ThemeColor>,
+        #[serde(default)]
+        angle: f32,
+    },
+    /// Evenly spaced colour stops from the centre outwards
+    Radial { colors: Vec<ThemeColor> },
+    /// A PNG file drawn over `background_color`
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        fit: ImageFit,
+    },
+}
+
+/// How a background image is scaled into the notch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFit {
+    /// Fill the notch, cropping the image
+    #[default]
+    Cover,
+    /// Show the whole image, leaving `background_color` around it
+    Contain,
+}
+
+/// Soft shadow around the notch, drawn in a transparent margin that is
+/// added to the surface on every side away from the anchored edge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Shadow {
+    /// Distance in pixels over which the shadow fades out
+    pub size: u32,
+    #[serde(default = "default_shadow_color")]
+    pub color: ThemeColor,
+}
+
+/// Stroke along the notch outline, except where it meets the screen edge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Border {
+    pub color: ThemeColor,
+    #[serde(default = "default_border_width")]
+    pub width: u32,
+}
+
+/// Edge or corner of the output the notch is attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotchAnchor {
+    #[default]
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl NotchAnchor {
+    /// Whether the notch sits on the top edge
+    pub fn top(self) -> bool {
+        matches!(self, Self::Top | Self::TopLeft | Self::TopRight)
+    }
+
+    /// Whether the notch sits on the bottom edge
+    pub fn bottom(self) -> bool {
+        matches!(self, Self::Bottom | Self::BottomLeft | Self::BottomRight)
+    }
+
+    /// Whether the notch sits on the left edge
+    pub fn left(self) -> bool {
+        matches!(self, Self::Left | Self::TopLeft | Self::BottomLeft)
+    }
+
+    /// Whether the notch sits on the right edge
+    pub fn right(self) -> bool {
+        matches!(self, Self::Right | Self::TopRight | Self::BottomRight)
+    }
+
+    /// Whether the notch hangs off a side edge and grows horizontally
+    pub fn is_vertical(self) -> bool {
+        matches!(self, Self::Left | Self::Right)
+    }
+
+    /// Layer shell anchor; a single edge centers the notch along it
+    pub fn to_anchor(self) -> Anchor {
+        let mut anchor = Anchor::empty();
+        anchor.set(Anchor::TOP, self.top());
+        anchor.set(Anchor::BOTTOM, self.bottom());
+        anchor.set(Anchor::LEFT, self.left());
+        anchor.set(Anchor::RIGHT, self.right());
+        anchor
+    }
+}
+
+/// Distance in pixels between the notch and each screen edge
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Margins {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+
+/// Size of the hover strip around the collapsed notch
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HotZone {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Layer shell layer the notch is placed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotchLayer {
+    Background,
+    Bottom,
+    #[default]
+    Top,
+    Overlay,
+}
+
+impl NotchLayer {
+    pub fn to_layer(self) -> Layer {
+        match self {
+            Self::Background => Layer::Background,
+            Self::Bottom => Layer::Bottom,
+            Self::Top => Layer::Top,
+            Self::Overlay => Layer::Overlay,
+        }
+    }
+}
+
+/// How the notch reacts to fullscreen windows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FullscreenBehavior {
+    /// Unmap the surface until fullscreen ends
+    #[default]
+    Hide,
+    /// Collapse to a thin strip that still expands on hover
+    Shrink,
+    /// Stay on top of the fullscreen window
+    Keep,
+}
+
+fn default_fullscreen_height() -> u32 {
+    4
+}
+
+fn default_background_color() -> ThemeColor {
+    ThemeColor::named("background")
+}
+
+fn default_shadow_color() -> ThemeColor {
+    ThemeColor::Fixed([0, 0, 0, 128])
+}
+
+fn default_border_width() -> u32 {
+    1
+}
+
+fn default_namespace() -> String {
+    "hypr-notch".to_string()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ModulesConfig {
     #[serde(default)]
@@ -43,7 +269,21 @@ impl Default for NotchConfig {
             expanded_width: 800,
             expanded_height: 400,
             corner_radius: 20,
-            background_color: [0, 0, 0, 255], // Black, fully opaque
+            background_color: default_background_color(),
+            background: Background::default(),
+            shadow: None,
+            border: None,
+            theme: ThemeConfig::default(),
+            icon_theme: None,
+            anchor: NotchAnchor::default(),
+            margin: Margins::default(),
+            layer: NotchLayer::default(),
+            exclusive_zone: false,
+            namespace: default_namespace(),
+            hot_zone: None,
+            click_through: false,
+            fullscreen_behavior: FullscreenBehavior::default(),
+            fullscreen_height: default_fullscreen_height(),
             modules: ModulesConfig::default(),
         }
     }
@@ -100,3 +340,32 @@ impl NotchConfig {
         Ok(())
     }
 }
+
+/// Read a colour from a module configuration table: a `#RRGGBBAA` or
+/// `rgb()`/`rgba()` string, a theme role or palette name, or a legacy
+/// `[b, g, r, a]` array
+pub fn color_from_config(config: &toml::Table, key: &str) -> Option<ThemeColor> {
+    match config.get(key)?.clone().try_into() {
+        Ok(color) => Some(color),
+        Err(e) => {
+            warn!("Invalid colour for {}: {}", key, e);
+            None
+        }
+    }
+}
+
+/// Expand a leading `~/` and resolve relative paths against the
+/// configuration directory
+pub fn resolve_path(path: &Path) -> PathBuf {
+    if let Ok(rest) = path.strip_prefix("~") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest);
+        }
+    }
+    if path.is_relative() {
+        if let Some(config_dir) = NotchConfig::get_config_path().parent() {
+            return config_dir.join(path);
+        }
+    }
+    path.to_path_buf()
+}
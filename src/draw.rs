@@ -5,66 +5,515 @@
 //! including handling transparency, rounded corners,
 //! and other visual elements.
 
+use crate::color::PremulColor;
+use crate::config::{ImageFit, Margins, NotchAnchor};
+use crate::image::{Filter, RgbaImage};
+use crate::module::Rect;
+use crate::theme::{self, Theme};
 use fontdue::{Font, FontSettings};
 use log::{info, warn};
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 
-/// Fill a canvas with color and rounded corners if expanded
-pub fn fill_canvas_with_rounded_corners(
-    canvas: &mut [u8],
-    width: u32,
-    height: u32,
-    expanded: bool,
-    corner_radius: u32,
-    color: [u8; 4],
-) {
-    if !expanded || corner_radius == 0 {
-        // If not expanded or radius is zero, just fill with solid color
-        for pixel in canvas.chunks_exact_mut(4) {
-            pixel.copy_from_slice(&color);
+/// Entries in the colour ramp a gradient background is drawn from
+const GRADIENT_STEPS: usize = 1024;
+
+/// Which corners of the notch are rounded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Corners {
+    pub top_left: bool,
+    pub top_right: bool,
+    pub bottom_left: bool,
+    pub bottom_right: bool,
+}
+
+impl Corners {
+    /// Round every corner that doesn't touch the screen edge the notch is
+    /// attached to, so it blends into the edge and bulges into the screen
+    pub fn for_anchor(anchor: NotchAnchor, margin: &Margins) -> Self {
+        let top = anchor.top() && margin.top == 0;
+        let bottom = anchor.bottom() && margin.bottom == 0;
+        let left = anchor.left() && margin.left == 0;
+        let right = anchor.right() && margin.right == 0;
+        Self {
+            top_left: !(top || left),
+            top_right: !(top || right),
+            bottom_left: !(bottom || left),
+            bottom_right: !(bottom || right),
         }
-        return;
     }
+}
 
-    // Draw with rounded corners at the bottom when expanded
-    let radius = corner_radius as i32;
+/// Resolved paint for the notch background, colours in buffer order
+pub enum Fill<'a> {
+    Solid([u8; 4]),
+    Linear {
+        stops: Vec<[u8; 4]>,
+        angle: f32,
+    },
+    Radial {
+        stops: Vec<[u8; 4]>,
+    },
+    Image {
+        image: &'a RgbaImage,
+        fit: ImageFit,
+        backdrop: [u8; 4],
+    },
+}
+
+/// Everything needed to paint the notch
+pub struct NotchStyle<'a> {
+    /// Round the corners; only the expanded notch is rounded
+    pub rounded: bool,
+    pub corner_radius: u32,
+    pub corners: Corners,
+    pub fill: Fill<'a>,
+    /// Fade-out distance and colour of the drop shadow
+    pub shadow: Option<(u32, [u8; 4])>,
+    /// Width and colour of the border
+    pub border: Option<(u32, [u8; 4])>,
+}
+
+/// Paint the notch into the `notch` area of a canvas: the drop shadow
+/// around it, the background clipped to its rounded shape, and the border.
+/// Everything else is cleared to transparent.
+pub fn draw_notch(canvas: &mut [u8], width: u32, height: u32, notch: Rect, style: &NotchStyle) {
+    let radius = if style.rounded {
+        style.corner_radius
+    } else {
+        0
+    };
+    let corners = style.corners;
+    let notch_height = notch.height as i32;
+
+    // Columns covered by each row of the notch, in notch coordinates
+    let spans: Vec<(i32, i32)> = (0..notch.height)
+        .map(|y| {
+            row_span(y, notch.width, notch.height, radius, corners)
+                .map_or((0, 0), |(start, end)| (start as i32, end as i32))
+        })
+        .collect();
+    let span = |y: i32| {
+        usize::try_from(y)
+            .ok()
+            .and_then(|y| spans.get(y).copied())
+            .unwrap_or((0, 0))
+    };
+
+    // Sides whose corners are both square sit against the screen edge and
+    // get no border, so treat everything beyond them as part of the notch
+    let flush_top = !corners.top_left && !corners.top_right;
+    let flush_bottom = !corners.bottom_left && !corners.bottom_right;
+    let flush_left = !corners.top_left && !corners.bottom_left;
+    let flush_right = !corners.top_right && !corners.bottom_right;
+    let solid_span = |y: i32| {
+        let y = match y {
+            y if y < 0 && flush_top => 0,
+            y if y >= notch_height && flush_bottom => notch_height - 1,
+            y => y,
+        };
+        match span(y) {
+            (start, end) if start < end => (
+                if flush_left { i32::MIN } else { start },
+                if flush_right { i32::MAX } else { end },
+            ),
+            _ => (0, 0),
+        }
+    };
+    // Columns of row `y` that are more than the border width away from
+    // the edge of the notch in every direction
+    let interior = |y: i32, (start, end): (i32, i32)| {
+        let Some((border_width, _)) = style.border else {
+            return (start, end);
+        };
+        let border_width = border_width as i32;
+        let (solid_start, solid_end) = solid_span(y);
+        let mut inner = (
+            solid_start.saturating_add(border_width),
+            solid_end.saturating_sub(border_width),
+        );
+        for k in 1..=border_width {
+            for (row_start, row_end) in [solid_span(y - k), solid_span(y + k)] {
+                inner = (inner.0.max(row_start), inner.1.min(row_end));
+            }
+        }
+        (inner.0.max(start), inner.1.min(end))
+    };
 
-    for y in 0..height as i32 {
-        for x in 0..width as i32 {
-            let idx = (y * width as i32 + x) as usize * 4;
-
-            // Check if this pixel is in the rounded corner areas
-            let in_rounded_area = if y > height as i32 - radius {
-                // Bottom left corner
-                if x < radius {
-                    let dx = radius - x;
-                    let dy = y - (height as i32 - radius);
-                    dx * dx + dy * dy > radius * radius
+    let border = style.border.map(|(_, color)| PremulColor::from_bgra(color));
+    let shader = Shader::new(&style.fill, notch.width, notch.height);
+    let to_surface = |x: i32| (x + notch.x).clamp(0, width as i32) as usize;
+
+    for (y, row) in canvas
+        .chunks_exact_mut(width as usize * 4)
+        .take(height as usize)
+        .enumerate()
+    {
+        let ny = y as i32 - notch.y;
+        let (start, end) = span(ny);
+        let (inner_start, inner_end) = match interior(ny, (start, end)) {
+            (inner_start, inner_end) if inner_start < inner_end => (inner_start, inner_end),
+            _ => (end, end),
+        };
+        let [start, inner_start, inner_end, end] =
+            [start, inner_start, inner_end, end].map(to_surface);
+
+        // Shadow or nothing either side of the notch
+        for (x0, x1) in [(0, start), (end, width as usize)] {
+            let pixels = &mut row[x0 * 4..x1 * 4];
+            match style.shadow {
+                Some((size, color)) => {
+                    for (i, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+                        let nx = (x0 + i) as i32 - notch.x;
+                        let distance = rounded_rect_distance(
+                            nx as f32 + 0.5,
+                            ny as f32 + 0.5,
+                            notch.width as f32,
+                            notch.height as f32,
+                            radius as f32,
+                            corners,
+                        );
+                        pixel.copy_from_slice(
+                            &shadow_pixel(distance, size as f32, color).to_bytes(),
+                        );
+                    }
                 }
-                // Bottom right corner
-                else if x >= width as i32 - radius {
-                    let dx = x - (width as i32 - radius);
-                    let dy = y - (height as i32 - radius);
-                    dx * dx + dy * dy > radius * radius
-                } else {
-                    false
+                None => fill_span(pixels, PremulColor::TRANSPARENT),
+            }
+        }
+
+        if let Some(border) = border {
+            fill_span(&mut row[start * 4..inner_start * 4], border);
+            fill_span(&mut row[inner_end * 4..end * 4], border);
+        }
+
+        shader.shade_row(
+            &mut row[inner_start * 4..inner_end * 4],
+            inner_start as i32 - notch.x,
+            ny,
+        );
+    }
+}
+
+/// Replace every pixel of a run of buffer bytes with `f(index, pixel)`,
+/// pixels being native `0xAARRGGBB` values. Runs are worked on as whole
+/// words when they are aligned, which shm buffers always are.
+#[inline]
+fn map_pixels(pixels: &mut [u8], mut f: impl FnMut(usize, u32) -> u32) {
+    match bytemuck::try_cast_slice_mut::<u8, u32>(&mut *pixels) {
+        Ok(words) => {
+            for (i, word) in words.iter_mut().enumerate() {
+                *word = f(i, u32::from_le(*word)).to_le();
+            }
+        }
+        Err(_) => {
+            for (i, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+                let below = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                pixel.copy_from_slice(&f(i, below).to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Set every pixel of a run of buffer bytes to `color`
+fn fill_span(pixels: &mut [u8], color: PremulColor) {
+    let argb = color.to_argb();
+    match bytemuck::try_cast_slice_mut::<u8, u32>(&mut *pixels) {
+        Ok(words) => words.fill(argb.to_le()),
+        Err(_) => map_pixels(pixels, |_, _| argb),
+    }
+}
+
+/// Composite `color` over every pixel of a run of buffer bytes
+fn blend_span(pixels: &mut [u8], color: PremulColor) {
+    map_pixels(pixels, |_, below| color.over_argb(below));
+}
+
+/// The notch background, ready to be painted a row at a time. Everything
+/// that doesn't depend on the position is worked out up front, as the
+/// rest runs for every pixel.
+enum Shader<'a> {
+    Solid(PremulColor),
+    Linear {
+        ramp: Vec<u32>,
+        center: (f32, f32),
+        sin: f32,
+        cos: f32,
+        /// Half the length of the gradient line across the notch
+        half: f32,
+    },
+    Radial {
+        ramp: Vec<u32>,
+        center: (f32, f32),
+        reach: f32,
+    },
+    Image {
+        image: &'a RgbaImage,
+        backdrop: PremulColor,
+        scale: f32,
+        offset: (f32, f32),
+    },
+}
+
+impl<'a> Shader<'a> {
+    fn new(fill: &'a Fill, width: u32, height: u32) -> Self {
+        let center = (width as f32 / 2.0, height as f32 / 2.0);
+        // Gradients look their pixels up in a ramp rather than
+        // interpolating the stops at every pixel
+        let ramp = |stops: &[[u8; 4]]| -> Vec<u32> {
+            let stops: Vec<PremulColor> =
+                stops.iter().copied().map(PremulColor::from_bgra).collect();
+            (0..GRADIENT_STEPS)
+                .map(|i| gradient(&stops, i as f32 / (GRADIENT_STEPS - 1) as f32).to_argb())
+                .collect()
+        };
+
+        match fill {
+            Fill::Solid(color) => Self::Solid(PremulColor::from_bgra(*color)),
+            Fill::Linear { stops, angle } => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                Self::Linear {
+                    ramp: ramp(stops),
+                    center,
+                    sin,
+                    cos,
+                    half: (width as f32 * cos.abs() + height as f32 * sin.abs()) / 2.0,
                 }
-            } else {
-                false
-            };
+            }
+            Fill::Radial { stops } => Self::Radial {
+                ramp: ramp(stops),
+                center,
+                reach: center.0.hypot(center.1).max(1.0),
+            },
+            Fill::Image {
+                image,
+                fit,
+                backdrop,
+            } => {
+                let scale_x = width as f32 / image.width.max(1) as f32;
+                let scale_y = height as f32 / image.height.max(1) as f32;
+                let scale = match fit {
+                    ImageFit::Cover => scale_x.max(scale_y),
+                    ImageFit::Contain => scale_x.min(scale_y),
+                };
+                Self::Image {
+                    image,
+                    backdrop: PremulColor::from_bgra(*backdrop),
+                    scale,
+                    offset: (
+                        (width as f32 - image.width as f32 * scale) / 2.0,
+                        (height as f32 - image.height as f32 * scale) / 2.0,
+                    ),
+                }
+            }
+        }
+    }
 
-            if !in_rounded_area {
-                canvas[idx..idx + 4].copy_from_slice(&color);
-            } else {
-                // Set transparent for rounded corners
-                canvas[idx..idx + 3].copy_from_slice(&[0, 0, 0]);
-                canvas[idx + 3] = 0; // Transparent
+    /// Paint a run of buffer bytes starting at `(x, y)` in notch coordinates
+    fn shade_row(&self, pixels: &mut [u8], x: i32, y: i32) {
+        let sample = |ramp: &[u32], t: f32| {
+            ramp[(t.clamp(0.0, 1.0) * (GRADIENT_STEPS - 1) as f32 + 0.5) as usize]
+        };
+        let px = |i: usize| (x + i as i32) as f32 + 0.5;
+        let py = y as f32 + 0.5;
+
+        match self {
+            Self::Solid(color) => fill_span(pixels, *color),
+            Self::Linear {
+                ramp,
+                center: (cx, cy),
+                sin,
+                cos,
+                half,
+            } => {
+                // Position along the gradient changes by the same step
+                // from one pixel to the next
+                let length = (2.0 * half).max(1.0);
+                let mut t = ((px(0) - cx) * cos + (py - cy) * sin + half) / length;
+                let step = cos / length;
+                map_pixels(pixels, |_, _| {
+                    let pixel = sample(ramp, t);
+                    t += step;
+                    pixel
+                });
+            }
+            Self::Radial {
+                ramp,
+                center: (cx, cy),
+                reach,
+            } => {
+                let dy = py - cy;
+                map_pixels(pixels, |i, _| sample(ramp, (px(i) - cx).hypot(dy) / reach));
+            }
+            Self::Image {
+                image,
+                backdrop,
+                scale,
+                offset: (offset_x, offset_y),
+            } => {
+                let iy = ((py - offset_y) / scale).floor();
+                if iy < 0.0 || iy >= image.height as f32 {
+                    return fill_span(pixels, *backdrop);
+                }
+                map_pixels(pixels, |i, _| {
+                    let ix = ((px(i) - offset_x) / scale).floor();
+                    if ix < 0.0 || ix >= image.width as f32 {
+                        return backdrop.to_argb();
+                    }
+                    PremulColor::from_rgba(image.pixel(ix as u32, iy as u32))
+                        .over_argb(backdrop.to_argb())
+                });
+            }
+        }
+    }
+}
+
+/// Colour at `t` (0 to 1) of evenly spaced gradient stops, interpolated
+/// with premultiplied alpha so translucent stops don't darken the blend
+fn gradient(stops: &[PremulColor], t: f32) -> PremulColor {
+    match stops {
+        [] => PremulColor::TRANSPARENT,
+        [color] => *color,
+        _ => {
+            let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+            let index = (position as usize).min(stops.len() - 2);
+            let frac = position - index as f32;
+            let (from, to) = (stops[index], stops[index + 1]);
+            let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * frac).round() as u8;
+            PremulColor {
+                r: mix(from.r, to.r),
+                g: mix(from.g, to.g),
+                b: mix(from.b, to.b),
+                a: mix(from.a, to.a),
+            }
+        }
+    }
+}
+
+/// Signed distance from a point to a rectangle at the origin whose rounded
+/// corners use `radius`; negative inside
+fn rounded_rect_distance(
+    px: f32,
+    py: f32,
+    width: f32,
+    height: f32,
+    radius: f32,
+    corners: Corners,
+) -> f32 {
+    let (half_width, half_height) = (width / 2.0, height / 2.0);
+    let (dx, dy) = (px - half_width, py - half_height);
+    let rounded = match (dx < 0.0, dy < 0.0) {
+        (true, true) => corners.top_left,
+        (false, true) => corners.top_right,
+        (true, false) => corners.bottom_left,
+        (false, false) => corners.bottom_right,
+    };
+    let radius = if rounded {
+        radius.min(half_width).min(half_height)
+    } else {
+        0.0
+    };
+
+    let qx = dx.abs() - half_width + radius;
+    let qy = dy.abs() - half_height + radius;
+    qx.max(0.0).hypot(qy.max(0.0)) + qx.max(qy).min(0.0) - radius
+}
+
+/// Shadow pixel `distance` pixels away from the notch, fading out
+/// quadratically over `size`
+fn shadow_pixel(distance: f32, size: f32, color: [u8; 4]) -> PremulColor {
+    if distance <= 0.0 || distance >= size {
+        return PremulColor::TRANSPARENT;
+    }
+    let falloff = (1.0 - distance / size).powi(2);
+    PremulColor::from_bgra(color).with_coverage(falloff)
+}
+
+/// Whether a pixel lies in the transparent part of a rounded corner
+fn in_rounded_corner(
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    corner_radius: u32,
+    corners: Corners,
+) -> bool {
+    let radius = corner_radius as i32;
+    let (x, y) = (x as i32, y as i32);
+    let (width, height) = (width as i32, height as i32);
+
+    // Distance into the corner square, measured from the arc's center
+    let dx = if x < radius {
+        radius - x
+    } else if x >= width - radius {
+        x - (width - radius)
+    } else {
+        return false;
+    };
+    let dy = if y < radius {
+        radius - y
+    } else if y > height - radius {
+        y - (height - radius)
+    } else {
+        return false;
+    };
+
+    let rounded = match (x < radius, y < radius) {
+        (true, true) => corners.top_left,
+        (false, true) => corners.top_right,
+        (true, false) => corners.bottom_left,
+        (false, false) => corners.bottom_right,
+    };
+    rounded && dx * dx + dy * dy > radius * radius
+}
+
+/// Rectangles covering the opaque part of an expanded notch, as drawn by
+/// `draw_notch`. Corner arcs are approximated by one rectangle per run of
+/// rows with the same extent.
+pub fn shape_rects(width: u32, height: u32, corner_radius: u32, corners: Corners) -> Vec<Rect> {
+    let mut rects: Vec<Rect> = Vec::new();
+
+    for y in 0..height {
+        let Some((start, end)) = row_span(y, width, height, corner_radius, corners) else {
+            continue;
+        };
+
+        match rects.last_mut() {
+            Some(last)
+                if last.x == start as i32
+                    && last.width == end - start
+                    && last.y + last.height as i32 == y as i32 =>
+            {
+                last.height += 1;
             }
+            _ => rects.push(Rect {
+                x: start as i32,
+                y: y as i32,
+                width: end - start,
+                height: 1,
+            }),
         }
     }
+
+    rects
+}
+
+/// Columns `start..end` of row `y` that lie inside a notch of the given
+/// size, or None if the corners cut away the whole row
+fn row_span(
+    y: u32,
+    width: u32,
+    height: u32,
+    corner_radius: u32,
+    corners: Corners,
+) -> Option<(u32, u32)> {
+    let opaque = |x: &u32| !in_rounded_corner(*x, y, width, height, corner_radius, corners);
+    // Corners only cut into the ends of a row, so both searches stop
+    // within a radius of where they start
+    let start = (0..width).find(opaque)?;
+    let end = (start..width).rev().find(opaque).unwrap_or(start) + 1;
+    Some((start, end))
 }
 
 fn get_system_font() -> &'static Font {
@@ -97,7 +546,7 @@ fn get_system_font() -> &'static Font {
 }
 
 fn load_font_from_path(path: &str) -> Result<Font, Box<dyn std::error::Error>> {
-    let path = Path::new(path);
+    let path = std::path::Path::new(path);
     if !path.exists() {
         return Err(format!("Font file not found: {}", path.display()).into());
     }
@@ -110,11 +559,154 @@ fn load_font_from_path(path: &str) -> Result<Font, Box<dyn std::error::Error>> {
     Ok(font)
 }
 
+/// Width in pixels of `text` when drawn with `Canvas::draw_text` at `size`
+pub fn text_width(text: &str, size: f32) -> u32 {
+    let font = get_system_font();
+    text.chars()
+        .map(|c| {
+            // Mirror the cursor advance in draw_text, including its blank glyph spacing
+            let metrics = font.metrics(c, size);
+            if metrics.width == 0 || metrics.height == 0 {
+                (metrics.advance_width + 1.0) as u32
+            } else {
+                metrics.advance_width as u32
+            }
+        })
+        .sum()
+}
+
+/// Shorten `text` with an ellipsis so it fits in `max_width` pixels
+pub fn truncate_text(text: &str, size: f32, max_width: u32) -> String {
+    if text_width(text, size) <= max_width {
+        return text.to_string();
+    }
+
+    let mut truncated = String::new();
+    for c in text.chars() {
+        truncated.push(c);
+        if text_width(&truncated, size) + text_width("…", size) > max_width {
+            truncated.pop();
+            break;
+        }
+    }
+    truncated.push('…');
+    truncated
+}
+
+/// Sub-scanlines sampled per pixel row when filling paths
+const PATH_SUBSAMPLES: usize = 4;
+
+/// Segments a curve is split into when a path is flattened
+const CURVE_SEGMENTS: usize = 16;
+
+/// Outline made of straight and curved segments, filled with
+/// [`Canvas::fill_path`] using the non-zero winding rule
+#[derive(Debug, Clone, Default)]
+pub struct Path {
+    /// Flattened subpaths; each is implicitly closed when filled
+    subpaths: Vec<Vec<(f32, f32)>>,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Closed polygon through `points`
+    pub fn polygon(points: &[(f32, f32)]) -> Self {
+        Self {
+            subpaths: vec![points.to_vec()],
+        }
+    }
+
+    /// Start a new subpath at `(x, y)`
+    pub fn move_to(&mut self, x: f32, y: f32) -> &mut Self {
+        self.subpaths.push(vec![(x, y)]);
+        self
+    }
+
+    pub fn line_to(&mut self, x: f32, y: f32) -> &mut Self {
+        match self.subpaths.last_mut() {
+            Some(subpath) => subpath.push((x, y)),
+            None => self.subpaths.push(vec![(x, y)]),
+        }
+        self
+    }
+
+    /// Cubic Bézier curve through control points `(c1x, c1y)` and `(c2x, c2y)`
+    pub fn cubic_to(
+        &mut self,
+        (c1x, c1y): (f32, f32),
+        (c2x, c2y): (f32, f32),
+        x: f32,
+        y: f32,
+    ) -> &mut Self {
+        let (x0, y0) = self.current();
+        for i in 1..=CURVE_SEGMENTS {
+            let t = i as f32 / CURVE_SEGMENTS as f32;
+            let u = 1.0 - t;
+            let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+            self.line_to(
+                a * x0 + b * c1x + c * c2x + d * x,
+                a * y0 + b * c1y + c * c2y + d * y,
+            );
+        }
+        self
+    }
+
+    /// Close the current subpath; the next segment starts a new one
+    pub fn close(&mut self) -> &mut Self {
+        if let Some(&start) = self.subpaths.last().and_then(|subpath| subpath.first()) {
+            self.subpaths.push(vec![start]);
+        }
+        self
+    }
+
+    fn current(&self) -> (f32, f32) {
+        self.subpaths
+            .last()
+            .and_then(|subpath| subpath.last())
+            .copied()
+            .unwrap_or((0.0, 0.0))
+    }
+
+    /// Every edge of the path, closing each subpath
+    fn edges(&self) -> impl Iterator<Item = ((f32, f32), (f32, f32))> + '_ {
+        self.subpaths
+            .iter()
+            .filter(|subpath| subpath.len() > 1)
+            .flat_map(|subpath| {
+                subpath
+                    .iter()
+                    .zip(subpath.iter().cycle().skip(1))
+                    .map(|(a, b)| (*a, *b))
+            })
+    }
+}
+
 /// Canvas abstraction for module drawing
+///
+/// Colours are given with straight alpha in buffer order and composited
+/// onto the premultiplied `Argb8888` buffer. Shapes take floating-point
+/// coordinates and are anti-aliased; everything is clipped to the
+/// innermost rectangle pushed with [`Canvas::push_clip`].
 pub struct Canvas<'a> {
     buffer: &'a mut [u8],
+    /// Size of the whole buffer in pixels
     width: u32,
     height: u32,
+    theme: Arc<Theme>,
+    /// Nested clip rectangles in buffer pixels, each already intersected
+    /// with its parent
+    clip_stack: Vec<Rect>,
+    /// Clips the canvas was created with, which `pop_clip` leaves in place
+    base_clips: usize,
+    /// Buffer position of this canvas' `(0, 0)`
+    origin: (f32, f32),
+    /// Buffer pixels per unit of this canvas
+    scale: f32,
+    /// Size of this canvas in its own units
+    size: (u32, u32),
 }
 
 impl<'a> Canvas<'a> {
@@ -124,45 +716,465 @@ impl<'a> Canvas<'a> {
             buffer,
             width,
             height,
+            theme: theme::current(),
+            clip_stack: Vec::new(),
+            base_clips: 0,
+            origin: (0.0, 0.0),
+            scale: 1.0,
+            size: (width, height),
         }
     }
 
+    /// A canvas for drawing into `area` of this one, where `(0, 0)` is the
+    /// top-left corner of `area` and nothing is drawn outside it. Every
+    /// coordinate and size given to the view is multiplied by `scale`.
+    pub fn view(&mut self, area: Rect, scale: f32) -> Canvas<'_> {
+        let clip = self.clip().intersect(&self.surface_rect(area));
+        let origin = self.to_surface(area.x as f32, area.y as f32);
+        Canvas {
+            width: self.width,
+            height: self.height,
+            theme: self.theme.clone(),
+            clip_stack: vec![clip],
+            base_clips: 1,
+            origin,
+            scale: self.scale * scale,
+            size: (
+                (area.width as f32 / scale).round() as u32,
+                (area.height as f32 / scale).round() as u32,
+            ),
+            buffer: &mut *self.buffer,
+        }
+    }
+
+    /// Theme to resolve module colours against
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
     /// Get the width of the canvas
     pub fn width(&self) -> u32 {
-        self.width
+        self.size.0
     }
 
     /// Get the height of the canvas
     pub fn height(&self) -> u32 {
-        self.height
+        self.size.1
+    }
+
+    /// Restrict drawing to `rect` (within the current clip) until the
+    /// matching [`Canvas::pop_clip`]
+    pub fn push_clip(&mut self, rect: Rect) {
+        let clip = self.clip().intersect(&self.surface_rect(rect));
+        self.clip_stack.push(clip);
+    }
+
+    /// Undo the last [`Canvas::push_clip`]. A view stays clipped to its
+    /// area however many times this is called.
+    pub fn pop_clip(&mut self) {
+        if self.clip_stack.len() > self.base_clips {
+            self.clip_stack.pop();
+        }
+    }
+
+    /// Buffer pixels drawing is currently restricted to
+    fn clip(&self) -> Rect {
+        self.clip_stack.last().copied().unwrap_or(Rect {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        })
+    }
+
+    /// Buffer position of the point `(x, y)` of this canvas
+    fn to_surface(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.origin.0 + x * self.scale,
+            self.origin.1 + y * self.scale,
+        )
+    }
+
+    /// Point of this canvas at the buffer position `(x, y)`
+    fn to_local(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            (x - self.origin.0) / self.scale,
+            (y - self.origin.1) / self.scale,
+        )
+    }
+
+    /// Buffer pixels covered by `rect` of this canvas
+    fn surface_rect(&self, rect: Rect) -> Rect {
+        let (left, top) = self.to_surface(rect.x as f32, rect.y as f32);
+        let (right, bottom) = self.to_surface(
+            (rect.x + rect.width as i32) as f32,
+            (rect.y + rect.height as i32) as f32,
+        );
+        let (left, top) = (left.round() as i32, top.round() as i32);
+        Rect {
+            x: left,
+            y: top,
+            width: (right.round() as i32 - left).max(0) as u32,
+            height: (bottom.round() as i32 - top).max(0) as u32,
+        }
+    }
+
+    /// Composite `color` (straight alpha) over the pixel at `(x, y)`,
+    /// scaled by `coverage` (0 to 1)
+    fn blend_pixel(&mut self, x: i32, y: i32, color: [u8; 4], coverage: f32) {
+        self.composite_pixel(x, y, PremulColor::from_bgra(color).with_coverage(coverage));
+    }
+
+    /// Composite a premultiplied colour over the pixel at `(x, y)`. Pixels
+    /// outside the clip are left alone.
+    fn composite_pixel(&mut self, x: i32, y: i32, color: PremulColor) {
+        let clip = self.clip();
+        if color.a == 0
+            || x < clip.x
+            || y < clip.y
+            || x >= clip.x + clip.width as i32
+            || y >= clip.y + clip.height as i32
+        {
+            return;
+        }
+        let idx = (y as u32 * self.width + x as u32) as usize * 4;
+        let Some(pixel) = self.buffer.get_mut(idx..idx + 4) else {
+            return;
+        };
+        let below = PremulColor::from_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+        pixel.copy_from_slice(&color.over(below).to_bytes());
+    }
+
+    /// Blend `color` into every pixel whose centre is within half a pixel
+    /// of the shape described by the signed distance function `distance`
+    /// (negative inside), looking only at the given bounds. Bounds and
+    /// distances are in canvas units.
+    fn fill_distance(
+        &mut self,
+        (min_x, min_y, max_x, max_y): (f32, f32, f32, f32),
+        color: [u8; 4],
+        distance: impl Fn(f32, f32) -> f32,
+    ) {
+        let (min_x, min_y) = self.to_surface(min_x, min_y);
+        let (max_x, max_y) = self.to_surface(max_x, max_y);
+        let clip = self.clip();
+        let x_start = (min_x.floor() as i32 - 1).max(clip.x);
+        let y_start = (min_y.floor() as i32 - 1).max(clip.y);
+        let x_end = (max_x.ceil() as i32 + 1).min(clip.x + clip.width as i32);
+        let y_end = (max_y.ceil() as i32 + 1).min(clip.y + clip.height as i32);
+
+        for y in y_start..y_end {
+            for x in x_start..x_end {
+                let (local_x, local_y) = self.to_local(x as f32 + 0.5, y as f32 + 0.5);
+                let coverage = 0.5 - distance(local_x, local_y) * self.scale;
+                if coverage > 0.0 {
+                    self.blend_pixel(x, y, color, coverage);
+                }
+            }
+        }
     }
 
     /// Draw a filled rectangle
     pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: [u8; 4]) {
         // Ensure the rectangle is within bounds
-        let x_start = x.max(0) as u32;
-        let y_start = y.max(0) as u32;
-        let x_end = (x + width as i32).min(self.width as i32) as u32;
-        let y_end = (y + height as i32).min(self.height as i32) as u32;
-
-        if x_end <= x_start || y_end <= y_start {
+        let area = self.clip().intersect(&self.surface_rect(Rect {
+            x,
+            y,
+            width,
+            height,
+        }));
+        if area.width == 0 || area.height == 0 || color[3] == 0 {
             return; // Nothing to draw
         }
 
+        let premultiplied = PremulColor::from_bgra(color);
+        // The area is already clipped, so whole rows can be written at once
+        let stride = self.width as usize * 4;
+        for y in area.y..area.y + area.height as i32 {
+            let start = y as usize * stride + area.x as usize * 4;
+            let Some(pixels) = self.buffer.get_mut(start..start + area.width as usize * 4) else {
+                continue;
+            };
+            if color[3] == 255 {
+                fill_span(pixels, premultiplied);
+            } else {
+                blend_span(pixels, premultiplied);
+            }
+        }
+    }
+
+    /// Draw a line `width` pixels wide with round caps
+    pub fn draw_line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, width: f32, color: [u8; 4]) {
+        self.draw_polyline(&[(x0, y0), (x1, y1)], width, color);
+    }
+
+    /// Draw connected line segments `width` pixels wide with round joins
+    pub fn draw_polyline(&mut self, points: &[(f32, f32)], width: f32, color: [u8; 4]) {
+        let Some(&first) = points.first() else {
+            return;
+        };
+        let half = width / 2.0;
+        let bounds = points.iter().fold(
+            (first.0, first.1, first.0, first.1),
+            |(min_x, min_y, max_x, max_y), &(x, y)| {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            },
+        );
+        let bounds = (
+            bounds.0 - half,
+            bounds.1 - half,
+            bounds.2 + half,
+            bounds.3 + half,
+        );
+        let segments: Vec<_> = if points.len() == 1 {
+            vec![(first, first)]
+        } else {
+            points.windows(2).map(|pair| (pair[0], pair[1])).collect()
+        };
+
+        self.fill_distance(bounds, color, |x, y| {
+            segments
+                .iter()
+                .map(|&(a, b)| segment_distance((x, y), a, b))
+                .fold(f32::MAX, f32::min)
+                - half
+        });
+    }
+
+    /// Draw a filled circle
+    pub fn fill_circle(&mut self, cx: f32, cy: f32, radius: f32, color: [u8; 4]) {
+        let bounds = (cx - radius, cy - radius, cx + radius, cy + radius);
+        self.fill_distance(bounds, color, |x, y| (x - cx).hypot(y - cy) - radius);
+    }
+
+    /// Draw a circle outline `width` pixels wide, centred on `radius`
+    pub fn draw_circle(&mut self, cx: f32, cy: f32, radius: f32, width: f32, color: [u8; 4]) {
+        let outer = radius + width / 2.0;
+        let bounds = (cx - outer, cy - outer, cx + outer, cy + outer);
+        self.fill_distance(bounds, color, |x, y| {
+            ((x - cx).hypot(y - cy) - radius).abs() - width / 2.0
+        });
+    }
+
+    /// Draw an arc `width` pixels wide with round caps. Angles are in
+    /// degrees clockwise from the positive x axis (so -90 is the top), and
+    /// a negative `sweep` runs counter-clockwise.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_arc(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        radius: f32,
+        start: f32,
+        sweep: f32,
+        width: f32,
+        color: [u8; 4],
+    ) {
+        if sweep.abs() >= 360.0 {
+            self.draw_circle(cx, cy, radius, width, color);
+            return;
+        }
+        if sweep == 0.0 {
+            return;
+        }
+
+        let half = width / 2.0;
+        let point_at = |angle: f32| {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            (cx + radius * cos, cy + radius * sin)
+        };
+        let (start_point, end_point) = (point_at(start), point_at(start + sweep));
+        let outer = radius + half;
+        let bounds = (cx - outer, cy - outer, cx + outer, cy + outer);
+
+        self.fill_distance(bounds, color, |x, y| {
+            let angle = (y - cy).atan2(x - cx).to_degrees();
+            let within = if sweep > 0.0 {
+                (angle - start).rem_euclid(360.0) <= sweep
+            } else {
+                (start - angle).rem_euclid(360.0) <= -sweep
+            };
+            if within {
+                ((x - cx).hypot(y - cy) - radius).abs() - half
+            } else {
+                // Past either end, measure to the round caps
+                let to_start = (x - start_point.0).hypot(y - start_point.1);
+                let to_end = (x - end_point.0).hypot(y - end_point.1);
+                to_start.min(to_end) - half
+            }
+        });
+    }
+
+    /// Draw a filled rectangle with corners rounded by `radius`
+    pub fn fill_rounded_rect(&mut self, rect: Rect, radius: f32, color: [u8; 4]) {
+        let bounds = rect_bounds(rect);
+        self.fill_distance(bounds, color, |x, y| {
+            rounded_box_distance(x, y, rect, radius)
+        });
+    }
+
+    /// Draw the outline of a rounded rectangle, `width` pixels wide and
+    /// kept inside `rect`
+    pub fn draw_rounded_rect(&mut self, rect: Rect, radius: f32, width: f32, color: [u8; 4]) {
+        let bounds = rect_bounds(rect);
+        let half = width / 2.0;
+        self.fill_distance(bounds, color, |x, y| {
+            (rounded_box_distance(x, y, rect, radius) + half).abs() - half
+        });
+    }
+
+    /// Fill a path using the non-zero winding rule
+    pub fn fill_path(&mut self, path: &Path, color: [u8; 4]) {
+        let edges: Vec<_> = path
+            .edges()
+            .map(|(a, b)| (self.to_surface(a.0, a.1), self.to_surface(b.0, b.1)))
+            .filter(|(a, b)| a.1 != b.1)
+            .collect();
+        if edges.is_empty() {
+            return;
+        }
+        let (mut min_y, mut max_y) = (f32::MAX, f32::MIN);
+        for &(a, b) in &edges {
+            min_y = min_y.min(a.1).min(b.1);
+            max_y = max_y.max(a.1).max(b.1);
+        }
+
+        let clip = self.clip();
+        let (clip_left, clip_right) = (clip.x, clip.x + clip.width as i32);
+        if clip_right <= clip_left {
+            return;
+        }
+        let y_start = (min_y.floor() as i32).max(clip.y);
+        let y_end = (max_y.ceil() as i32).min(clip.y + clip.height as i32);
+        let mut coverage = vec![0.0f32; (clip_right - clip_left) as usize];
+        let mut crossings: Vec<(f32, i32)> = Vec::new();
+
         for y in y_start..y_end {
-            for x in x_start..x_end {
-                let idx = (y * self.width + x) as usize * 4;
-                if idx + 3 < self.buffer.len() {
-                    self.buffer[idx..idx + 4].copy_from_slice(&color);
+            coverage.iter_mut().for_each(|c| *c = 0.0);
+
+            for sample in 0..PATH_SUBSAMPLES {
+                let sample_y = y as f32 + (sample as f32 + 0.5) / PATH_SUBSAMPLES as f32;
+                crossings.clear();
+                for &(a, b) in &edges {
+                    let (top, bottom, direction) = if a.1 < b.1 { (a, b, 1) } else { (b, a, -1) };
+                    if sample_y < top.1 || sample_y >= bottom.1 {
+                        continue;
+                    }
+                    let t = (sample_y - top.1) / (bottom.1 - top.1);
+                    crossings.push((top.0 + (bottom.0 - top.0) * t, direction));
+                }
+                crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                // Add the horizontal coverage of every span with a non-zero
+                // winding number
+                let mut winding = 0;
+                for pair in crossings.windows(2) {
+                    winding += pair[0].1;
+                    if winding == 0 {
+                        continue;
+                    }
+                    let span_start = (pair[0].0 - clip_left as f32).max(0.0);
+                    let span_end = (pair[1].0 - clip_left as f32).min(coverage.len() as f32);
+                    let mut x = span_start;
+                    while x < span_end {
+                        let pixel = x.floor();
+                        let next = (pixel + 1.0).min(span_end);
+                        coverage[pixel as usize] += (next - x) / PATH_SUBSAMPLES as f32;
+                        x = next;
+                    }
                 }
             }
+
+            for (i, &amount) in coverage.iter().enumerate() {
+                if amount > 0.0 {
+                    self.blend_pixel(clip_left + i as i32, y, color, amount);
+                }
+            }
+        }
+    }
+
+    /// Draw `image` scaled into `dest`
+    pub fn draw_image(&mut self, image: &RgbaImage, dest: Rect, filter: Filter) {
+        self.draw_image_with(image, dest, filter, PremulColor::from_rgba);
+    }
+
+    /// Draw `image` scaled into `dest`, using only its alpha channel as a
+    /// mask for `color`, as is done for symbolic icons
+    pub fn draw_image_tinted(&mut self, image: &RgbaImage, dest: Rect, color: [u8; 4]) {
+        let tint = PremulColor::from_bgra(color);
+        self.draw_image_with(image, dest, Filter::Smooth, |[_, _, _, a]| tint.scaled(a));
+    }
+
+    /// Sample `image` for every pixel of `dest` and composite the colours
+    /// `to_pixel` turns the straight RGBA samples into
+    fn draw_image_with(
+        &mut self,
+        image: &RgbaImage,
+        dest: Rect,
+        filter: Filter,
+        to_pixel: impl Fn([u8; 4]) -> PremulColor,
+    ) {
+        let dest = self.surface_rect(dest);
+        if dest.width == 0 || dest.height == 0 || image.width == 0 || image.height == 0 {
+            return;
+        }
+        let scale_x = image.width as f32 / dest.width as f32;
+        let scale_y = image.height as f32 / dest.height as f32;
+        let footprint = scale_x.max(scale_y);
+        let area = self.clip().intersect(&dest);
+
+        for y in area.y..area.y + area.height as i32 {
+            let source_y = (y - dest.y) as f32 + 0.5;
+            for x in area.x..area.x + area.width as i32 {
+                let source_x = (x - dest.x) as f32 + 0.5;
+                let sample =
+                    image.sample(source_x * scale_x, source_y * scale_y, footprint, filter);
+                self.composite_pixel(x, y, to_pixel(sample));
+            }
+        }
+    }
+
+    /// Draw a sparkline graph of `values` scaled against `max`,
+    /// one column per sample with the newest sample on the right
+    pub fn draw_sparkline(&mut self, area: Rect, values: &[f32], max: f32, color: [u8; 4]) {
+        let Rect {
+            x,
+            y,
+            width,
+            height,
+        } = area;
+        if values.is_empty() || width == 0 || max <= 0.0 {
+            return;
+        }
+
+        let column_width = (width / values.len() as u32).max(1);
+        let visible = (width / column_width) as usize;
+        let start = values.len().saturating_sub(visible);
+        let right = x + width as i32;
+
+        for (i, value) in values[start..].iter().rev().enumerate() {
+            let column_height = ((value / max).clamp(0.0, 1.0) * height as f32).round() as u32;
+            if column_height == 0 {
+                continue;
+            }
+            let column_x = right - (i as i32 + 1) * column_width as i32;
+            let column_y = y + (height - column_height) as i32;
+            self.fill_rect(column_x, column_y, column_width, column_height, color);
         }
     }
 
     /// Draw text with given color, size and position
     pub fn draw_text(&mut self, x: i32, y: i32, text: &str, color: [u8; 4], size: f32) {
         let font = get_system_font();
-        let scale = size;
+        let scale = size * self.scale;
+        let (x, y) = self.to_surface(x as f32, y as f32);
+        let (x, y) = (x.round() as i32, y.round() as i32);
+
+        // `y` is the top of the line; glyphs sit on the baseline below it
+        let ascent = font
+            .horizontal_line_metrics(scale)
+            .map_or(scale * 0.8, |line| line.ascent);
+        let baseline = y + ascent.round() as i32;
 
         // Track current position
         let mut cursor_x = x;
@@ -179,7 +1191,7 @@ impl<'a> Canvas<'a> {
 
             // Render the glyph
             let glyph_x = cursor_x + metrics.xmin;
-            let glyph_y = y + metrics.ymin;
+            let glyph_y = baseline - metrics.ymin - metrics.height as i32;
 
             for glyph_y_offset in 0..metrics.height {
                 let canvas_y = glyph_y + glyph_y_offset as i32;
@@ -199,26 +1211,8 @@ impl<'a> Canvas<'a> {
                         continue;
                     }
 
-                    // Calculate the index in our canvas buffer
-                    let idx = (canvas_y as u32 * self.width + canvas_x as u32) as usize * 4;
-                    if idx + 3 < self.buffer.len() {
-                        // Blend the glyph with existing color
-                        let blend_alpha = alpha as f32 / 255.0;
-
-                        for i in 0..3 {
-                            let existing = self.buffer[idx + i] as f32;
-                            let new = color[i] as f32;
-                            self.buffer[idx + i] =
-                                (existing * (1.0 - blend_alpha) + new * blend_alpha) as u8;
-                        }
-
-                        // Update alpha channel
-                        let existing_alpha = self.buffer[idx + 3] as f32 / 255.0;
-                        let new_alpha = (color[3] as f32 / 255.0) * blend_alpha;
-                        let final_alpha =
-                            (existing_alpha + new_alpha * (1.0 - existing_alpha)) * 255.0;
-                        self.buffer[idx + 3] = final_alpha.min(255.0) as u8;
-                    }
+                    // Blend the glyph with existing color
+                    self.blend_pixel(canvas_x, canvas_y, color, alpha as f32 / 255.0);
                 }
             }
 
@@ -228,6 +1222,37 @@ impl<'a> Canvas<'a> {
     }
 }
 
+/// Distance from `point` to the segment from `a` to `b`
+fn segment_distance(point: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared > 0.0 {
+        (((point.0 - a.0) * dx + (point.1 - a.1) * dy) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (point.0 - (a.0 + t * dx)).hypot(point.1 - (a.1 + t * dy))
+}
+
+/// Signed distance from a point to `rect` with every corner rounded by
+/// `radius`; negative inside
+fn rounded_box_distance(x: f32, y: f32, rect: Rect, radius: f32) -> f32 {
+    let (half_width, half_height) = (rect.width as f32 / 2.0, rect.height as f32 / 2.0);
+    let radius = radius.clamp(0.0, half_width.min(half_height));
+    let qx = (x - rect.x as f32 - half_width).abs() - half_width + radius;
+    let qy = (y - rect.y as f32 - half_height).abs() - half_height + radius;
+    qx.max(0.0).hypot(qy.max(0.0)) + qx.max(qy).min(0.0) - radius
+}
+
+fn rect_bounds(rect: Rect) -> (f32, f32, f32, f32) {
+    (
+        rect.x as f32,
+        rect.y as f32,
+        (rect.x + rect.width as i32) as f32,
+        (rect.y + rect.height as i32) as f32,
+    )
+}
+
 /// Draw an anti-aliased rounded corner
 /// This function can be used later for smoother corners
 #[allow(dead_code)]
@@ -240,3 +1265,351 @@ pub fn draw_antialiased_rounded_corner(
 ) {
     // Implementation for future enhancement
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    /// Pixels of a `width` wide buffer that aren't transparent
+    fn painted(buffer: &[u8], width: u32) -> Vec<(u32, u32)> {
+        buffer
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(_, pixel)| pixel.iter().any(|&byte| byte != 0))
+            .map(|(index, _)| (index as u32 % width, index as u32 / width))
+            .collect()
+    }
+
+    /// Alpha of the pixel at `(x, y)` of a `width` wide buffer
+    fn alpha(buffer: &[u8], width: u32, x: u32, y: u32) -> u8 {
+        buffer[(y * width + x) as usize * 4 + 3]
+    }
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn wide_lines_cover_their_width_and_end_in_round_caps() {
+        let mut buffer = vec![0; 20 * 10 * 4];
+        let mut canvas = Canvas::new(&mut buffer, 20, 10);
+        canvas.draw_line(5.0, 5.0, 15.0, 5.0, 4.0, WHITE);
+
+        // Rows whose centres are within two pixels of y = 5 are covered
+        for y in 3..=6 {
+            assert_eq!(alpha(&buffer, 20, 10, y), 255, "row {y}");
+        }
+        assert_eq!(alpha(&buffer, 20, 10, 2), 0);
+        assert_eq!(alpha(&buffer, 20, 10, 7), 0);
+
+        // The caps reach past the ends, rounded rather than square
+        assert!(alpha(&buffer, 20, 16, 4) > 200);
+        let corner = alpha(&buffer, 20, 16, 3);
+        assert!(corner > 0 && corner < 255, "{corner}");
+        assert_eq!(alpha(&buffer, 20, 17, 5), 0);
+        // and the same at both ends
+        assert_eq!(alpha(&buffer, 20, 3, 4), alpha(&buffer, 20, 16, 4));
+        assert_eq!(alpha(&buffer, 20, 3, 3), corner);
+    }
+
+    #[test]
+    fn circles_are_symmetric_with_soft_edges() {
+        let mut buffer = vec![0; 16 * 16 * 4];
+        let mut canvas = Canvas::new(&mut buffer, 16, 16);
+        canvas.fill_circle(8.0, 8.0, 5.0, WHITE);
+
+        for y in 0..16 {
+            for x in 0..16 {
+                let a = alpha(&buffer, 16, x, y);
+                assert_eq!(a, alpha(&buffer, 16, 15 - x, y));
+                assert_eq!(a, alpha(&buffer, 16, x, 15 - y));
+                assert_eq!(a, alpha(&buffer, 16, y, x));
+            }
+        }
+        assert_eq!(alpha(&buffer, 16, 8, 8), 255);
+        assert_eq!(alpha(&buffer, 16, 13, 7), 0);
+        // The diagonal edge cuts through a pixel, which is partly covered
+        let edge = alpha(&buffer, 16, 11, 11);
+        assert!(edge > 64 && edge < 192, "{edge}");
+        // Coverage adds up to the area of the circle
+        let area: f32 = buffer.chunks_exact(4).map(|p| p[3] as f32 / 255.0).sum();
+        assert!((area - 25.0 * std::f32::consts::PI).abs() < 1.0, "{area}");
+        // White stays premultiplied: no channel exceeds alpha
+        assert!(buffer
+            .chunks_exact(4)
+            .all(|p| p[0] == p[3] && p[1] == p[3] && p[2] == p[3]));
+    }
+
+    #[test]
+    fn arcs_start_and_end_at_their_angles() {
+        let mut clockwise = vec![0; 20 * 20 * 4];
+        let mut canvas = Canvas::new(&mut clockwise, 20, 20);
+        // From the top, a quarter turn clockwise to the right
+        canvas.draw_arc(10.0, 10.0, 6.0, -90.0, 90.0, 2.0, WHITE);
+
+        assert!(alpha(&clockwise, 20, 14, 5) > 200);
+        // Both ends, with a round cap just past each
+        assert!(alpha(&clockwise, 20, 10, 3) > 200);
+        assert!(alpha(&clockwise, 20, 9, 3) > 0);
+        assert_eq!(alpha(&clockwise, 20, 8, 3), 0);
+        assert!(alpha(&clockwise, 20, 16, 9) > 200);
+        assert!(alpha(&clockwise, 20, 16, 10) > 0);
+        assert_eq!(alpha(&clockwise, 20, 16, 11), 0);
+        // Nothing on the other three quarters
+        assert_eq!(alpha(&clockwise, 20, 3, 10), 0);
+        assert_eq!(alpha(&clockwise, 20, 10, 16), 0);
+        assert_eq!(alpha(&clockwise, 20, 5, 5), 0);
+
+        // A negative sweep runs the other way: the mirror image
+        let mut counter_clockwise = vec![0; 20 * 20 * 4];
+        let mut canvas = Canvas::new(&mut counter_clockwise, 20, 20);
+        canvas.draw_arc(10.0, 10.0, 6.0, -90.0, -90.0, 2.0, WHITE);
+        for y in 0..20 {
+            for x in 0..20 {
+                let (a, b) = (
+                    alpha(&clockwise, 20, x, y),
+                    alpha(&counter_clockwise, 20, 19 - x, y),
+                );
+                assert!(a.abs_diff(b) <= 1, "({x}, {y}): {a} and {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn rounded_rect_corners() {
+        let mut buffer = vec![0; 12 * 12 * 4];
+        let mut canvas = Canvas::new(&mut buffer, 12, 12);
+        canvas.fill_rounded_rect(rect(0, 0, 12, 12), 4.0, WHITE);
+
+        for (x, y) in [(0, 0), (11, 0), (0, 11), (11, 11)] {
+            assert_eq!(alpha(&buffer, 12, x, y), 0, "corner ({x}, {y})");
+        }
+        for (x, y) in [(1, 1), (10, 1), (1, 10), (10, 10)] {
+            assert!(alpha(&buffer, 12, x, y) > 200, "inside the arc ({x}, {y})");
+        }
+        // Where the arc meets the edge it only partly covers a pixel
+        let partial = alpha(&buffer, 12, 1, 0);
+        assert!(partial > 0 && partial < 255, "{partial}");
+        for (x, y) in [(0, 6), (6, 0), (11, 6), (6, 11), (6, 6), (0, 4)] {
+            assert_eq!(alpha(&buffer, 12, x, y), 255, "({x}, {y})");
+        }
+
+        // The outline keeps to the inside of the rectangle
+        let mut buffer = vec![0; 12 * 12 * 4];
+        let mut canvas = Canvas::new(&mut buffer, 12, 12);
+        canvas.draw_rounded_rect(rect(0, 0, 12, 12), 4.0, 1.0, WHITE);
+        assert_eq!(alpha(&buffer, 12, 0, 6), 255);
+        assert_eq!(alpha(&buffer, 12, 1, 6), 0);
+        assert_eq!(alpha(&buffer, 12, 6, 6), 0);
+        assert_eq!(alpha(&buffer, 12, 0, 0), 0);
+    }
+
+    #[test]
+    fn paths_fill_by_non_zero_winding() {
+        let outer = [(2.0, 2.0), (14.0, 2.0), (14.0, 14.0), (2.0, 14.0)];
+        let filled = |inner: &[(f32, f32)]| {
+            let mut path = Path::polygon(&outer);
+            path.move_to(inner[0].0, inner[0].1);
+            for &(x, y) in &inner[1..] {
+                path.line_to(x, y);
+            }
+            let mut buffer = vec![0; 16 * 16 * 4];
+            Canvas::new(&mut buffer, 16, 16).fill_path(&path, WHITE);
+            buffer
+        };
+
+        // A hole wound the other way is left empty
+        let hole = filled(&[(6.0, 6.0), (6.0, 10.0), (10.0, 10.0), (10.0, 6.0)]);
+        assert_eq!(alpha(&hole, 16, 8, 8), 0);
+        assert_eq!(alpha(&hole, 16, 3, 8), 255);
+        assert_eq!(alpha(&hole, 16, 1, 8), 0);
+        // Wound the same way it is filled twice over, which is still filled
+        let same = filled(&[(6.0, 6.0), (10.0, 6.0), (10.0, 10.0), (6.0, 10.0)]);
+        assert_eq!(alpha(&same, 16, 8, 8), 255);
+
+        // The middle of a pentagram is wound twice, so it is filled too
+        let star: Vec<_> = (0..5)
+            .map(|i| {
+                let angle = (-90.0 + i as f32 * 144.0).to_radians();
+                (8.0 + 7.0 * angle.cos(), 8.0 + 7.0 * angle.sin())
+            })
+            .collect();
+        let mut buffer = vec![0; 16 * 16 * 4];
+        Canvas::new(&mut buffer, 16, 16).fill_path(&Path::polygon(&star), WHITE);
+        assert_eq!(alpha(&buffer, 16, 7, 7), 255);
+        assert_eq!(alpha(&buffer, 16, 0, 15), 0);
+    }
+
+    /// Something drawn on a canvas
+    type Shape = fn(&mut Canvas);
+
+    #[test]
+    fn every_primitive_keeps_to_the_clip() {
+        let shapes: [(&str, Shape); 9] = [
+            ("fill_rect", |c| c.fill_rect(0, 0, 16, 16, WHITE)),
+            ("draw_line", |c| {
+                c.draw_line(0.0, 0.0, 16.0, 16.0, 4.0, WHITE)
+            }),
+            ("draw_polyline", |c| {
+                c.draw_polyline(&[(0.0, 8.0), (8.0, 4.0), (16.0, 8.0)], 4.0, WHITE)
+            }),
+            ("fill_circle", |c| c.fill_circle(8.0, 8.0, 10.0, WHITE)),
+            ("draw_circle", |c| c.draw_circle(8.0, 8.0, 6.0, 4.0, WHITE)),
+            ("draw_arc", |c| {
+                c.draw_arc(8.0, 8.0, 6.0, 180.0, 180.0, 4.0, WHITE)
+            }),
+            ("fill_rounded_rect", |c| {
+                c.fill_rounded_rect(rect(0, 0, 16, 16), 4.0, WHITE)
+            }),
+            ("draw_rounded_rect", |c| {
+                c.draw_rounded_rect(rect(2, 2, 12, 12), 2.0, 4.0, WHITE)
+            }),
+            ("fill_path", |c| {
+                let square = [(0.0, 0.0), (16.0, 0.0), (16.0, 16.0), (0.0, 16.0)];
+                c.fill_path(&Path::polygon(&square), WHITE)
+            }),
+        ];
+        let outer = rect(4, 4, 8, 8);
+        // Nested clips intersect with the one outside them
+        let inner = rect(4, 4, 4, 4);
+        let inside = |clip: Rect, (x, y): (u32, u32)| {
+            (clip.x..clip.x + clip.width as i32).contains(&(x as i32))
+                && (clip.y..clip.y + clip.height as i32).contains(&(y as i32))
+        };
+
+        for (name, shape) in shapes {
+            let mut buffer = vec![0; 16 * 16 * 4];
+            let mut canvas = Canvas::new(&mut buffer, 16, 16);
+            canvas.push_clip(outer);
+            canvas.push_clip(rect(0, 0, 8, 8));
+            shape(&mut canvas);
+            let pixels = painted(&buffer, 16);
+            assert!(!pixels.is_empty(), "{name} drew nothing");
+            assert!(pixels.iter().all(|&p| inside(inner, p)), "{name}");
+
+            let mut canvas = Canvas::new(&mut buffer, 16, 16);
+            canvas.push_clip(outer);
+            canvas.push_clip(rect(0, 0, 8, 8));
+            canvas.pop_clip();
+            shape(&mut canvas);
+            let pixels = painted(&buffer, 16);
+            assert!(pixels.iter().all(|&p| inside(outer, p)), "{name}");
+            assert!(pixels.iter().any(|&p| !inside(inner, p)), "{name}");
+
+            canvas = Canvas::new(&mut buffer, 16, 16);
+            canvas.push_clip(outer);
+            canvas.pop_clip();
+            shape(&mut canvas);
+            let pixels = painted(&buffer, 16);
+            assert!(pixels.iter().any(|&p| !inside(outer, p)), "{name}");
+        }
+    }
+
+    #[test]
+    fn unbalanced_pops_stay_inside_a_view() {
+        let mut buffer = vec![0; 8 * 8 * 4];
+        let mut canvas = Canvas::new(&mut buffer, 8, 8);
+        let area = Rect {
+            x: 2,
+            y: 2,
+            width: 2,
+            height: 2,
+        };
+        let mut view = canvas.view(area, 1.0);
+        view.push_clip(Rect {
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1,
+        });
+        view.pop_clip();
+        view.pop_clip();
+        view.pop_clip();
+        view.fill_rect(-2, -2, 8, 8, WHITE);
+
+        assert_eq!(painted(&buffer, 8), [(2, 2), (3, 2), (2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn spans_are_the_same_aligned_or_not() {
+        let color = PremulColor::from_bgra([200, 100, 50, 128]);
+        let below = PremulColor::from_bgra([10, 20, 30, 255]);
+        let blended = color.over(below).to_bytes().repeat(4);
+        let filled = color.to_bytes().repeat(4);
+
+        let mut words = [0u32; 5];
+        let bytes: &mut [u8] = bytemuck::cast_slice_mut(&mut words);
+        // Offset 0 is word aligned, offset 1 can't be
+        for start in [0, 1] {
+            let span = &mut bytes[start..start + 16];
+            span.copy_from_slice(&below.to_bytes().repeat(4));
+            blend_span(span, color);
+            assert_eq!(span, blended);
+            fill_span(span, color);
+            assert_eq!(span, filled);
+        }
+    }
+
+    #[test]
+    fn linear_gradients_run_along_their_angle() {
+        let fill = Fill::Linear {
+            stops: vec![[0, 0, 0, 255], WHITE],
+            angle: 0.0,
+        };
+        let style = NotchStyle {
+            rounded: false,
+            corner_radius: 0,
+            corners: Corners::default(),
+            fill,
+            shadow: None,
+            border: None,
+        };
+        let notch = Rect {
+            x: 0,
+            y: 0,
+            width: 8,
+            height: 2,
+        };
+        let mut buffer = vec![0; 8 * 2 * 4];
+        draw_notch(&mut buffer, 8, 2, notch, &style);
+
+        let rows: Vec<Vec<u8>> = buffer
+            .chunks_exact(8 * 4)
+            .map(|row| row.chunks_exact(4).map(|pixel| pixel[0]).collect())
+            .collect();
+        // Left to right, the same in every row, and opaque throughout
+        assert!(
+            rows[0].windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            rows[0]
+        );
+        assert!(rows[0][0] < 32 && rows[0][7] > 223, "{:?}", rows[0]);
+        assert_eq!(rows[0], rows[1]);
+        assert!(buffer.chunks_exact(4).all(|pixel| pixel[3] == 255));
+    }
+
+    #[test]
+    fn fill_rect_bytes() {
+        let mut buffer = vec![0; 3 * 2 * 4];
+        let mut canvas = Canvas::new(&mut buffer, 3, 2);
+        canvas.fill_rect(1, 0, 2, 2, [10, 20, 30, 255]);
+        // Half transparent white over the first two columns, clipped at the edge
+        canvas.fill_rect(-1, 0, 3, 1, [255, 255, 255, 128]);
+        // Fully transparent draws nothing
+        canvas.fill_rect(0, 0, 3, 2, [255, 255, 255, 0]);
+
+        #[rustfmt::skip]
+        let expected = [
+            128, 128, 128, 128,  133, 138, 143, 255,  10, 20, 30, 255,
+            0, 0, 0, 0,          10, 20, 30, 255,     10, 20, 30, 255,
+        ];
+        assert_eq!(buffer, expected);
+    }
+}
@@ -0,0 +1,199 @@
+//! Hyprland IPC client for hypr-notch
+//!
+//! Talks to the compositor over its two UNIX sockets in
+//! `$XDG_RUNTIME_DIR/hypr/$HYPRLAND_INSTANCE_SIGNATURE/`:
+//! `.socket.sock` answers one request per connection (e.g. `dispatch` or
+//! `j/workspaces`), and `.socket2.sock` streams `EVENT>>DATA` lines that are
+//! forwarded to modules as `ModuleEvent::Hyprland`.
+
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Events from the Hyprland event socket that modules care about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HyprEvent {
+    /// The focused workspace changed
+    Workspace {
+        id: i32,
+        name: String,
+    },
+    CreateWorkspace {
+        id: i32,
+        name: String,
+    },
+    DestroyWorkspace {
+        id: i32,
+        name: String,
+    },
+    ActiveWindow {
+        class: String,
+        title: String,
+    },
+    /// A submap was entered; empty when returning to the default one
+    Submap(String),
+    ActiveLayout {
+        keyboard: String,
+        layout: String,
+    },
+    /// The focused window entered or left fullscreen
+    Fullscreen(bool),
+    /// Focus moved to the monitor with this name
+    FocusedMonitor(String),
+    /// Screen sharing started or stopped
+    Screencast(bool),
+    /// Any other event, passed through unparsed
+    Other {
+        name: String,
+        data: String,
+    },
+}
+
+fn parse_workspace_v2(data: &str) -> Option<(i32, String)> {
+    let (id, name) = data.split_once(',')?;
+    Some((id.parse().ok()?, name.to_string()))
+}
+
+/// Parse one `EVENT>>DATA` line from `.socket2.sock`
+pub fn parse_event(line: &str) -> Option<HyprEvent> {
+    let (name, data) = line.trim_end_matches('\n').split_once(">>")?;
+
+    let event = match name {
+        // Only the v2 workspace events carry ids; the v1 variants are sent
+        // alongside them and would duplicate every change
+        "workspacev2" => {
+            let (id, name) = parse_workspace_v2(data)?;
+            HyprEvent::Workspace { id, name }
+        }
+        "createworkspacev2" => {
+            let (id, name) = parse_workspace_v2(data)?;
+            HyprEvent::CreateWorkspace { id, name }
+        }
+        "destroyworkspacev2" => {
+            let (id, name) = parse_workspace_v2(data)?;
+            HyprEvent::DestroyWorkspace { id, name }
+        }
+        "activewindow" => {
+            // The class can't contain a comma but the title can
+            let (class, title) = data.split_once(',').unwrap_or((data, ""));
+            HyprEvent::ActiveWindow {
+                class: class.to_string(),
+                title: title.to_string(),
+            }
+        }
+        "submap" => HyprEvent::Submap(data.to_string()),
+        "activelayout" => {
+            // Keyboard names can't contain commas, layout names can
+            let (keyboard, layout) = data.split_once(',')?;
+            HyprEvent::ActiveLayout {
+                keyboard: keyboard.to_string(),
+                layout: layout.to_string(),
+            }
+        }
+        "fullscreen" => HyprEvent::Fullscreen(data == "1"),
+        // `MONITOR,WORKSPACE`; monitor names can't contain commas
+        "focusedmon" => HyprEvent::FocusedMonitor(data.split(',').next()?.to_string()),
+        // `STATE,OWNER`, where the owner says whether a monitor or a
+        // window is shared
+        "screencast" => HyprEvent::Screencast(data.split(',').next() == Some("1")),
+        _ => HyprEvent::Other {
+            name: name.to_string(),
+            data: data.to_string(),
+        },
+    };
+    Some(event)
+}
+
+/// Directory holding the sockets of the running Hyprland instance
+pub fn socket_dir() -> Option<PathBuf> {
+    let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
+    let runtime_dir = env::var("XDG_RUNTIME_DIR").ok()?;
+    let dir = Path::new(&runtime_dir).join("hypr").join(&signature);
+    if dir.exists() {
+        return Some(dir);
+    }
+    // Hyprland before 0.40 kept its sockets in /tmp
+    let legacy = Path::new("/tmp/hypr").join(&signature);
+    legacy.exists().then_some(legacy)
+}
+
+/// Send a request to `.socket.sock` in `dir` and return the reply
+pub fn request_at(dir: &Path, command: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(dir.join(".socket.sock"))?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    stream.write_all(command.as_bytes())?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+/// Send a request to the running Hyprland instance
+pub fn request(command: &str) -> io::Result<String> {
+    let dir = socket_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Hyprland is not running"))?;
+    request_at(&dir, command)
+}
+
+/// Send a JSON request (`j/<command>`) and deserialize the reply
+pub fn request_json<T: DeserializeOwned>(command: &str) -> io::Result<T> {
+    let reply = request(&format!("j/{}", command))?;
+    serde_json::from_str(&reply).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Run a dispatcher, e.g. `dispatch("workspace", "3")`
+pub fn dispatch(dispatcher: &str, args: &str) -> io::Result<()> {
+    let reply = request(&format!("dispatch {} {}", dispatcher, args))?;
+    if reply.trim() == "ok" {
+        Ok(())
+    } else {
+        Err(io::Error::other(reply))
+    }
+}
+
+/// Read events from `.socket2.sock` in `dir` until the connection closes,
+/// handing each parsed event to `on_event`. Returning false from `on_event`
+/// stops reading.
+pub fn read_events_at(dir: &Path, mut on_event: impl FnMut(HyprEvent) -> bool) -> io::Result<()> {
+    let stream = UnixStream::connect(dir.join(".socket2.sock"))?;
+    for line in BufReader::new(stream).lines() {
+        if let Some(event) = parse_event(&line?) {
+            if !on_event(event) {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Listen for Hyprland events on a background thread, reconnecting if the
+/// socket goes away. Events are delivered to the main loop through `sender`.
+pub fn spawn_event_listener(dir: PathBuf, sender: calloop::channel::Sender<HyprEvent>) {
+    let spawned = thread::Builder::new()
+        .name("hyprland-events".to_string())
+        .spawn(move || loop {
+            info!("Listening for Hyprland events in {}", dir.display());
+            let mut closed = false;
+            let result = read_events_at(&dir, |event| {
+                debug!("Hyprland event: {:?}", event);
+                closed = sender.send(event).is_err();
+                !closed
+            });
+            if closed {
+                // The event loop is gone, nobody is listening any more
+                return;
+            }
+            if let Err(e) = result {
+                warn!("Hyprland event socket error: {}", e);
+            }
+            thread::sleep(Duration::from_secs(2));
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start Hyprland event listener: {}", e);
+    }
+}
@@ -0,0 +1,889 @@
+//! iCalendar parsing for hypr-notch
+//!
+//! Reads the `VEVENT`s of an `.ics` file (RFC 5545) and expands them into
+//! the occurrences that fall in a time range, following `RRULE`, `EXDATE`
+//! and `RECURRENCE-ID` overrides. Times with a `TZID` are looked up in the
+//! system time zone database; floating times are taken as local time.
+
+use jiff::civil::{Date, DateTime, Time, Weekday};
+use jiff::tz::TimeZone;
+use jiff::{SignedDuration, Span, Timestamp, ToSpan};
+use log::debug;
+use std::collections::HashSet;
+
+/// Give up expanding a rule after this many days, weeks, months or years
+const MAX_PERIODS: i64 = 50_000;
+
+/// A `DTSTART`, `DTEND`, `EXDATE` or similar value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Moment {
+    /// A whole day, as used by all-day events
+    Date(Date),
+    /// A time of day in a time zone
+    DateTime(DateTime, TimeZone),
+}
+
+impl Moment {
+    pub fn date(&self) -> Date {
+        match self {
+            Self::Date(date) => *date,
+            Self::DateTime(datetime, _) => datetime.date(),
+        }
+    }
+
+    /// The instant this starts; dates start at local midnight
+    pub fn timestamp(&self, local: &TimeZone) -> Option<Timestamp> {
+        match self {
+            Self::Date(date) => local.to_timestamp(date.to_datetime(Time::midnight())).ok(),
+            Self::DateTime(datetime, zone) => zone.to_timestamp(*datetime).ok(),
+        }
+    }
+
+    /// The same time of day in the same zone on another date
+    fn on(&self, date: Date) -> Self {
+        match self {
+            Self::Date(_) => Self::Date(date),
+            Self::DateTime(datetime, zone) => {
+                Self::DateTime(date.to_datetime(datetime.time()), zone.clone())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A recurrence rule. Sub-daily frequencies aren't supported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub frequency: Frequency,
+    pub interval: i64,
+    pub count: Option<u32>,
+    pub until: Option<Moment>,
+    /// Weekdays, with an optional position such as the `-1` of `-1FR`
+    /// (0 means every one)
+    pub by_day: Vec<(i16, Weekday)>,
+    pub by_month_day: Vec<i8>,
+    pub by_month: Vec<i8>,
+    pub by_set_pos: Vec<i32>,
+    pub week_start: Weekday,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub uid: String,
+    pub summary: String,
+    pub location: Option<String>,
+    pub start: Moment,
+    /// Length of each occurrence; a whole number of days for all-day events
+    pub duration: SignedDuration,
+    pub rule: Option<Rule>,
+    pub exdates: Vec<Moment>,
+    /// Set when this replaces one occurrence of a recurring event
+    pub recurrence_id: Option<Moment>,
+    pub cancelled: bool,
+}
+
+/// One occurrence of an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occurrence {
+    pub summary: String,
+    pub location: Option<String>,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub all_day: bool,
+}
+
+/// A content line: `NAME;PARAM=VALUE:value`
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Join folded lines: a line starting with a space or tab continues the
+/// previous one
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn parse_property(line: &str) -> Option<Property> {
+    // The value starts at the first colon outside a quoted parameter
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    quoted = false;
+    for c in head.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => parts.push(std::mem::take(&mut part)),
+            _ => part.push(c),
+        }
+    }
+    parts.push(part);
+
+    let mut parts = parts.into_iter();
+    let name = parts.next()?.to_ascii_uppercase();
+    let params = parts
+        .filter_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            Some((key.to_ascii_uppercase(), value.to_string()))
+        })
+        .collect();
+    Some(Property {
+        name,
+        params,
+        value: value.to_string(),
+    })
+}
+
+/// Undo TEXT escaping (`\n`, `\,`, `\;`, `\\`)
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn parse_date(text: &str) -> Option<Date> {
+    if text.len() != 8 || !text.is_ascii() {
+        return None;
+    }
+    Date::new(
+        text[0..4].parse().ok()?,
+        text[4..6].parse().ok()?,
+        text[6..8].parse().ok()?,
+    )
+    .ok()
+}
+
+/// The zone named by a `TZID`. Names the database doesn't know, such as
+/// Outlook's Windows zone names, fall back to local time.
+fn zone(tzid: Option<&str>, local: &TimeZone) -> TimeZone {
+    let Some(tzid) = tzid else {
+        return local.clone();
+    };
+    TimeZone::get(tzid.trim_start_matches('/')).unwrap_or_else(|_| {
+        debug!("Unknown time zone {:?}, using local time", tzid);
+        local.clone()
+    })
+}
+
+/// Parse a DATE (`20241018`) or DATE-TIME (`20241018T093000`, with a
+/// trailing `Z` for UTC)
+pub fn parse_moment(text: &str, tzid: Option<&str>, local: &TimeZone) -> Option<Moment> {
+    let text = text.trim();
+    let Some((date, time)) = text.split_once('T') else {
+        return parse_date(text).map(Moment::Date);
+    };
+    let date = parse_date(date)?;
+    let (time, utc) = match time.strip_suffix('Z') {
+        Some(time) => (time, true),
+        None => (time, false),
+    };
+    if time.len() != 6 || !time.is_ascii() {
+        return None;
+    }
+    let time = Time::new(
+        time[0..2].parse().ok()?,
+        time[2..4].parse().ok()?,
+        // Leap seconds are folded into the minute
+        time[4..6].parse::<i8>().ok()?.min(59),
+        0,
+    )
+    .ok()?;
+    let zone = if utc {
+        TimeZone::UTC
+    } else {
+        zone(tzid, local)
+    };
+    Some(Moment::DateTime(date.to_datetime(time), zone))
+}
+
+/// Parse a DURATION such as `PT1H30M`, `P1D` or `-P2W`
+pub fn parse_duration(text: &str) -> Option<SignedDuration> {
+    let text = text.trim();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let text = text.strip_prefix('P')?;
+
+    let mut secs: i64 = 0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        if c == 'T' && number.is_empty() {
+            in_time = true;
+            continue;
+        }
+        let value: i64 = number.parse().ok()?;
+        number.clear();
+        let unit = match (c, in_time) {
+            ('W', false) => 7 * 86400,
+            ('D', false) => 86400,
+            ('H', true) => 3600,
+            ('M', true) => 60,
+            ('S', true) => 1,
+            _ => return None,
+        };
+        secs = secs.checked_add(value.checked_mul(unit)?)?;
+    }
+    if !number.is_empty() {
+        return None;
+    }
+    Some(SignedDuration::from_secs(if negative {
+        -secs
+    } else {
+        secs
+    }))
+}
+
+fn parse_weekday(text: &str) -> Option<Weekday> {
+    Some(match text {
+        "MO" => Weekday::Monday,
+        "TU" => Weekday::Tuesday,
+        "WE" => Weekday::Wednesday,
+        "TH" => Weekday::Thursday,
+        "FR" => Weekday::Friday,
+        "SA" => Weekday::Saturday,
+        "SU" => Weekday::Sunday,
+        _ => return None,
+    })
+}
+
+fn parse_list<T: std::str::FromStr>(text: &str) -> Option<Vec<T>> {
+    text.split(',')
+        .map(|item| item.trim().parse().ok())
+        .collect()
+}
+
+/// Parse an RRULE value such as `FREQ=MONTHLY;BYDAY=-1FR;COUNT=6`
+pub fn parse_rule(text: &str, local: &TimeZone) -> Option<Rule> {
+    let mut rule = Rule {
+        frequency: Frequency::Daily,
+        interval: 1,
+        count: None,
+        until: None,
+        by_day: Vec::new(),
+        by_month_day: Vec::new(),
+        by_month: Vec::new(),
+        by_set_pos: Vec::new(),
+        week_start: Weekday::Monday,
+    };
+    let mut frequency = None;
+    for part in text.split(';') {
+        let Some((key, value)) = part.split_once('=') else {
+            continue;
+        };
+        match key.to_ascii_uppercase().as_str() {
+            "FREQ" => {
+                frequency = Some(match value.to_ascii_uppercase().as_str() {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return None,
+                })
+            }
+            "INTERVAL" => rule.interval = value.parse::<i64>().ok()?.max(1),
+            "COUNT" => rule.count = Some(value.parse().ok()?),
+            "UNTIL" => rule.until = Some(parse_moment(value, None, local)?),
+            "BYDAY" => {
+                rule.by_day = value
+                    .split(',')
+                    .map(|day| {
+                        let day = day.trim().to_ascii_uppercase();
+                        let split = day.len().checked_sub(2)?;
+                        // `get` rather than indexing: the split may fall
+                        // inside a multi-byte character
+                        let nth = match day.get(..split)? {
+                            "" => 0,
+                            nth => nth.trim_start_matches('+').parse().ok()?,
+                        };
+                        Some((nth, parse_weekday(day.get(split..)?)?))
+                    })
+                    .collect::<Option<_>>()?
+            }
+            "BYMONTHDAY" => rule.by_month_day = parse_list(value)?,
+            "BYMONTH" => rule.by_month = parse_list(value)?,
+            "BYSETPOS" => rule.by_set_pos = parse_list(value)?,
+            "WKST" => rule.week_start = parse_weekday(&value.to_ascii_uppercase())?,
+            _ => {}
+        }
+    }
+    rule.frequency = frequency?;
+    Some(rule)
+}
+
+/// Read the events of an iCalendar file. Malformed events are skipped.
+pub fn parse_calendar(text: &str, local: &TimeZone) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut current: Option<EventBuilder> = None;
+    // Components nested in an event, such as alarms
+    let mut nested = 0;
+
+    for line in unfold(text) {
+        let Some(property) = parse_property(&line) else {
+            continue;
+        };
+        let value = property.value.to_ascii_uppercase();
+        match (property.name.as_str(), current.as_mut()) {
+            ("BEGIN", None) if value == "VEVENT" => current = Some(EventBuilder::default()),
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            ("END", Some(_)) => {
+                if let Some(event) = current.take().and_then(|builder| builder.build()) {
+                    events.push(event);
+                }
+            }
+            (_, Some(builder)) if nested == 0 => builder.add(&property, local),
+            _ => {}
+        }
+    }
+    events
+}
+
+#[derive(Default)]
+struct EventBuilder {
+    uid: String,
+    summary: String,
+    location: Option<String>,
+    start: Option<Moment>,
+    end: Option<Moment>,
+    duration: Option<SignedDuration>,
+    rule: Option<Rule>,
+    exdates: Vec<Moment>,
+    recurrence_id: Option<Moment>,
+    cancelled: bool,
+}
+
+impl EventBuilder {
+    fn add(&mut self, property: &Property, local: &TimeZone) {
+        let tzid = property.param("TZID");
+        let value = property.value.as_str();
+        match property.name.as_str() {
+            "UID" => self.uid = value.to_string(),
+            "SUMMARY" => self.summary = unescape(value),
+            "LOCATION" => self.location = Some(unescape(value)).filter(|l| !l.is_empty()),
+            "DTSTART" => self.start = parse_moment(value, tzid, local),
+            "DTEND" => self.end = parse_moment(value, tzid, local),
+            "DURATION" => self.duration = parse_duration(value),
+            "RRULE" => {
+                self.rule = parse_rule(value, local);
+                if self.rule.is_none() {
+                    debug!("Unsupported RRULE {:?}, showing the event once", value);
+                }
+            }
+            "EXDATE" => self.exdates.extend(
+                value
+                    .split(',')
+                    .filter_map(|exdate| parse_moment(exdate, tzid, local)),
+            ),
+            "RECURRENCE-ID" => self.recurrence_id = parse_moment(value, tzid, local),
+            "STATUS" => self.cancelled = value.eq_ignore_ascii_case("CANCELLED"),
+            _ => {}
+        }
+    }
+
+    fn build(self) -> Option<Event> {
+        let start = self.start?;
+        let duration = match (&self.end, self.duration) {
+            (_, Some(duration)) => duration,
+            (Some(Moment::Date(end)), _) => {
+                let days = end.since(start.date()).ok()?.get_days();
+                SignedDuration::from_hours(24 * days as i64)
+            }
+            (Some(end), _) => {
+                let utc = TimeZone::UTC;
+                let end = end.timestamp(&utc)?;
+                end.duration_since(start.timestamp(&utc)?)
+            }
+            // An all-day event without an end lasts the day
+            (None, _) if matches!(start, Moment::Date(_)) => SignedDuration::from_hours(24),
+            (None, _) => SignedDuration::ZERO,
+        };
+        Some(Event {
+            uid: self.uid,
+            summary: self.summary,
+            location: self.location,
+            start,
+            duration: duration.max(SignedDuration::ZERO),
+            rule: self.rule,
+            exdates: self.exdates,
+            recurrence_id: self.recurrence_id,
+            cancelled: self.cancelled,
+        })
+    }
+}
+
+/// Dates from `first` to `last` on the given weekdays. A position counts
+/// from the start of the range, or from its end when negative.
+fn weekday_dates(first: Date, last: Date, by_day: &[(i16, Weekday)]) -> Vec<Date> {
+    let mut dates = Vec::new();
+    for &(nth, weekday) in by_day {
+        let offset = first.weekday().until(weekday) as i64;
+        let Ok(mut date) = first.checked_add(offset.days()) else {
+            continue;
+        };
+        let mut matches = Vec::new();
+        while date <= last {
+            matches.push(date);
+            match date.checked_add(7.days()) {
+                Ok(next) => date = next,
+                Err(_) => break,
+            }
+        }
+        match nth {
+            0 => dates.extend(matches),
+            nth if nth > 0 => dates.extend(matches.get(nth as usize - 1)),
+            nth => dates.extend(
+                matches
+                    .len()
+                    .checked_sub(nth.unsigned_abs() as usize)
+                    .map(|i| matches[i]),
+            ),
+        }
+    }
+    dates
+}
+
+/// Days of a month picked by BYMONTHDAY (negative counts from the end)
+fn month_days(first: Date, by_month_day: &[i8]) -> Vec<Date> {
+    let length = first.days_in_month();
+    by_month_day
+        .iter()
+        .filter_map(|&day| {
+            let day = if day < 0 { length + 1 + day } else { day };
+            (1..=length)
+                .contains(&day)
+                .then(|| first.checked_add(((day - 1) as i64).days()).ok())
+                .flatten()
+        })
+        .collect()
+}
+
+impl Rule {
+    fn has_weekday(&self, date: Date) -> bool {
+        self.by_day.iter().any(|&(_, day)| day == date.weekday())
+    }
+
+    fn in_month(&self, date: Date) -> bool {
+        self.by_month.is_empty() || self.by_month.contains(&date.month())
+    }
+
+    /// Candidate dates in the month starting at `first`
+    fn dates_in_month(&self, first: Date, start: Date) -> Vec<Date> {
+        if !self.by_month_day.is_empty() {
+            let mut dates = month_days(first, &self.by_month_day);
+            if !self.by_day.is_empty() {
+                dates.retain(|&date| self.has_weekday(date));
+            }
+            dates
+        } else if !self.by_day.is_empty() {
+            weekday_dates(first, first.last_of_month(), &self.by_day)
+        } else {
+            month_days(first, &[start.day()])
+        }
+    }
+
+    /// Candidate dates in the `n`th period (day, week, month or year) after
+    /// the one containing `start`, sorted and filtered by BYSETPOS
+    fn dates_in_period(&self, start: Date, n: i64) -> Option<Vec<Date>> {
+        let step = n.checked_mul(self.interval)?;
+        let mut dates = match self.frequency {
+            Frequency::Daily => {
+                let date = start.checked_add(Span::new().try_days(step).ok()?).ok()?;
+                let keep = self.in_month(date)
+                    && (self.by_month_day.is_empty()
+                        || month_days(date.first_of_month(), &self.by_month_day).contains(&date))
+                    && (self.by_day.is_empty() || self.has_weekday(date));
+                if keep {
+                    vec![date]
+                } else {
+                    Vec::new()
+                }
+            }
+            Frequency::Weekly => {
+                let back = self.week_start.until(start.weekday()) as i64;
+                let first = start.checked_sub(back.days()).ok()?;
+                let first = first.checked_add(Span::new().try_weeks(step).ok()?).ok()?;
+                let mut dates = Vec::new();
+                for offset in 0..7i64 {
+                    let date = first.checked_add(offset.days()).ok()?;
+                    let weekday = if self.by_day.is_empty() {
+                        date.weekday() == start.weekday()
+                    } else {
+                        self.has_weekday(date)
+                    };
+                    if weekday && self.in_month(date) {
+                        dates.push(date);
+                    }
+                }
+                dates
+            }
+            Frequency::Monthly => {
+                let first = start
+                    .first_of_month()
+                    .checked_add(Span::new().try_months(step).ok()?)
+                    .ok()?;
+                if !self.in_month(first) {
+                    return Some(Vec::new());
+                }
+                self.dates_in_month(first, start)
+            }
+            Frequency::Yearly => {
+                let first = start
+                    .first_of_year()
+                    .checked_add(Span::new().try_years(step).ok()?)
+                    .ok()?;
+                if self.by_month.is_empty()
+                    && self.by_month_day.is_empty()
+                    && !self.by_day.is_empty()
+                {
+                    // Positions count through the whole year
+                    let last = first.checked_add(1.year()).ok()?.yesterday().ok()?;
+                    weekday_dates(first, last, &self.by_day)
+                } else {
+                    let months = match (self.by_month.is_empty(), self.by_month_day.is_empty()) {
+                        (false, _) => self.by_month.clone(),
+                        (true, false) => (1..=12).collect(),
+                        (true, true) => vec![start.month()],
+                    };
+                    let mut dates = Vec::new();
+                    for month in months {
+                        let Ok(first) = Date::new(first.year(), month, 1) else {
+                            continue;
+                        };
+                        dates.extend(self.dates_in_month(first, start));
+                    }
+                    dates
+                }
+            }
+        };
+        dates.sort();
+        dates.dedup();
+
+        if !self.by_set_pos.is_empty() {
+            let picked = self
+                .by_set_pos
+                .iter()
+                .filter_map(|&pos| {
+                    let index = if pos < 0 {
+                        dates.len().checked_sub(pos.unsigned_abs() as usize)?
+                    } else {
+                        (pos as usize).checked_sub(1)?
+                    };
+                    dates.get(index).copied()
+                })
+                .collect::<HashSet<_>>();
+            dates.retain(|date| picked.contains(date));
+        }
+        Some(dates)
+    }
+}
+
+impl Event {
+    fn all_day(&self) -> bool {
+        matches!(self.start, Moment::Date(_))
+    }
+
+    fn occurrence(&self, start: Timestamp) -> Option<Occurrence> {
+        Some(Occurrence {
+            summary: self.summary.clone(),
+            location: self.location.clone(),
+            start,
+            end: start.checked_add(self.duration).ok()?,
+            all_day: self.all_day(),
+        })
+    }
+
+    fn excluded(&self, moment: &Moment, start: Timestamp, local: &TimeZone) -> bool {
+        self.exdates.iter().any(|exdate| match exdate {
+            Moment::Date(date) => *date == moment.date(),
+            exdate => exdate.timestamp(local) == Some(start),
+        })
+    }
+
+    /// Start times of every occurrence beginning before `to`
+    fn starts(&self, to: Timestamp, local: &TimeZone) -> Vec<(Moment, Timestamp)> {
+        let Some(rule) = &self.rule else {
+            return self
+                .start
+                .timestamp(local)
+                .filter(|&start| start < to)
+                .map(|start| (self.start.clone(), start))
+                .into_iter()
+                .collect();
+        };
+
+        let first = self.start.date();
+        let until = rule.until.as_ref();
+        let mut starts = Vec::new();
+        let mut count = 0;
+        for n in 0..MAX_PERIODS {
+            let Some(dates) = rule.dates_in_period(first, n) else {
+                break;
+            };
+            for date in dates.into_iter().filter(|&date| date >= first) {
+                let moment = self.start.on(date);
+                let Some(start) = moment.timestamp(local) else {
+                    continue;
+                };
+                let past_until = match until {
+                    Some(Moment::Date(until)) => date > *until,
+                    Some(until) => until.timestamp(local).is_some_and(|until| start > until),
+                    None => false,
+                };
+                if start >= to || past_until || rule.count.is_some_and(|max| count >= max) {
+                    return starts;
+                }
+                count += 1;
+                starts.push((moment, start));
+            }
+        }
+        starts
+    }
+}
+
+/// Every occurrence of `events` overlapping `from..to`, sorted by start
+pub fn occurrences(
+    events: &[Event],
+    from: Timestamp,
+    to: Timestamp,
+    local: &TimeZone,
+) -> Vec<Occurrence> {
+    // Occurrences replaced by a RECURRENCE-ID override
+    let overridden: HashSet<(&str, Timestamp)> = events
+        .iter()
+        .filter_map(|event| {
+            let id = event.recurrence_id.as_ref()?.timestamp(local)?;
+            Some((event.uid.as_str(), id))
+        })
+        .collect();
+
+    let mut occurrences = Vec::new();
+    for event in events.iter().filter(|event| !event.cancelled) {
+        let master = event.recurrence_id.is_none();
+        for (moment, start) in event.starts(to, local) {
+            if master
+                && (overridden.contains(&(event.uid.as_str(), start))
+                    || event.excluded(&moment, start, local))
+            {
+                continue;
+            }
+            if let Some(occurrence) = event.occurrence(start) {
+                // Zero-length events still show at their start
+                if occurrence.end > from || occurrence.start >= from {
+                    occurrences.push(occurrence);
+                }
+            }
+        }
+    }
+    occurrences.sort_by_key(|occurrence| (occurrence.start, !occurrence.all_day));
+    occurrences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A calendar of `events`, each given as its lines
+    fn calendar(events: &[&str]) -> Vec<Event> {
+        let mut text = String::from("BEGIN:VCALENDAR\r\n");
+        for event in events {
+            text.push_str("BEGIN:VEVENT\r\n");
+            for line in event.lines() {
+                text.push_str(line.trim());
+                text.push_str("\r\n");
+            }
+            text.push_str("END:VEVENT\r\n");
+        }
+        text.push_str("END:VCALENDAR\r\n");
+        parse_calendar(&text, &TimeZone::UTC)
+    }
+
+    /// Start times of the occurrences in 2024, in UTC
+    fn starts(events: &[Event]) -> Vec<String> {
+        let from: Timestamp = "2024-01-01T00:00:00Z".parse().unwrap();
+        let to: Timestamp = "2025-01-01T00:00:00Z".parse().unwrap();
+        occurrences(events, from, to, &TimeZone::UTC)
+            .iter()
+            .map(|occurrence| occurrence.start.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn byday_positions() {
+        let rule = parse_rule("FREQ=MONTHLY;BYDAY=-1FR,+2MO", &TimeZone::UTC).unwrap();
+        assert_eq!(rule.by_day, [(-1, Weekday::Friday), (2, Weekday::Monday)]);
+
+        let events = calendar(&["UID:a
+            DTSTART:20240101T090000Z
+            RRULE:FREQ=MONTHLY;BYDAY=-1FR;COUNT=3"]);
+        assert_eq!(
+            starts(&events),
+            [
+                "2024-01-26T09:00:00Z",
+                "2024-02-23T09:00:00Z",
+                "2024-03-29T09:00:00Z"
+            ]
+        );
+    }
+
+    #[test]
+    fn bysetpos_picks_from_each_period() {
+        // The last weekday of the month
+        let events = calendar(&["UID:a
+            DTSTART:20240101T090000Z
+            RRULE:FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1;COUNT=3"]);
+        assert_eq!(
+            starts(&events),
+            [
+                "2024-01-31T09:00:00Z",
+                "2024-02-29T09:00:00Z",
+                "2024-03-29T09:00:00Z"
+            ]
+        );
+    }
+
+    #[test]
+    fn count_and_until_end_a_rule() {
+        let events = calendar(&[
+            "UID:count
+            DTSTART:20240101T090000Z
+            RRULE:FREQ=DAILY;INTERVAL=2;COUNT=3",
+            "UID:until
+            DTSTART:20240601T120000Z
+            RRULE:FREQ=WEEKLY;UNTIL=20240615T120000Z",
+        ]);
+        assert_eq!(
+            starts(&events),
+            [
+                "2024-01-01T09:00:00Z",
+                "2024-01-03T09:00:00Z",
+                "2024-01-05T09:00:00Z",
+                "2024-06-01T12:00:00Z",
+                "2024-06-08T12:00:00Z",
+                "2024-06-15T12:00:00Z"
+            ]
+        );
+    }
+
+    #[test]
+    fn all_day_events_end_on_their_dtend() {
+        let events = calendar(&["UID:a
+            SUMMARY:Trip
+            DTSTART;VALUE=DATE:20240110
+            DTEND;VALUE=DATE:20240112"]);
+        assert_eq!(events[0].duration, SignedDuration::from_hours(48));
+
+        let from: Timestamp = "2024-01-11T12:00:00Z".parse().unwrap();
+        let to: Timestamp = "2024-01-12T00:00:00Z".parse().unwrap();
+        let occurrences = occurrences(&events, from, to, &TimeZone::UTC);
+        assert_eq!(occurrences.len(), 1);
+        assert!(occurrences[0].all_day);
+        assert_eq!(occurrences[0].end.to_string(), "2024-01-12T00:00:00Z");
+    }
+
+    #[test]
+    fn overrides_and_exdates_replace_occurrences() {
+        let events = calendar(&[
+            "UID:standup
+            SUMMARY:Standup
+            DTSTART:20240101T090000Z
+            RRULE:FREQ=DAILY;COUNT=4
+            EXDATE:20240102T090000Z",
+            "UID:standup
+            SUMMARY:Late standup
+            RECURRENCE-ID:20240103T090000Z
+            DTSTART:20240103T110000Z
+            DTEND:20240103T113000Z",
+            "UID:standup
+            SUMMARY:Cancelled standup
+            RECURRENCE-ID:20240104T090000Z
+            DTSTART:20240104T090000Z
+            STATUS:CANCELLED",
+        ]);
+        let from: Timestamp = "2024-01-01T00:00:00Z".parse().unwrap();
+        let to: Timestamp = "2024-02-01T00:00:00Z".parse().unwrap();
+        let summaries: Vec<(String, String)> = occurrences(&events, from, to, &TimeZone::UTC)
+            .into_iter()
+            .map(|occurrence| (occurrence.start.to_string(), occurrence.summary))
+            .collect();
+        assert_eq!(
+            summaries,
+            [
+                ("2024-01-01T09:00:00Z".to_string(), "Standup".to_string()),
+                (
+                    "2024-01-03T11:00:00Z".to_string(),
+                    "Late standup".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_values_are_rejected() {
+        assert_eq!(parse_rule("FREQ=WEEKLY;BYDAY=éA", &TimeZone::UTC), None);
+        assert_eq!(parse_rule("FREQ=WEEKLY;BYDAY=1é", &TimeZone::UTC), None);
+        assert_eq!(parse_duration("P9223372036854775807W"), None);
+        assert_eq!(parse_duration("P1DT9223372036854775807S"), None);
+        assert_eq!(
+            parse_duration("-P1DT2H"),
+            Some(SignedDuration::from_hours(-26))
+        );
+
+        // A huge interval ends the rule instead of overflowing
+        let events = calendar(&["UID:a
+            DTSTART:20240101T090000Z
+            RRULE:FREQ=YEARLY;INTERVAL=9223372036854775807"]);
+        assert_eq!(starts(&events), ["2024-01-01T09:00:00Z"]);
+    }
+}
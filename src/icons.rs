@@ -0,0 +1,619 @@
+//! Icon lookup for hypr-notch
+//!
+//! Resolves freedesktop icon names such as `audio-volume-high-symbolic` to
+//! files following the Icon Theme Specification: the current icon theme
+//! and the themes it inherits from, then `hicolor`, then the bare icon
+//! directories. Decoded icons are cached by name and size, so modules can
+//! ask for the same icon on every update.
+
+use crate::image::{self, RgbaImage};
+use log::{debug, info};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// File extensions tried for every icon, in order of preference
+const EXTENSIONS: [&str; 2] = ["png", "svg"];
+
+/// Theme every other theme falls back to
+const FALLBACK_THEME: &str = "hicolor";
+
+/// Shared lookup used by all modules
+static LOOKUP: Mutex<Option<IconLookup>> = Mutex::new(None);
+
+/// Use `theme` for lookups, or the GTK icon theme when None
+pub fn set_theme(theme: Option<String>) {
+    let theme = theme
+        .or_else(gtk_icon_theme)
+        .unwrap_or_else(|| FALLBACK_THEME.to_string());
+    info!("Using icon theme {}", theme);
+    *LOOKUP.lock().unwrap() = Some(IconLookup::new(default_base_dirs(), theme));
+}
+
+/// Load the icon `name` (an icon name, an absolute path or a `file://`
+/// URI) at about `size` pixels, from the cache when possible
+pub fn load(name: &str, size: u32) -> Option<Arc<RgbaImage>> {
+    let mut lookup = LOOKUP.lock().unwrap();
+    let lookup = lookup.get_or_insert_with(|| {
+        IconLookup::new(
+            default_base_dirs(),
+            gtk_icon_theme().unwrap_or_else(|| FALLBACK_THEME.to_string()),
+        )
+    });
+    lookup.load(name, size)
+}
+
+/// How the icons of a theme directory may be scaled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirectoryType {
+    Fixed,
+    Scalable,
+    Threshold,
+}
+
+/// One subdirectory listed in `index.theme`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IconDirectory {
+    pub path: String,
+    pub size: u32,
+    pub scale: u32,
+    pub kind: DirectoryType,
+    pub min_size: u32,
+    pub max_size: u32,
+    pub threshold: u32,
+}
+
+impl IconDirectory {
+    /// `DirectoryMatchesSize` from the specification
+    fn matches(&self, size: u32, scale: u32) -> bool {
+        if self.scale != scale {
+            return false;
+        }
+        match self.kind {
+            DirectoryType::Fixed => self.size == size,
+            DirectoryType::Scalable => (self.min_size..=self.max_size).contains(&size),
+            DirectoryType::Threshold => (self.size.saturating_sub(self.threshold)
+                ..=self.size + self.threshold)
+                .contains(&size),
+        }
+    }
+
+    /// `DirectorySizeDistance` from the specification
+    fn distance(&self, size: u32, scale: u32) -> u32 {
+        let wanted = size * scale;
+        let (low, high) = match self.kind {
+            DirectoryType::Fixed => (self.size, self.size),
+            DirectoryType::Scalable => (self.min_size, self.max_size),
+            DirectoryType::Threshold => (
+                self.size.saturating_sub(self.threshold),
+                self.size + self.threshold,
+            ),
+        };
+        if wanted < low * self.scale {
+            low * self.scale - wanted
+        } else {
+            wanted.saturating_sub(high * self.scale)
+        }
+    }
+}
+
+/// The parts of an `index.theme` file used for lookups
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IconTheme {
+    pub inherits: Vec<String>,
+    pub directories: Vec<IconDirectory>,
+}
+
+/// Parse the contents of an `index.theme` file
+pub fn parse_index_theme(content: &str) -> IconTheme {
+    let mut sections: HashMap<&str, HashMap<&str, &str>> = HashMap::new();
+    let mut current = None;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(name);
+            continue;
+        }
+        if let (Some(section), Some((key, value))) = (current, line.split_once('=')) {
+            sections
+                .entry(section)
+                .or_default()
+                .insert(key.trim(), value.trim());
+        }
+    }
+
+    let list = |value: Option<&&str>| -> Vec<String> {
+        value
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let Some(header) = sections.get("Icon Theme") else {
+        return IconTheme::default();
+    };
+    let mut names = list(header.get("Directories"));
+    names.extend(list(header.get("ScaledDirectories")));
+
+    let directories = names
+        .into_iter()
+        .filter_map(|path| {
+            let keys = sections.get(path.as_str())?;
+            let number = |key: &str| keys.get(key).and_then(|value| value.parse::<u32>().ok());
+            let size = number("Size")?;
+            let kind = match keys.get("Type").copied() {
+                Some("Fixed") => DirectoryType::Fixed,
+                Some("Scalable") => DirectoryType::Scalable,
+                _ => DirectoryType::Threshold,
+            };
+            Some(IconDirectory {
+                size,
+                scale: number("Scale").unwrap_or(1),
+                kind,
+                min_size: number("MinSize").unwrap_or(size),
+                max_size: number("MaxSize").unwrap_or(size),
+                threshold: number("Threshold").unwrap_or(2),
+                path,
+            })
+        })
+        .collect();
+
+    IconTheme {
+        inherits: list(header.get("Inherits")),
+        directories,
+    }
+}
+
+/// Resolves icon names against a set of base directories
+pub struct IconLookup {
+    /// Directories holding icon themes and loose icons, most important first
+    base_dirs: Vec<PathBuf>,
+    theme: String,
+    /// Parsed `index.theme` files; None for themes that aren't installed
+    themes: HashMap<String, Option<IconTheme>>,
+    /// Decoded icons by name and size; None for icons that weren't found
+    cache: HashMap<(String, u32), Option<Arc<RgbaImage>>>,
+}
+
+impl IconLookup {
+    pub fn new(base_dirs: Vec<PathBuf>, theme: String) -> Self {
+        Self {
+            base_dirs,
+            theme,
+            themes: HashMap::new(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Decoded icon for `name` at about `size` pixels
+    pub fn load(&mut self, name: &str, size: u32) -> Option<Arc<RgbaImage>> {
+        let key = (name.to_string(), size);
+        if let Some(cached) = self.cache.get(&key) {
+            return cached.clone();
+        }
+
+        let icon = self.find(name, size).and_then(|path| {
+            debug!("Icon {} resolved to {}", name, path.display());
+            match image::load_image(&path, size) {
+                Ok(image) => Some(Arc::new(image)),
+                Err(e) => {
+                    info!("Failed to decode icon {}: {}", path.display(), e);
+                    None
+                }
+            }
+        });
+        self.cache.insert(key, icon.clone());
+        icon
+    }
+
+    /// Path of the best file for `name` at `size`. Names that are paths or
+    /// `file://` URIs are used as they are. Unknown names fall back to
+    /// shorter names, so `network-wireless-signal-good` can be served by
+    /// `network-wireless`.
+    pub fn find(&mut self, name: &str, size: u32) -> Option<PathBuf> {
+        let name = name.strip_prefix("file://").unwrap_or(name);
+        if name.starts_with('/') {
+            return Path::new(name).is_file().then(|| PathBuf::from(name));
+        }
+        if name.is_empty() {
+            return None;
+        }
+
+        // Symbolic icons keep their suffix while the name gets shorter, and
+        // fall back to the full-colour icon of the same name
+        let symbolic = name.ends_with("-symbolic");
+        let mut base = name.strip_suffix("-symbolic").unwrap_or(name);
+        loop {
+            if symbolic {
+                if let Some(path) = self.find_exact(&format!("{}-symbolic", base), size) {
+                    return Some(path);
+                }
+            }
+            if let Some(path) = self.find_exact(base, size) {
+                return Some(path);
+            }
+            base = base.rsplit_once('-')?.0;
+        }
+    }
+
+    /// `FindIcon` from the specification for one exact name
+    fn find_exact(&mut self, name: &str, size: u32) -> Option<PathBuf> {
+        let mut visited = Vec::new();
+        let theme = self.theme.clone();
+        self.find_in_theme(name, size, &theme, &mut visited)
+            .or_else(|| self.find_in_theme(name, size, FALLBACK_THEME, &mut visited))
+            .or_else(|| self.find_loose(name))
+    }
+
+    /// `FindIconHelper`: look in `theme`, then in the themes it inherits from
+    fn find_in_theme(
+        &mut self,
+        name: &str,
+        size: u32,
+        theme_name: &str,
+        visited: &mut Vec<String>,
+    ) -> Option<PathBuf> {
+        if visited.iter().any(|seen| seen == theme_name) {
+            return None;
+        }
+        visited.push(theme_name.to_string());
+
+        let theme = self.theme(theme_name)?.clone();
+        if let Some(path) = self.lookup_icon(name, size, theme_name, &theme) {
+            return Some(path);
+        }
+        theme
+            .inherits
+            .iter()
+            .find_map(|parent| self.find_in_theme(name, size, parent, visited))
+    }
+
+    /// `LookupIcon`: an exactly matching directory, or else the closest size
+    fn lookup_icon(
+        &self,
+        name: &str,
+        size: u32,
+        theme_name: &str,
+        theme: &IconTheme,
+    ) -> Option<PathBuf> {
+        let exact = theme
+            .directories
+            .iter()
+            .filter(|directory| directory.matches(size, 1))
+            .flat_map(|directory| self.candidates(name, theme_name, directory))
+            .find(|path| path.is_file());
+        if exact.is_some() {
+            return exact;
+        }
+
+        theme
+            .directories
+            .iter()
+            .flat_map(|directory| {
+                self.candidates(name, theme_name, directory)
+                    .map(move |path| (directory, path))
+            })
+            .filter(|(_, path)| path.is_file())
+            // On a tie, shrinking a larger icon looks better than enlarging
+            .min_by_key(|(directory, _)| {
+                (
+                    directory.distance(size, 1),
+                    std::cmp::Reverse(directory.size),
+                )
+            })
+            .map(|(_, path)| path)
+    }
+
+    /// Possible files for `name` in one directory of a theme
+    fn candidates<'a>(
+        &'a self,
+        name: &'a str,
+        theme_name: &'a str,
+        directory: &'a IconDirectory,
+    ) -> impl Iterator<Item = PathBuf> + 'a {
+        self.base_dirs.iter().flat_map(move |base| {
+            EXTENSIONS.iter().map(move |ext| {
+                base.join(theme_name)
+                    .join(&directory.path)
+                    .join(format!("{}.{}", name, ext))
+            })
+        })
+    }
+
+    /// `LookupFallbackIcon`: icons placed directly in a base directory
+    fn find_loose(&self, name: &str) -> Option<PathBuf> {
+        self.base_dirs
+            .iter()
+            .flat_map(|base| {
+                EXTENSIONS
+                    .iter()
+                    .map(move |ext| base.join(format!("{}.{}", name, ext)))
+            })
+            .find(|path| path.is_file())
+    }
+
+    /// Parsed `index.theme` of the theme called `name`, read from the first
+    /// base directory that has one
+    fn theme(&mut self, name: &str) -> Option<&IconTheme> {
+        if !self.themes.contains_key(name) {
+            let theme = self
+                .base_dirs
+                .iter()
+                .find_map(|base| fs::read_to_string(base.join(name).join("index.theme")).ok())
+                .map(|content| parse_index_theme(&content));
+            if theme.is_none() {
+                debug!("Icon theme {} is not installed", name);
+            }
+            self.themes.insert(name.to_string(), theme);
+        }
+        self.themes.get(name)?.as_ref()
+    }
+}
+
+/// Icon directories from the XDG base directory variables, plus
+/// `~/.icons` and `/usr/share/pixmaps`
+pub fn default_base_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(home) = dirs::home_dir() {
+        dirs.push(home.join(".icons"));
+    }
+    if let Some(data_dir) = dirs::data_dir() {
+        dirs.push(data_dir.join("icons"));
+    }
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(
+        data_dirs
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| Path::new(dir).join("icons")),
+    );
+    dirs.push(PathBuf::from("/usr/share/pixmaps"));
+    dirs
+}
+
+/// Icon theme chosen in the GTK settings, if any
+fn gtk_icon_theme() -> Option<String> {
+    let config_dir = dirs::config_dir()?;
+    ["gtk-4.0", "gtk-3.0"].iter().find_map(|version| {
+        let content = fs::read_to_string(config_dir.join(version).join("settings.ini")).ok()?;
+        content.lines().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "gtk-icon-theme-name")
+                .then(|| value.trim().trim_matches('"').to_string())
+                .filter(|value| !value.is_empty())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HICOLOR_INDEX: &str = "
+        [Icon Theme]
+        Name=Hicolor
+        Directories=16x16/apps,48x48/apps,scalable/apps
+
+        [16x16/apps]
+        Size=16
+        Type=Fixed
+
+        [48x48/apps]
+        Size=48
+        Type=Fixed
+
+        [scalable/apps]
+        Size=128
+        MinSize=64
+        MaxSize=256
+        Type=Scalable
+    ";
+
+    /// Install a theme called `name` under `base` with every listed icon as
+    /// an empty file
+    fn theme(base: &Path, name: &str, index: &str, icons: &[&str]) {
+        let dir = base.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("index.theme"), index).unwrap();
+        for icon in icons {
+            let path = dir.join(icon);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+    }
+
+    /// Theme with a single `48x48/apps` directory that inherits `parents`
+    fn index_inheriting(parents: &str) -> String {
+        format!(
+            "[Icon Theme]\nInherits={}\nDirectories=48x48/apps\n\n\
+             [48x48/apps]\nSize=48\nType=Fixed\n",
+            parents
+        )
+    }
+
+    /// Where `name` was found, relative to `base`
+    fn found(lookup: &mut IconLookup, base: &Path, name: &str, size: u32) -> Option<String> {
+        let path = lookup.find(name, size)?;
+        Some(path.strip_prefix(base).unwrap().display().to_string())
+    }
+
+    #[test]
+    fn inherited_themes_then_hicolor() {
+        let base = tempfile::tempdir().unwrap();
+        let base = base.path();
+        theme(
+            base,
+            "child",
+            &index_inheriting("parent"),
+            &["48x48/apps/both.png"],
+        );
+        theme(
+            base,
+            "parent",
+            &index_inheriting("grandparent, missing"),
+            &["48x48/apps/both.png", "48x48/apps/parent.png"],
+        );
+        theme(
+            base,
+            "grandparent",
+            // A cycle back to the child must not loop forever
+            &index_inheriting("child"),
+            &["48x48/apps/grandparent.png"],
+        );
+        theme(base, "hicolor", HICOLOR_INDEX, &["48x48/apps/plain.png"]);
+        fs::write(base.join("loose.svg"), "").unwrap();
+
+        let mut lookup = IconLookup::new(vec![base.to_path_buf()], "child".to_string());
+        let mut find = |name| found(&mut lookup, base, name, 48);
+        assert_eq!(find("both").as_deref(), Some("child/48x48/apps/both.png"));
+        assert_eq!(
+            find("parent").as_deref(),
+            Some("parent/48x48/apps/parent.png")
+        );
+        assert_eq!(
+            find("grandparent").as_deref(),
+            Some("grandparent/48x48/apps/grandparent.png")
+        );
+        // hicolor is searched even though nothing inherits it
+        assert_eq!(
+            find("plain").as_deref(),
+            Some("hicolor/48x48/apps/plain.png")
+        );
+        assert_eq!(find("loose").as_deref(), Some("loose.svg"));
+        assert_eq!(find("nothing"), None);
+        // Unknown names get shorter until one is found
+        assert_eq!(
+            find("plain-but-longer-symbolic").as_deref(),
+            Some("hicolor/48x48/apps/plain.png")
+        );
+    }
+
+    #[test]
+    fn closest_size_wins() {
+        let base = tempfile::tempdir().unwrap();
+        let base = base.path();
+        theme(
+            base,
+            "hicolor",
+            HICOLOR_INDEX,
+            &[
+                "16x16/apps/fixed.png",
+                "48x48/apps/fixed.png",
+                "16x16/apps/vector.png",
+                "scalable/apps/vector.svg",
+            ],
+        );
+
+        let mut lookup = IconLookup::new(vec![base.to_path_buf()], "hicolor".to_string());
+        let mut find = |name, size| found(&mut lookup, base, name, size);
+        assert_eq!(
+            find("fixed", 16).as_deref(),
+            Some("hicolor/16x16/apps/fixed.png")
+        );
+        assert_eq!(
+            find("fixed", 20).as_deref(),
+            Some("hicolor/16x16/apps/fixed.png")
+        );
+        assert_eq!(
+            find("fixed", 40).as_deref(),
+            Some("hicolor/48x48/apps/fixed.png")
+        );
+        // Halfway between, the larger icon is shrunk rather than the
+        // smaller one enlarged
+        assert_eq!(
+            find("fixed", 32).as_deref(),
+            Some("hicolor/48x48/apps/fixed.png")
+        );
+        assert_eq!(
+            find("fixed", 512).as_deref(),
+            Some("hicolor/48x48/apps/fixed.png")
+        );
+        // Scalable directories match anything in their range
+        assert_eq!(
+            find("vector", 200).as_deref(),
+            Some("hicolor/scalable/apps/vector.svg")
+        );
+        assert_eq!(
+            find("vector", 48).as_deref(),
+            Some("hicolor/scalable/apps/vector.svg")
+        );
+        assert_eq!(
+            find("vector", 16).as_deref(),
+            Some("hicolor/16x16/apps/vector.png")
+        );
+    }
+
+    #[test]
+    fn user_icons_come_before_system_icons() {
+        let user = tempfile::tempdir().unwrap();
+        let system = tempfile::tempdir().unwrap();
+        for base in [user.path(), system.path()] {
+            theme(base, "hicolor", HICOLOR_INDEX, &["48x48/apps/app.png"]);
+        }
+        theme(
+            system.path(),
+            "hicolor",
+            HICOLOR_INDEX,
+            &["48x48/apps/system-only.png"],
+        );
+
+        let bases = vec![user.path().to_path_buf(), system.path().to_path_buf()];
+        let mut lookup = IconLookup::new(bases, "hicolor".to_string());
+        assert!(lookup.find("app", 48).unwrap().starts_with(user.path()));
+        assert!(lookup
+            .find("system-only", 48)
+            .unwrap()
+            .starts_with(system.path()));
+
+        // ~/.local/share/icons is searched before the system data directories
+        let defaults = default_base_dirs();
+        let position = |dir: &Path| defaults.iter().position(|base| base == dir);
+        let local = position(&dirs::data_dir().unwrap().join("icons")).unwrap();
+        let usr = position(Path::new("/usr/share/icons"));
+        if std::env::var_os("XDG_DATA_DIRS").is_none() {
+            assert!(usr.is_some());
+        }
+        assert!(usr.is_none_or(|usr| local < usr));
+        assert_eq!(defaults.last(), Some(&PathBuf::from("/usr/share/pixmaps")));
+    }
+
+    #[test]
+    fn svgs_are_rasterised_at_the_requested_size_and_cached() {
+        let base = tempfile::tempdir().unwrap();
+        theme(base.path(), "hicolor", HICOLOR_INDEX, &[]);
+        let path = base.path().join("hicolor/scalable/apps/dot.svg");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" width="16" height="16">
+            <circle cx="8" cy="8" r="8" fill="#ff0000"/>
+        </svg>"##;
+        fs::write(&path, svg).unwrap();
+
+        let mut lookup = IconLookup::new(vec![base.path().to_path_buf()], "hicolor".to_string());
+        let icon = lookup.load("dot", 96).unwrap();
+        assert_eq!((icon.width, icon.height), (96, 96));
+        assert_eq!(icon.pixel(48, 48), [255, 0, 0, 255]);
+        assert_eq!(icon.pixel(0, 0)[3], 0);
+
+        // The second lookup doesn't read the file again
+        fs::write(&path, "not an svg").unwrap();
+        let again = lookup.load("dot", 96).unwrap();
+        assert!(Arc::ptr_eq(&icon, &again));
+        // but another size is decoded on its own
+        assert!(lookup.load("dot", 64).is_none());
+        // and so are misses
+        assert!(lookup.load("missing", 96).is_none());
+        fs::write(base.path().join("missing.svg"), svg).unwrap();
+        assert!(lookup.load("missing", 96).is_none());
+    }
+}
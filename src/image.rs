@@ -0,0 +1,335 @@
+//! Image loading for hypr-notch
+//!
+//! Decodes PNG files and rasterises SVG files into straight
+//! (non-premultiplied) RGBA pixels that the drawing code samples from.
+
+use png::{ColorType, Decoder, Transformations};
+use resvg::{tiny_skia, usvg};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Seek};
+use std::path::Path;
+
+/// How an image is sampled when drawn at a different size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// Nearest pixel, for pixel art and exact 1:1 drawing
+    Nearest,
+    /// Bilinear when enlarging, averaging when shrinking
+    #[default]
+    Smooth,
+}
+
+/// Decoded image with 8-bit RGBA pixels, row by row
+#[derive(Debug, Clone)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// RGBA value of the pixel at `(x, y)`, clamped to the image bounds
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let x = x.min(self.width.saturating_sub(1));
+        let y = y.min(self.height.saturating_sub(1));
+        let idx = (y * self.width + x) as usize * 4;
+        [
+            self.data[idx],
+            self.data[idx + 1],
+            self.data[idx + 2],
+            self.data[idx + 3],
+        ]
+    }
+
+    /// Build an image from rows of 8-bit RGB or RGBA samples, where each
+    /// row takes `rowstride` bytes (as in the notification `image-data`
+    /// hint). Returns None if `data` is too short.
+    pub fn from_rows(
+        width: u32,
+        height: u32,
+        rowstride: usize,
+        has_alpha: bool,
+        data: &[u8],
+    ) -> Option<Self> {
+        let channels = if has_alpha { 4 } else { 3 };
+        let row_bytes = width as usize * channels;
+        if rowstride < row_bytes
+            || data.len() < rowstride * height.saturating_sub(1) as usize + row_bytes
+        {
+            return None;
+        }
+
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        for row in data.chunks(rowstride).take(height as usize) {
+            for pixel in row[..row_bytes].chunks_exact(channels) {
+                let alpha = if has_alpha { pixel[3] } else { 255 };
+                pixels.extend([pixel[0], pixel[1], pixel[2], alpha]);
+            }
+        }
+        Some(Self {
+            width,
+            height,
+            data: pixels,
+        })
+    }
+
+    /// Build an image from 32-bit ARGB pixels in network byte order (as in
+    /// the StatusNotifierItem `IconPixmap` property). Returns None if `data`
+    /// is too short.
+    pub fn from_argb32(width: u32, height: u32, data: &[u8]) -> Option<Self> {
+        let len = width as usize * height as usize * 4;
+        if data.len() < len {
+            return None;
+        }
+        let data = data[..len]
+            .chunks_exact(4)
+            .flat_map(|pixel| [pixel[1], pixel[2], pixel[3], pixel[0]])
+            .collect();
+        Some(Self {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Colour at `(x, y)` in image pixels, where pixel centres sit at
+    /// half-integer coordinates. `footprint` is how many image pixels one
+    /// sample spans; larger footprints are averaged to avoid aliasing.
+    pub fn sample(&self, x: f32, y: f32, footprint: f32, filter: Filter) -> [u8; 4] {
+        if self.width == 0 || self.height == 0 {
+            return [0, 0, 0, 0];
+        }
+        if filter == Filter::Nearest {
+            return self.pixel(x.max(0.0) as u32, y.max(0.0) as u32);
+        }
+
+        // Average a grid of bilinear taps across the footprint, all in
+        // premultiplied space so transparent pixels don't darken edges
+        let taps = footprint.ceil().clamp(1.0, 8.0) as u32;
+        let mut sum = [0.0f32; 4];
+        for j in 0..taps {
+            for i in 0..taps {
+                let offset_x = ((i as f32 + 0.5) / taps as f32 - 0.5) * footprint.max(1.0);
+                let offset_y = ((j as f32 + 0.5) / taps as f32 - 0.5) * footprint.max(1.0);
+                let tap = self.bilinear(x + offset_x, y + offset_y);
+                for (total, value) in sum.iter_mut().zip(tap) {
+                    *total += value;
+                }
+            }
+        }
+        let count = (taps * taps) as f32;
+        let alpha = sum[3] / count;
+        if alpha <= 0.0 {
+            return [0, 0, 0, 0];
+        }
+        [
+            (sum[0] / count / alpha * 255.0).round().min(255.0) as u8,
+            (sum[1] / count / alpha * 255.0).round().min(255.0) as u8,
+            (sum[2] / count / alpha * 255.0).round().min(255.0) as u8,
+            (alpha * 255.0).round() as u8,
+        ]
+    }
+
+    /// Premultiplied colour (0 to 1 per channel) interpolated between the
+    /// four pixels around `(x, y)`
+    fn bilinear(&self, x: f32, y: f32) -> [f32; 4] {
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let at = |px: f32, py: f32| {
+            let [r, g, b, a] = self.pixel(px.max(0.0) as u32, py.max(0.0) as u32);
+            let alpha = a as f32 / 255.0;
+            [
+                r as f32 / 255.0 * alpha,
+                g as f32 / 255.0 * alpha,
+                b as f32 / 255.0 * alpha,
+                alpha,
+            ]
+        };
+        let (top_left, top_right) = (at(x0, y0), at(x0 + 1.0, y0));
+        let (bottom_left, bottom_right) = (at(x0, y0 + 1.0), at(x0 + 1.0, y0 + 1.0));
+        let mut color = [0.0; 4];
+        for (i, channel) in color.iter_mut().enumerate() {
+            let top = top_left[i] + (top_right[i] - top_left[i]) * fx;
+            let bottom = bottom_left[i] + (bottom_right[i] - bottom_left[i]) * fx;
+            *channel = top + (bottom - top) * fy;
+        }
+        color
+    }
+
+    /// Copy of the image scaled to `width` x `height`
+    pub fn scaled(&self, width: u32, height: u32) -> Self {
+        if (width, height) == (self.width, self.height) {
+            return self.clone();
+        }
+        let scale_x = self.width as f32 / width.max(1) as f32;
+        let scale_y = self.height as f32 / height.max(1) as f32;
+        let footprint = scale_x.max(scale_y);
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                data.extend(self.sample(
+                    (x as f32 + 0.5) * scale_x,
+                    (y as f32 + 0.5) * scale_y,
+                    footprint,
+                    Filter::Smooth,
+                ));
+            }
+        }
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+/// Load a PNG or SVG file. SVGs are rasterised to fit in `size` x `size`
+/// pixels; PNGs are scaled down to fit when larger.
+pub fn load_image(path: &Path, size: u32) -> Result<RgbaImage, Box<dyn std::error::Error>> {
+    let is_svg = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+    if is_svg {
+        return load_svg(path, size);
+    }
+
+    let image = load_png(path)?;
+    if image.width <= size && image.height <= size {
+        return Ok(image);
+    }
+    let (width, height) = fit_within(image.width, image.height, size);
+    Ok(image.scaled(width, height))
+}
+
+/// Rasterise an SVG file so it fits in `size` x `size` pixels, keeping its
+/// aspect ratio
+pub fn load_svg(path: &Path, size: u32) -> Result<RgbaImage, Box<dyn std::error::Error>> {
+    let tree = usvg::Tree::from_data(&fs::read(path)?, &usvg::Options::default())?;
+    let natural = tree.size();
+    let (width, height) = fit_within(
+        natural.width().ceil() as u32,
+        natural.height().ceil() as u32,
+        size,
+    );
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or("SVG has an empty size")?;
+    let transform = tiny_skia::Transform::from_scale(
+        width as f32 / natural.width(),
+        height as f32 / natural.height(),
+    );
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    let data = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let pixel = pixel.demultiply();
+            [pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()]
+        })
+        .collect();
+    Ok(RgbaImage {
+        width,
+        height,
+        data,
+    })
+}
+
+/// Largest size with the aspect ratio of `width` x `height` that fits in a
+/// `size` square
+fn fit_within(width: u32, height: u32, size: u32) -> (u32, u32) {
+    let scale = size as f32 / width.max(height).max(1) as f32;
+    (
+        ((width as f32 * scale).round() as u32).max(1),
+        ((height as f32 * scale).round() as u32).max(1),
+    )
+}
+
+/// Decode a PNG file of any colour type and bit depth
+pub fn load_png(path: &Path) -> Result<RgbaImage, Box<dyn std::error::Error>> {
+    decode_png(BufReader::new(File::open(path)?))
+}
+
+/// Decode a PNG image of any colour type and bit depth from `reader`
+pub fn decode_png(reader: impl BufRead + Seek) -> Result<RgbaImage, Box<dyn std::error::Error>> {
+    let mut decoder = Decoder::new(reader);
+    // Expand palettes and low bit depths, and strip 16-bit samples to 8 bits
+    decoder.set_transformations(Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info()?;
+    let size = reader
+        .output_buffer_size()
+        .ok_or("image is too large to decode")?;
+    let mut buffer = vec![0; size];
+    let info = reader.next_frame(&mut buffer)?;
+    buffer.truncate(info.buffer_size());
+
+    let data = match info.color_type {
+        ColorType::Rgba => buffer,
+        ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        ColorType::Grayscale => buffer.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        ColorType::Indexed => return Err("indexed PNG was not expanded".into()),
+    };
+
+    Ok(RgbaImage {
+        width: info.width,
+        height: info.height,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svgs_fit_the_requested_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wide.svg");
+        fs::write(
+            &path,
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10">
+                <rect width="10" height="10" fill="#0000ff"/>
+            </svg>"##,
+        )
+        .unwrap();
+
+        // Scaled up or down to the size, keeping the aspect ratio
+        for size in [8, 40] {
+            let image = load_image(&path, size).unwrap();
+            assert_eq!((image.width, image.height), (size, size / 2));
+        }
+        let image = load_svg(&path, 40).unwrap();
+        assert_eq!(image.pixel(5, 10), [0, 0, 255, 255]);
+        assert_eq!(image.pixel(35, 10), [0, 0, 0, 0]);
+
+        fs::write(&path, "<svg").unwrap();
+        assert!(load_image(&path, 40).is_err());
+    }
+
+    #[test]
+    fn large_pngs_are_scaled_down_and_small_ones_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("icon.png");
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, 64, 32);
+        encoder.set_color(ColorType::Rgb);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0x40; 64 * 32 * 3]).unwrap();
+        writer.finish().unwrap();
+        fs::write(&path, data).unwrap();
+
+        let image = load_image(&path, 16).unwrap();
+        assert_eq!((image.width, image.height), (16, 8));
+        let image = load_image(&path, 128).unwrap();
+        assert_eq!((image.width, image.height), (64, 32));
+        assert_eq!(image.pixel(0, 0), [0x40, 0x40, 0x40, 255]);
+    }
+}
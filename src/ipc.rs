@@ -0,0 +1,211 @@
+//! Control socket for hypr-notch
+//!
+//! Listens on `$XDG_RUNTIME_DIR/hypr-notch.sock` for one-line commands of
+//! the form `MODULE ARGS...` and forwards them to modules as
+//! `ModuleEvent::Command`. `hypr-notch msg MODULE ARGS...` sends one
+//! command to the running instance and prints the reply, so commands can
+//! be bound to keys, e.g. `bind = SUPER, T, exec, hypr-notch msg timer toggle`.
+
+use log::{debug, info, warn};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// How long a client waits for the reply, and the listener for the command
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The way back to the client that sent a command
+#[derive(Debug, Clone)]
+pub struct Reply(mpsc::Sender<String>);
+
+impl Reply {
+    /// Answer the client; later replies are ignored
+    pub fn send(&self, text: impl Into<String>) {
+        let _ = self.0.send(text.into());
+    }
+}
+
+/// A command for a module, with a way to answer the client that sent it
+#[derive(Debug)]
+pub struct IpcCommand {
+    pub module: String,
+    pub args: Vec<String>,
+    pub reply: Reply,
+}
+
+/// Removes the control socket when dropped, so a clean shutdown leaves
+/// nothing behind
+pub struct SocketGuard {
+    path: PathBuf,
+}
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        match fs::remove_file(&self.path) {
+            Ok(()) => debug!("Removed {}", self.path.display()),
+            Err(e) => debug!("Failed to remove {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+/// Path of the control socket
+pub fn socket_path() -> Option<PathBuf> {
+    Some(PathBuf::from(env::var_os("XDG_RUNTIME_DIR")?).join("hypr-notch.sock"))
+}
+
+/// Split a command line into the module it is for and its arguments
+pub fn parse_command(line: &str) -> Option<(String, Vec<String>)> {
+    let mut words = line.split_whitespace().map(str::to_string);
+    let module = words.next()?;
+    Some((module, words.collect()))
+}
+
+/// Read one command from a client, pass it on and write back the reply
+fn serve_client(
+    stream: UnixStream,
+    sender: &calloop::channel::Sender<IpcCommand>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let mut stream = stream;
+
+    let Some((module, args)) = parse_command(&line) else {
+        return writeln!(stream, "error: expected MODULE ARGS...");
+    };
+    debug!("IPC command for {}: {:?}", module, args);
+    let (reply, replies) = mpsc::channel();
+    if sender
+        .send(IpcCommand {
+            module,
+            args,
+            reply: Reply(reply),
+        })
+        .is_err()
+    {
+        return writeln!(stream, "error: hypr-notch is shutting down");
+    }
+    let reply = replies
+        .recv_timeout(TIMEOUT)
+        .unwrap_or_else(|_| "error: no reply".to_string());
+    writeln!(stream, "{}", reply)
+}
+
+/// Listen on `path` on a background thread, sending every command to the
+/// event loop through `sender`. The socket is removed when the returned
+/// guard is dropped.
+pub fn spawn_listener(
+    path: PathBuf,
+    sender: calloop::channel::Sender<IpcCommand>,
+) -> Option<SocketGuard> {
+    // A socket nobody answers on is left over from a crash
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            warn!(
+                "Another hypr-notch is listening on {}, not taking commands",
+                path.display()
+            );
+            return None;
+        }
+        let _ = fs::remove_file(&path);
+    }
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Failed to listen on {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    info!("Listening for commands on {}", path.display());
+    let guard = SocketGuard { path };
+
+    let spawned = thread::Builder::new()
+        .name("ipc".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(|stream| serve_client(stream, &sender));
+                if let Err(e) = result {
+                    debug!("IPC client error: {}", e);
+                }
+            }
+        });
+    match spawned {
+        Ok(_) => Some(guard),
+        Err(e) => {
+            warn!("Failed to start the command listener: {}", e);
+            None
+        }
+    }
+}
+
+/// Send `args` (module first) to the instance listening on `path` and
+/// return its reply
+pub fn send_at(path: &Path, args: &[String]) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(TIMEOUT * 2))?;
+    writeln!(stream, "{}", args.join(" "))?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply.trim_end().to_string())
+}
+
+/// `hypr-notch msg MODULE ARGS...`: send a command and print the reply
+pub fn run_client(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if args.is_empty() {
+        return Err("usage: hypr-notch msg MODULE ARGS...".into());
+    }
+    let path = socket_path().ok_or("XDG_RUNTIME_DIR is not set")?;
+    let reply = send_at(&path, args)
+        .map_err(|e| format!("could not reach hypr-notch at {}: {}", path.display(), e))?;
+    match reply.strip_prefix("error: ") {
+        Some(error) => Err(error.into()),
+        None => {
+            println!("{}", reply);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies_reach_the_client_and_the_socket_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hypr-notch.sock");
+        let (sender, channel) = calloop::channel::channel();
+        let socket = spawn_listener(path.clone(), sender).unwrap();
+
+        let mut event_loop = calloop::EventLoop::<()>::try_new().unwrap();
+        event_loop
+            .handle()
+            .insert_source(channel, |event, _, _| {
+                if let calloop::channel::Event::Msg(command) = event {
+                    let reply = command.reply.clone();
+                    reply.send(format!("{}: {}", command.module, command.args.join(",")));
+                    command.reply.send("ok");
+                }
+            })
+            .unwrap();
+
+        let client = {
+            let path = path.clone();
+            thread::spawn(move || send_at(&path, &["timer".into(), "status".into(), "now".into()]))
+        };
+        while !client.is_finished() {
+            event_loop
+                .dispatch(Duration::from_millis(10), &mut ())
+                .unwrap();
+        }
+        assert_eq!(client.join().unwrap().unwrap(), "timer: status,now");
+
+        drop(socket);
+        assert!(!path.exists());
+    }
+}
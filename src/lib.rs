@@ -0,0 +1,26 @@
+//! hypr-notch: a notch-style status bar for Wayland compositors
+//!
+//! The binary in `main.rs` sets up the Wayland connection and event loop;
+//! everything else lives here so benchmarks and integration tests can use
+//! it directly.
+
+pub mod app;
+pub mod appearance;
+pub mod bluez;
+pub mod clipboard;
+pub mod color;
+pub mod config;
+pub mod draw;
+pub mod hyprland;
+pub mod ical;
+pub mod icons;
+pub mod image;
+pub mod ipc;
+pub mod module;
+pub mod modules;
+pub mod pointer;
+pub mod pulse;
+pub mod sni;
+pub mod theme;
+pub mod toplevel;
+pub mod wayland;
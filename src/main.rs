@@ -1,37 +1,93 @@
 // filepath: src/main.rs
-mod app;
-mod config;
-mod draw;
-mod module;
-mod modules;
-mod pointer;
-mod wayland;
+//! hypr-notch entry point: connects to Wayland and runs the event loop
 
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::time::Duration;
+use std::thread;
 
-use app::AppData;
-use calloop::{timer::TimeoutAction, timer::Timer, EventLoop};
+use calloop::{ping::make_ping, timer::TimeoutAction, timer::Timer, EventLoop, LoopSignal};
 use calloop_wayland_source::WaylandSource;
-use config::NotchConfig;
-use log::info;
+use hypr_notch::app::AppData;
+use hypr_notch::appearance::{self, ColorScheme};
+use hypr_notch::config::NotchConfig;
+use hypr_notch::hyprland::{self, HyprEvent};
+use hypr_notch::module::{self, ModuleEvent};
+use hypr_notch::{icons, ipc, theme};
+use log::{info, warn};
 use smithay_client_toolkit::{
     compositor::CompositorState,
     output::OutputState,
     registry::RegistryState,
     seat::SeatState,
-    shell::wlr_layer::{Layer, LayerShell},
+    shell::wlr_layer::LayerShell,
     shm::{slot::SlotPool, Shm},
 };
 use wayland_client::Connection;
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1;
+
+/// Block SIGINT and SIGTERM in this thread and every thread started after
+/// it, so they can be waited for instead of killing the process
+fn block_termination_signals() -> libc::sigset_t {
+    // SAFETY: the set is initialised by sigemptyset before it is used
+    unsafe {
+        let mut signals = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGINT);
+        libc::sigaddset(&mut signals, libc::SIGTERM);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+        signals
+    }
+}
+
+/// Stop the event loop when one of the blocked `signals` arrives, so
+/// `main` returns and cleans up
+fn stop_on_signals(signals: libc::sigset_t, event_loop: LoopSignal) {
+    let spawned = thread::Builder::new()
+        .name("signals".to_string())
+        .spawn(move || {
+            let mut signal = 0;
+            // SAFETY: `signals` is a valid set and blocked in every thread
+            if unsafe { libc::sigwait(&signals, &mut signal) } == 0 {
+                info!("Received signal {}, shutting down", signal);
+                event_loop.stop();
+                event_loop.wakeup();
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start the signal thread: {}", e);
+    }
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // `hypr-notch msg MODULE ARGS...` talks to the running instance
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "msg") {
+        return ipc::run_client(&args[1..]);
+    }
+
+    // Before any other thread starts, so they all leave the signals to us
+    let signals = block_termination_signals();
+
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug")).init();
     info!("Starting hypr-notch (minimal modular)");
 
     let config = NotchConfig::load_from_file().unwrap_or_default();
     info!("Configuration loaded");
+    theme::set_current(theme::Theme::from_config(&config.theme));
+    icons::set_theme(config.icon_theme.clone());
+    if config.theme.mode == theme::ThemeMode::System {
+        let theme_config = config.theme.clone();
+        appearance::spawn_listener(move |scheme| {
+            info!("System colour scheme is now {:?}", scheme);
+            let theme = match scheme {
+                ColorScheme::Dark => theme::Theme::for_dark_mode(&theme_config, true),
+                ColorScheme::Light => theme::Theme::for_dark_mode(&theme_config, false),
+                ColorScheme::NoPreference => theme::Theme::from_config(&theme_config),
+            };
+            theme::set_current(theme);
+            module::request_update();
+        });
+    }
 
     // Set up Wayland connection and event queue
     let conn = Connection::connect_to_env()?;
@@ -54,8 +110,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let pool = SlotPool::new(pool_size, &shm)?;
 
     let surface = compositor.create_surface(&qh);
-    let layer_surface =
-        layer_shell.create_layer_surface(&qh, surface, Layer::Top, Some("hypr-notch"), None);
+    let layer_surface = layer_shell.create_layer_surface(
+        &qh,
+        surface,
+        config.layer.to_layer(),
+        Some(config.namespace.clone()),
+        None,
+    );
 
     // Now create your AppData instance
     let app_data = Rc::new(RefCell::new(AppData::new(
@@ -69,7 +130,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         config,
         &conn,
     )));
+
+    // Watch for fullscreen windows; without this protocol the notch falls
+    // back to Hyprland's fullscreen event
+    match global_list.bind::<ZwlrForeignToplevelManagerV1, _, _>(&qh, 1..=3, ()) {
+        Ok(manager) => app_data.borrow_mut().set_toplevel_manager(manager),
+        Err(e) => info!("Foreign toplevel management not available: {}", e),
+    }
+
     let mut event_loop = EventLoop::try_new()?;
+    stop_on_signals(signals, event_loop.get_signal());
 
     // Register Wayland event queue as a source
     {
@@ -88,19 +158,98 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Register a timer for periodic updates
     {
         let app_data = app_data.clone();
-        let timer = Timer::from_duration(Duration::from_secs(1));
+        let timer = Timer::from_duration(module::UPDATE_INTERVAL);
         event_loop.handle().insert_source(timer, move |_, _, _| {
             let mut app = app_data.borrow_mut();
             app.update_modules();
-            if app.is_configured() && app.buffer_drawn && app.expanded {
+            if app.is_configured() && app.buffer_drawn {
                 let _ = app.draw();
             }
-            TimeoutAction::ToDuration(Duration::from_secs(1))
+            TimeoutAction::ToDuration(module::UPDATE_INTERVAL)
         })?;
     }
 
+    // Let modules wake the loop from background threads (e.g. D-Bus events)
+    {
+        let (ping, ping_source) = make_ping()?;
+        module::set_waker(ping);
+        let app_data = app_data.clone();
+        event_loop
+            .handle()
+            .insert_source(ping_source, move |_, _, _| {
+                let mut app = app_data.borrow_mut();
+                app.update_modules();
+                if app.is_configured() && app.buffer_drawn {
+                    let _ = app.draw();
+                }
+            })?;
+    }
+
+    // Forward Hyprland events to modules when running under Hyprland
+    if let Some(socket_dir) = hyprland::socket_dir() {
+        let (sender, channel) = calloop::channel::channel();
+        hyprland::spawn_event_listener(socket_dir, sender);
+        let app_data = app_data.clone();
+        event_loop
+            .handle()
+            .insert_source(channel, move |event, _, _| {
+                if let calloop::channel::Event::Msg(event) = event {
+                    let mut app = app_data.borrow_mut();
+                    match &event {
+                        HyprEvent::Fullscreen(fullscreen) => {
+                            app.set_hyprland_fullscreen(*fullscreen)
+                        }
+                        HyprEvent::FocusedMonitor(monitor) => app.set_hyprland_monitor(monitor),
+                        _ => {}
+                    }
+                    if app.handle_module_event(&ModuleEvent::Hyprland(event))
+                        && app.is_configured()
+                        && app.buffer_drawn
+                    {
+                        let _ = app.draw();
+                    }
+                }
+            })?;
+    }
+
+    // Pass commands from the control socket on to modules
+    let mut socket = None;
+    if let Some(socket_path) = ipc::socket_path() {
+        let (sender, channel) = calloop::channel::channel();
+        socket = ipc::spawn_listener(socket_path, sender);
+        let app_data = app_data.clone();
+        event_loop
+            .handle()
+            .insert_source(channel, move |event, _, _| {
+                if let calloop::channel::Event::Msg(command) = event {
+                    let mut app = app_data.borrow_mut();
+                    let event = ModuleEvent::Command {
+                        module: command.module.clone(),
+                        args: command.args.clone(),
+                        reply: command.reply.clone(),
+                    };
+                    if app.handle_module_event(&event) {
+                        command.reply.send("ok");
+                    } else {
+                        command.reply.send(format!(
+                            "error: {} did not accept \"{}\"",
+                            command.module,
+                            command.args.join(" ")
+                        ));
+                    }
+                    // A command may ask for the notch to expand
+                    app.update_modules();
+                    if app.is_configured() && app.buffer_drawn {
+                        let _ = app.draw();
+                    }
+                }
+            })?;
+    }
+
     info!("Entering event loop");
     event_loop.run(None, &mut (), |_| {})?;
+    info!("Event loop stopped");
+    drop(socket);
 
     Ok(())
 }
@@ -3,10 +3,35 @@
 //!
 //! This file defines the core traits and types that all modules must implement.
 
+use calloop::ping::Ping;
 use std::any::Any;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// How often the main loop sends `ModuleEvent::Update`. Modules that poll
+/// can't sample more often than this.
+pub const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+static WAKER: OnceLock<Ping> = OnceLock::new();
+
+/// Install the handle modules use to wake the main loop
+pub fn set_waker(ping: Ping) {
+    let _ = WAKER.set(ping);
+}
+
+/// Ask the main loop to update and redraw modules now instead of waiting
+/// for the next timer tick. Can be called from any thread.
+pub fn request_update() {
+    if let Some(ping) = WAKER.get() {
+        ping.ping();
+    }
+}
+
+/// Touchpad scroll distance that counts as one wheel click
+const SCROLL_PIXELS_PER_CLICK: f64 = 15.0;
 
 /// Rectangle used for layout
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct Rect {
     pub x: i32,
     pub y: i32,
@@ -14,6 +39,30 @@ pub struct Rect {
     pub height: u32,
 }
 
+impl Rect {
+    /// Whether a point in surface coordinates lies inside this rectangle
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x as f64
+            && y >= self.y as f64
+            && x < (self.x + self.width as i32) as f64
+            && y < (self.y + self.height as i32) as f64
+    }
+
+    /// Overlap of two rectangles, empty (zero-sized) when they don't meet
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width as i32).min(other.x + other.width as i32);
+        let bottom = (self.y + self.height as i32).min(other.y + other.height as i32);
+        Rect {
+            x,
+            y,
+            width: (right - x).max(0) as u32,
+            height: (bottom - y).max(0) as u32,
+        }
+    }
+}
+
 /// Events that can be sent to modules
 #[derive(Debug, Clone)]
 pub enum ModuleEvent {
@@ -46,6 +95,27 @@ pub enum ModuleEvent {
         y: f64,
     },
 
+    /// Wheel or touchpad scrolled over the module area. `delta` is in
+    /// wheel clicks, positive when scrolling down.
+    Scroll {
+        x: f64,
+        y: f64,
+        delta: f64,
+    },
+
+    /// An event from the Hyprland event socket
+    Hyprland(crate::hyprland::HyprEvent),
+
+    /// A command from the control socket (`hypr-notch msg MODULE ARGS...`).
+    /// Every module sees it; only the one whose id is `module` should act
+    /// on it and return true. The client is told `ok` unless the module
+    /// answers through `reply` first.
+    Command {
+        module: String,
+        args: Vec<String>,
+        reply: crate::ipc::Reply,
+    },
+
     /// Module should update its state (e.g., clock tick)
     Update,
     UpdateExpanded,
@@ -83,6 +153,34 @@ pub trait Module: Send + Sync {
     /// Get the preferred size of this module
     fn preferred_size(&self) -> (u32, u32);
 
+    /// Draw the module's compact content while the notch is collapsed
+    fn draw_collapsed(
+        &self,
+        _canvas: &mut crate::draw::Canvas,
+        _area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Default implementation: nothing is shown when collapsed
+        Ok(())
+    }
+
+    /// Get the size of this module in the collapsed notch
+    /// Returns None if the module is hidden while collapsed
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        None
+    }
+
+    /// Whether this module wants the notch to expand on its own
+    /// (e.g. a low battery warning). Polled after every update.
+    fn wants_expand(&self) -> bool {
+        false
+    }
+
+    /// Whether this module reacts to clicks, so its area keeps accepting
+    /// input when the notch is in click-through mode
+    fn is_interactive(&self) -> bool {
+        false
+    }
+
     fn as_any(&self) -> &dyn Any {
         // This is a workaround - in a real impl you'd return a reference to self
         // For now, just return a static empty value
@@ -129,6 +227,23 @@ pub fn convert_pointer_event(
             y: event.position.1,
         }),
 
+        PointerEventKind::Axis { vertical, .. } if !vertical.is_none() => {
+            // Prefer the high-resolution wheel value, then whole clicks,
+            // and fall back to the distance for touchpads
+            let delta = if vertical.value120 != 0 {
+                vertical.value120 as f64 / 120.0
+            } else if vertical.discrete != 0 {
+                vertical.discrete as f64
+            } else {
+                vertical.absolute / SCROLL_PIXELS_PER_CLICK
+            };
+            Some(ModuleEvent::Scroll {
+                x: event.position.0,
+                y: event.position.1,
+                delta,
+            })
+        }
+
         _ => None,
     }
 }
@@ -7,7 +7,7 @@
 pub mod interface;
 mod registry;
 
-pub use interface::{Module, ModuleEvent, Rect};
+pub use interface::{request_update, set_waker, Module, ModuleEvent, Rect, UPDATE_INTERVAL};
 pub use registry::ModuleRegistry;
 
 // Remove this unused import
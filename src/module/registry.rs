@@ -10,11 +10,104 @@ use std::collections::HashMap;
 use crate::config::NotchConfig;
 use crate::draw::Canvas;
 use crate::module::{Module, ModuleEvent, Rect};
+use crate::modules;
+
+/// Where a module is drawn: its area of the surface, and how far its
+/// content is scaled down to fit there
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    area: Rect,
+    scale: f32,
+}
+
+impl Placement {
+    /// Place content of the given size at `(x, y)`, scaled down if needed
+    /// to fit in `max_width` x `max_height`
+    fn fit(x: i32, y: i32, (width, height): (u32, u32), max_width: u32, max_height: u32) -> Self {
+        let scale = (max_width as f32 / width.max(1) as f32)
+            .min(max_height as f32 / height.max(1) as f32)
+            .min(1.0);
+        Self {
+            area: Rect {
+                x,
+                y,
+                width: (width as f32 * scale).round() as u32,
+                height: (height as f32 * scale).round() as u32,
+            },
+            scale,
+        }
+    }
+
+    /// The area a module sees: its own size, with `(0, 0)` at its top-left
+    fn local_area(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width: (self.area.width as f32 / self.scale).round() as u32,
+            height: (self.area.height as f32 / self.scale).round() as u32,
+        }
+    }
+
+    /// `event` with pointer positions moved into the module's coordinates
+    fn localize(&self, event: &ModuleEvent) -> ModuleEvent {
+        let local = |x: &f64, y: &f64| {
+            (
+                (x - self.area.x as f64) / self.scale as f64,
+                (y - self.area.y as f64) / self.scale as f64,
+            )
+        };
+        match event {
+            ModuleEvent::Enter { x, y } => {
+                let (x, y) = local(x, y);
+                ModuleEvent::Enter { x, y }
+            }
+            ModuleEvent::Motion { x, y } => {
+                let (x, y) = local(x, y);
+                ModuleEvent::Motion { x, y }
+            }
+            ModuleEvent::Press { button, x, y } => {
+                let (x, y) = local(x, y);
+                ModuleEvent::Press {
+                    button: *button,
+                    x,
+                    y,
+                }
+            }
+            ModuleEvent::Release { button, x, y } => {
+                let (x, y) = local(x, y);
+                ModuleEvent::Release {
+                    button: *button,
+                    x,
+                    y,
+                }
+            }
+            ModuleEvent::Scroll { x, y, delta } => {
+                let (x, y) = local(x, y);
+                ModuleEvent::Scroll {
+                    x,
+                    y,
+                    delta: *delta,
+                }
+            }
+            event => event.clone(),
+        }
+    }
+}
 
 /// Manages the collection of loaded modules
 pub struct ModuleRegistry {
     modules: Vec<Box<dyn Module>>,
-    module_areas: HashMap<String, Rect>,
+    module_areas: HashMap<String, Placement>,
+    collapsed_areas: HashMap<String, Placement>,
+    /// Module that took the last button press; it keeps getting pointer
+    /// motion and the release, so drags work past the edge of its area
+    grab: Option<String>,
+}
+
+impl Default for ModuleRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ModuleRegistry {
@@ -23,6 +116,8 @@ impl ModuleRegistry {
         Self {
             modules: Vec::new(),
             module_areas: HashMap::new(),
+            collapsed_areas: HashMap::new(),
+            grab: None,
         }
     }
 
@@ -35,93 +130,196 @@ impl ModuleRegistry {
     /// Load modules based on configuration
     pub fn load_modules_from_config(
         &mut self,
-        _config: &NotchConfig,
+        config: &NotchConfig,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        // Will implement later when we have modules to load
+        let empty = toml::Table::new();
+
+        for name in &config.modules.enabled {
+            let Some(mut module) = modules::create_module(name) else {
+                error!("Unknown module in config: {}", name);
+                continue;
+            };
+
+            let module_config = config.modules.module_configs.get(name).unwrap_or(&empty);
+            if let Err(e) = module.init(module_config) {
+                error!("Failed to initialize module {}: {}", name, e);
+                continue;
+            }
+
+            self.add_module(module);
+        }
+
         Ok(())
     }
 
-    /// Calculate the layout of all modules based on available space
-    pub fn calculate_layout(&mut self, total_width: u32, _total_height: u32) {
+    /// Calculate the layout of all modules in the expanded notch,
+    /// which occupies `notch` within the surface
+    pub fn calculate_layout(&mut self, notch: Rect) {
+        let total_width = notch.width;
         // Simple layout: stack modules vertically with margins
         let margin = 10i32; // Change to i32
-        let mut y_offset = margin;
+        let mut y_offset = notch.y + margin;
 
         for module in &self.modules {
-            let (width, height) = module.preferred_size();
-            let width = width.min(total_width - 2 * margin as u32);
-
-            self.module_areas.insert(
-                module.id().to_string(),
-                Rect {
-                    x: margin,
-                    y: y_offset,
-                    width,
-                    height,
-                },
+            // Modules wider than the notch are scaled down to fit
+            let placement = Placement::fit(
+                notch.x + margin,
+                y_offset,
+                module.preferred_size(),
+                total_width.saturating_sub(2 * margin as u32),
+                u32::MAX,
             );
-
-            y_offset += height as i32 + margin; // Now both are i32
+            y_offset += placement.area.height as i32 + margin; // Now both are i32
+            self.module_areas.insert(module.id().to_string(), placement);
         }
     }
 
-    /// Draw all modules to the canvas
-    pub fn draw(&mut self, canvas: &mut Canvas) {
-        // Calculate layout if not already done
-        if self.module_areas.is_empty() && !self.modules.is_empty() {
-            self.calculate_layout(canvas.width(), canvas.height());
+    /// Calculate the layout of modules shown in the collapsed notch,
+    /// which occupies `notch` within the surface
+    pub fn calculate_collapsed_layout(&mut self, notch: Rect) {
+        let (total_width, total_height) = (notch.width, notch.height);
+        // Simple layout: place modules side by side, centered horizontally
+        let spacing = 8i32;
+        // Modules taller than the notch are scaled down to fit
+        let placements: Vec<(String, Placement)> = self
+            .modules
+            .iter()
+            .filter_map(|m| {
+                let size = m.collapsed_size()?;
+                let placement = Placement::fit(0, 0, size, u32::MAX, total_height);
+                Some((m.id().to_string(), placement))
+            })
+            .collect();
+
+        let content_width: i32 = placements
+            .iter()
+            .map(|(_, p)| p.area.width as i32)
+            .sum::<i32>()
+            + spacing * (placements.len() as i32 - 1).max(0);
+        let mut x_offset = notch.x + ((total_width as i32 - content_width) / 2).max(0);
+
+        self.collapsed_areas.clear();
+        for (id, mut placement) in placements {
+            placement.area.x = x_offset;
+            placement.area.y = notch.y + (total_height as i32 - placement.area.height as i32) / 2;
+            x_offset += placement.area.width as i32 + spacing;
+            self.collapsed_areas.insert(id, placement);
         }
+    }
+
+    /// Draw all modules to the expanded notch at `notch`
+    pub fn draw(&mut self, canvas: &mut Canvas, notch: Rect) {
+        // Module sizes may change with their content, so lay out every frame
+        self.calculate_layout(notch);
 
-        // Draw each module in its area
+        // Draw each module into a view of its own area
         for module in &self.modules {
-            if let Some(area) = self.module_areas.get(module.id()) {
-                if let Err(e) = module.draw(canvas, *area) {
+            if let Some(placement) = self.module_areas.get(module.id()) {
+                let mut view = canvas.view(placement.area, placement.scale);
+                if let Err(e) = module.draw(&mut view, placement.local_area()) {
                     error!("Error drawing module {}: {}", module.name(), e);
                 }
             }
         }
     }
 
+    /// Draw the compact representation of modules in the collapsed notch
+    pub fn draw_collapsed(&mut self, canvas: &mut Canvas, notch: Rect) {
+        // Collapsed sizes may change with module state, so lay out every frame
+        self.calculate_collapsed_layout(notch);
+
+        for module in &self.modules {
+            if let Some(placement) = self.collapsed_areas.get(module.id()) {
+                let mut view = canvas.view(placement.area, placement.scale);
+                if let Err(e) = module.draw_collapsed(&mut view, placement.local_area()) {
+                    error!("Error drawing collapsed module {}: {}", module.name(), e);
+                }
+            }
+        }
+    }
+
     /// Send an event to the appropriate module
     pub fn handle_event(&mut self, event: &ModuleEvent) -> bool {
         // For Enter/Motion/Press events, find which module contains the point
         log::debug!("ModuleRegistry::handle_event: received event {:?}", event);
 
+        // The pointer left the notch, so no release is coming
+        if let ModuleEvent::Leave = event {
+            self.grab = None;
+        }
+
         match event {
             ModuleEvent::Enter { x, y }
             | ModuleEvent::Motion { x, y }
             | ModuleEvent::Press { x, y, .. }
-            | ModuleEvent::Release { x, y, .. } => {
-                // Find module that contains this point
+            | ModuleEvent::Release { x, y, .. }
+            | ModuleEvent::Scroll { x, y, .. } => {
+                // While a button is held, the module it was pressed on gets
+                // the motion and the release wherever the pointer is
+                let grabbed = match event {
+                    ModuleEvent::Motion { .. } | ModuleEvent::Release { .. } => self.grab.clone(),
+                    _ => None,
+                };
+                if let ModuleEvent::Release { .. } = event {
+                    self.grab = None;
+                }
+
                 for module in &mut self.modules {
-                    if let Some(area) = self.module_areas.get(module.id()) {
-                        if *x >= area.x as f64
-                            && *y >= area.y as f64
-                            && *x < (area.x + area.width as i32) as f64
-                            && *y < (area.y + area.height as i32) as f64
-                        {
-                            // Point is within this module's area
-                            return module.handle_event(event, *area);
+                    let Some(placement) = self.module_areas.get(module.id()) else {
+                        continue;
+                    };
+                    let target = match &grabbed {
+                        Some(id) => id == module.id(),
+                        // Find module that contains this point
+                        None => placement.area.contains(*x, *y),
+                    };
+                    if target {
+                        if let ModuleEvent::Press { .. } = event {
+                            self.grab = Some(module.id().to_string());
                         }
+                        return module
+                            .handle_event(&placement.localize(event), placement.local_area());
                     }
                 }
             }
 
-            // For other events, send to all modules
+            // For other events, send to all modules so every module gets its update
             _ => {
+                let mut handled = false;
                 for module in &mut self.modules {
-                    if let Some(area) = self.module_areas.get(module.id()) {
-                        if module.handle_event(event, *area) {
-                            return true;
-                        }
-                    }
+                    let area = self
+                        .module_areas
+                        .get(module.id())
+                        .map(Placement::local_area)
+                        .unwrap_or_default();
+                    handled |= module.handle_event(event, area);
                 }
+                return handled;
             }
         }
 
         false
     }
 
+    /// Areas of the modules that react to clicks, as laid out by the last draw
+    pub fn interactive_areas(&self, expanded: bool) -> Vec<Rect> {
+        let areas = if expanded {
+            &self.module_areas
+        } else {
+            &self.collapsed_areas
+        };
+        self.modules
+            .iter()
+            .filter(|m| m.is_interactive())
+            .filter_map(|m| areas.get(m.id()).map(|placement| placement.area))
+            .collect()
+    }
+
+    /// Whether any module is asking for the notch to be expanded
+    pub fn wants_expand(&self) -> bool {
+        self.modules.iter().any(|m| m.wants_expand())
+    }
+
     /// Update the Canvas structure to prepare for module implementation
     pub fn has_modules(&self) -> bool {
         !self.modules.is_empty()
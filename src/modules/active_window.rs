@@ -0,0 +1,239 @@
+//! Hyprland active window module for hypr-notch
+//!
+//! Shows the title of the focused window in the collapsed notch, and its
+//! class together with the current submap and keyboard layout when expanded.
+//! The window and layout at startup are read on a thread of their own, so
+//! a slow or missing compositor socket can't hold up the notch.
+
+use crate::config::color_from_config;
+use crate::draw::{truncate_text, Canvas};
+use crate::hyprland::{self, HyprEvent};
+use crate::module::{self, Module, ModuleEvent, Rect};
+use crate::theme::ThemeColor;
+use log::warn;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Reply of `j/activewindow`; empty when no window is focused
+#[derive(Debug, Default, Deserialize)]
+struct ActiveWindowInfo {
+    #[serde(default)]
+    class: String,
+    #[serde(default)]
+    title: String,
+}
+
+/// Reply of `j/devices`, reduced to the keyboards
+#[derive(Debug, Default, Deserialize)]
+struct DevicesInfo {
+    #[serde(default)]
+    keyboards: Vec<KeyboardInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyboardInfo {
+    active_keymap: String,
+    #[serde(default)]
+    main: bool,
+}
+
+/// What is shown, from the startup requests and Hyprland's events
+#[derive(Debug, Default)]
+struct ActiveWindowState {
+    class: String,
+    title: String,
+    submap: String,
+    layout: String,
+    /// An event has set the window, which the startup request mustn't undo
+    window_known: bool,
+    /// Likewise for the keyboard layout
+    layout_known: bool,
+}
+
+/// Read the focused window and keyboard layout from Hyprland, keeping
+/// whatever events have already reported
+fn refresh(state: &Mutex<ActiveWindowState>) {
+    let window = hyprland::request_json::<ActiveWindowInfo>("activewindow")
+        .map_err(|e| warn!("ActiveWindowModule: failed to get active window: {}", e));
+    let devices = hyprland::request_json::<DevicesInfo>("devices")
+        .map_err(|e| warn!("ActiveWindowModule: failed to list devices: {}", e));
+
+    let mut state = state.lock().unwrap();
+    if let Ok(window) = window {
+        if !state.window_known {
+            state.class = window.class;
+            state.title = window.title;
+        }
+    }
+    if let Ok(devices) = devices {
+        let keyboard = devices
+            .keyboards
+            .iter()
+            .find(|k| k.main)
+            .or(devices.keyboards.first());
+        if let Some(keyboard) = keyboard {
+            if !state.layout_known {
+                state.layout = keyboard.active_keymap.clone();
+            }
+        }
+    }
+}
+
+pub struct ActiveWindowModule {
+    id: String,
+    name: String,
+    state: Arc<Mutex<ActiveWindowState>>,
+    color: ThemeColor,
+    secondary_color: ThemeColor,
+    font_size: f32,
+    collapsed_width: u32,
+}
+
+impl Default for ActiveWindowModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ActiveWindowModule {
+    pub fn new() -> Self {
+        Self {
+            id: "active_window".to_string(),
+            name: "Active Window".to_string(),
+            state: Arc::new(Mutex::new(ActiveWindowState::default())),
+            color: ThemeColor::named("foreground"),
+            secondary_color: ThemeColor::named("muted"),
+            font_size: 14.0,
+            collapsed_width: 160,
+        }
+    }
+}
+
+impl Module for ActiveWindowModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "secondary_color") {
+            self.secondary_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+        if let Some(width) = config.get("collapsed_width").and_then(|v| v.as_integer()) {
+            self.collapsed_width = width.max(20) as u32;
+        }
+
+        let state = self.state.clone();
+        thread::Builder::new()
+            .name("active-window".to_string())
+            .spawn(move || {
+                refresh(&state);
+                module::request_update();
+            })?;
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let secondary_color = self.secondary_color.resolve(canvas.theme());
+        let line_height = self.font_size as i32 + 6;
+        let small_font = self.font_size * 0.85;
+        let max_width = area.width.saturating_sub(20);
+        let state = self.state.lock().unwrap();
+
+        canvas.draw_text(
+            area.x + 10,
+            area.y,
+            &truncate_text(&state.class, small_font, max_width),
+            secondary_color,
+            small_font,
+        );
+        canvas.draw_text(
+            area.x + 10,
+            area.y + line_height,
+            &truncate_text(&state.title, self.font_size, max_width),
+            color,
+            self.font_size,
+        );
+
+        let mut status = Vec::new();
+        if !state.submap.is_empty() {
+            status.push(format!("Submap: {}", state.submap));
+        }
+        if !state.layout.is_empty() {
+            status.push(format!("Layout: {}", state.layout));
+        }
+        canvas.draw_text(
+            area.x + 10,
+            area.y + 2 * line_height,
+            &status.join("   "),
+            secondary_color,
+            small_font,
+        );
+
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let state = self.state.lock().unwrap();
+        // Show the submap in place of the title so a modal binding is obvious
+        let text = if state.submap.is_empty() {
+            truncate_text(&state.title, self.font_size, area.width)
+        } else {
+            format!("[{}]", state.submap)
+        };
+        let y_pos = area.y + ((area.height as i32 - self.font_size as i32) / 2);
+        canvas.draw_text(area.x, y_pos, &text, color, self.font_size);
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, _area: Rect) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match event {
+            ModuleEvent::Hyprland(HyprEvent::ActiveWindow { class, title }) => {
+                state.class = class.clone();
+                state.title = title.clone();
+                state.window_known = true;
+                true
+            }
+            ModuleEvent::Hyprland(HyprEvent::Submap(submap)) => {
+                state.submap = submap.clone();
+                true
+            }
+            ModuleEvent::Hyprland(HyprEvent::ActiveLayout { layout, .. }) => {
+                state.layout = layout.clone();
+                state.layout_known = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        (500, 3 * (self.font_size as u32 + 6))
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        let state = self.state.lock().unwrap();
+        if state.title.is_empty() && state.submap.is_empty() {
+            None
+        } else {
+            Some((self.collapsed_width, 20))
+        }
+    }
+}
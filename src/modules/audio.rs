@@ -0,0 +1,598 @@
+//! Audio module for hypr-notch
+//!
+//! Follows the volume and mute state of the default sink and source over
+//! the PulseAudio native protocol, which `pipewire-pulse` serves as well.
+//! The collapsed notch shows the output volume, and the notch expands
+//! briefly as an on-screen display whenever it changes. Scrolling over a
+//! device changes its volume and clicking it toggles mute.
+
+use crate::config::color_from_config;
+use crate::draw::{self, Canvas};
+use crate::module::{self, Module, ModuleEvent, Rect};
+use crate::pulse::{
+    Connection, Device, DeviceInfo, Facility, SUBSCRIBE_SERVER, SUBSCRIBE_SINK, SUBSCRIBE_SOURCE,
+};
+use crate::theme::ThemeColor;
+use log::{debug, info, warn};
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Linux input event code for the left mouse button
+const BTN_LEFT: u32 = 0x110;
+
+/// How long the client thread waits for server events before checking
+/// for commands again
+const COMMAND_LATENCY: Duration = Duration::from_millis(50);
+
+/// Height of one device row in the expanded layout
+const ROW_HEIGHT: u32 = 36;
+
+/// Changes seen this soon after a click or scroll are taken to be our
+/// own, and don't bring up the on-screen display
+const OWN_CHANGE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Default sink and source as last read from the server
+#[derive(Debug, Clone, Default)]
+struct AudioState {
+    sink: Option<DeviceInfo>,
+    source: Option<DeviceInfo>,
+}
+
+impl AudioState {
+    fn device(&self, device: Device) -> Option<&DeviceInfo> {
+        match device {
+            Device::Sink => self.sink.as_ref(),
+            Device::Source => self.source.as_ref(),
+        }
+    }
+
+    fn device_mut(&mut self, device: Device) -> Option<&mut DeviceInfo> {
+        match device {
+            Device::Sink => self.sink.as_mut(),
+            Device::Source => self.source.as_mut(),
+        }
+    }
+}
+
+/// Requests from the module to the client thread
+#[derive(Debug, Clone, Copy)]
+enum AudioCommand {
+    /// Set the loudest channel to a percentage, keeping the balance
+    SetVolume(Device, f64),
+    SetMute(Device, bool),
+}
+
+fn run_client(state: Arc<Mutex<Option<AudioState>>>, commands: Receiver<AudioCommand>) {
+    loop {
+        match Connection::connect("hypr-notch") {
+            Ok(mut conn) => {
+                info!("AudioModule: connected to the sound server");
+                let result = serve(&mut conn, &state, &commands);
+                *state.lock().unwrap() = None;
+                module::request_update();
+                match result {
+                    Ok(()) => return, // The module was dropped
+                    Err(e) => warn!("AudioModule: lost the sound server: {}", e),
+                }
+            }
+            Err(e) => debug!("AudioModule: could not connect to the sound server: {}", e),
+        }
+        thread::sleep(Duration::from_secs(5));
+    }
+}
+
+/// Read both default devices. A missing source (no microphone) isn't an
+/// error; a missing sink is.
+fn read_state(conn: &mut Connection) -> io::Result<AudioState> {
+    Ok(AudioState {
+        sink: Some(conn.device_info(Device::Sink)?),
+        source: conn
+            .device_info(Device::Source)
+            .map_err(|e| debug!("AudioModule: no default source: {}", e))
+            .ok(),
+    })
+}
+
+fn serve(
+    conn: &mut Connection,
+    state: &Mutex<Option<AudioState>>,
+    commands: &Receiver<AudioCommand>,
+) -> io::Result<()> {
+    // Server events cover changes of the default devices
+    conn.subscribe(SUBSCRIBE_SINK | SUBSCRIBE_SOURCE | SUBSCRIBE_SERVER)?;
+    let mut current = read_state(conn)?;
+    publish(state, &current);
+
+    loop {
+        let mut applied = false;
+        loop {
+            let command = match commands.try_recv() {
+                Ok(command) => command,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            };
+            match apply(conn, &current, command) {
+                Ok(()) => {}
+                // The server refused, which leaves the connection usable
+                Err(e) if e.kind() == io::ErrorKind::Other => {
+                    warn!("AudioModule: {:?} failed: {}", command, e)
+                }
+                Err(e) => return Err(e),
+            }
+            applied = true;
+        }
+        // The module shows its commands before they are applied; replace
+        // that with what the server made of them, even when nothing changed
+        if applied {
+            current = read_state(conn)?;
+            publish(state, &current);
+        }
+
+        let Some(event) = conn.next_event(COMMAND_LATENCY)? else {
+            continue;
+        };
+        if !matches!(
+            event.facility,
+            Facility::Sink | Facility::Source | Facility::Server
+        ) {
+            continue;
+        }
+        let fresh = read_state(conn)?;
+        if fresh.sink != current.sink || fresh.source != current.source {
+            current = fresh;
+            publish(state, &current);
+        }
+    }
+}
+
+fn publish(state: &Mutex<Option<AudioState>>, current: &AudioState) {
+    *state.lock().unwrap() = Some(current.clone());
+    module::request_update();
+}
+
+fn apply(conn: &mut Connection, current: &AudioState, command: AudioCommand) -> io::Result<()> {
+    match command {
+        AudioCommand::SetVolume(device, percent) => {
+            if let Some(info) = current.device(device) {
+                conn.set_volume(device, info.index, &info.volume_at(percent))?;
+            }
+        }
+        AudioCommand::SetMute(device, muted) => {
+            if let Some(info) = current.device(device) {
+                conn.set_mute(device, info.index, muted)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Speaker glyph with sound waves for the volume, or a cross when muted
+fn draw_speaker(canvas: &mut Canvas, x: f32, cy: f32, percent: f64, muted: bool, color: [u8; 4]) {
+    let cone = draw::Path::polygon(&[
+        (x, cy - 3.0),
+        (x + 4.0, cy - 3.0),
+        (x + 9.0, cy - 7.0),
+        (x + 9.0, cy + 7.0),
+        (x + 4.0, cy + 3.0),
+        (x, cy + 3.0),
+    ]);
+    canvas.fill_path(&cone, color);
+
+    if muted {
+        canvas.draw_line(x + 12.0, cy - 4.0, x + 18.0, cy + 4.0, 1.5, color);
+        canvas.draw_line(x + 12.0, cy + 4.0, x + 18.0, cy - 4.0, 1.5, color);
+        return;
+    }
+    if percent > 0.0 {
+        canvas.draw_arc(x + 9.0, cy, 4.0, -45.0, 90.0, 1.5, color);
+    }
+    if percent > 50.0 {
+        canvas.draw_arc(x + 9.0, cy, 8.0, -45.0, 90.0, 1.5, color);
+    }
+}
+
+/// Microphone glyph, struck through when muted
+fn draw_microphone(canvas: &mut Canvas, x: f32, cy: f32, muted: bool, color: [u8; 4]) {
+    let capsule = Rect {
+        x: (x + 5.0) as i32,
+        y: (cy - 8.0) as i32,
+        width: 6,
+        height: 10,
+    };
+    canvas.fill_rounded_rect(capsule, 3.0, color);
+    canvas.draw_arc(x + 8.0, cy - 1.0, 6.0, 0.0, 180.0, 1.5, color);
+    canvas.draw_line(x + 8.0, cy + 5.0, x + 8.0, cy + 8.0, 1.5, color);
+
+    if muted {
+        canvas.draw_line(x + 1.0, cy - 8.0, x + 15.0, cy + 8.0, 1.5, color);
+    }
+}
+
+pub struct AudioModule {
+    id: String,
+    name: String,
+    state: Arc<Mutex<Option<AudioState>>>,
+    commands: Option<Sender<AudioCommand>>,
+    color: ThemeColor,
+    muted_color: ThemeColor,
+    track_color: ThemeColor,
+    fill_color: ThemeColor,
+    font_size: f32,
+    step: f64,
+    max_volume: f64,
+    auto_expand: bool,
+    osd_duration: Duration,
+    /// Output volume and mute state the last update saw
+    last_seen: Option<(u32, bool)>,
+    /// When we last changed the volume or mute state ourselves
+    set_at: Option<Instant>,
+    expand_until: Option<Instant>,
+}
+
+impl Default for AudioModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioModule {
+    pub fn new() -> Self {
+        Self {
+            id: "audio".to_string(),
+            name: "Audio".to_string(),
+            state: Arc::new(Mutex::new(None)),
+            commands: None,
+            color: ThemeColor::named("foreground"),
+            muted_color: ThemeColor::named("muted"),
+            track_color: ThemeColor::named("surface"),
+            fill_color: ThemeColor::named("accent"),
+            font_size: 14.0,
+            step: 5.0,
+            max_volume: 100.0,
+            auto_expand: true,
+            osd_duration: Duration::from_secs(2),
+            last_seen: None,
+            set_at: None,
+            expand_until: None,
+        }
+    }
+
+    fn audio(&self) -> Option<AudioState> {
+        self.state.lock().unwrap().clone()
+    }
+
+    fn send(&self, command: AudioCommand) {
+        if let Some(commands) = &self.commands {
+            if commands.send(command).is_err() {
+                warn!("AudioModule: sound server thread is gone");
+            }
+        }
+    }
+
+    /// Change a device in the shared state before the server does, so
+    /// a click or scroll shows right away
+    fn set_locally(&mut self, device: Device, change: impl FnOnce(&mut DeviceInfo)) {
+        if let Some(info) = self
+            .state
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|audio| audio.device_mut(device))
+        {
+            change(info);
+        }
+        self.set_at = Some(Instant::now());
+    }
+
+    /// Check for a change of the output volume or mute state, and open
+    /// the on-screen display if there was one that we didn't make
+    fn refresh(&mut self) -> bool {
+        let seen = self
+            .audio()
+            .and_then(|audio| audio.sink)
+            .map(|sink| (sink.percent().round() as u32, sink.muted));
+        if seen == self.last_seen {
+            return false;
+        }
+        let now = Instant::now();
+        // The first reading after connecting isn't a change
+        let external = self.last_seen.is_some()
+            && seen.is_some()
+            && self
+                .set_at
+                .is_none_or(|set_at| now.duration_since(set_at) >= OWN_CHANGE_WINDOW);
+        if external && self.auto_expand {
+            self.expand_until = Some(now + self.osd_duration);
+        }
+        self.last_seen = seen;
+        true
+    }
+
+    /// Rows of the expanded layout, one per available device
+    fn row_rects(&self, audio: &AudioState, area: Rect) -> Vec<(Device, Rect)> {
+        [Device::Sink, Device::Source]
+            .into_iter()
+            .filter(|device| audio.device(*device).is_some())
+            .enumerate()
+            .map(|(i, device)| {
+                let rect = Rect {
+                    x: area.x,
+                    y: area.y + (i as u32 * ROW_HEIGHT) as i32,
+                    width: area.width,
+                    height: ROW_HEIGHT,
+                };
+                (device, rect)
+            })
+            .collect()
+    }
+
+    fn device_at(&self, area: Rect, x: f64, y: f64) -> Option<(Device, DeviceInfo)> {
+        let audio = self.audio()?;
+        let (device, _) = self
+            .row_rects(&audio, area)
+            .into_iter()
+            .find(|(_, rect)| rect.contains(x, y))?;
+        Some((device, audio.device(device)?.clone()))
+    }
+
+    fn draw_row(&self, canvas: &mut Canvas, row: Rect, device: Device, info: &DeviceInfo) {
+        let color = if info.muted {
+            self.muted_color.resolve(canvas.theme())
+        } else {
+            self.color.resolve(canvas.theme())
+        };
+        let muted_color = self.muted_color.resolve(canvas.theme());
+        let track_color = self.track_color.resolve(canvas.theme());
+        let fill_color = self.fill_color.resolve(canvas.theme());
+        let percent = info.percent();
+
+        let cy = row.y as f32 + row.height as f32 / 2.0;
+        match device {
+            Device::Sink => {
+                draw_speaker(canvas, row.x as f32 + 10.0, cy, percent, info.muted, color)
+            }
+            Device::Source => draw_microphone(canvas, row.x as f32 + 10.0, cy, info.muted, color),
+        }
+
+        // Device name above the volume bar
+        let left = row.x + 40;
+        let bar_width = row.width.saturating_sub(110);
+        let small_font = self.font_size * 0.85;
+        let description = draw::truncate_text(&info.description, small_font, bar_width);
+        canvas.draw_text(left, row.y + 2, &description, muted_color, small_font);
+
+        let track = Rect {
+            x: left,
+            y: row.y + row.height as i32 - 10,
+            width: bar_width,
+            height: 5,
+        };
+        canvas.fill_rounded_rect(track, 2.5, track_color);
+        if !info.muted {
+            let fraction = (percent / self.max_volume.max(1.0)).min(1.0);
+            let fill = Rect {
+                width: (track.width as f64 * fraction) as u32,
+                ..track
+            };
+            canvas.fill_rounded_rect(fill, 2.5, fill_color);
+        }
+
+        let label = if info.muted {
+            "Muted".to_string()
+        } else {
+            format!("{:.0}%", percent)
+        };
+        let text_y = row.y + (row.height as i32 - self.font_size as i32) / 2;
+        canvas.draw_text(
+            track.x + track.width as i32 + 12,
+            text_y,
+            &label,
+            color,
+            self.font_size,
+        );
+    }
+}
+
+impl Module for AudioModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "muted_color") {
+            self.muted_color = color;
+        }
+        if let Some(color) = color_from_config(config, "track_color") {
+            self.track_color = color;
+        }
+        if let Some(color) = color_from_config(config, "fill_color") {
+            self.fill_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+        if let Some(step) = config.get("step").and_then(|v| v.as_integer()) {
+            self.step = step.clamp(1, 100) as f64;
+        }
+        if let Some(max) = config.get("max_volume").and_then(|v| v.as_integer()) {
+            self.max_volume = max.clamp(1, 150) as f64;
+        }
+        if let Some(auto_expand) = config.get("auto_expand").and_then(|v| v.as_bool()) {
+            self.auto_expand = auto_expand;
+        }
+        if let Some(secs) = config.get("osd_seconds").and_then(|v| v.as_float()) {
+            self.osd_duration = Duration::from_secs_f64(secs.max(0.0));
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let state = self.state.clone();
+        thread::Builder::new()
+            .name("audio".to_string())
+            .spawn(move || run_client(state, receiver))?;
+        self.commands = Some(sender);
+
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(audio) = self.audio() else {
+            let color = self.muted_color.resolve(canvas.theme());
+            canvas.draw_text(
+                area.x + 10,
+                area.y,
+                "No sound server",
+                color,
+                self.font_size,
+            );
+            return Ok(());
+        };
+
+        for (device, row) in self.row_rects(&audio, area) {
+            if let Some(info) = audio.device(device) {
+                self.draw_row(canvas, row, device, info);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(sink) = self.audio().and_then(|audio| audio.sink) else {
+            return Ok(());
+        };
+        let color = if sink.muted {
+            self.muted_color.resolve(canvas.theme())
+        } else {
+            self.color.resolve(canvas.theme())
+        };
+
+        let cy = area.y as f32 + area.height as f32 / 2.0;
+        draw_speaker(canvas, area.x as f32, cy, sink.percent(), sink.muted, color);
+
+        let text_y = area.y + (area.height as i32 - self.font_size as i32) / 2;
+        canvas.draw_text(
+            area.x + 22,
+            text_y,
+            &format!("{:.0}%", sink.percent()),
+            color,
+            self.font_size,
+        );
+
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, area: Rect) -> bool {
+        match event {
+            ModuleEvent::Press { button, x, y } if *button == BTN_LEFT => {
+                let Some((device, info)) = self.device_at(area, *x, *y) else {
+                    return false;
+                };
+                self.send(AudioCommand::SetMute(device, !info.muted));
+                self.set_locally(device, |info| info.muted = !info.muted);
+                true
+            }
+            ModuleEvent::Scroll { x, y, delta } => {
+                let Some((device, info)) = self.device_at(area, *x, *y) else {
+                    return false;
+                };
+                // Never raise past max_volume, but don't jump down to it
+                // when the volume was set higher elsewhere
+                let percent = (info.percent() - delta * self.step)
+                    .clamp(0.0, self.max_volume.max(info.percent()));
+                self.send(AudioCommand::SetVolume(device, percent));
+                self.set_locally(device, |info| info.volume = info.volume_at(percent));
+                true
+            }
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                self.refresh()
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        let rows = self
+            .audio()
+            .map(|audio| audio.source.is_some() as u32 + 1)
+            .unwrap_or(1);
+        (400, rows * ROW_HEIGHT)
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        self.state
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|audio| audio.sink.as_ref())
+            .map(|_| (70, 20))
+    }
+
+    fn wants_expand(&self) -> bool {
+        self.expand_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pulse::VOLUME_NORM;
+
+    fn sink(percent: u32, muted: bool) -> AudioState {
+        AudioState {
+            sink: Some(DeviceInfo {
+                index: 1,
+                name: "speakers".to_string(),
+                description: "Speakers".to_string(),
+                volume: vec![VOLUME_NORM * percent / 100; 2],
+                muted,
+            }),
+            source: None,
+        }
+    }
+
+    #[test]
+    fn only_outside_changes_open_the_osd() {
+        let mut audio = AudioModule::new();
+        *audio.state.lock().unwrap() = Some(sink(50, false));
+        // Connecting isn't a change
+        assert!(audio.refresh());
+        assert!(!audio.wants_expand());
+        assert!(!audio.refresh());
+
+        // A scroll over the sink
+        audio.set_locally(Device::Sink, |info| info.volume = info.volume_at(55.0));
+        assert!(audio.refresh());
+        assert!(!audio.wants_expand());
+
+        // The server refused the change, and the thread put the real
+        // state back
+        *audio.state.lock().unwrap() = Some(sink(50, false));
+        assert!(audio.refresh());
+        assert!(!audio.wants_expand());
+
+        // Muted elsewhere, long enough after our own change
+        audio.set_at = Some(Instant::now() - OWN_CHANGE_WINDOW);
+        *audio.state.lock().unwrap() = Some(sink(50, true));
+        assert!(audio.refresh());
+        assert!(audio.wants_expand());
+    }
+}
@@ -0,0 +1,749 @@
+//! Battery module for hypr-notch
+//!
+//! Reads battery state from the kernel's power_supply class in sysfs
+//! (`/sys/class/power_supply/*`) and shows a battery glyph in the
+//! collapsed notch and detailed per-battery stats when expanded.
+
+use crate::config::color_from_config;
+use crate::draw::{self, Canvas};
+use crate::module::{Module, ModuleEvent, Rect};
+use crate::theme::ThemeColor;
+use log::{debug, info};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const DEFAULT_SYSFS_PATH: &str = "/sys/class/power_supply";
+
+/// Charging state as reported by the `status` attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatteryStatus {
+    Charging,
+    Discharging,
+    NotCharging,
+    Full,
+    Unknown,
+}
+
+impl BatteryStatus {
+    fn parse(status: &str) -> Self {
+        match status.trim() {
+            "Charging" => Self::Charging,
+            "Discharging" => Self::Discharging,
+            "Not charging" => Self::NotCharging,
+            "Full" => Self::Full,
+            _ => Self::Unknown,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Charging => "Charging",
+            Self::Discharging => "Discharging",
+            Self::NotCharging => "Not charging",
+            Self::Full => "Full",
+            Self::Unknown => "Unknown",
+        }
+    }
+}
+
+/// State of a single battery read from sysfs
+#[derive(Debug, Clone)]
+pub struct BatteryInfo {
+    pub name: String,
+    pub capacity: u8,
+    pub status: BatteryStatus,
+    /// Remaining energy, in µWh (or µAh when only charge_* is exposed)
+    pub energy_now: Option<u64>,
+    /// Energy when full, in the same unit as `energy_now`
+    pub energy_full: Option<u64>,
+    /// Whether `energy_now` and `energy_full` are charges in µAh rather
+    /// than energies
+    pub energy_is_charge: bool,
+    /// Current draw, in µW (or µA when only current_now is exposed)
+    pub power_now: Option<u64>,
+    /// Whether `power_now` is a current in µA rather than a power
+    pub power_is_current: bool,
+    /// Voltage, in µV
+    pub voltage_now: Option<u64>,
+}
+
+impl BatteryInfo {
+    /// Estimated time until empty (discharging) or full (charging)
+    pub fn time_remaining(&self) -> Option<Duration> {
+        let power = self.power_now.filter(|p| *p > 0)? as f64;
+        // Bring the rate to the unit of the energy: µW for µWh, µA for µAh
+        let power = match (self.energy_is_charge, self.power_is_current) {
+            (false, true) => power * self.voltage_now? as f64 / 1e6,
+            (true, false) => power / self.voltage_now.filter(|v| *v > 0)? as f64 * 1e6,
+            _ => power,
+        };
+        let energy = match self.status {
+            BatteryStatus::Discharging => self.energy_now?,
+            BatteryStatus::Charging => self.energy_full?.saturating_sub(self.energy_now?),
+            _ => return None,
+        };
+        Some(Duration::from_secs_f64(energy as f64 / power * 3600.0))
+    }
+
+    /// Power draw in watts, worked out from the voltage when the battery
+    /// only reports a current
+    pub fn watts(&self) -> Option<f64> {
+        let power = self.power_now.filter(|p| *p > 0)? as f64;
+        if self.power_is_current {
+            Some(power * self.voltage_now? as f64 / 1e12)
+        } else {
+            Some(power / 1e6)
+        }
+    }
+}
+
+fn read_attr(dir: &Path, attr: &str) -> Option<String> {
+    fs::read_to_string(dir.join(attr))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+fn read_u64(dir: &Path, attr: &str) -> Option<u64> {
+    read_attr(dir, attr)?.parse().ok()
+}
+
+/// Read a single power_supply directory, returning None if it isn't a battery
+pub fn read_battery(dir: &Path) -> Option<BatteryInfo> {
+    if read_attr(dir, "type")? != "Battery" {
+        return None;
+    }
+    // Peripheral batteries (mice, headsets) report scope=Device
+    if read_attr(dir, "scope").as_deref() == Some("Device") {
+        return None;
+    }
+    if read_attr(dir, "present").as_deref() == Some("0") {
+        return None;
+    }
+
+    let energy = read_u64(dir, "energy_now");
+    let energy_is_charge = energy.is_none();
+    let (energy_now, energy_full) = if energy_is_charge {
+        (read_u64(dir, "charge_now"), read_u64(dir, "charge_full"))
+    } else {
+        (energy, read_u64(dir, "energy_full"))
+    };
+    let power = read_u64(dir, "power_now");
+    let power_is_current = power.is_none();
+    let power_now = power.or_else(|| read_u64(dir, "current_now"));
+
+    let capacity = read_u64(dir, "capacity")
+        .or_else(|| match (energy_now, energy_full) {
+            (Some(now), Some(full)) if full > 0 => Some(now * 100 / full),
+            _ => None,
+        })?
+        .min(100) as u8;
+
+    Some(BatteryInfo {
+        name: dir.file_name()?.to_string_lossy().into_owned(),
+        capacity,
+        status: read_attr(dir, "status")
+            .map(|s| BatteryStatus::parse(&s))
+            .unwrap_or(BatteryStatus::Unknown),
+        energy_now,
+        energy_full,
+        energy_is_charge,
+        power_now,
+        power_is_current,
+        voltage_now: read_u64(dir, "voltage_now"),
+    })
+}
+
+/// Read all system batteries below a power_supply class directory
+pub fn read_batteries(root: &Path) -> Vec<BatteryInfo> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+
+    let mut batteries: Vec<BatteryInfo> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| read_battery(&entry.path()))
+        .collect();
+    batteries.sort_by(|a, b| a.name.cmp(&b.name));
+    batteries
+}
+
+/// Combined charge level of all batteries, weighted by their size when known
+pub fn combined_capacity(batteries: &[BatteryInfo]) -> Option<u8> {
+    if batteries.is_empty() {
+        return None;
+    }
+
+    let energy: Option<(u64, u64)> = batteries.iter().try_fold((0, 0), |(now, full), b| {
+        Some((now + b.energy_now?, full + b.energy_full?))
+    });
+    match energy {
+        Some((now, full)) if full > 0 => Some((now * 100 / full).min(100) as u8),
+        _ => {
+            let sum: u32 = batteries.iter().map(|b| b.capacity as u32).sum();
+            Some((sum / batteries.len() as u32) as u8)
+        }
+    }
+}
+
+/// Combined charging state of all batteries
+pub fn combined_status(batteries: &[BatteryInfo]) -> BatteryStatus {
+    let any = |status| batteries.iter().any(|b| b.status == status);
+    if any(BatteryStatus::Charging) {
+        BatteryStatus::Charging
+    } else if any(BatteryStatus::Discharging) {
+        BatteryStatus::Discharging
+    } else if !batteries.is_empty() && batteries.iter().all(|b| b.status == BatteryStatus::Full) {
+        BatteryStatus::Full
+    } else if any(BatteryStatus::NotCharging) {
+        BatteryStatus::NotCharging
+    } else {
+        BatteryStatus::Unknown
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+pub struct BatteryModule {
+    id: String,
+    name: String,
+    sysfs_path: PathBuf,
+    batteries: Vec<BatteryInfo>,
+    color: ThemeColor,
+    fill_color: ThemeColor,
+    low_color: ThemeColor,
+    font_size: f32,
+    interval: Duration,
+    low_threshold: u8,
+    auto_expand: bool,
+    expand_duration: Duration,
+    last_read: Option<Instant>,
+    low_warned: bool,
+    expand_until: Option<Instant>,
+}
+
+impl Default for BatteryModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BatteryModule {
+    pub fn new() -> Self {
+        Self {
+            id: "battery".to_string(),
+            name: "Battery".to_string(),
+            sysfs_path: PathBuf::from(DEFAULT_SYSFS_PATH),
+            batteries: Vec::new(),
+            color: ThemeColor::named("foreground"),
+            fill_color: ThemeColor::named("success"),
+            low_color: ThemeColor::named("critical"),
+            font_size: 14.0,
+            interval: Duration::from_secs(5),
+            low_threshold: 15,
+            auto_expand: true,
+            expand_duration: Duration::from_secs(5),
+            last_read: None,
+            low_warned: false,
+            expand_until: None,
+        }
+    }
+
+    /// Re-read sysfs if the update interval has elapsed
+    fn refresh(&mut self) -> bool {
+        let now = Instant::now();
+        if let Some(last_read) = self.last_read {
+            if now.duration_since(last_read) < self.interval {
+                return false;
+            }
+        }
+        self.last_read = Some(now);
+        self.batteries = read_batteries(&self.sysfs_path);
+        debug!("BatteryModule: read {} batteries", self.batteries.len());
+        self.check_low_battery(now);
+        true
+    }
+
+    fn check_low_battery(&mut self, now: Instant) {
+        let Some(capacity) = combined_capacity(&self.batteries) else {
+            return;
+        };
+        let discharging = combined_status(&self.batteries) == BatteryStatus::Discharging;
+
+        if discharging && capacity <= self.low_threshold {
+            if !self.low_warned {
+                info!("Battery low ({}%)", capacity);
+                self.low_warned = true;
+                if self.auto_expand {
+                    self.expand_until = Some(now + self.expand_duration);
+                }
+            }
+        } else {
+            // Re-arm the warning once the battery is charging or above the threshold
+            self.low_warned = false;
+        }
+    }
+
+    fn is_low(&self) -> bool {
+        combined_capacity(&self.batteries).is_some_and(|c| c <= self.low_threshold)
+            && combined_status(&self.batteries) != BatteryStatus::Charging
+    }
+
+    fn draw_glyph(&self, canvas: &mut Canvas, x: i32, y: i32, capacity: u8, charging: bool) {
+        let color = self.color.resolve(canvas.theme());
+        let (width, height) = (24u32, 12u32);
+
+        // Outline
+        let body = Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        canvas.draw_rounded_rect(body, 3.0, 1.0, color);
+        // Terminal nub
+        let nub = Rect {
+            x: x + width as i32,
+            y: y + 3,
+            width: 2,
+            height: height - 6,
+        };
+        canvas.fill_rounded_rect(nub, 1.0, color);
+
+        // Charge fill
+        let inner_width = width - 4;
+        let fill_width = (inner_width * capacity as u32 / 100).max(1);
+        let fill_color = if self.is_low() {
+            &self.low_color
+        } else {
+            &self.fill_color
+        };
+        let fill_color = fill_color.resolve(canvas.theme());
+        let fill = Rect {
+            x: x + 2,
+            y: y + 2,
+            width: fill_width,
+            height: height - 4,
+        };
+        canvas.fill_rounded_rect(fill, 1.5, fill_color);
+
+        if charging {
+            // Lightning bolt across the middle of the glyph
+            let (cx, top, bottom) = (
+                (x + width as i32 / 2) as f32,
+                y as f32,
+                (y + height as i32) as f32,
+            );
+            let mut bolt = draw::Path::new();
+            bolt.move_to(cx + 2.0, top)
+                .line_to(cx - 3.0, top + 6.5)
+                .line_to(cx, top + 6.5)
+                .line_to(cx - 2.0, bottom)
+                .line_to(cx + 3.0, top + 5.5)
+                .line_to(cx, top + 5.5)
+                .close();
+            canvas.fill_path(&bolt, color);
+        }
+    }
+}
+
+impl Module for BatteryModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = config.get("sysfs_path").and_then(|v| v.as_str()) {
+            self.sysfs_path = PathBuf::from(path);
+        }
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "fill_color") {
+            self.fill_color = color;
+        }
+        if let Some(color) = color_from_config(config, "low_color") {
+            self.low_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+        if let Some(secs) = config.get("interval").and_then(|v| v.as_integer()) {
+            self.interval = Duration::from_secs(secs.max(1) as u64);
+        }
+        if let Some(threshold) = config.get("low_threshold").and_then(|v| v.as_integer()) {
+            self.low_threshold = threshold.clamp(0, 100) as u8;
+        }
+        if let Some(auto_expand) = config.get("auto_expand").and_then(|v| v.as_bool()) {
+            self.auto_expand = auto_expand;
+        }
+        if let Some(secs) = config.get("expand_seconds").and_then(|v| v.as_integer()) {
+            self.expand_duration = Duration::from_secs(secs.max(0) as u64);
+        }
+
+        self.refresh();
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let Some(capacity) = combined_capacity(&self.batteries) else {
+            canvas.draw_text(area.x + 10, area.y, "No battery", color, self.font_size);
+            return Ok(());
+        };
+        let status = combined_status(&self.batteries);
+
+        self.draw_glyph(
+            canvas,
+            area.x + 10,
+            area.y + 4,
+            capacity,
+            status == BatteryStatus::Charging,
+        );
+
+        let summary = format!("{}%  {}", capacity, status.label());
+        canvas.draw_text(area.x + 46, area.y, &summary, color, self.font_size);
+
+        // One line of detail per battery
+        let line_height = self.font_size as i32 + 6;
+        for (i, battery) in self.batteries.iter().enumerate() {
+            let mut line = format!(
+                "{}: {}%  {}",
+                battery.name,
+                battery.capacity,
+                battery.status.label()
+            );
+            if let Some(remaining) = battery.time_remaining() {
+                let suffix = match battery.status {
+                    BatteryStatus::Charging => "until full",
+                    _ => "left",
+                };
+                line.push_str(&format!("  {} {}", format_duration(remaining), suffix));
+            }
+            if let Some(watts) = battery.watts() {
+                line.push_str(&format!("  {:.1} W", watts));
+            }
+
+            let y = area.y + line_height * (i as i32 + 1);
+            canvas.draw_text(area.x + 10, y, &line, color, self.font_size);
+        }
+
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let Some(capacity) = combined_capacity(&self.batteries) else {
+            return Ok(());
+        };
+        let charging = combined_status(&self.batteries) == BatteryStatus::Charging;
+
+        let glyph_y = area.y + (area.height as i32 - 12) / 2;
+        self.draw_glyph(canvas, area.x, glyph_y, capacity, charging);
+
+        let text_y = area.y + ((area.height as i32 - self.font_size as i32) / 2);
+        canvas.draw_text(
+            area.x + 30,
+            text_y,
+            &format!("{}%", capacity),
+            color,
+            self.font_size,
+        );
+
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, _area: Rect) -> bool {
+        match event {
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                self.refresh()
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        let lines = self.batteries.len() as u32 + 1;
+        (400, lines * (self.font_size as u32 + 6))
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        if self.batteries.is_empty() {
+            None
+        } else {
+            Some((70, 20))
+        }
+    }
+
+    fn wants_expand(&self) -> bool {
+        self.expand_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Add a power supply called `name` to a fake `/sys/class/power_supply`
+    fn supply(root: &Path, name: &str, attrs: &[(&str, &str)]) {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        for (attr, value) in attrs {
+            fs::write(dir.join(attr), format!("{}\n", value)).unwrap();
+        }
+    }
+
+    #[test]
+    fn charging_battery() {
+        let root = tempfile::tempdir().unwrap();
+        supply(
+            root.path(),
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("status", "Charging"),
+                ("capacity", "40"),
+                ("energy_now", "20000000"),
+                ("energy_full", "50000000"),
+                ("power_now", "15000000"),
+            ],
+        );
+        let battery = read_battery(&root.path().join("BAT0")).unwrap();
+        assert_eq!(battery.name, "BAT0");
+        assert_eq!(battery.capacity, 40);
+        assert_eq!(battery.status, BatteryStatus::Charging);
+        // 30 Wh to go at 15 W
+        assert_eq!(
+            battery.time_remaining(),
+            Some(Duration::from_secs(2 * 3600))
+        );
+        assert_eq!(battery.watts(), Some(15.0));
+    }
+
+    #[test]
+    fn discharging_battery_with_charge_attributes() {
+        let root = tempfile::tempdir().unwrap();
+        supply(
+            root.path(),
+            "BAT1",
+            &[
+                ("type", "Battery"),
+                ("status", "Discharging"),
+                ("charge_now", "3000000"),
+                ("charge_full", "4000000"),
+                ("current_now", "2000000"),
+            ],
+        );
+        let battery = read_battery(&root.path().join("BAT1")).unwrap();
+        // Worked out from the charge without a capacity attribute
+        assert_eq!(battery.capacity, 75);
+        assert_eq!(battery.status, BatteryStatus::Discharging);
+        assert_eq!(battery.time_remaining(), Some(Duration::from_secs(90 * 60)));
+        // A current alone isn't a power
+        assert_eq!(battery.watts(), None);
+
+        supply(root.path(), "BAT1", &[("voltage_now", "12000000")]);
+        let battery = read_battery(&root.path().join("BAT1")).unwrap();
+        // 2 A at 12 V
+        assert_eq!(battery.watts(), Some(24.0));
+    }
+
+    #[test]
+    fn energy_with_a_current() {
+        let root = tempfile::tempdir().unwrap();
+        supply(
+            root.path(),
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("status", "Discharging"),
+                ("energy_now", "24000000"),
+                ("energy_full", "48000000"),
+                ("current_now", "2000000"),
+            ],
+        );
+        let battery = read_battery(&root.path().join("BAT0")).unwrap();
+        // Watt-hours can't be divided by amps
+        assert_eq!(battery.time_remaining(), None);
+
+        supply(root.path(), "BAT0", &[("voltage_now", "12000000")]);
+        let battery = read_battery(&root.path().join("BAT0")).unwrap();
+        // 24 Wh at 2 A and 12 V
+        assert_eq!(battery.time_remaining(), Some(Duration::from_secs(3600)));
+
+        // and the other way round, a charge with a power
+        supply(
+            root.path(),
+            "BAT1",
+            &[
+                ("type", "Battery"),
+                ("status", "Charging"),
+                ("charge_now", "1000000"),
+                ("charge_full", "4000000"),
+                ("power_now", "12000000"),
+                ("voltage_now", "12000000"),
+            ],
+        );
+        let battery = read_battery(&root.path().join("BAT1")).unwrap();
+        // 3 Ah to go at 1 A
+        assert_eq!(
+            battery.time_remaining(),
+            Some(Duration::from_secs(3 * 3600))
+        );
+    }
+
+    #[test]
+    fn missing_attributes() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        // Nothing to work out the level from
+        supply(root, "BAT0", &[("type", "Battery"), ("status", "Full")]);
+        assert!(read_battery(&root.join("BAT0")).is_none());
+
+        // No status or power draw
+        supply(root, "BAT1", &[("type", "Battery"), ("capacity", "150")]);
+        let battery = read_battery(&root.join("BAT1")).unwrap();
+        assert_eq!(battery.capacity, 100);
+        assert_eq!(battery.status, BatteryStatus::Unknown);
+        assert_eq!(battery.time_remaining(), None);
+
+        supply(root, "AC", &[("type", "Mains"), ("online", "1")]);
+        assert!(read_battery(&root.join("AC")).is_none());
+        supply(root, "empty", &[]);
+        assert!(read_battery(&root.join("empty")).is_none());
+    }
+
+    #[test]
+    fn multiple_batteries() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        supply(
+            root,
+            "BAT1",
+            &[
+                ("type", "Battery"),
+                ("status", "Discharging"),
+                ("capacity", "90"),
+                ("energy_now", "9000000"),
+                ("energy_full", "10000000"),
+            ],
+        );
+        supply(
+            root,
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("status", "Not charging"),
+                ("capacity", "10"),
+                ("energy_now", "3000000"),
+                ("energy_full", "30000000"),
+            ],
+        );
+        // Not counted: mains, a mouse and a removed battery
+        supply(root, "AC", &[("type", "Mains")]);
+        supply(
+            root,
+            "hidpp_battery_0",
+            &[("type", "Battery"), ("scope", "Device"), ("capacity", "50")],
+        );
+        supply(
+            root,
+            "BAT2",
+            &[("type", "Battery"), ("present", "0"), ("capacity", "0")],
+        );
+
+        let batteries = read_batteries(root);
+        let names: Vec<&str> = batteries.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["BAT0", "BAT1"]);
+        // Weighted by size: 12 of 40 Wh
+        assert_eq!(combined_capacity(&batteries), Some(30));
+        assert_eq!(combined_status(&batteries), BatteryStatus::Discharging);
+
+        assert!(read_batteries(&root.join("missing")).is_empty());
+        assert_eq!(combined_capacity(&[]), None);
+    }
+
+    /// A battery module reading a fake sysfs at `root`
+    fn module(root: &Path) -> BatteryModule {
+        let mut module = BatteryModule::new();
+        let config: toml::Table =
+            toml::from_str(&format!("sysfs_path = {:?}\nlow_threshold = 15", root)).unwrap();
+        module.init(&config).unwrap();
+        module
+    }
+
+    fn set_battery(root: &Path, status: &str, capacity: u8) {
+        supply(
+            root,
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("status", status),
+                ("capacity", &capacity.to_string()),
+            ],
+        );
+    }
+
+    /// Read sysfs again without waiting for the interval
+    fn reread(module: &mut BatteryModule) {
+        module.last_read = None;
+        module.expand_until = None;
+        module.refresh();
+    }
+
+    #[test]
+    fn low_battery_expands_once() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        set_battery(root, "Discharging", 16);
+        let mut module = module(root);
+        assert!(!module.wants_expand());
+
+        // At the threshold
+        set_battery(root, "Discharging", 15);
+        reread(&mut module);
+        assert!(module.wants_expand());
+        assert!(module.is_low());
+
+        // Not again while it stays low
+        set_battery(root, "Discharging", 12);
+        reread(&mut module);
+        assert!(!module.wants_expand());
+
+        // Plugging in re-arms the warning
+        set_battery(root, "Charging", 12);
+        reread(&mut module);
+        assert!(!module.wants_expand());
+        assert!(!module.is_low());
+        set_battery(root, "Discharging", 11);
+        reread(&mut module);
+        assert!(module.wants_expand());
+    }
+
+    #[test]
+    fn low_battery_expansion_can_be_turned_off() {
+        let root = tempfile::tempdir().unwrap();
+        set_battery(root.path(), "Discharging", 5);
+        let mut module = BatteryModule::new();
+        let config: toml::Table = toml::from_str(&format!(
+            "sysfs_path = {:?}\nauto_expand = false",
+            root.path()
+        ))
+        .unwrap();
+        module.init(&config).unwrap();
+        assert!(module.is_low());
+        assert!(!module.wants_expand());
+    }
+}
@@ -0,0 +1,562 @@
+//! Bluetooth module for hypr-notch
+//!
+//! Lists paired devices from BlueZ with their connection state and battery
+//! level when expanded, and the connected devices in the collapsed notch.
+//! Clicking a device connects or disconnects it, and the notch expands for
+//! a moment when a headset or other audio device connects.
+
+use crate::bluez::{Client, Device};
+use crate::config::color_from_config;
+use crate::draw::{self, Canvas};
+use crate::icons;
+use crate::image::{Filter, RgbaImage};
+use crate::module::{self, Module, ModuleEvent, Rect};
+use crate::theme::ThemeColor;
+use log::{info, warn};
+use std::collections::HashSet;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Linux input event code for the left mouse button
+const BTN_LEFT: u32 = 0x110;
+
+/// Height of one device row in the expanded layout
+const ROW_HEIGHT: u32 = 36;
+/// Size of the device icons
+const ICON_SIZE: u32 = 22;
+/// Size of the device icons in the collapsed notch
+const COLLAPSED_ICON_SIZE: u32 = 16;
+
+/// Requests for the command thread, which waits on BlueZ so the notch
+/// doesn't have to
+enum BluetoothCommand {
+    Connect(Device),
+    Disconnect(Device),
+}
+
+/// Devices with a connect or disconnect under way
+#[derive(Default)]
+struct Pending {
+    paths: HashSet<String>,
+    /// Bumped on every change, so the module knows to redraw
+    version: u64,
+}
+
+impl Pending {
+    fn set(&mut self, path: &str, pending: bool) {
+        if pending {
+            self.paths.insert(path.to_string());
+        } else {
+            self.paths.remove(path);
+        }
+        self.version += 1;
+    }
+}
+
+fn run_commands(
+    client: Arc<Client>,
+    commands: Receiver<BluetoothCommand>,
+    pending: Arc<Mutex<Pending>>,
+) {
+    for command in commands {
+        let (device, result) = match command {
+            BluetoothCommand::Connect(device) => {
+                let result = client.connect(&device);
+                (device, result)
+            }
+            BluetoothCommand::Disconnect(device) => {
+                let result = client.disconnect(&device);
+                (device, result)
+            }
+        };
+        if let Err(e) = result {
+            warn!("BluetoothModule: {}: {}", device.name, e);
+        }
+        pending.lock().unwrap().set(&device.path, false);
+        module::request_update();
+    }
+}
+
+pub struct BluetoothModule {
+    id: String,
+    name: String,
+    client: Option<Arc<Client>>,
+    commands: Option<Sender<BluetoothCommand>>,
+    pending: Arc<Mutex<Pending>>,
+    /// Paired devices as of the last update, which is what is drawn
+    devices: Vec<Device>,
+    icons: Vec<Option<Arc<RgbaImage>>>,
+    /// Whether any adapter is switched on
+    powered: bool,
+    /// Whether the devices have been read once, so later connections
+    /// count as changes
+    loaded: bool,
+    generation: u64,
+    pending_version: u64,
+    color: ThemeColor,
+    secondary_color: ThemeColor,
+    connected_color: ThemeColor,
+    font_size: f32,
+    auto_expand: bool,
+    expand_duration: Duration,
+    expand_until: Option<Instant>,
+}
+
+impl Default for BluetoothModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BluetoothModule {
+    pub fn new() -> Self {
+        Self {
+            id: "bluetooth".to_string(),
+            name: "Bluetooth".to_string(),
+            client: None,
+            commands: None,
+            pending: Arc::new(Mutex::new(Pending::default())),
+            devices: Vec::new(),
+            icons: Vec::new(),
+            powered: false,
+            loaded: false,
+            generation: 0,
+            pending_version: 0,
+            color: ThemeColor::named("foreground"),
+            secondary_color: ThemeColor::named("muted"),
+            connected_color: ThemeColor::named("accent"),
+            font_size: 14.0,
+            auto_expand: true,
+            expand_duration: Duration::from_secs(3),
+            expand_until: None,
+        }
+    }
+
+    fn send(&self, command: BluetoothCommand) {
+        let Some(commands) = &self.commands else {
+            return;
+        };
+        if commands.send(command).is_err() {
+            warn!("BluetoothModule: command thread is gone");
+        }
+    }
+
+    /// Take the latest devices from the client, expanding if an audio
+    /// device just connected; returns true if anything changed
+    fn refresh(&mut self) -> bool {
+        let Some(client) = &self.client else {
+            return false;
+        };
+        let generation = client.generation();
+        if generation == self.generation {
+            return false;
+        }
+        self.generation = generation;
+        let powered = client.adapters().iter().any(|adapter| adapter.powered);
+        let devices = client.devices();
+        self.set_devices(powered, devices);
+        true
+    }
+
+    /// Show the paired ones of `devices`, expanding if an audio device
+    /// just connected
+    fn set_devices(&mut self, powered: bool, devices: Vec<Device>) {
+        self.powered = powered;
+        let devices: Vec<Device> = devices.into_iter().filter(|device| device.paired).collect();
+
+        let was_connected = |device: &Device| {
+            self.devices
+                .iter()
+                .any(|old| old.path == device.path && old.connected)
+        };
+        let headset = devices
+            .iter()
+            .find(|device| device.connected && device.is_audio() && !was_connected(device));
+        // Devices that were connected before the first read aren't news
+        if let Some(headset) = headset.filter(|_| self.loaded) {
+            info!("BluetoothModule: {} connected", headset.name);
+            if self.auto_expand {
+                self.expand_until = Some(Instant::now() + self.expand_duration);
+            }
+        }
+
+        self.icons = devices
+            .iter()
+            .map(|device| {
+                (!device.icon.is_empty())
+                    .then(|| icons::load(&device.icon, ICON_SIZE))
+                    .flatten()
+            })
+            .collect();
+        self.devices = devices;
+        self.loaded = true;
+    }
+
+    fn row_rect(&self, area: Rect, index: usize) -> Rect {
+        Rect {
+            x: area.x,
+            y: area.y + (index as u32 * ROW_HEIGHT) as i32,
+            width: area.width,
+            height: ROW_HEIGHT,
+        }
+    }
+
+    fn is_pending(&self, device: &Device) -> bool {
+        self.pending.lock().unwrap().paths.contains(&device.path)
+    }
+
+    fn status_text(&self, device: &Device) -> String {
+        let state = match (self.is_pending(device), device.connected) {
+            (true, true) => "Disconnecting…",
+            (true, false) => "Connecting…",
+            (false, true) => "Connected",
+            (false, false) => "Not connected",
+        };
+        match device.battery {
+            Some(percent) if device.connected => format!("{} · {}%", state, percent),
+            _ => state.to_string(),
+        }
+    }
+
+    fn draw_icon(
+        &self,
+        canvas: &mut Canvas,
+        icon: &Option<Arc<RgbaImage>>,
+        rect: Rect,
+        color: [u8; 4],
+    ) {
+        match icon {
+            Some(image) => canvas.draw_image(image, rect, Filter::Smooth),
+            None => {
+                let radius = rect.width as f32 / 2.0;
+                canvas.draw_circle(
+                    rect.x as f32 + radius,
+                    rect.y as f32 + radius,
+                    radius - 1.0,
+                    1.5,
+                    color,
+                );
+            }
+        }
+    }
+
+    fn draw_row(
+        &self,
+        canvas: &mut Canvas,
+        row: Rect,
+        device: &Device,
+        icon: &Option<Arc<RgbaImage>>,
+    ) {
+        let color = self.color.resolve(canvas.theme());
+        let secondary_color = self.secondary_color.resolve(canvas.theme());
+        let connected_color = self.connected_color.resolve(canvas.theme());
+
+        let icon_rect = Rect {
+            x: row.x + 4,
+            y: row.y + (ROW_HEIGHT - ICON_SIZE) as i32 / 2,
+            width: ICON_SIZE,
+            height: ICON_SIZE,
+        };
+        self.draw_icon(canvas, icon, icon_rect, secondary_color);
+        if device.connected {
+            let (cx, cy) = (
+                (icon_rect.x + icon_rect.width as i32) as f32,
+                (icon_rect.y + icon_rect.height as i32) as f32,
+            );
+            canvas.fill_circle(cx, cy, 3.5, connected_color);
+        }
+
+        let left = row.x + ICON_SIZE as i32 + 16;
+        let text_width = row.width.saturating_sub(ICON_SIZE + 20);
+        let name = draw::truncate_text(&device.name, self.font_size, text_width);
+        canvas.draw_text(left, row.y + 1, &name, color, self.font_size);
+
+        let small_font = self.font_size * 0.85;
+        let status_color = if device.connected {
+            connected_color
+        } else {
+            secondary_color
+        };
+        canvas.draw_text(
+            left,
+            row.y + self.font_size as i32 + 4,
+            &self.status_text(device),
+            status_color,
+            small_font,
+        );
+    }
+
+    fn connected(&self) -> impl Iterator<Item = (&Device, &Option<Arc<RgbaImage>>)> {
+        self.devices
+            .iter()
+            .zip(&self.icons)
+            .filter(|(device, _)| device.connected)
+    }
+
+    /// What the collapsed notch shows after a connected device's icon
+    fn collapsed_label(device: &Device) -> Option<String> {
+        device.battery.map(|percent| format!("{}%", percent))
+    }
+}
+
+impl Module for BluetoothModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "secondary_color") {
+            self.secondary_color = color;
+        }
+        if let Some(color) = color_from_config(config, "connected_color") {
+            self.connected_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+        if let Some(auto_expand) = config.get("auto_expand").and_then(|v| v.as_bool()) {
+            self.auto_expand = auto_expand;
+        }
+        if let Some(secs) = config.get("expand_seconds").and_then(|v| v.as_integer()) {
+            self.expand_duration = Duration::from_secs(secs.max(0) as u64);
+        }
+
+        // Connecting waits for the device to answer, which can take a while
+        let connection = zbus::blocking::connection::Builder::system()?
+            .method_timeout(Duration::from_secs(30))
+            .build()?;
+        let client = Arc::new(Client::start(connection, module::request_update)?);
+
+        let (sender, receiver) = mpsc::channel();
+        let pending = self.pending.clone();
+        let command_client = client.clone();
+        thread::Builder::new()
+            .name("bluetooth-commands".to_string())
+            .spawn(move || run_commands(command_client, receiver, pending))?;
+        self.commands = Some(sender);
+        self.client = Some(client);
+        self.refresh();
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let message = if !self.powered {
+            Some("Bluetooth is off")
+        } else if self.devices.is_empty() {
+            Some("No paired devices")
+        } else {
+            None
+        };
+        if let Some(message) = message {
+            let color = self.secondary_color.resolve(canvas.theme());
+            canvas.draw_text(area.x, area.y + 8, message, color, self.font_size);
+            return Ok(());
+        }
+
+        for (index, (device, icon)) in self.devices.iter().zip(&self.icons).enumerate() {
+            self.draw_row(canvas, self.row_rect(area, index), device, icon);
+        }
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let secondary_color = self.secondary_color.resolve(canvas.theme());
+        let icon_y = area.y + (area.height as i32 - COLLAPSED_ICON_SIZE as i32) / 2;
+        let text_y = area.y + (area.height as i32 - self.font_size as i32) / 2;
+        let mut x = area.x;
+        for (device, icon) in self.connected() {
+            let rect = Rect {
+                x,
+                y: icon_y,
+                width: COLLAPSED_ICON_SIZE,
+                height: COLLAPSED_ICON_SIZE,
+            };
+            self.draw_icon(canvas, icon, rect, secondary_color);
+            x += COLLAPSED_ICON_SIZE as i32 + 4;
+            if let Some(label) = Self::collapsed_label(device) {
+                canvas.draw_text(x, text_y, &label, color, self.font_size);
+                x += draw::text_width(&label, self.font_size) as i32 + 8;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, area: Rect) -> bool {
+        match event {
+            ModuleEvent::Press { button, x, y } if *button == BTN_LEFT => {
+                if !self.powered {
+                    return false;
+                }
+                let Some(device) = (0..self.devices.len())
+                    .find(|&index| self.row_rect(area, index).contains(*x, *y))
+                    .map(|index| self.devices[index].clone())
+                else {
+                    return false;
+                };
+                // Let a connection attempt finish before starting another
+                if self.is_pending(&device) {
+                    return true;
+                }
+                self.pending.lock().unwrap().set(&device.path, true);
+                if device.connected {
+                    self.send(BluetoothCommand::Disconnect(device));
+                } else {
+                    self.send(BluetoothCommand::Connect(device));
+                }
+                true
+            }
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                let changed = self.refresh();
+                let version = self.pending.lock().unwrap().version;
+                let pending_changed = version != self.pending_version;
+                self.pending_version = version;
+                changed || pending_changed
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        if !self.powered || self.devices.is_empty() {
+            return (
+                draw::text_width("No paired devices", self.font_size),
+                ROW_HEIGHT,
+            );
+        }
+        let name_width = self
+            .devices
+            .iter()
+            .map(|device| draw::text_width(&device.name, self.font_size))
+            .max()
+            .unwrap_or(0);
+        (
+            (name_width + ICON_SIZE + 20).clamp(220, 400),
+            self.devices.len() as u32 * ROW_HEIGHT,
+        )
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        let width: u32 = self
+            .connected()
+            .map(|(device, _)| {
+                COLLAPSED_ICON_SIZE
+                    + 4
+                    + Self::collapsed_label(device)
+                        .map_or(0, |label| draw::text_width(&label, self.font_size) + 8)
+            })
+            .sum();
+        (width > 0).then_some((width, 20))
+    }
+
+    fn wants_expand(&self) -> bool {
+        self.expand_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(name: &str, icon: &str, connected: bool) -> Device {
+        Device {
+            path: format!("/org/bluez/hci0/dev_{}", name),
+            name: name.to_string(),
+            icon: icon.to_string(),
+            paired: true,
+            connected,
+            ..Default::default()
+        }
+    }
+
+    /// Every kind of device, none of them connected
+    fn devices() -> Vec<Device> {
+        [
+            ("headset", "audio-headset"),
+            ("headphones", "audio-headphones"),
+            ("speaker", "audio-card"),
+            ("mouse", "input-mouse"),
+            ("keyboard", "input-keyboard"),
+            ("phone", "phone"),
+            ("unknown", ""),
+        ]
+        .into_iter()
+        .map(|(name, icon)| device(name, icon, false))
+        .collect()
+    }
+
+    /// The devices, with `name` connected
+    fn with_connected(name: &str) -> Vec<Device> {
+        let mut devices = devices();
+        for device in &mut devices {
+            device.connected = device.name == name;
+        }
+        devices
+    }
+
+    #[test]
+    fn audio_devices_expand_the_notch_when_they_connect() {
+        for name in ["headset", "headphones", "speaker"] {
+            let mut module = BluetoothModule::new();
+            module.set_devices(true, devices());
+            assert!(!module.wants_expand());
+            module.set_devices(true, with_connected(name));
+            assert!(module.wants_expand(), "{name}");
+        }
+    }
+
+    #[test]
+    fn other_devices_dont() {
+        for name in ["mouse", "keyboard", "phone", "unknown"] {
+            let mut module = BluetoothModule::new();
+            module.set_devices(true, devices());
+            module.set_devices(true, with_connected(name));
+            assert!(!module.wants_expand(), "{name}");
+        }
+    }
+
+    #[test]
+    fn only_new_connections_expand() {
+        // Connected before the first read
+        let mut module = BluetoothModule::new();
+        module.set_devices(true, with_connected("headset"));
+        assert!(!module.wants_expand());
+        // and still connected
+        module.set_devices(true, with_connected("headset"));
+        assert!(!module.wants_expand());
+
+        // Unpaired devices aren't shown at all
+        let mut module = BluetoothModule::new();
+        module.set_devices(true, devices());
+        let mut unpaired = with_connected("headset");
+        unpaired[0].paired = false;
+        module.set_devices(true, unpaired);
+        assert!(!module.wants_expand());
+        assert_eq!(module.devices.len(), devices().len() - 1);
+
+        // or when turned off
+        let mut module = BluetoothModule::new();
+        module.auto_expand = false;
+        module.set_devices(true, devices());
+        module.set_devices(true, with_connected("headset"));
+        assert!(!module.wants_expand());
+    }
+}
@@ -0,0 +1,676 @@
+//! Brightness module for hypr-notch
+//!
+//! Reads the display backlight from sysfs (`/sys/class/backlight/*`) and
+//! shows a slider when expanded. Changes made elsewhere, such as with the
+//! brightness keys, are picked up through inotify and shown as a short
+//! on-screen display in the collapsed notch. Scrolling over the module or
+//! dragging the slider sets the brightness through logind's
+//! `SetBrightness` call, which needs no special permissions, and falls
+//! back to writing sysfs directly when logind isn't available.
+
+use crate::config::color_from_config;
+use crate::draw::Canvas;
+use crate::module::{self, Module, ModuleEvent, Rect};
+use crate::theme::ThemeColor;
+use inotify::{Inotify, WatchMask};
+use log::{debug, info, warn};
+use std::f32::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use zbus::blocking::{Connection, Proxy};
+
+const DEFAULT_SYSFS_PATH: &str = "/sys/class/backlight";
+
+pub const LOGIND_NAME: &str = "org.freedesktop.login1";
+/// logind's object for the session of the calling process
+pub const LOGIND_SESSION_PATH: &str = "/org/freedesktop/login1/session/auto";
+pub const LOGIND_SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+/// Linux input event code for the left mouse button
+const BTN_LEFT: u32 = 0x110;
+
+/// Changes seen this soon after we set the brightness are taken to be our
+/// own, and don't bring up the on-screen display
+const OWN_CHANGE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Height of the slider track, in pixels
+const TRACK_HEIGHT: u32 = 6;
+
+/// State of a backlight device read from sysfs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backlight {
+    pub name: String,
+    pub path: PathBuf,
+    pub brightness: u32,
+    pub max_brightness: u32,
+}
+
+impl Backlight {
+    /// Brightness as a percentage of the maximum
+    pub fn percent(&self) -> u8 {
+        if self.max_brightness == 0 {
+            return 0;
+        }
+        (self.brightness as u64 * 100 / self.max_brightness as u64).min(100) as u8
+    }
+
+    /// Raw brightness value for a percentage of the maximum
+    pub fn value_for(&self, percent: f64) -> u32 {
+        (percent.clamp(0.0, 100.0) / 100.0 * self.max_brightness as f64).round() as u32
+    }
+}
+
+fn read_attr(dir: &Path, attr: &str) -> Option<String> {
+    fs::read_to_string(dir.join(attr))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+fn read_u32(dir: &Path, attr: &str) -> Option<u32> {
+    read_attr(dir, attr)?.parse().ok()
+}
+
+/// Read a single backlight directory
+pub fn read_backlight(dir: &Path) -> Option<Backlight> {
+    Some(Backlight {
+        name: dir.file_name()?.to_string_lossy().into_owned(),
+        path: dir.to_path_buf(),
+        brightness: read_u32(dir, "brightness")?,
+        max_brightness: read_u32(dir, "max_brightness")?,
+    })
+}
+
+/// Find the backlight to control below a backlight class directory: the
+/// one called `name` if given, otherwise the first by name of the kind
+/// most likely to work, preferring firmware interfaces over platform
+/// drivers over raw registers as the kernel documentation suggests
+pub fn find_backlight(root: &Path, name: Option<&str>) -> Option<PathBuf> {
+    if let Some(name) = name {
+        let dir = root.join(name);
+        return dir.join("brightness").exists().then_some(dir);
+    }
+
+    let rank = |dir: &Path| match read_attr(dir, "type").as_deref() {
+        Some("firmware") => 0,
+        Some("platform") => 1,
+        _ => 2,
+    };
+    let mut dirs: Vec<PathBuf> = fs::read_dir(root)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|dir| dir.join("brightness").exists())
+        .collect();
+    dirs.sort_by(|a, b| rank(a).cmp(&rank(b)).then_with(|| a.cmp(b)));
+    dirs.into_iter().next()
+}
+
+/// Brightness state shared with the watcher thread
+#[derive(Debug, Default)]
+struct BrightnessState {
+    backlight: Option<Backlight>,
+    /// When we last set the brightness ourselves
+    set_at: Option<Instant>,
+    /// Show the on-screen display until this time
+    osd_until: Option<Instant>,
+}
+
+impl BrightnessState {
+    /// Store a fresh reading; returns whether it changed. A change we
+    /// didn't make ourselves brings up the on-screen display.
+    fn update(&mut self, backlight: Option<Backlight>, osd_duration: Duration) -> bool {
+        if backlight == self.backlight {
+            return false;
+        }
+        let now = Instant::now();
+        let external = self.backlight.is_some()
+            && backlight.is_some()
+            && self
+                .set_at
+                .is_none_or(|set_at| now.duration_since(set_at) >= OWN_CHANGE_WINDOW);
+        if external {
+            self.osd_until = Some(now + osd_duration);
+        }
+        self.backlight = backlight;
+        true
+    }
+}
+
+/// Re-read the backlight whenever its brightness changes, until the
+/// watch can't be read any more
+fn watch(dir: PathBuf, state: Arc<Mutex<BrightnessState>>, osd_duration: Duration) {
+    let mut inotify = match Inotify::init() {
+        Ok(inotify) => inotify,
+        Err(e) => {
+            warn!("BrightnessModule: inotify unavailable: {}", e);
+            return;
+        }
+    };
+    // Writes through sysfs modify `brightness`; the kernel notifies
+    // `actual_brightness` when a driver or firmware changes it
+    for attr in ["brightness", "actual_brightness"] {
+        if let Err(e) = inotify.watches().add(dir.join(attr), WatchMask::MODIFY) {
+            debug!("BrightnessModule: can't watch {}: {}", attr, e);
+        }
+    }
+
+    let mut buffer = [0u8; 1024];
+    loop {
+        if let Err(e) = inotify.read_events_blocking(&mut buffer) {
+            warn!("BrightnessModule: stopped watching the backlight: {}", e);
+            return;
+        }
+        let backlight = read_backlight(&dir);
+        if state.lock().unwrap().update(backlight, osd_duration) {
+            module::request_update();
+        }
+    }
+}
+
+/// A logind session object to set brightness through
+pub struct LogindSession {
+    pub connection: Connection,
+    /// Object path of the session, normally [`LOGIND_SESSION_PATH`]
+    pub path: String,
+}
+
+/// Ask logind to set the brightness of a backlight for `session`
+pub fn set_with_logind(session: &LogindSession, name: &str, value: u32) -> zbus::Result<()> {
+    let proxy = Proxy::new(
+        &session.connection,
+        LOGIND_NAME,
+        session.path.as_str(),
+        LOGIND_SESSION_INTERFACE,
+    )?;
+    proxy.call::<_, _, ()>("SetBrightness", &("backlight", name, value))
+}
+
+/// Set `backlight` to `value` through logind, or by writing sysfs when
+/// there is no session or logind refuses
+pub fn set_brightness(
+    logind: Option<&LogindSession>,
+    backlight: &Backlight,
+    value: u32,
+) -> std::io::Result<()> {
+    let result = match logind {
+        Some(session) => set_with_logind(session, &backlight.name, value),
+        None => Err(zbus::Error::Failure("no system bus".to_string())),
+    };
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            debug!("BrightnessModule: logind SetBrightness failed: {}", e);
+            fs::write(backlight.path.join("brightness"), value.to_string())
+        }
+    }
+}
+
+/// Apply requested brightness values, skipping straight to the latest one
+/// when a drag sends them faster than they can be set. The logind session
+/// is looked up with `connect` on first use, and again while it fails.
+fn run_setter(
+    backlight: Backlight,
+    values: Receiver<u32>,
+    connect: impl Fn() -> zbus::Result<LogindSession>,
+) {
+    let mut logind = None;
+    while let Ok(value) = values.recv() {
+        let value = values.try_iter().last().unwrap_or(value);
+
+        if logind.is_none() {
+            logind = connect()
+                .map_err(|e| debug!("BrightnessModule: no system bus: {}", e))
+                .ok();
+        }
+        if let Err(e) = set_brightness(logind.as_ref(), &backlight, value) {
+            warn!(
+                "BrightnessModule: failed to set brightness of {}: {}",
+                backlight.name, e
+            );
+        }
+    }
+}
+
+/// Small sun glyph centred on `(cx, cy)`
+fn draw_sun(canvas: &mut Canvas, cx: f32, cy: f32, radius: f32, color: [u8; 4]) {
+    canvas.fill_circle(cx, cy, radius * 0.45, color);
+    for i in 0..8 {
+        let angle = i as f32 * PI / 4.0;
+        let (sin, cos) = angle.sin_cos();
+        canvas.draw_line(
+            cx + cos * radius * 0.7,
+            cy + sin * radius * 0.7,
+            cx + cos * radius,
+            cy + sin * radius,
+            1.5,
+            color,
+        );
+    }
+}
+
+pub struct BrightnessModule {
+    id: String,
+    name: String,
+    sysfs_path: PathBuf,
+    device: Option<String>,
+    state: Arc<Mutex<BrightnessState>>,
+    setter: Option<Sender<u32>>,
+    color: ThemeColor,
+    track_color: ThemeColor,
+    fill_color: ThemeColor,
+    font_size: f32,
+    step: f64,
+    min_percent: f64,
+    osd_duration: Duration,
+    interval: Duration,
+    last_read: Option<Instant>,
+    dragging: bool,
+    osd_shown: bool,
+}
+
+impl Default for BrightnessModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BrightnessModule {
+    pub fn new() -> Self {
+        Self {
+            id: "brightness".to_string(),
+            name: "Brightness".to_string(),
+            sysfs_path: PathBuf::from(DEFAULT_SYSFS_PATH),
+            device: None,
+            state: Arc::new(Mutex::new(BrightnessState::default())),
+            setter: None,
+            color: ThemeColor::named("foreground"),
+            track_color: ThemeColor::named("surface"),
+            fill_color: ThemeColor::named("accent"),
+            font_size: 14.0,
+            step: 5.0,
+            min_percent: 1.0,
+            osd_duration: Duration::from_secs(2),
+            interval: Duration::from_secs(5),
+            last_read: None,
+            dragging: false,
+            osd_shown: false,
+        }
+    }
+
+    fn backlight(&self) -> Option<Backlight> {
+        self.state.lock().unwrap().backlight.clone()
+    }
+
+    fn osd_active(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .osd_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Re-read sysfs if the update interval has elapsed, for drivers that
+    /// change the brightness without notifying inotify
+    fn refresh(&mut self) -> bool {
+        let now = Instant::now();
+        let mut changed = false;
+        if self
+            .last_read
+            .is_none_or(|last_read| now.duration_since(last_read) >= self.interval)
+        {
+            self.last_read = Some(now);
+            let backlight = self
+                .state
+                .lock()
+                .unwrap()
+                .backlight
+                .as_ref()
+                .and_then(|backlight| read_backlight(&backlight.path));
+            if backlight.is_some() {
+                changed = self
+                    .state
+                    .lock()
+                    .unwrap()
+                    .update(backlight, self.osd_duration);
+            }
+        }
+
+        // Redraw when the on-screen display comes up or goes away
+        let osd_shown = self.osd_active();
+        changed |= osd_shown != self.osd_shown;
+        self.osd_shown = osd_shown;
+        changed
+    }
+
+    /// Set the brightness to `percent`, keeping it above `min_percent` so
+    /// the screen never goes fully dark
+    fn set_percent(&mut self, percent: f64) {
+        let mut state = self.state.lock().unwrap();
+        let Some(backlight) = state.backlight.as_mut() else {
+            return;
+        };
+        let value = backlight.value_for(percent.max(self.min_percent));
+        if value == backlight.brightness {
+            return;
+        }
+        // Show the new value right away instead of waiting for the watcher
+        backlight.brightness = value;
+        state.set_at = Some(Instant::now());
+        drop(state);
+
+        if let Some(setter) = &self.setter {
+            if setter.send(value).is_err() {
+                warn!("BrightnessModule: brightness thread is gone");
+            }
+        }
+    }
+
+    /// Slider track in the expanded layout
+    fn slider_rect(&self, area: Rect) -> Rect {
+        let left = 40;
+        let right = 60;
+        Rect {
+            x: area.x + left,
+            y: area.y + (area.height as i32 - TRACK_HEIGHT as i32) / 2,
+            width: area.width.saturating_sub((left + right) as u32).max(1),
+            height: TRACK_HEIGHT,
+        }
+    }
+
+    /// Percentage under the pointer at `x` along the slider
+    fn percent_at(&self, area: Rect, x: f64) -> f64 {
+        let slider = self.slider_rect(area);
+        (x - slider.x as f64) / slider.width as f64 * 100.0
+    }
+
+    fn draw_bar(&self, canvas: &mut Canvas, track: Rect, percent: u8) {
+        let track_color = self.track_color.resolve(canvas.theme());
+        let fill_color = self.fill_color.resolve(canvas.theme());
+        let radius = track.height as f32 / 2.0;
+        canvas.fill_rounded_rect(track, radius, track_color);
+        let fill = Rect {
+            width: (track.width * percent as u32 / 100).max(track.height),
+            ..track
+        };
+        canvas.fill_rounded_rect(fill, radius, fill_color);
+    }
+}
+
+impl Module for BrightnessModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = config.get("sysfs_path").and_then(|v| v.as_str()) {
+            self.sysfs_path = PathBuf::from(path);
+        }
+        if let Some(device) = config.get("device").and_then(|v| v.as_str()) {
+            self.device = Some(device.to_string());
+        }
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "track_color") {
+            self.track_color = color;
+        }
+        if let Some(color) = color_from_config(config, "fill_color") {
+            self.fill_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+        if let Some(step) = config.get("step").and_then(|v| v.as_integer()) {
+            self.step = step.clamp(1, 100) as f64;
+        }
+        if let Some(min) = config.get("min_percent").and_then(|v| v.as_integer()) {
+            self.min_percent = min.clamp(0, 100) as f64;
+        }
+        if let Some(secs) = config.get("osd_seconds").and_then(|v| v.as_float()) {
+            self.osd_duration = Duration::from_secs_f64(secs.max(0.0));
+        }
+        if let Some(secs) = config.get("interval").and_then(|v| v.as_integer()) {
+            self.interval = Duration::from_secs(secs.max(1) as u64);
+        }
+
+        let Some(dir) = find_backlight(&self.sysfs_path, self.device.as_deref()) else {
+            info!(
+                "BrightnessModule: no backlight found in {}",
+                self.sysfs_path.display()
+            );
+            return Ok(());
+        };
+        let Some(backlight) = read_backlight(&dir) else {
+            warn!("BrightnessModule: can't read {}", dir.display());
+            return Ok(());
+        };
+        info!("BrightnessModule: controlling backlight {}", backlight.name);
+        self.last_read = Some(Instant::now());
+        self.state.lock().unwrap().backlight = Some(backlight.clone());
+
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("brightness".to_string())
+            .spawn(move || {
+                run_setter(backlight, receiver, || {
+                    Ok(LogindSession {
+                        connection: Connection::system()?,
+                        path: LOGIND_SESSION_PATH.to_string(),
+                    })
+                })
+            })?;
+        self.setter = Some(sender);
+
+        let state = self.state.clone();
+        let osd_duration = self.osd_duration;
+        thread::Builder::new()
+            .name("brightness-watch".to_string())
+            .spawn(move || watch(dir, state, osd_duration))?;
+
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let Some(backlight) = self.backlight() else {
+            canvas.draw_text(area.x + 10, area.y, "No backlight", color, self.font_size);
+            return Ok(());
+        };
+        let percent = backlight.percent();
+
+        let cy = area.y as f32 + area.height as f32 / 2.0;
+        draw_sun(canvas, area.x as f32 + 20.0, cy, 9.0, color);
+
+        let track = self.slider_rect(area);
+        self.draw_bar(canvas, track, percent);
+        // Knob at the current value
+        let knob_x = track.x as f32 + track.width as f32 * percent as f32 / 100.0;
+        canvas.fill_circle(knob_x, cy, TRACK_HEIGHT as f32 + 1.0, color);
+
+        let text_y = area.y + (area.height as i32 - self.font_size as i32) / 2;
+        canvas.draw_text(
+            track.x + track.width as i32 + 14,
+            text_y,
+            &format!("{}%", percent),
+            color,
+            self.font_size,
+        );
+
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let Some(backlight) = self.backlight() else {
+            return Ok(());
+        };
+        if !self.osd_active() {
+            return Ok(());
+        }
+
+        let cy = area.y as f32 + area.height as f32 / 2.0;
+        draw_sun(canvas, area.x as f32 + 8.0, cy, 7.0, color);
+        let track = Rect {
+            x: area.x + 22,
+            y: area.y + (area.height as i32 - TRACK_HEIGHT as i32) / 2,
+            width: area.width.saturating_sub(22),
+            height: TRACK_HEIGHT,
+        };
+        self.draw_bar(canvas, track, backlight.percent());
+
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, area: Rect) -> bool {
+        match event {
+            ModuleEvent::Press { button, x, .. } if *button == BTN_LEFT => {
+                if self.backlight().is_none() {
+                    return false;
+                }
+                self.dragging = true;
+                self.set_percent(self.percent_at(area, *x));
+                true
+            }
+            ModuleEvent::Motion { x, .. } if self.dragging => {
+                self.set_percent(self.percent_at(area, *x));
+                true
+            }
+            ModuleEvent::Release { button, .. } if *button == BTN_LEFT => {
+                std::mem::take(&mut self.dragging)
+            }
+            ModuleEvent::Leave => {
+                self.dragging = false;
+                false
+            }
+            ModuleEvent::Scroll { delta, .. } => {
+                let Some(backlight) = self.backlight() else {
+                    return false;
+                };
+                let current =
+                    backlight.brightness as f64 * 100.0 / backlight.max_brightness.max(1) as f64;
+                self.set_percent(current - delta * self.step);
+                true
+            }
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                self.refresh()
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        (400, 30)
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        self.osd_active().then_some((120, 20))
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Add a backlight called `name` to a fake `/sys/class/backlight`
+    fn backlight(root: &Path, name: &str, kind: Option<&str>, brightness: &str, max: &str) {
+        let dir = root.join(name);
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("brightness"), brightness).unwrap();
+        fs::write(dir.join("max_brightness"), max).unwrap();
+        if let Some(kind) = kind {
+            fs::write(dir.join("type"), format!("{}\n", kind)).unwrap();
+        }
+    }
+
+    #[test]
+    fn firmware_backlights_are_preferred() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        assert_eq!(find_backlight(root, None), None);
+
+        backlight(root, "a_raw", Some("raw"), "5", "10");
+        assert_eq!(find_backlight(root, None), Some(root.join("a_raw")));
+        backlight(root, "intel_backlight", Some("platform"), "5", "10");
+        backlight(root, "nvidia_0", None, "5", "10");
+        assert_eq!(
+            find_backlight(root, None),
+            Some(root.join("intel_backlight"))
+        );
+        backlight(root, "acpi_video1", Some("firmware"), "5", "10");
+        backlight(root, "acpi_video0", Some("firmware"), "5", "10");
+        assert_eq!(find_backlight(root, None), Some(root.join("acpi_video0")));
+
+        // Not a backlight without a brightness file
+        fs::create_dir(root.join("0_empty")).unwrap();
+        fs::write(root.join("0_empty/type"), "firmware").unwrap();
+        assert_eq!(find_backlight(root, None), Some(root.join("acpi_video0")));
+
+        // A configured name wins, if it exists
+        assert_eq!(
+            find_backlight(root, Some("nvidia_0")),
+            Some(root.join("nvidia_0"))
+        );
+        assert_eq!(find_backlight(root, Some("0_empty")), None);
+        assert_eq!(find_backlight(root, Some("missing")), None);
+    }
+
+    #[test]
+    fn backlights_are_read() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        backlight(root, "intel_backlight", None, "1200\n", " 4800\n");
+        let read = read_backlight(&root.join("intel_backlight")).unwrap();
+        assert_eq!(
+            read,
+            Backlight {
+                name: "intel_backlight".to_string(),
+                path: root.join("intel_backlight"),
+                brightness: 1200,
+                max_brightness: 4800,
+            }
+        );
+        assert_eq!(read.percent(), 25);
+
+        backlight(root, "broken", None, "bright", "100");
+        assert_eq!(read_backlight(&root.join("broken")), None);
+        assert_eq!(read_backlight(&root.join("missing")), None);
+    }
+
+    #[test]
+    fn values_for_percentages() {
+        let backlight = Backlight {
+            name: "test".to_string(),
+            path: PathBuf::new(),
+            brightness: 0,
+            max_brightness: 937,
+        };
+        assert_eq!(backlight.value_for(0.0), 0);
+        assert_eq!(backlight.value_for(50.0), 469);
+        assert_eq!(backlight.value_for(100.0), 937);
+        assert_eq!(backlight.value_for(-20.0), 0);
+        assert_eq!(backlight.value_for(150.0), 937);
+
+        let none = Backlight {
+            max_brightness: 0,
+            ..backlight
+        };
+        assert_eq!(none.value_for(50.0), 0);
+        assert_eq!(none.percent(), 0);
+    }
+}
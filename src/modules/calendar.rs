@@ -0,0 +1,683 @@
+//! Calendar module for hypr-notch
+//!
+//! Reads events from `.ics` files, or from directories of them as kept by
+//! vdirsyncer, including recurring and all-day events. The collapsed notch
+//! shows the next event; the expanded notch shows this month with today
+//! highlighted and the upcoming events beside it.
+
+use crate::config::{color_from_config, resolve_path};
+use crate::draw::{self, Canvas};
+use crate::ical::{self, Event, Occurrence};
+use crate::module::{self, Module, ModuleEvent, Rect};
+use crate::theme::ThemeColor;
+use jiff::civil::{Date, Time, Weekday};
+use jiff::fmt::strtime;
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, ToSpan, Zoned};
+use log::{debug, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Times of day are shown like this unless `time_format` says otherwise
+const DEFAULT_TIME_FORMAT: &str = "%H:%M";
+
+/// Every `.ics` file at `path`: the file itself, or those found anywhere
+/// under a directory
+pub fn calendar_files(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    let mut files = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // Skip vdirsyncer's status and other hidden files
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("ics"))
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Parsed calendar files, reread only when they change
+#[derive(Default)]
+struct FileCache {
+    files: HashMap<PathBuf, (SystemTime, Vec<Event>)>,
+}
+
+impl FileCache {
+    fn load(&mut self, paths: &[PathBuf], local: &TimeZone) -> Vec<Event> {
+        let files: Vec<PathBuf> = paths.iter().flat_map(|path| calendar_files(path)).collect();
+        self.files.retain(|path, _| files.contains(path));
+
+        let mut events = Vec::new();
+        for path in files {
+            let Ok(modified) = fs::metadata(&path).and_then(|meta| meta.modified()) else {
+                debug!("CalendarModule: can't read {}", path.display());
+                continue;
+            };
+            let cached = self.files.get(&path).filter(|(seen, _)| *seen == modified);
+            if cached.is_none() {
+                let parsed = match fs::read_to_string(&path) {
+                    Ok(text) => ical::parse_calendar(&text, local),
+                    Err(e) => {
+                        warn!("CalendarModule: failed to read {}: {}", path.display(), e);
+                        Vec::new()
+                    }
+                };
+                debug!(
+                    "CalendarModule: {} events in {}",
+                    parsed.len(),
+                    path.display()
+                );
+                self.files.insert(path.clone(), (modified, parsed));
+            }
+            events.extend(self.files[&path].1.iter().cloned());
+        }
+        events
+    }
+}
+
+/// Local midnight at the start of `date`
+fn midnight(date: Date, local: &TimeZone) -> Option<Timestamp> {
+    local.to_timestamp(date.to_datetime(Time::midnight())).ok()
+}
+
+/// Reread the calendars every `interval` and publish the occurrences from
+/// the start of this month's grid to `lookahead_days` ahead, or the end of
+/// the grid if that's later
+fn run_loader(
+    state: Arc<Mutex<Vec<Occurrence>>>,
+    paths: Vec<PathBuf>,
+    interval: Duration,
+    lookahead_days: i64,
+) {
+    let mut cache = FileCache::default();
+    loop {
+        let local = TimeZone::system();
+        let today = Zoned::now().date();
+        let first = today.first_of_month().saturating_sub(7.days());
+        let last = today
+            .saturating_add(lookahead_days.days())
+            .max(today.last_of_month().saturating_add(8.days()));
+
+        let events = cache.load(&paths, &local);
+        if let (Some(from), Some(to)) = (midnight(first, &local), midnight(last, &local)) {
+            let occurrences = ical::occurrences(&events, from, to, &local);
+            let mut state = state.lock().unwrap();
+            if *state != occurrences {
+                *state = occurrences;
+                module::request_update();
+            }
+        }
+        thread::sleep(interval);
+    }
+}
+
+fn parse_weekday(name: &str) -> Option<Weekday> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "monday" => Weekday::Monday,
+        "tuesday" => Weekday::Tuesday,
+        "wednesday" => Weekday::Wednesday,
+        "thursday" => Weekday::Thursday,
+        "friday" => Weekday::Friday,
+        "saturday" => Weekday::Saturday,
+        "sunday" => Weekday::Sunday,
+        _ => return None,
+    })
+}
+
+fn weekday_label(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Monday => "Mo",
+        Weekday::Tuesday => "Tu",
+        Weekday::Wednesday => "We",
+        Weekday::Thursday => "Th",
+        Weekday::Friday => "Fr",
+        Weekday::Saturday => "Sa",
+        Weekday::Sunday => "Su",
+    }
+}
+
+pub struct CalendarModule {
+    id: String,
+    name: String,
+    state: Arc<Mutex<Vec<Occurrence>>>,
+    /// Occurrences as of the last update, which is what is drawn
+    shown: Vec<Occurrence>,
+    /// Days of the shown occurrences, for the dots in the month grid
+    event_days: HashSet<Date>,
+    /// The minute last drawn, so relative times are kept current
+    minute: i64,
+    paths: Vec<PathBuf>,
+    interval: Duration,
+    lookahead_days: i64,
+    week_start: Weekday,
+    time_format: String,
+    max_events: usize,
+    color: ThemeColor,
+    secondary_color: ThemeColor,
+    today_color: ThemeColor,
+    today_text_color: ThemeColor,
+    event_color: ThemeColor,
+    font_size: f32,
+}
+
+impl Default for CalendarModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CalendarModule {
+    pub fn new() -> Self {
+        Self {
+            id: "calendar".to_string(),
+            name: "Calendar".to_string(),
+            state: Arc::new(Mutex::new(Vec::new())),
+            shown: Vec::new(),
+            event_days: HashSet::new(),
+            minute: 0,
+            paths: Vec::new(),
+            interval: Duration::from_secs(60),
+            lookahead_days: 14,
+            week_start: Weekday::Monday,
+            time_format: DEFAULT_TIME_FORMAT.to_string(),
+            max_events: 4,
+            color: ThemeColor::named("foreground"),
+            secondary_color: ThemeColor::named("muted"),
+            today_color: ThemeColor::named("accent"),
+            today_text_color: ThemeColor::named("background"),
+            event_color: ThemeColor::named("accent"),
+            font_size: 14.0,
+        }
+    }
+
+    /// The next timed event that hasn't ended, or failing that the next
+    /// all-day one
+    fn next_event(&self, now: Timestamp) -> Option<&Occurrence> {
+        let until = now.checked_add((self.lookahead_days * 24).hours()).ok()?;
+        let mut upcoming = self
+            .shown
+            .iter()
+            .filter(|occurrence| occurrence.end > now && occurrence.start < until);
+        let first = upcoming.clone().find(|occurrence| !occurrence.all_day);
+        first.or_else(|| upcoming.find(|occurrence| occurrence.all_day))
+    }
+
+    /// When `occurrence` is, relative to `now`: `now`, `14:30`,
+    /// `Tomorrow 09:00`, `Fri 16:00`, `Today` or `Sat 24`
+    fn describe(&self, occurrence: &Occurrence, now: &Zoned) -> String {
+        let tz = now.time_zone();
+        let today = now.date();
+        let start = occurrence.start.to_zoned(tz.clone());
+        let day = start.date();
+        let tomorrow = today.tomorrow().ok();
+
+        if occurrence.all_day {
+            let last = occurrence
+                .end
+                .to_zoned(tz.clone())
+                .date()
+                .yesterday()
+                .unwrap_or(day);
+            return if day <= today && today <= last {
+                "Today".to_string()
+            } else if Some(day) == tomorrow {
+                "Tomorrow".to_string()
+            } else {
+                day.strftime("%a %-d").to_string()
+            };
+        }
+
+        if occurrence.start <= now.timestamp() {
+            return "now".to_string();
+        }
+        let time = start.strftime(&self.time_format).to_string();
+        if day == today {
+            time
+        } else if Some(day) == tomorrow {
+            format!("Tomorrow {}", time)
+        } else {
+            format!("{} {}", day.strftime("%a"), time)
+        }
+    }
+
+    fn compact_text(&self) -> Option<String> {
+        let now = Zoned::now();
+        let next = self.next_event(now.timestamp())?;
+        Some(format!("{} · {}", self.describe(next, &now), next.summary))
+    }
+
+    fn draw_month(&self, canvas: &mut Canvas, area: Rect, today: Date) -> u32 {
+        let color = self.color.resolve(canvas.theme());
+        let secondary_color = self.secondary_color.resolve(canvas.theme());
+        let today_color = self.today_color.resolve(canvas.theme());
+        let today_text_color = self.today_text_color.resolve(canvas.theme());
+        let event_color = self.event_color.resolve(canvas.theme());
+        let small_font = self.font_size * 0.8;
+
+        let title = today.strftime("%B %Y").to_string();
+        canvas.draw_text(area.x + 4, area.y + 2, &title, color, self.font_size);
+
+        // The weekday header and up to six weeks share the height below
+        let top = area.y + self.font_size as i32 + 8;
+        let cell_height = ((area.y + area.height as i32 - top) / 7).max(1);
+        let cell_width = (small_font * 2.0).ceil() as i32;
+        let cell_center = |column: i32, row: i32| {
+            (
+                area.x + column * cell_width + cell_width / 2,
+                top + row * cell_height + cell_height / 2,
+            )
+        };
+        let draw_centered = |canvas: &mut Canvas, (cx, cy): (i32, i32), text: &str, color| {
+            let width = draw::text_width(text, small_font) as i32;
+            canvas.draw_text(
+                cx - width / 2,
+                cy - small_font as i32 / 2,
+                text,
+                color,
+                small_font,
+            );
+        };
+
+        for column in 0..7 {
+            let weekday = self.week_start.wrapping_add(column);
+            draw_centered(
+                canvas,
+                cell_center(column, 0),
+                weekday_label(weekday),
+                secondary_color,
+            );
+        }
+
+        let first = today.first_of_month();
+        let offset = self.week_start.until(first.weekday()) as i32;
+        for day in 1..=first.days_in_month() {
+            let index = offset + day as i32 - 1;
+            let (cx, cy) = cell_center(index % 7, index / 7 + 1);
+            let Ok(date) = Date::new(first.year(), first.month(), day) else {
+                continue;
+            };
+            let text_color = if date == today {
+                let radius = (cell_width.min(cell_height) as f32 / 2.0 - 1.0).max(1.0);
+                canvas.fill_circle(cx as f32, cy as f32, radius, today_color);
+                today_text_color
+            } else {
+                color
+            };
+            draw_centered(canvas, (cx, cy), &day.to_string(), text_color);
+            if self.event_days.contains(&date) && date != today {
+                let dot_y = cy as f32 + small_font / 2.0 + 2.0;
+                canvas.fill_circle(cx as f32, dot_y, 1.5, event_color);
+            }
+        }
+
+        (cell_width * 7) as u32
+    }
+
+    fn draw_agenda(&self, canvas: &mut Canvas, area: Rect) {
+        let color = self.color.resolve(canvas.theme());
+        let secondary_color = self.secondary_color.resolve(canvas.theme());
+        let small_font = self.font_size * 0.8;
+        let now = Zoned::now();
+
+        let upcoming: Vec<&Occurrence> = self
+            .shown
+            .iter()
+            .filter(|occurrence| occurrence.end > now.timestamp())
+            .take(self.max_events)
+            .collect();
+        if upcoming.is_empty() {
+            canvas.draw_text(
+                area.x,
+                area.y + 4,
+                "No upcoming events",
+                secondary_color,
+                small_font,
+            );
+            return;
+        }
+
+        let row_height = self.font_size as i32 + small_font as i32 + 10;
+        for (i, occurrence) in upcoming.into_iter().enumerate() {
+            let y = area.y + 2 + i as i32 * row_height;
+            if y + row_height > area.y + area.height as i32 {
+                break;
+            }
+            let mut when = self.describe(occurrence, &now);
+            if let Some(location) = &occurrence.location {
+                when = format!("{} · {}", when, location);
+            }
+            let summary = draw::truncate_text(&occurrence.summary, self.font_size, area.width);
+            let when = draw::truncate_text(&when, small_font, area.width);
+            canvas.draw_text(area.x, y, &summary, color, self.font_size);
+            canvas.draw_text(
+                area.x,
+                y + self.font_size as i32 + 3,
+                &when,
+                secondary_color,
+                small_font,
+            );
+        }
+    }
+
+    /// Take the latest occurrences from the loader; returns true if they
+    /// changed
+    fn refresh(&mut self) -> bool {
+        let state = self.state.lock().unwrap();
+        if *state == self.shown {
+            return false;
+        }
+        self.shown = state.clone();
+        drop(state);
+
+        let tz = TimeZone::system();
+        self.event_days.clear();
+        for occurrence in &self.shown {
+            let mut day = occurrence.start.to_zoned(tz.clone()).date();
+            let end = occurrence.end.to_zoned(tz.clone());
+            // An event ending at midnight doesn't reach into that day
+            let last = if end.time() == Time::midnight() && occurrence.end > occurrence.start {
+                end.date().yesterday().unwrap_or(day)
+            } else {
+                end.date()
+            };
+            while day <= last {
+                self.event_days.insert(day);
+                match day.tomorrow() {
+                    Ok(next) => day = next,
+                    Err(_) => break,
+                }
+            }
+        }
+        true
+    }
+}
+
+impl Module for CalendarModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        // `paths` may be a single path or a list
+        match config.get("paths") {
+            Some(toml::Value::String(path)) => self.paths = vec![resolve_path(Path::new(path))],
+            Some(toml::Value::Array(paths)) => {
+                self.paths = paths
+                    .iter()
+                    .filter_map(|path| path.as_str())
+                    .map(|path| resolve_path(Path::new(path)))
+                    .collect()
+            }
+            _ => {}
+        }
+        if let Some(secs) = config.get("interval").and_then(|v| v.as_integer()) {
+            self.interval = Duration::from_secs(secs.max(1) as u64);
+        }
+        if let Some(days) = config.get("lookahead_days").and_then(|v| v.as_integer()) {
+            self.lookahead_days = days.clamp(1, 366);
+        }
+        if let Some(name) = config.get("week_start").and_then(|v| v.as_str()) {
+            match parse_weekday(name) {
+                Some(weekday) => self.week_start = weekday,
+                None => warn!("CalendarModule: unknown week_start {:?}", name),
+            }
+        }
+        if let Some(format) = config.get("time_format").and_then(|v| v.as_str()) {
+            // Formatting with an unknown directive fails, and would on
+            // every draw, so try it once here
+            match strtime::format(format, &Zoned::now()) {
+                Ok(_) => self.time_format = format.to_string(),
+                Err(e) => warn!(
+                    "CalendarModule: bad time_format {:?}, using {:?}: {}",
+                    format, DEFAULT_TIME_FORMAT, e
+                ),
+            }
+        }
+        if let Some(count) = config.get("max_events").and_then(|v| v.as_integer()) {
+            self.max_events = count.max(0) as usize;
+        }
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "secondary_color") {
+            self.secondary_color = color;
+        }
+        if let Some(color) = color_from_config(config, "today_color") {
+            self.today_color = color;
+        }
+        if let Some(color) = color_from_config(config, "today_text_color") {
+            self.today_text_color = color;
+        }
+        if let Some(color) = color_from_config(config, "event_color") {
+            self.event_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+
+        if self.paths.is_empty() {
+            info!("CalendarModule: no calendars configured, showing the month only");
+            return Ok(());
+        }
+        let state = self.state.clone();
+        let paths = self.paths.clone();
+        let interval = self.interval;
+        let lookahead_days = self.lookahead_days;
+        thread::Builder::new()
+            .name("calendar".to_string())
+            .spawn(move || run_loader(state, paths, interval, lookahead_days))?;
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let today = Zoned::now().date();
+        let grid_width = self.draw_month(canvas, area, today);
+        let agenda_x = area.x + grid_width as i32 + 20;
+        let agenda = Rect {
+            x: agenda_x,
+            y: area.y,
+            width: (area.x + area.width as i32 - agenda_x).max(0) as u32,
+            height: area.height,
+        };
+        if agenda.width > 0 {
+            self.draw_agenda(canvas, agenda);
+        }
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(text) = self.compact_text() else {
+            return Ok(());
+        };
+        let color = self.color.resolve(canvas.theme());
+        let font_size = self.font_size * 0.9;
+        let text = draw::truncate_text(&text, font_size, area.width);
+        let y = area.y + (area.height as i32 - font_size as i32) / 2;
+        canvas.draw_text(area.x, y, &text, color, font_size);
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, _area: Rect) -> bool {
+        match event {
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                let changed = self.refresh();
+                // Relative times like "now" move on with the clock
+                let minute = Timestamp::now().as_second() / 60;
+                let ticked = minute != self.minute;
+                self.minute = minute;
+                changed || ticked
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        (460, 190)
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        let text = self.compact_text()?;
+        let width = draw::text_width(&text, self.font_size * 0.9).min(240);
+        Some((width, 20))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    /// A calendar with one event called `summary`
+    fn ics(summary: &str) -> String {
+        format!(
+            "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:{summary}\r\nSUMMARY:{summary}\r\n\
+             DTSTART:20240101T090000Z\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n"
+        )
+    }
+
+    fn summaries(events: &[Event]) -> Vec<&str> {
+        let mut summaries: Vec<&str> = events.iter().map(|e| e.summary.as_str()).collect();
+        summaries.sort();
+        summaries
+    }
+
+    /// Set the modification time of `path`, so a rewrite counts as a
+    /// change however quickly it follows the last one
+    fn touch(path: &Path, secs: u64) {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn calendar_files_are_found_in_directories() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir_all(root.join("work/.hidden")).unwrap();
+        fs::create_dir(root.join("home")).unwrap();
+        for file in [
+            "work/a.ics",
+            "work/b.ICS",
+            "work/notes.txt",
+            "work/.status.ics",
+            "work/.hidden/c.ics",
+            "home/d.ics",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        assert_eq!(
+            calendar_files(root),
+            [
+                root.join("home/d.ics"),
+                root.join("work/a.ics"),
+                root.join("work/b.ICS"),
+            ]
+        );
+        // A file is taken as it is, whatever its name
+        assert_eq!(
+            calendar_files(&root.join("work/notes.txt")),
+            [root.join("work/notes.txt")]
+        );
+        // And so is a missing one, which the cache then skips
+        assert_eq!(
+            calendar_files(&root.join("missing.ics")),
+            [root.join("missing.ics")]
+        );
+    }
+
+    #[test]
+    fn file_cache_rereads_changed_files_only() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let (a, b) = (root.join("a.ics"), root.join("b.ics"));
+        fs::write(&a, ics("First")).unwrap();
+        touch(&a, 1000);
+        fs::write(&b, ics("Second")).unwrap();
+        touch(&b, 1000);
+
+        let mut cache = FileCache::default();
+        let paths = [root.to_path_buf()];
+        assert_eq!(
+            summaries(&cache.load(&paths, &TimeZone::UTC)),
+            ["First", "Second"]
+        );
+
+        // Unchanged modification times keep the parsed events
+        fs::write(&a, ics("Rewritten")).unwrap();
+        touch(&a, 1000);
+        assert_eq!(
+            summaries(&cache.load(&paths, &TimeZone::UTC)),
+            ["First", "Second"]
+        );
+
+        touch(&a, 2000);
+        assert_eq!(
+            summaries(&cache.load(&paths, &TimeZone::UTC)),
+            ["Rewritten", "Second"]
+        );
+
+        // Removed files are dropped from the cache
+        fs::remove_file(&b).unwrap();
+        assert_eq!(
+            summaries(&cache.load(&paths, &TimeZone::UTC)),
+            ["Rewritten"]
+        );
+        assert_eq!(cache.files.len(), 1);
+
+        // A path that doesn't exist has no events
+        assert!(cache
+            .load(&[root.join("missing.ics")], &TimeZone::UTC)
+            .is_empty());
+        assert!(cache.files.is_empty());
+    }
+
+    #[test]
+    fn bad_time_formats_fall_back() {
+        let mut calendar = CalendarModule::new();
+        let config = toml::toml! { time_format = "%I:%M %p" };
+        calendar.init(&config).unwrap();
+        assert_eq!(calendar.time_format, "%I:%M %p");
+
+        let config = toml::toml! { time_format = "%H:%!" };
+        calendar.init(&config).unwrap();
+        assert_eq!(calendar.time_format, "%I:%M %p");
+
+        let mut calendar = CalendarModule::new();
+        calendar.init(&config).unwrap();
+        assert_eq!(calendar.time_format, DEFAULT_TIME_FORMAT);
+    }
+}
@@ -0,0 +1,313 @@
+//! Clipboard history module for hypr-notch
+//!
+//! Lists recently copied text and images in the expanded notch. Clicking
+//! an entry makes it the current selection again and right-clicking removes
+//! it from the history. `hypr-notch msg clipboard clear` forgets
+//! everything.
+
+use crate::clipboard::{self, Command, History, Options, THUMBNAIL_WIDTH};
+use crate::config::{color_from_config, resolve_path};
+use crate::draw::{self, Canvas};
+use crate::image::{Filter, RgbaImage};
+use crate::module::{self, Module, ModuleEvent, Rect};
+use crate::theme::ThemeColor;
+use calloop::channel::Sender;
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Linux input event codes for the mouse buttons
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+
+/// Height of one entry in the expanded layout
+const ROW_HEIGHT: u32 = 30;
+
+/// One entry as drawn
+struct Row {
+    id: u64,
+    label: String,
+    thumbnail: Option<Arc<RgbaImage>>,
+    copied: i64,
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Short age such as `now`, `5m`, `2h` or `3d`
+fn format_age(seconds: i64) -> String {
+    match seconds.max(0) {
+        0..60 => "now".to_string(),
+        seconds @ 60..3600 => format!("{}m", seconds / 60),
+        seconds @ 3600..86400 => format!("{}h", seconds / 3600),
+        seconds => format!("{}d", seconds / 86400),
+    }
+}
+
+pub struct ClipboardModule {
+    id: String,
+    name: String,
+    history: Arc<Mutex<History>>,
+    commands: Option<Sender<Command>>,
+    /// Entries as of the last update, which is what is drawn
+    rows: Vec<Row>,
+    version: u64,
+    /// Minute of the last redraw, so ages stay current
+    minute: i64,
+    max_entries: usize,
+    max_visible: usize,
+    max_entry_size: usize,
+    ignore_mime_types: Vec<String>,
+    persist: bool,
+    history_dir: Option<PathBuf>,
+    color: ThemeColor,
+    secondary_color: ThemeColor,
+    font_size: f32,
+}
+
+impl Default for ClipboardModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClipboardModule {
+    pub fn new() -> Self {
+        Self {
+            id: "clipboard".to_string(),
+            name: "Clipboard".to_string(),
+            history: Arc::new(Mutex::new(History::new(30))),
+            commands: None,
+            rows: Vec::new(),
+            version: 0,
+            minute: 0,
+            max_entries: 30,
+            max_visible: 8,
+            max_entry_size: 5 * 1024 * 1024,
+            ignore_mime_types: Vec::new(),
+            persist: false,
+            history_dir: dirs::state_dir().map(|dir| dir.join("hypr-notch").join("clipboard")),
+            color: ThemeColor::named("foreground"),
+            secondary_color: ThemeColor::named("muted"),
+            font_size: 14.0,
+        }
+    }
+
+    fn send(&self, command: Command) {
+        let Some(commands) = &self.commands else {
+            return;
+        };
+        if commands.send(command).is_err() {
+            warn!("ClipboardModule: clipboard thread is gone");
+        }
+    }
+
+    /// Take the latest entries from the history; returns true if they
+    /// changed
+    fn refresh(&mut self) -> bool {
+        let history = self.history.lock().unwrap();
+        if history.version() == self.version {
+            return false;
+        }
+        self.version = history.version();
+
+        self.rows = history
+            .entries()
+            .take(self.max_visible)
+            .map(|entry| {
+                let label = match &entry.thumbnail {
+                    Some(thumbnail) => format!("Image {}×{}", thumbnail.width, thumbnail.height),
+                    None if entry.is_image() => format!("Image ({})", entry.mime_type),
+                    None => entry.preview().unwrap_or_default(),
+                };
+                Row {
+                    id: entry.id,
+                    label,
+                    thumbnail: entry.thumbnail.as_ref().map(|t| t.image.clone()),
+                    copied: entry.copied,
+                }
+            })
+            .collect();
+        true
+    }
+
+    fn row_rect(&self, area: Rect, index: usize) -> Rect {
+        Rect {
+            x: area.x,
+            y: area.y + (index as u32 * ROW_HEIGHT) as i32,
+            width: area.width,
+            height: ROW_HEIGHT,
+        }
+    }
+
+    fn row_at(&self, area: Rect, x: f64, y: f64) -> Option<u64> {
+        (0..self.rows.len())
+            .find(|&index| self.row_rect(area, index).contains(x, y))
+            .map(|index| self.rows[index].id)
+    }
+
+    fn command(&mut self, args: &[String]) -> bool {
+        match args.first().map(String::as_str) {
+            Some("clear") => {
+                self.send(Command::Clear);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Module for ClipboardModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(count) = config.get("max_entries").and_then(|v| v.as_integer()) {
+            self.max_entries = count.max(1) as usize;
+        }
+        if let Some(count) = config.get("max_visible").and_then(|v| v.as_integer()) {
+            self.max_visible = count.max(1) as usize;
+        }
+        if let Some(kb) = config.get("max_entry_kb").and_then(|v| v.as_integer()) {
+            self.max_entry_size = kb.max(1) as usize * 1024;
+        }
+        if let Some(types) = config.get("ignore_mime_types").and_then(|v| v.as_array()) {
+            self.ignore_mime_types = types
+                .iter()
+                .filter_map(|v| v.as_str())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(persist) = config.get("persist").and_then(|v| v.as_bool()) {
+            self.persist = persist;
+        }
+        if let Some(dir) = config.get("history_dir").and_then(|v| v.as_str()) {
+            self.history_dir = Some(resolve_path(Path::new(dir)));
+        }
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "secondary_color") {
+            self.secondary_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+
+        // A saved history is loaded by the clipboard thread
+        let history_dir = self.history_dir.clone().filter(|_| self.persist);
+        *self.history.lock().unwrap() = History::new(self.max_entries);
+        let options = Options {
+            max_entry_size: self.max_entry_size,
+            ignore_mime_types: self.ignore_mime_types.clone(),
+            history_dir,
+        };
+        self.commands = Some(clipboard::spawn(
+            self.history.clone(),
+            options,
+            module::request_update,
+        )?);
+        info!(
+            "ClipboardModule: recording up to {} entries",
+            self.max_entries
+        );
+        self.refresh();
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let secondary_color = self.secondary_color.resolve(canvas.theme());
+        if self.rows.is_empty() {
+            canvas.draw_text(
+                area.x,
+                area.y + 8,
+                "Clipboard is empty",
+                secondary_color,
+                self.font_size,
+            );
+            return Ok(());
+        }
+
+        let now = now_secs();
+        let small_font = self.font_size * 0.85;
+        for (index, row) in self.rows.iter().enumerate() {
+            let rect = self.row_rect(area, index);
+            let age = format_age(now - row.copied);
+            let age_width = draw::text_width(&age, small_font);
+            let mut left = rect.x;
+            if let Some(image) = &row.thumbnail {
+                let thumbnail = Rect {
+                    x: rect.x,
+                    y: rect.y + (rect.height as i32 - image.height as i32) / 2,
+                    width: image.width,
+                    height: image.height,
+                };
+                canvas.draw_image(image, thumbnail, Filter::Smooth);
+                left += THUMBNAIL_WIDTH as i32 + 8;
+            }
+
+            let text_width = (rect.x + rect.width as i32 - left) as u32;
+            let label = draw::truncate_text(
+                &row.label,
+                self.font_size,
+                text_width.saturating_sub(age_width + 12),
+            );
+            let text_y = rect.y + (rect.height as i32 - self.font_size as i32) / 2;
+            // The newest entry is the current selection
+            let label_color = if index == 0 { color } else { secondary_color };
+            canvas.draw_text(left, text_y, &label, label_color, self.font_size);
+            canvas.draw_text(
+                rect.x + rect.width as i32 - age_width as i32,
+                text_y + 1,
+                &age,
+                secondary_color,
+                small_font,
+            );
+        }
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, area: Rect) -> bool {
+        match event {
+            ModuleEvent::Press { button, x, y } => {
+                let Some(id) = self.row_at(area, *x, *y) else {
+                    return false;
+                };
+                match *button {
+                    BTN_LEFT => self.send(Command::Restore(id)),
+                    BTN_RIGHT => self.send(Command::Remove(id)),
+                    _ => return false,
+                }
+                true
+            }
+            ModuleEvent::Command { module, args, .. } if *module == self.id => self.command(args),
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                let changed = self.refresh();
+                let minute = now_secs() / 60;
+                let aged = minute != self.minute && !self.rows.is_empty();
+                self.minute = minute;
+                changed || aged
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        (360, self.rows.len().max(1) as u32 * ROW_HEIGHT)
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
@@ -3,17 +3,25 @@
 //!
 //! Displays the current time in the notch.
 
+use crate::config::color_from_config;
 use crate::draw::Canvas;
 use crate::module::{Module, ModuleEvent, Rect};
+use crate::theme::ThemeColor;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub struct ClockModule {
     id: String,
     name: String,
-    color: [u8; 4],
+    color: ThemeColor,
     format: String,
     font_size: f32,
-    background_color: [u8; 4],
+    background_color: ThemeColor,
+}
+
+impl Default for ClockModule {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ClockModule {
@@ -21,10 +29,10 @@ impl ClockModule {
         Self {
             id: "clock".to_string(),
             name: "Clock".to_string(),
-            color: [255, 255, 255, 255], // White
+            color: ThemeColor::named("foreground"),
             format: "%H:%M:%S".to_string(),
             font_size: 16.0,
-            background_color: [50, 50, 50, 200], // Semi-transparent dark gray
+            background_color: ThemeColor::named("surface"),
         }
     }
 
@@ -54,14 +62,8 @@ impl Module for ClockModule {
 
     fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
         // Parse color from config if present
-        if let Some(color) = config.get("color").and_then(|v| v.as_array()) {
-            if color.len() >= 4 {
-                for (i, component) in color.iter().take(4).enumerate() {
-                    if let Some(val) = component.as_integer() {
-                        self.color[i] = val as u8;
-                    }
-                }
-            }
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
         }
 
         // Parse format from config if present
@@ -78,19 +80,15 @@ impl Module for ClockModule {
     }
 
     fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let background_color = self.background_color.resolve(canvas.theme());
         // Fill background
-        canvas.fill_rect(
-            area.x,
-            area.y,
-            area.width,
-            area.height,
-            self.background_color,
-        );
+        canvas.fill_rect(area.x, area.y, area.width, area.height, background_color);
 
         // Draw time text
         let time_str = self.get_current_time();
         let y_pos = area.y + ((area.height as i32 - self.font_size as i32) / 2);
-        canvas.draw_text(area.x + 10, y_pos, &time_str, self.color, self.font_size);
+        canvas.draw_text(area.x + 10, y_pos, &time_str, color, self.font_size);
 
         Ok(())
     }
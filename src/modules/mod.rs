@@ -3,7 +3,62 @@
 //!
 //! This module contains all the built-in modules that come with hypr-notch.
 
+pub mod active_window;
+pub mod audio;
+pub mod battery;
+pub mod bluetooth;
+pub mod brightness;
+pub mod calendar;
+pub mod clipboard;
 pub mod clock;
+pub mod mpris;
+pub mod network;
+pub mod notifications;
+pub mod privacy;
+pub mod sysmon;
+pub mod timer;
+pub mod tray;
+pub mod workspaces;
+
+use crate::module::Module;
 
 // Re-export all modules for convenience
+pub use active_window::ActiveWindowModule;
+pub use audio::AudioModule;
+pub use battery::BatteryModule;
+pub use bluetooth::BluetoothModule;
+pub use brightness::BrightnessModule;
+pub use calendar::CalendarModule;
+pub use clipboard::ClipboardModule;
 pub use clock::ClockModule;
+pub use mpris::MprisModule;
+pub use network::NetworkModule;
+pub use notifications::NotificationsModule;
+pub use privacy::PrivacyModule;
+pub use sysmon::SysmonModule;
+pub use timer::TimerModule;
+pub use tray::TrayModule;
+pub use workspaces::WorkspacesModule;
+
+/// Create a built-in module from its name in the `enabled` list
+pub fn create_module(name: &str) -> Option<Box<dyn Module>> {
+    match name {
+        "active_window" => Some(Box::new(ActiveWindowModule::new())),
+        "audio" => Some(Box::new(AudioModule::new())),
+        "battery" => Some(Box::new(BatteryModule::new())),
+        "bluetooth" => Some(Box::new(BluetoothModule::new())),
+        "brightness" => Some(Box::new(BrightnessModule::new())),
+        "calendar" => Some(Box::new(CalendarModule::new())),
+        "clipboard" => Some(Box::new(ClipboardModule::new())),
+        "clock" => Some(Box::new(ClockModule::new())),
+        "mpris" => Some(Box::new(MprisModule::new())),
+        "network" => Some(Box::new(NetworkModule::new())),
+        "notifications" => Some(Box::new(NotificationsModule::new())),
+        "privacy" => Some(Box::new(PrivacyModule::new())),
+        "sysmon" => Some(Box::new(SysmonModule::new())),
+        "timer" => Some(Box::new(TimerModule::new())),
+        "tray" => Some(Box::new(TrayModule::new())),
+        "workspaces" => Some(Box::new(WorkspacesModule::new())),
+        _ => None,
+    }
+}
@@ -0,0 +1,663 @@
+//! Media player module for hypr-notch
+//!
+//! Talks to MPRIS players (`org.mpris.MediaPlayer2.*`) on the session bus.
+//! A background thread polls the active player and owns the D-Bus
+//! connection; the module only reads the shared state and sends commands,
+//! so a slow player never blocks drawing.
+
+use crate::config::color_from_config;
+use crate::draw::{Canvas, Path};
+use crate::image::{self, Filter, RgbaImage};
+use crate::module::{Module, ModuleEvent, Rect};
+use crate::theme::ThemeColor;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use zbus::blocking::{fdo::DBusProxy, Connection, Proxy};
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{OwnedValue, Value};
+
+pub const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+pub const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+pub const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// Size album art is decoded at, twice the largest size it is drawn at
+const ART_SIZE: u32 = 180;
+
+/// Linux input event code of the left mouse button
+const BTN_LEFT: u32 = 0x110;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    #[default]
+    Stopped,
+}
+
+impl PlaybackStatus {
+    fn parse(status: &str) -> Self {
+        match status {
+            "Playing" => Self::Playing,
+            "Paused" => Self::Paused,
+            _ => Self::Stopped,
+        }
+    }
+}
+
+/// Snapshot of the active player, refreshed by the background thread
+#[derive(Debug, Clone, Default)]
+pub struct PlayerState {
+    pub bus_name: String,
+    pub status: PlaybackStatus,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub length: Option<Duration>,
+    pub position: Duration,
+    /// When `position` was read, for interpolating between polls
+    pub position_read: Option<Instant>,
+    /// `mpris:artUrl` of the current track
+    pub art_url: String,
+    /// Decoded album art, kept until the art URL changes
+    pub art: Option<Arc<RgbaImage>>,
+}
+
+impl PlayerState {
+    /// Playback position extrapolated to now while playing
+    pub fn current_position(&self) -> Duration {
+        let mut position = self.position;
+        if self.status == PlaybackStatus::Playing {
+            if let Some(read) = self.position_read {
+                position += read.elapsed();
+            }
+        }
+        match self.length {
+            Some(length) => position.min(length),
+            None => position,
+        }
+    }
+}
+
+/// Commands sent from the module to the background thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerCommand {
+    Previous,
+    PlayPause,
+    Next,
+}
+
+impl PlayerCommand {
+    fn method(&self) -> &'static str {
+        match self {
+            Self::Previous => "Previous",
+            Self::PlayPause => "PlayPause",
+            Self::Next => "Next",
+        }
+    }
+}
+
+fn metadata_str(metadata: &HashMap<String, OwnedValue>, key: &str) -> Option<String> {
+    metadata
+        .get(key)
+        .and_then(|v| v.downcast_ref::<&str>().ok())
+        .map(|s| s.to_string())
+}
+
+fn metadata_artists(metadata: &HashMap<String, OwnedValue>) -> String {
+    match metadata.get("xesam:artist").map(|v| &**v) {
+        Some(Value::Array(artists)) => artists
+            .inner()
+            .iter()
+            .filter_map(|v| v.downcast_ref::<&str>().ok())
+            .collect::<Vec<_>>()
+            .join(", "),
+        // Some players send a plain string despite the spec
+        Some(Value::Str(artist)) => artist.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn metadata_length(metadata: &HashMap<String, OwnedValue>) -> Option<Duration> {
+    let micros = match metadata.get("mpris:length").map(|v| &**v)? {
+        Value::I64(v) => (*v).max(0) as u64,
+        Value::U64(v) => *v,
+        _ => return None,
+    };
+    (micros > 0).then(|| Duration::from_micros(micros))
+}
+
+fn player_proxy<'a>(conn: &Connection, bus_name: &'a str) -> zbus::Result<Proxy<'a>> {
+    zbus::blocking::proxy::Builder::new(conn)
+        .destination(bus_name)?
+        .path(MPRIS_PATH)?
+        .interface(PLAYER_INTERFACE)?
+        .cache_properties(CacheProperties::No)
+        .build()
+}
+
+/// Read the full state of one player
+fn read_player(conn: &Connection, bus_name: &str) -> zbus::Result<PlayerState> {
+    let proxy = player_proxy(conn, bus_name)?;
+    let status: String = proxy.get_property("PlaybackStatus")?;
+    let metadata: HashMap<String, OwnedValue> = proxy.get_property("Metadata")?;
+    // Position is optional for players that can't seek
+    let position: i64 = proxy.get_property("Position").unwrap_or(0);
+
+    Ok(PlayerState {
+        bus_name: bus_name.to_string(),
+        status: PlaybackStatus::parse(&status),
+        title: metadata_str(&metadata, "xesam:title").unwrap_or_default(),
+        artist: metadata_artists(&metadata),
+        album: metadata_str(&metadata, "xesam:album").unwrap_or_default(),
+        length: metadata_length(&metadata),
+        position: Duration::from_micros(position.max(0) as u64),
+        position_read: Some(Instant::now()),
+        art_url: metadata_str(&metadata, "mpris:artUrl").unwrap_or_default(),
+        art: None,
+    })
+}
+
+/// Pick the player to show: a playing one first, then the preferred
+/// player, then whichever was shown before
+fn select_player(
+    players: Vec<PlayerState>,
+    preferred: Option<&str>,
+    previous: Option<&str>,
+) -> Option<PlayerState> {
+    let rank = |p: &PlayerState| {
+        (
+            p.status == PlaybackStatus::Playing,
+            preferred.is_some_and(|name| p.bus_name.contains(name)),
+            previous == Some(p.bus_name.as_str()),
+        )
+    };
+    players.into_iter().max_by_key(rank)
+}
+
+/// Background thread: poll players and forward commands
+fn run_client(
+    state: Arc<Mutex<Option<PlayerState>>>,
+    commands: Receiver<PlayerCommand>,
+    preferred: Option<String>,
+    poll_interval: Duration,
+) {
+    loop {
+        let conn = match Connection::session() {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("MprisModule: could not connect to session bus: {}", e);
+                thread::sleep(Duration::from_secs(5));
+                continue;
+            }
+        };
+        info!("MprisModule: connected to session bus");
+
+        match poll_loop(
+            &conn,
+            &state,
+            &commands,
+            preferred.as_deref(),
+            poll_interval,
+        ) {
+            Ok(()) => return, // The module was dropped
+            Err(e) => {
+                warn!("MprisModule: D-Bus error, reconnecting: {}", e);
+                thread::sleep(Duration::from_secs(1));
+            }
+        }
+    }
+}
+
+/// Publish the player to show to `state` every `poll_interval` and pass
+/// `commands` on to it, until the command sender is dropped
+pub fn poll_loop(
+    conn: &Connection,
+    state: &Mutex<Option<PlayerState>>,
+    commands: &Receiver<PlayerCommand>,
+    preferred: Option<&str>,
+    poll_interval: Duration,
+) -> zbus::Result<()> {
+    let dbus = DBusProxy::new(conn)?;
+
+    loop {
+        let players: Vec<PlayerState> = dbus
+            .list_names()?
+            .into_iter()
+            .filter(|name| name.starts_with(MPRIS_PREFIX))
+            .filter_map(|name| match read_player(conn, name.as_str()) {
+                Ok(player) => Some(player),
+                Err(e) => {
+                    debug!("MprisModule: skipping {}: {}", name, e);
+                    None
+                }
+            })
+            .collect();
+
+        let (previous, previous_art) = match state.lock().unwrap().as_ref() {
+            Some(p) => (
+                Some(p.bus_name.clone()),
+                Some((p.art_url.clone(), p.art.clone())),
+            ),
+            None => (None, None),
+        };
+        let mut selected = select_player(players, preferred, previous.as_deref());
+        if let Some(player) = &mut selected {
+            player.art = match previous_art {
+                Some((url, art)) if url == player.art_url => art,
+                _ => load_art(&player.art_url),
+            };
+        }
+        *state.lock().unwrap() = selected;
+
+        // Wait for the next poll; a command cuts the wait short so the
+        // refreshed state reflects it right away
+        match commands.recv_timeout(poll_interval) {
+            Ok(command) => {
+                let bus_name = state.lock().unwrap().as_ref().map(|p| p.bus_name.clone());
+                if let Some(bus_name) = bus_name {
+                    debug!("MprisModule: {} -> {}", command.method(), bus_name);
+                    player_proxy(conn, &bus_name)?.call_method(command.method(), &())?;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+/// Decode album art from a local `file://` URL
+fn load_art(url: &str) -> Option<Arc<RgbaImage>> {
+    if url.is_empty() {
+        return None;
+    }
+    let Some(path) = url.strip_prefix("file://") else {
+        debug!("MprisModule: skipping remote album art {}", url);
+        return None;
+    };
+    let path = percent_decode(path);
+    match image::load_image(std::path::Path::new(&path), ART_SIZE) {
+        Ok(art) => Some(Arc::new(art)),
+        Err(e) => {
+            debug!("MprisModule: failed to load album art {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Undo the `%XX` escapes of a URI path
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn format_time(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+pub struct MprisModule {
+    id: String,
+    name: String,
+    state: Arc<Mutex<Option<PlayerState>>>,
+    commands: Option<Sender<PlayerCommand>>,
+    color: ThemeColor,
+    secondary_color: ThemeColor,
+    accent_color: ThemeColor,
+    button_color: ThemeColor,
+    font_size: f32,
+}
+
+impl Default for MprisModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MprisModule {
+    pub fn new() -> Self {
+        Self {
+            id: "mpris".to_string(),
+            name: "Media Player".to_string(),
+            state: Arc::new(Mutex::new(None)),
+            commands: None,
+            color: ThemeColor::named("foreground"),
+            secondary_color: ThemeColor::named("muted"),
+            accent_color: ThemeColor::named("accent"),
+            button_color: ThemeColor::named("surface"),
+            font_size: 16.0,
+        }
+    }
+
+    fn player(&self) -> Option<PlayerState> {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Previous / play-pause / next button areas in the expanded layout
+    fn button_rects(&self, area: Rect) -> [(PlayerCommand, Rect); 3] {
+        let (width, height) = (36u32, 24u32);
+        let y = area.y + area.height as i32 - height as i32;
+        let x = area.x + 100;
+        let rect = |i: i32| Rect {
+            x: x + i * (width as i32 + 8),
+            y,
+            width,
+            height,
+        };
+        [
+            (PlayerCommand::Previous, rect(0)),
+            (PlayerCommand::PlayPause, rect(1)),
+            (PlayerCommand::Next, rect(2)),
+        ]
+    }
+
+    fn send(&self, command: PlayerCommand) {
+        if let Some(commands) = &self.commands {
+            if commands.send(command).is_err() {
+                warn!("MprisModule: player thread is gone");
+            }
+        }
+    }
+}
+
+/// Draw a music note centred in `area`
+fn draw_note(canvas: &mut Canvas, area: Rect, color: [u8; 4]) {
+    let size = area.width.min(area.height) as f32 / 3.0;
+    let cx = area.x as f32 + area.width as f32 / 2.0;
+    let cy = area.y as f32 + area.height as f32 / 2.0;
+    let head_radius = size * 0.22;
+    let (head_x, head_y) = (cx - size * 0.2, cy + size * 0.3);
+    let stem_x = head_x + head_radius - 0.75;
+    let stem_top = cy - size * 0.5;
+
+    canvas.fill_circle(head_x, head_y, head_radius, color);
+    canvas.draw_line(stem_x, head_y, stem_x, stem_top, 1.5, color);
+    // Flag curling down from the top of the stem
+    let mut flag = Path::new();
+    flag.move_to(stem_x, stem_top)
+        .cubic_to(
+            (stem_x + size * 0.1, stem_top + size * 0.25),
+            (stem_x + size * 0.45, stem_top + size * 0.2),
+            stem_x + size * 0.35,
+            stem_top + size * 0.55,
+        )
+        .cubic_to(
+            (stem_x + size * 0.3, stem_top + size * 0.35),
+            (stem_x + size * 0.1, stem_top + size * 0.35),
+            stem_x,
+            stem_top + size * 0.3,
+        )
+        .close();
+    canvas.fill_path(&flag, color);
+}
+
+/// Draw the previous / play / pause / next symbol centred in `rect`
+fn draw_transport_icon(
+    canvas: &mut Canvas,
+    command: PlayerCommand,
+    playing: bool,
+    rect: Rect,
+    color: [u8; 4],
+) {
+    let cx = rect.x as f32 + rect.width as f32 / 2.0;
+    let cy = rect.y as f32 + rect.height as f32 / 2.0;
+    let half = rect.height as f32 / 5.0;
+
+    match command {
+        PlayerCommand::PlayPause if playing => {
+            for x in [cx - half * 0.5, cx + half * 0.5] {
+                canvas.draw_line(x, cy - half, x, cy + half, half * 0.5, color);
+            }
+        }
+        PlayerCommand::PlayPause => {
+            let triangle = [
+                (cx - half * 0.7, cy - half),
+                (cx + half, cy),
+                (cx - half * 0.7, cy + half),
+            ];
+            canvas.fill_path(&Path::polygon(&triangle), color);
+        }
+        PlayerCommand::Previous | PlayerCommand::Next => {
+            // Mirror the "next" symbol for "previous"
+            let direction = if command == PlayerCommand::Next {
+                1.0
+            } else {
+                -1.0
+            };
+            let triangle = [
+                (cx - direction * half * 0.8, cy - half),
+                (cx + direction * half * 0.6, cy),
+                (cx - direction * half * 0.8, cy + half),
+            ];
+            canvas.fill_path(&Path::polygon(&triangle), color);
+            let bar_x = cx + direction * half * 0.8;
+            canvas.draw_line(bar_x, cy - half, bar_x, cy + half, 2.0, color);
+        }
+    }
+}
+
+impl Module for MprisModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "accent_color") {
+            self.accent_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+        let preferred = config
+            .get("player")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+        let poll_interval = config
+            .get("poll_interval_ms")
+            .and_then(|v| v.as_integer())
+            .map(|ms| Duration::from_millis(ms.max(100) as u64))
+            .unwrap_or(Duration::from_secs(1));
+
+        let (sender, receiver) = mpsc::channel();
+        let state = self.state.clone();
+        thread::Builder::new()
+            .name("mpris".to_string())
+            .spawn(move || run_client(state, receiver, preferred, poll_interval))?;
+        self.commands = Some(sender);
+
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let secondary_color = self.secondary_color.resolve(canvas.theme());
+        let accent_color = self.accent_color.resolve(canvas.theme());
+        let button_color = self.button_color.resolve(canvas.theme());
+        let Some(player) = self.player() else {
+            canvas.draw_text(
+                area.x + 10,
+                area.y,
+                "Nothing playing",
+                secondary_color,
+                self.font_size,
+            );
+            return Ok(());
+        };
+
+        // Album art placeholder
+        let art_size = area.height.min(90);
+        let art = Rect {
+            x: area.x,
+            y: area.y,
+            width: art_size,
+            height: art_size,
+        };
+        match &player.art {
+            Some(image) => canvas.draw_image(image, art, Filter::Smooth),
+            None => {
+                canvas.fill_rounded_rect(art, 6.0, button_color);
+                draw_note(canvas, art, accent_color);
+            }
+        }
+
+        // Title and artist/album
+        let text_x = area.x + art_size as i32 + 10;
+        canvas.draw_text(text_x, area.y, &player.title, color, self.font_size);
+        let subtitle = match (player.artist.is_empty(), player.album.is_empty()) {
+            (false, false) => format!("{} — {}", player.artist, player.album),
+            (false, true) => player.artist.clone(),
+            (true, false) => player.album.clone(),
+            (true, true) => String::new(),
+        };
+        let small_font = self.font_size * 0.8;
+        canvas.draw_text(
+            text_x,
+            area.y + self.font_size as i32 + 6,
+            &subtitle,
+            secondary_color,
+            small_font,
+        );
+
+        // Progress bar
+        if let Some(length) = player.length {
+            let position = player.current_position();
+            let bar_y = area.y + 2 * (self.font_size as i32 + 6);
+            let bar_width = (area.x + area.width as i32 - text_x - 90).max(0) as u32;
+            let progress = position.as_secs_f32() / length.as_secs_f32().max(1.0);
+            let bar = Rect {
+                x: text_x,
+                y: bar_y + 6,
+                width: bar_width,
+                height: 4,
+            };
+            canvas.fill_rounded_rect(bar, 2.0, button_color);
+            let elapsed = Rect {
+                width: (bar_width as f32 * progress.clamp(0.0, 1.0)) as u32,
+                ..bar
+            };
+            canvas.fill_rounded_rect(elapsed, 2.0, accent_color);
+            let times = format!("{} / {}", format_time(position), format_time(length));
+            canvas.draw_text(
+                text_x + bar_width as i32 + 8,
+                bar_y,
+                &times,
+                secondary_color,
+                small_font,
+            );
+        }
+
+        // Transport buttons
+        let playing = player.status == PlaybackStatus::Playing;
+        for (command, rect) in self.button_rects(area) {
+            canvas.fill_rounded_rect(rect, 6.0, button_color);
+            draw_transport_icon(canvas, command, playing, rect, color);
+        }
+
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let accent_color = self.accent_color.resolve(canvas.theme());
+        let Some(player) = self.player() else {
+            return Ok(());
+        };
+
+        // Five-bar waveform that moves while playing and rests when paused
+        let bars = 5;
+        let bar_width = 3u32;
+        let tick = player.current_position().as_secs() as usize;
+        for i in 0..bars {
+            let level = if player.status == PlaybackStatus::Playing {
+                [0.4, 0.9, 0.6, 1.0, 0.5][(i + tick) % bars]
+            } else {
+                0.25
+            };
+            let height = ((area.height as f32 * level) as u32).max(2);
+            canvas.fill_rect(
+                area.x + (i as u32 * (bar_width + 2)) as i32,
+                area.y + (area.height - height) as i32 / 2,
+                bar_width,
+                height,
+                accent_color,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, area: Rect) -> bool {
+        match event {
+            ModuleEvent::Press { button, x, y } if *button == BTN_LEFT => {
+                let pressed = self
+                    .button_rects(area)
+                    .into_iter()
+                    .find(|(_, rect)| rect.contains(*x, *y));
+                let Some((command, _)) = pressed else {
+                    return false;
+                };
+
+                self.send(command);
+                // Flip the play state right away instead of waiting for the next poll
+                if command == PlayerCommand::PlayPause {
+                    if let Some(player) = self.state.lock().unwrap().as_mut() {
+                        player.position = player.current_position();
+                        player.position_read = Some(Instant::now());
+                        player.status = match player.status {
+                            PlaybackStatus::Playing => PlaybackStatus::Paused,
+                            _ => PlaybackStatus::Playing,
+                        };
+                    }
+                }
+                true
+            }
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                self.state.lock().unwrap().is_some()
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        (500, 90)
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        self.state.lock().unwrap().as_ref().map(|_| (25, 20))
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,609 @@
+//! Network throughput module for hypr-notch
+//!
+//! Reads byte counters from `/proc/net/dev`, link state from
+//! `/sys/class/net/*/operstate` and wifi signal from `/proc/net/wireless`,
+//! showing up/down rates in the collapsed notch and per-interface details
+//! with a rate history graph when expanded. With `show_ssid`, the network
+//! name of wireless interfaces is looked up with `iw` as well.
+
+use super::sysmon::History;
+use crate::config::color_from_config;
+use crate::draw::Canvas;
+use crate::module::{self, Module, ModuleEvent, Rect, UPDATE_INTERVAL};
+use crate::theme::ThemeColor;
+use log::{debug, info};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Byte counters for one interface from `/proc/net/dev`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceCounters {
+    pub name: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+/// Parse `/proc/net/dev`
+pub fn parse_net_dev(content: &str) -> Vec<InterfaceCounters> {
+    content
+        .lines()
+        .filter_map(|line| {
+            // The two header lines have no ':' before the first '|'
+            let (name, counters) = line.split_once(':')?;
+            let fields: Vec<u64> = counters
+                .split_whitespace()
+                .filter_map(|f| f.parse().ok())
+                .collect();
+            if fields.len() < 9 {
+                return None;
+            }
+            Some(InterfaceCounters {
+                name: name.trim().to_string(),
+                rx_bytes: fields[0],
+                tx_bytes: fields[8],
+            })
+        })
+        .collect()
+}
+
+/// Signal information for one wireless interface from `/proc/net/wireless`
+#[derive(Debug, Clone, PartialEq)]
+pub struct WirelessInfo {
+    pub name: String,
+    /// Link quality, usually out of 70
+    pub quality: f32,
+    /// Signal level in dBm
+    pub level: f32,
+}
+
+impl WirelessInfo {
+    /// Link quality as a percentage of the usual 70 maximum
+    pub fn quality_percent(&self) -> u8 {
+        (self.quality / 70.0 * 100.0).clamp(0.0, 100.0) as u8
+    }
+}
+
+/// Parse `/proc/net/wireless`
+pub fn parse_wireless(content: &str) -> Vec<WirelessInfo> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (name, rest) = line.split_once(':')?;
+            let mut fields = rest.split_whitespace();
+            let _status = fields.next()?;
+            // Values carry a trailing '.' when they were updated since the last read
+            let mut value = || fields.next()?.trim_end_matches('.').parse::<f32>().ok();
+            let quality = value()?;
+            let level = value()?;
+            Some(WirelessInfo {
+                name: name.trim().to_string(),
+                quality,
+                level,
+            })
+        })
+        .collect()
+}
+
+/// Extract the SSID from `iw dev <interface> link` output
+pub fn parse_iw_link(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("SSID: "))
+        .map(|ssid| ssid.to_string())
+}
+
+/// Query the SSID of every wireless interface in `/proc/net/wireless`
+/// with `iw`. Fails only when `iw` isn't installed.
+fn read_ssids(proc_path: &Path) -> io::Result<HashMap<String, String>> {
+    let wireless = fs::read_to_string(proc_path.join("net/wireless"))
+        .map(|content| parse_wireless(&content))
+        .unwrap_or_default();
+
+    let mut ssids = HashMap::new();
+    for interface in wireless {
+        let output = Command::new("iw")
+            .args(["dev", &interface.name, "link"])
+            .output();
+        match output {
+            Ok(output) if output.status.success() => {
+                if let Some(ssid) = parse_iw_link(&String::from_utf8_lossy(&output.stdout)) {
+                    ssids.insert(interface.name, ssid);
+                }
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(e),
+            Err(e) => debug!("NetworkModule: could not run iw: {}", e),
+        }
+    }
+    Ok(ssids)
+}
+
+/// Re-read the SSIDs every `interval` until the module drops the other end
+/// of `stop`, or for good if `iw` isn't installed. Spawning `iw` can take a
+/// while, so this runs on its own thread rather than on the update tick.
+fn run_ssid_reader(
+    proc_path: PathBuf,
+    ssids: Arc<Mutex<HashMap<String, String>>>,
+    interval: Duration,
+    stop: Receiver<()>,
+) {
+    loop {
+        let current = match read_ssids(&proc_path) {
+            Ok(current) => current,
+            Err(e) => {
+                info!("NetworkModule: not showing SSIDs, iw is unavailable: {}", e);
+                return;
+            }
+        };
+        {
+            let mut known = ssids.lock().unwrap();
+            if *known != current {
+                *known = current;
+                module::request_update();
+            }
+        }
+        match stop.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => {}
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Format a byte rate with a unit suffix, e.g. `1.2 MiB/s`
+pub fn format_rate(bytes_per_sec: f64) -> String {
+    const UNITS: [&str; 4] = ["B/s", "KiB/s", "MiB/s", "GiB/s"];
+    let mut value = bytes_per_sec.max(0.0);
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Current state of one interface
+#[derive(Debug, Clone)]
+struct InterfaceState {
+    name: String,
+    operstate: String,
+    rx_rate: f64,
+    tx_rate: f64,
+    wireless: Option<WirelessInfo>,
+}
+
+pub struct NetworkModule {
+    id: String,
+    name: String,
+    proc_path: PathBuf,
+    sysfs_path: PathBuf,
+    interfaces_filter: Vec<String>,
+    interval: Duration,
+    /// Look up SSIDs with `iw`
+    show_ssid: bool,
+    ssid_interval: Duration,
+    /// Dropped with the module, which stops the SSID thread
+    ssid_reader: Option<Sender<()>>,
+    last_sample: Option<Instant>,
+    prev_counters: HashMap<String, InterfaceCounters>,
+    interfaces: Vec<InterfaceState>,
+    ssids: Arc<Mutex<HashMap<String, String>>>,
+    rx_history: History,
+    tx_history: History,
+    color: ThemeColor,
+    rx_color: ThemeColor,
+    tx_color: ThemeColor,
+    graph_background: ThemeColor,
+    font_size: f32,
+}
+
+impl Default for NetworkModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkModule {
+    pub fn new() -> Self {
+        Self {
+            id: "network".to_string(),
+            name: "Network".to_string(),
+            proc_path: PathBuf::from("/proc"),
+            sysfs_path: PathBuf::from("/sys/class/net"),
+            interfaces_filter: Vec::new(),
+            interval: UPDATE_INTERVAL,
+            show_ssid: false,
+            ssid_interval: Duration::from_secs(30),
+            ssid_reader: None,
+            last_sample: None,
+            prev_counters: HashMap::new(),
+            interfaces: Vec::new(),
+            ssids: Arc::new(Mutex::new(HashMap::new())),
+            rx_history: History::new(60),
+            tx_history: History::new(60),
+            color: ThemeColor::named("foreground"),
+            rx_color: ThemeColor::named("success"),
+            tx_color: ThemeColor::named("accent"),
+            graph_background: ThemeColor::named("surface"),
+            font_size: 14.0,
+        }
+    }
+
+    fn is_selected(&self, name: &str) -> bool {
+        if self.interfaces_filter.is_empty() {
+            name != "lo"
+        } else {
+            self.interfaces_filter.iter().any(|f| f == name)
+        }
+    }
+
+    fn read_operstate(&self, name: &str) -> String {
+        fs::read_to_string(self.sysfs_path.join(name).join("operstate"))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_else(|_| "unknown".to_string())
+    }
+
+    /// Take a new sample if the sampling interval has elapsed
+    fn sample(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = match self.last_sample {
+            // Allow for jitter in the update tick so a 1s interval doesn't skip ticks
+            Some(last) if now.duration_since(last) + Duration::from_millis(50) < self.interval => {
+                return false;
+            }
+            Some(last) => Some(now.duration_since(last).as_secs_f64()),
+            None => None,
+        };
+        self.last_sample = Some(now);
+
+        let counters = fs::read_to_string(self.proc_path.join("net/dev"))
+            .map(|content| parse_net_dev(&content))
+            .unwrap_or_default();
+        let wireless = fs::read_to_string(self.proc_path.join("net/wireless"))
+            .map(|content| parse_wireless(&content))
+            .unwrap_or_default();
+
+        let mut interfaces = Vec::new();
+        let mut prev_counters = HashMap::new();
+        let (mut rx_total, mut tx_total) = (0.0, 0.0);
+
+        for counter in counters.into_iter().filter(|c| self.is_selected(&c.name)) {
+            let (rx_rate, tx_rate) = match (self.prev_counters.get(&counter.name), elapsed) {
+                (Some(prev), Some(elapsed)) if elapsed > 0.0 => (
+                    counter.rx_bytes.saturating_sub(prev.rx_bytes) as f64 / elapsed,
+                    counter.tx_bytes.saturating_sub(prev.tx_bytes) as f64 / elapsed,
+                ),
+                _ => (0.0, 0.0),
+            };
+            rx_total += rx_rate;
+            tx_total += tx_rate;
+
+            interfaces.push(InterfaceState {
+                name: counter.name.clone(),
+                operstate: self.read_operstate(&counter.name),
+                rx_rate,
+                tx_rate,
+                wireless: wireless.iter().find(|w| w.name == counter.name).cloned(),
+            });
+            prev_counters.insert(counter.name.clone(), counter);
+        }
+
+        if elapsed.is_some() {
+            self.rx_history.push(rx_total as f32);
+            self.tx_history.push(tx_total as f32);
+        }
+        self.prev_counters = prev_counters;
+        self.interfaces = interfaces;
+
+        true
+    }
+
+    fn total_rates(&self) -> (f64, f64) {
+        self.interfaces
+            .iter()
+            .fold((0.0, 0.0), |(rx, tx), i| (rx + i.rx_rate, tx + i.tx_rate))
+    }
+
+    fn draw_graph(&self, canvas: &mut Canvas, area: Rect, history: &History, color: [u8; 4]) {
+        let graph_background = self.graph_background.resolve(canvas.theme());
+        canvas.fill_rect(area.x, area.y, area.width, area.height, graph_background);
+        // Scale both graphs to the same peak so they are comparable
+        let max = self.rx_history.max().max(self.tx_history.max()).max(1024.0);
+        canvas.draw_sparkline(area, &history.to_vec(), max, color);
+    }
+}
+
+impl Module for NetworkModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(interfaces) = config.get("interfaces").and_then(|v| v.as_array()) {
+            self.interfaces_filter = interfaces
+                .iter()
+                .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                .collect();
+        }
+        if let Some(path) = config.get("proc_path").and_then(|v| v.as_str()) {
+            self.proc_path = PathBuf::from(path);
+        }
+        if let Some(path) = config.get("sysfs_path").and_then(|v| v.as_str()) {
+            self.sysfs_path = PathBuf::from(path);
+        }
+        if let Some(ms) = config.get("interval_ms").and_then(|v| v.as_integer()) {
+            // Samples are taken on the update tick, so no faster than that
+            self.interval = Duration::from_millis(ms.max(0) as u64).max(UPDATE_INTERVAL);
+        }
+        if let Some(len) = config.get("history").and_then(|v| v.as_integer()) {
+            self.rx_history = History::new(len.max(2) as usize);
+            self.tx_history = History::new(len.max(2) as usize);
+        }
+        if let Some(show) = config.get("show_ssid").and_then(|v| v.as_bool()) {
+            self.show_ssid = show;
+        }
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "rx_color") {
+            self.rx_color = color;
+        }
+        if let Some(color) = color_from_config(config, "tx_color") {
+            self.tx_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+
+        self.sample();
+
+        if self.show_ssid {
+            let (sender, receiver) = mpsc::channel();
+            let proc_path = self.proc_path.clone();
+            let ssids = self.ssids.clone();
+            let ssid_interval = self.ssid_interval;
+            thread::Builder::new()
+                .name("network-ssid".to_string())
+                .spawn(move || run_ssid_reader(proc_path, ssids, ssid_interval, receiver))?;
+            self.ssid_reader = Some(sender);
+        }
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let rx_color = self.rx_color.resolve(canvas.theme());
+        let tx_color = self.tx_color.resolve(canvas.theme());
+        let margin = 10;
+        let line_height = self.font_size as i32 + 6;
+
+        let ssids = self.ssids.lock().unwrap();
+
+        // One line per interface
+        for (i, interface) in self.interfaces.iter().enumerate() {
+            let mut line = format!(
+                "{}  {}  RX {}  TX {}",
+                interface.name,
+                interface.operstate,
+                format_rate(interface.rx_rate),
+                format_rate(interface.tx_rate)
+            );
+            if let Some(wireless) = &interface.wireless {
+                if let Some(ssid) = ssids.get(&interface.name) {
+                    line.push_str(&format!("  {}", ssid));
+                }
+                line.push_str(&format!(
+                    "  {:.0} dBm ({}%)",
+                    wireless.level,
+                    wireless.quality_percent()
+                ));
+            }
+
+            let y = area.y + line_height * i as i32;
+            canvas.draw_text(area.x + margin, y, &line, color, self.font_size);
+        }
+        drop(ssids);
+
+        // Download and upload history side by side
+        let graph_y = area.y + line_height * self.interfaces.len() as i32 + margin / 2;
+        let graph_height = 50;
+        let graph_width = (area.width as i32 - 3 * margin).max(0) as u32 / 2;
+        self.draw_graph(
+            canvas,
+            Rect {
+                x: area.x + margin,
+                y: graph_y,
+                width: graph_width,
+                height: graph_height,
+            },
+            &self.rx_history,
+            rx_color,
+        );
+        self.draw_graph(
+            canvas,
+            Rect {
+                x: area.x + 2 * margin + graph_width as i32,
+                y: graph_y,
+                width: graph_width,
+                height: graph_height,
+            },
+            &self.tx_history,
+            tx_color,
+        );
+
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let (rx, tx) = self.total_rates();
+        let text = format!("RX {}  TX {}", format_rate(rx), format_rate(tx));
+        let y_pos = area.y + ((area.height as i32 - self.font_size as i32) / 2);
+        canvas.draw_text(area.x, y_pos, &text, color, self.font_size);
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, _area: Rect) -> bool {
+        match event {
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                self.sample()
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        let lines = self.interfaces.len() as u32;
+        (600, lines * (self.font_size as u32 + 6) + 5 + 50)
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        Some((170, 20))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NET_DEV: &str = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:  123456     789    0    0    0     0          0         0   123456     789    0    0    0     0       0          0
+  eth0: 9876543210 7654321    0   12    0     0          0      1234 1234567890 3456789    0    0    0     0       0          0
+ wlan0:       0       0    0    0    0     0          0         0        0       0    0    0    0     0       0          0
+";
+
+    const WIRELESS: &str = "\
+Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE
+ face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22
+ wlan0: 0000   56.  -54.  -256        0      0      0      0     34        0
+";
+
+    #[test]
+    fn net_dev() {
+        let counters = parse_net_dev(NET_DEV);
+        assert_eq!(counters.len(), 3);
+        assert_eq!(
+            counters[1],
+            InterfaceCounters {
+                name: "eth0".to_string(),
+                rx_bytes: 9876543210,
+                tx_bytes: 1234567890,
+            }
+        );
+        assert_eq!(counters[2].name, "wlan0");
+        assert_eq!(counters[2].rx_bytes, 0);
+    }
+
+    #[test]
+    fn net_dev_skips_short_lines() {
+        assert!(parse_net_dev("eth0: 1 2 3\ngarbage\n").is_empty());
+    }
+
+    #[test]
+    fn wireless() {
+        let wireless = parse_wireless(WIRELESS);
+        assert_eq!(
+            wireless,
+            vec![WirelessInfo {
+                name: "wlan0".to_string(),
+                quality: 56.0,
+                level: -54.0,
+            }]
+        );
+        assert_eq!(wireless[0].quality_percent(), 80);
+    }
+
+    #[test]
+    fn iw_link() {
+        let output = "\
+Connected to 00:11:22:33:44:55 (on wlan0)
+\tSSID: Home Network
+\tfreq: 5180
+";
+        assert_eq!(parse_iw_link(output).as_deref(), Some("Home Network"));
+        assert_eq!(parse_iw_link("Not connected.\n"), None);
+    }
+
+    #[test]
+    fn rates_are_formatted() {
+        assert_eq!(format_rate(0.0), "0 B/s");
+        assert_eq!(format_rate(1023.0), "1023 B/s");
+        assert_eq!(format_rate(1536.0), "1.5 KiB/s");
+        assert_eq!(format_rate(3.0 * 1024.0 * 1024.0), "3.0 MiB/s");
+    }
+
+    #[test]
+    fn interfaces_are_read_from_proc() {
+        let proc = tempfile::tempdir().unwrap();
+        let sys = tempfile::tempdir().unwrap();
+        fs::create_dir(proc.path().join("net")).unwrap();
+        fs::write(proc.path().join("net/dev"), NET_DEV).unwrap();
+        fs::create_dir(sys.path().join("eth0")).unwrap();
+        fs::write(sys.path().join("eth0/operstate"), "up\n").unwrap();
+
+        let mut module = NetworkModule::new();
+        let config = toml::from_str(&format!(
+            "proc_path = {:?}\nsysfs_path = {:?}\ninterval_ms = 100",
+            proc.path(),
+            sys.path()
+        ))
+        .unwrap();
+        module.init(&config).unwrap();
+        // Faster than the update tick isn't possible
+        assert_eq!(module.interval, UPDATE_INTERVAL);
+
+        // lo is left out unless asked for; wlan0 has no operstate here
+        let names: Vec<_> = module.interfaces.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["eth0", "wlan0"]);
+        assert_eq!(module.interfaces[0].operstate, "up");
+        assert_eq!(module.interfaces[1].operstate, "unknown");
+        assert!(module.interfaces[1].wireless.is_none());
+        assert_eq!(module.total_rates(), (0.0, 0.0));
+        // iw is only run when asked for
+        assert!(module.ssid_reader.is_none());
+    }
+
+    #[test]
+    fn ssid_reader_stops_with_the_module() {
+        // Without /proc/net/wireless there is nothing to run iw for
+        let proc = tempfile::tempdir().unwrap();
+        let mut module = NetworkModule::new();
+        let config =
+            toml::from_str(&format!("proc_path = {:?}\nshow_ssid = true", proc.path())).unwrap();
+        module.init(&config).unwrap();
+        assert!(module.ssid_reader.is_some());
+
+        let (stop, stopped) = mpsc::channel();
+        let (done, finished) = mpsc::channel();
+        let ssids = module.ssids.clone();
+        let proc_path = proc.path().to_path_buf();
+        thread::spawn(move || {
+            run_ssid_reader(proc_path, ssids, Duration::from_secs(60), stopped);
+            done.send(()).unwrap();
+        });
+        // It keeps going while the module is around
+        assert!(finished.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(stop);
+        assert!(finished.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+}
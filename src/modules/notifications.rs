@@ -0,0 +1,810 @@
+//! Notification daemon module for hypr-notch
+//!
+//! Implements the freedesktop notification server
+//! (`org.freedesktop.Notifications`) so notifications show up in the notch.
+//! Incoming notifications are queued and the notch expands to show them one
+//! at a time until they time out or the user dismisses them.
+
+use crate::config::color_from_config;
+use crate::draw::{text_width, Canvas};
+use crate::icons;
+use crate::image::{Filter, RgbaImage};
+use crate::module::{request_update, Module, ModuleEvent, Rect};
+use crate::theme::ThemeColor;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zbus::blocking::Connection;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::OwnedValue;
+
+pub const BUS_NAME: &str = "org.freedesktop.Notifications";
+pub const OBJECT_PATH: &str = "/org/freedesktop/Notifications";
+
+/// Linux input event codes of the left and right mouse buttons
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+
+/// Size of the icon shown left of the notification text
+const ICON_SIZE: u32 = 40;
+
+/// Reasons passed with the `NotificationClosed` signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    Expired = 1,
+    Dismissed = 2,
+    Closed = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    fn from_hints(hints: &HashMap<String, OwnedValue>) -> Self {
+        match hints
+            .get("urgency")
+            .and_then(|v| v.downcast_ref::<u8>().ok())
+        {
+            Some(0) => Self::Low,
+            Some(2) => Self::Critical,
+            _ => Self::Normal,
+        }
+    }
+}
+
+/// Image shown next to a notification
+#[derive(Debug, Clone)]
+pub enum NotificationIcon {
+    /// Full-colour image or icon
+    Image(Arc<RgbaImage>),
+    /// Symbolic icon, drawn in the text colour
+    Symbolic(Arc<RgbaImage>),
+}
+
+impl NotificationIcon {
+    /// Pick the image of a notification as the specification orders it:
+    /// the `image-data` hint, the `image-path` hint, then `app_icon`
+    pub fn from_notify(app_icon: &str, hints: &HashMap<String, OwnedValue>) -> Option<Self> {
+        // Older clients use the underscored names from version 1.1
+        let hint = |names: [&str; 2]| names.iter().find_map(|name| hints.get(*name));
+
+        if let Some(value) = hint(["image-data", "image_data"]) {
+            match image_from_data(value) {
+                Some(image) => return Some(Self::Image(Arc::new(image))),
+                None => warn!("Ignoring malformed notification image-data"),
+            }
+        }
+        let path = hint(["image-path", "image_path"])
+            .and_then(|value| value.downcast_ref::<String>().ok())
+            .filter(|path| !path.is_empty());
+        let name = path.as_deref().unwrap_or(app_icon);
+        if name.is_empty() {
+            return None;
+        }
+
+        let image = icons::load(name, ICON_SIZE)?;
+        Some(if name.ends_with("-symbolic") {
+            Self::Symbolic(image)
+        } else {
+            Self::Image(image)
+        })
+    }
+}
+
+/// Decode the raw `(iiibiiay)` image of the `image-data` hint
+fn image_from_data(value: &OwnedValue) -> Option<RgbaImage> {
+    let (width, height, rowstride, has_alpha, bits_per_sample, _channels, data): (
+        i32,
+        i32,
+        i32,
+        bool,
+        i32,
+        i32,
+        Vec<u8>,
+    ) = value.try_clone().ok()?.try_into().ok()?;
+    if bits_per_sample != 8 || width <= 0 || height <= 0 || rowstride <= 0 {
+        return None;
+    }
+    RgbaImage::from_rows(
+        width as u32,
+        height as u32,
+        rowstride as usize,
+        has_alpha,
+        &data,
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub id: u32,
+    pub app_name: String,
+    pub summary: String,
+    pub body: String,
+    /// Action keys and their display labels
+    pub actions: Vec<(String, String)>,
+    pub urgency: Urgency,
+    pub icon: Option<NotificationIcon>,
+    /// None for notifications that stay until dismissed
+    pub timeout: Option<Duration>,
+    /// When the notification was first shown in the notch
+    pub shown_at: Option<Instant>,
+}
+
+/// Pending notifications, shared between the D-Bus server and the module
+#[derive(Debug)]
+pub struct NotificationQueue {
+    notifications: Vec<Notification>,
+    next_id: u32,
+    default_timeout: Duration,
+}
+
+impl NotificationQueue {
+    pub fn new(default_timeout: Duration) -> Self {
+        Self {
+            notifications: Vec::new(),
+            next_id: 1,
+            default_timeout,
+        }
+    }
+
+    /// Add a notification, or replace an existing one, returning its id
+    #[allow(clippy::too_many_arguments)]
+    pub fn notify(
+        &mut self,
+        app_name: String,
+        replaces_id: u32,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        urgency: Urgency,
+        icon: Option<NotificationIcon>,
+        expire_timeout: i32,
+    ) -> u32 {
+        let timeout = match expire_timeout {
+            // Critical notifications should not expire on their own
+            _ if urgency == Urgency::Critical && expire_timeout < 0 => None,
+            0 => None,
+            t if t < 0 => Some(self.default_timeout),
+            t => Some(Duration::from_millis(t as u64)),
+        };
+        let actions = actions
+            .chunks_exact(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+
+        if let Some(existing) = self
+            .notifications
+            .iter_mut()
+            .find(|n| replaces_id != 0 && n.id == replaces_id)
+        {
+            existing.app_name = app_name;
+            existing.summary = summary;
+            existing.body = strip_markup(&body);
+            existing.actions = actions;
+            existing.urgency = urgency;
+            existing.icon = icon;
+            existing.timeout = timeout;
+            // Restart the timeout for the updated content
+            if existing.shown_at.is_some() {
+                existing.shown_at = Some(Instant::now());
+            }
+            return replaces_id;
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        self.notifications.push(Notification {
+            id,
+            app_name,
+            summary,
+            body: strip_markup(&body),
+            actions,
+            urgency,
+            icon,
+            timeout,
+            shown_at: None,
+        });
+        id
+    }
+
+    /// Remove a notification, returning whether it existed
+    pub fn close(&mut self, id: u32) -> bool {
+        let len = self.notifications.len();
+        self.notifications.retain(|n| n.id != id);
+        self.notifications.len() != len
+    }
+
+    /// The notification currently shown: the one already on screen, otherwise
+    /// the most urgent, oldest pending one
+    pub fn current(&self) -> Option<&Notification> {
+        self.notifications
+            .iter()
+            .find(|n| n.shown_at.is_some())
+            .or_else(|| {
+                self.notifications
+                    .iter()
+                    .min_by_key(|n| (std::cmp::Reverse(n.urgency), n.id))
+            })
+    }
+
+    /// Mark the current notification as shown and drop it once its timeout
+    /// has passed. Returns the ids of expired notifications.
+    pub fn tick(&mut self, now: Instant) -> Vec<u32> {
+        let mut expired = Vec::new();
+        while let Some(current) = self.current().map(|n| n.id) {
+            let notification = self
+                .notifications
+                .iter_mut()
+                .find(|n| n.id == current)
+                .expect("current notification is queued");
+            let shown_at = *notification.shown_at.get_or_insert(now);
+            match notification.timeout {
+                Some(timeout) if now.duration_since(shown_at) >= timeout => {
+                    self.close(current);
+                    expired.push(current);
+                }
+                _ => break,
+            }
+        }
+        expired
+    }
+
+    pub fn len(&self) -> usize {
+        self.notifications.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notifications.is_empty()
+    }
+}
+
+/// Remove the simple markup allowed in notification bodies
+pub fn strip_markup(body: &str) -> String {
+    let mut text = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        // Only `<b>`, `</b>` and the like are tags; a `<` followed by
+        // anything else, or never closed, is part of the text
+        let opens_tag = rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/');
+        match rest.find('>').filter(|_| opens_tag) {
+            Some(end) => rest = &rest[end + 1..],
+            None => {
+                text.push('<');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Horizontal position of the notification text within the module area
+fn text_offset(notification: &Notification) -> i32 {
+    match notification.icon {
+        Some(_) => 12 + ICON_SIZE as i32 + 10,
+        None => 12,
+    }
+}
+
+/// Largest rectangle with the aspect ratio of `image` centred in `area`
+fn fit_rect(image: &RgbaImage, area: Rect) -> Rect {
+    let scale = (area.width as f32 / image.width.max(1) as f32)
+        .min(area.height as f32 / image.height.max(1) as f32);
+    let (width, height) = (
+        (image.width as f32 * scale).round() as u32,
+        (image.height as f32 * scale).round() as u32,
+    );
+    Rect {
+        x: area.x + (area.width as i32 - width as i32) / 2,
+        y: area.y + (area.height as i32 - height as i32) / 2,
+        width,
+        height,
+    }
+}
+
+/// Split text into lines no wider than `max_width` pixels
+fn wrap_text(text: &str, size: f32, max_width: u32, max_lines: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", line, word)
+            };
+            if text_width(&candidate, size) > max_width && !line.is_empty() {
+                lines.push(std::mem::replace(&mut line, word.to_string()));
+            } else {
+                line = candidate;
+            }
+        }
+        lines.push(line);
+    }
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            last.push('…');
+        }
+    }
+    lines
+}
+
+/// The `org.freedesktop.Notifications` D-Bus interface
+struct NotificationServer {
+    queue: Arc<Mutex<NotificationQueue>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl NotificationServer {
+    fn get_capabilities(&self) -> Vec<String> {
+        vec![
+            "actions".to_string(),
+            "body".to_string(),
+            "icon-static".to_string(),
+        ]
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        expire_timeout: i32,
+    ) -> u32 {
+        let urgency = Urgency::from_hints(&hints);
+        // Icons are decoded here rather than while drawing
+        let icon = NotificationIcon::from_notify(&app_icon, &hints);
+        debug!(
+            "Notification from {}: {} ({:?})",
+            app_name, summary, urgency
+        );
+        let id = self.queue.lock().unwrap().notify(
+            app_name,
+            replaces_id,
+            summary,
+            body,
+            actions,
+            urgency,
+            icon,
+            expire_timeout,
+        );
+        request_update();
+        id
+    }
+
+    async fn close_notification(
+        &self,
+        id: u32,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let closed = self.queue.lock().unwrap().close(id);
+        if closed {
+            Self::notification_closed(&emitter, id, CloseReason::Closed as u32).await?;
+            request_update();
+        }
+        Ok(())
+    }
+
+    fn get_server_information(&self) -> (String, String, String, String) {
+        (
+            "hypr-notch".to_string(),
+            "hypr-notch".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+            "1.2".to_string(),
+        )
+    }
+
+    #[zbus(signal)]
+    async fn notification_closed(
+        emitter: &SignalEmitter<'_>,
+        id: u32,
+        reason: u32,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn action_invoked(
+        emitter: &SignalEmitter<'_>,
+        id: u32,
+        action_key: &str,
+    ) -> zbus::Result<()>;
+}
+
+pub struct NotificationsModule {
+    id: String,
+    name: String,
+    queue: Arc<Mutex<NotificationQueue>>,
+    connection: Option<Connection>,
+    color: ThemeColor,
+    secondary_color: ThemeColor,
+    button_color: ThemeColor,
+    low_color: ThemeColor,
+    normal_color: ThemeColor,
+    critical_color: ThemeColor,
+    font_size: f32,
+    width: u32,
+}
+
+impl Default for NotificationsModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NotificationsModule {
+    pub fn new() -> Self {
+        Self {
+            id: "notifications".to_string(),
+            name: "Notifications".to_string(),
+            queue: Arc::new(Mutex::new(NotificationQueue::new(Duration::from_secs(5)))),
+            connection: None,
+            color: ThemeColor::named("foreground"),
+            secondary_color: ThemeColor::named("muted"),
+            button_color: ThemeColor::named("surface"),
+            low_color: ThemeColor::named("muted"),
+            normal_color: ThemeColor::named("accent"),
+            critical_color: ThemeColor::named("critical"),
+            font_size: 16.0,
+            width: 600,
+        }
+    }
+
+    /// Serve `org.freedesktop.Notifications` on `connection`. Fails if
+    /// another notification daemon already owns the name.
+    pub fn serve(&mut self, connection: Connection) -> zbus::Result<()> {
+        let server = NotificationServer {
+            queue: self.queue.clone(),
+        };
+        connection.object_server().at(OBJECT_PATH, server)?;
+        connection.request_name(BUS_NAME)?;
+        info!("Notification server running as {}", BUS_NAME);
+        self.connection = Some(connection);
+        Ok(())
+    }
+
+    fn emit<B>(&self, signal: &str, body: &B)
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        let Some(connection) = &self.connection else {
+            return;
+        };
+        if let Err(e) = connection.emit_signal(None::<()>, OBJECT_PATH, BUS_NAME, signal, body) {
+            warn!("Failed to emit {}: {}", signal, e);
+        }
+    }
+
+    fn dismiss(&self, id: u32) {
+        if self.queue.lock().unwrap().close(id) {
+            self.emit("NotificationClosed", &(id, CloseReason::Dismissed as u32));
+        }
+    }
+
+    fn body_lines(&self, notification: &Notification) -> Vec<String> {
+        wrap_text(
+            &notification.body,
+            self.font_size * 0.85,
+            self.width
+                .saturating_sub(18 + text_offset(notification) as u32),
+            3,
+        )
+    }
+
+    /// Areas of the action buttons of a notification within the module area
+    fn action_rects(&self, notification: &Notification, area: Rect) -> Vec<(String, Rect)> {
+        let height = self.font_size as u32 + 10;
+        let y = area.y + area.height as i32 - height as i32;
+        let mut x = area.x + 10;
+
+        notification
+            .actions
+            .iter()
+            // The default action is triggered by clicking the notification itself
+            .filter(|(key, _)| key != "default")
+            .map(|(key, label)| {
+                let width = text_width(label, self.font_size * 0.85) + 20;
+                let rect = Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                };
+                x += width as i32 + 8;
+                (key.clone(), rect)
+            })
+            .collect()
+    }
+}
+
+impl Module for NotificationsModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(ms) = config
+            .get("default_timeout_ms")
+            .and_then(|v| v.as_integer())
+        {
+            self.queue.lock().unwrap().default_timeout = Duration::from_millis(ms.max(0) as u64);
+        }
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "low_color") {
+            self.low_color = color;
+        }
+        if let Some(color) = color_from_config(config, "normal_color") {
+            self.normal_color = color;
+        }
+        if let Some(color) = color_from_config(config, "critical_color") {
+            self.critical_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+        if let Some(width) = config.get("width").and_then(|v| v.as_integer()) {
+            self.width = width.max(100) as u32;
+        }
+
+        self.serve(Connection::session()?)?;
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let secondary_color = self.secondary_color.resolve(canvas.theme());
+        let button_color = self.button_color.resolve(canvas.theme());
+        let queue = self.queue.lock().unwrap();
+        let Some(notification) = queue.current() else {
+            return Ok(());
+        };
+
+        // Urgency stripe
+        let stripe_color = match notification.urgency {
+            Urgency::Low => &self.low_color,
+            Urgency::Normal => &self.normal_color,
+            Urgency::Critical => &self.critical_color,
+        };
+        let stripe_color = stripe_color.resolve(canvas.theme());
+        canvas.fill_rect(area.x, area.y, 4, area.height, stripe_color);
+
+        let icon_rect = Rect {
+            x: area.x + 12,
+            y: area.y + 2,
+            width: ICON_SIZE,
+            height: ICON_SIZE,
+        };
+        match &notification.icon {
+            Some(NotificationIcon::Image(image)) => {
+                canvas.draw_image(image, fit_rect(image, icon_rect), Filter::Smooth)
+            }
+            Some(NotificationIcon::Symbolic(image)) => {
+                canvas.draw_image_tinted(image, fit_rect(image, icon_rect), color)
+            }
+            None => {}
+        }
+        let text_x = area.x + text_offset(notification);
+
+        let small_font = self.font_size * 0.85;
+        let line_height = self.font_size as i32 + 6;
+        let mut header = notification.app_name.clone();
+        if queue.len() > 1 {
+            header.push_str(&format!("  (+{} more)", queue.len() - 1));
+        }
+        canvas.draw_text(text_x, area.y, &header, secondary_color, small_font);
+        canvas.draw_text(
+            text_x,
+            area.y + line_height,
+            &notification.summary,
+            color,
+            self.font_size,
+        );
+
+        for (i, line) in self.body_lines(notification).iter().enumerate() {
+            canvas.draw_text(
+                text_x,
+                area.y + line_height * (i as i32 + 2),
+                line,
+                secondary_color,
+                small_font,
+            );
+        }
+
+        for (key, rect) in self.action_rects(notification, area) {
+            let label = notification
+                .actions
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, label)| label.as_str())
+                .unwrap_or_default();
+            canvas.fill_rect(rect.x, rect.y, rect.width, rect.height, button_color);
+            canvas.draw_text(rect.x + 10, rect.y + 3, label, color, small_font);
+        }
+
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, area: Rect) -> bool {
+        match event {
+            ModuleEvent::Press { button, x, y } => {
+                let Some(notification) = self.queue.lock().unwrap().current().cloned() else {
+                    return false;
+                };
+
+                if *button == BTN_RIGHT {
+                    self.dismiss(notification.id);
+                    return true;
+                }
+                if *button != BTN_LEFT {
+                    return false;
+                }
+
+                let action = self
+                    .action_rects(&notification, area)
+                    .into_iter()
+                    .find(|(_, rect)| rect.contains(*x, *y))
+                    .map(|(key, _)| key)
+                    .or_else(|| {
+                        notification
+                            .actions
+                            .iter()
+                            .any(|(key, _)| key == "default")
+                            .then(|| "default".to_string())
+                    });
+                if let Some(action) = action {
+                    info!(
+                        "Invoking action {} on notification {}",
+                        action, notification.id
+                    );
+                    self.emit("ActionInvoked", &(notification.id, action.as_str()));
+                }
+                self.dismiss(notification.id);
+                true
+            }
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                let expired = self.queue.lock().unwrap().tick(Instant::now());
+                for id in &expired {
+                    self.emit("NotificationClosed", &(*id, CloseReason::Expired as u32));
+                }
+                !expired.is_empty()
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        let queue = self.queue.lock().unwrap();
+        let Some(notification) = queue.current() else {
+            return (self.width, 0);
+        };
+
+        let line_height = self.font_size as u32 + 6;
+        let mut height = line_height * (2 + self.body_lines(notification).len() as u32);
+        if notification.actions.iter().any(|(key, _)| key != "default") {
+            height += self.font_size as u32 + 14;
+        }
+        (self.width, height)
+    }
+
+    fn wants_expand(&self) -> bool {
+        !self.queue.lock().unwrap().is_empty()
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notify(
+        queue: &mut NotificationQueue,
+        replaces_id: u32,
+        summary: &str,
+        urgency: Urgency,
+    ) -> u32 {
+        queue.notify(
+            "app".to_string(),
+            replaces_id,
+            summary.to_string(),
+            String::new(),
+            Vec::new(),
+            urgency,
+            None,
+            -1,
+        )
+    }
+
+    #[test]
+    fn markup_is_stripped() {
+        assert_eq!(
+            strip_markup("<b>bold</b> and <i>italic</i>"),
+            "bold and italic"
+        );
+        assert_eq!(
+            strip_markup("<a href=\"https://example.com\">link</a>"),
+            "link"
+        );
+        assert_eq!(
+            strip_markup("a &lt; b &amp;&amp; c &gt; d"),
+            "a < b && c > d"
+        );
+    }
+
+    #[test]
+    fn stray_angle_brackets_are_text() {
+        assert_eq!(strip_markup("a < b"), "a < b");
+        assert_eq!(strip_markup("a < b > c"), "a < b > c");
+        assert_eq!(strip_markup("1 <2 and 3> 2"), "1 <2 and 3> 2");
+        assert_eq!(strip_markup("<b>unclosed <"), "unclosed <");
+        assert_eq!(strip_markup("<b"), "<b");
+        assert_eq!(strip_markup("<<b>>"), "<>");
+    }
+
+    #[test]
+    fn urgent_notifications_go_first() {
+        let mut queue = NotificationQueue::new(Duration::from_secs(5));
+        let low = notify(&mut queue, 0, "low", Urgency::Low);
+        let normal = notify(&mut queue, 0, "normal", Urgency::Normal);
+        let critical = notify(&mut queue, 0, "critical", Urgency::Critical);
+        assert_eq!((low, normal, critical), (1, 2, 3));
+        assert_eq!(queue.current().unwrap().id, critical);
+        // Critical notifications stay until dismissed
+        assert_eq!(queue.current().unwrap().timeout, None);
+
+        assert!(queue.close(critical));
+        assert!(!queue.close(critical));
+        assert_eq!(queue.current().unwrap().id, normal);
+    }
+
+    #[test]
+    fn replacing_keeps_the_id() {
+        let mut queue = NotificationQueue::new(Duration::from_secs(5));
+        let id = notify(&mut queue, 0, "first", Urgency::Normal);
+        assert_eq!(notify(&mut queue, id, "second", Urgency::Normal), id);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.current().unwrap().summary, "second");
+        // An id that isn't queued any more gets a new notification
+        assert_eq!(notify(&mut queue, 42, "third", Urgency::Normal), id + 1);
+    }
+
+    #[test]
+    fn shown_notifications_expire() {
+        let mut queue = NotificationQueue::new(Duration::from_secs(5));
+        let first = notify(&mut queue, 0, "first", Urgency::Normal);
+        let second = notify(&mut queue, 0, "second", Urgency::Normal);
+
+        let start = Instant::now();
+        assert!(queue.tick(start).is_empty());
+        assert_eq!(queue.current().unwrap().id, first);
+        // The next one's timeout only starts once it is shown
+        assert_eq!(queue.tick(start + Duration::from_secs(5)), [first]);
+        assert!(queue.tick(start + Duration::from_secs(9)).is_empty());
+        assert_eq!(queue.tick(start + Duration::from_secs(10)), [second]);
+        assert!(queue.is_empty());
+    }
+}
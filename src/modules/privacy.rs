@@ -0,0 +1,678 @@
+//! Privacy module for hypr-notch
+//!
+//! Shows a coloured dot in the collapsed notch while the microphone, a
+//! camera or the screen is in use, and which applications are using them
+//! when expanded:
+//!
+//! - Microphone: streams recording from a PulseAudio (or `pipewire-pulse`)
+//!   source, leaving out monitors of outputs and paused streams
+//! - Camera: processes with a `/dev/video*` device open, found through
+//!   `/proc/*/fd`, plus PipeWire clients reading a camera node
+//! - Screen: PipeWire clients reading a video source that isn't a device,
+//!   which is how the desktop portal shares screens, and Hyprland's
+//!   `screencast` event
+//!
+//! The PipeWire graph is followed with one long-running `pw-dump --monitor`.
+
+use crate::config::color_from_config;
+use crate::draw::{self, Canvas};
+use crate::hyprland::HyprEvent;
+use crate::module::{self, Module, ModuleEvent, Rect};
+use crate::pulse::{Connection, Facility, SUBSCRIBE_SOURCE_OUTPUT};
+use crate::theme::ThemeColor;
+use log::{debug, info, warn};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_PROC_PATH: &str = "/proc";
+
+/// Seconds between attempts to reach the sound server
+const PULSE_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Seconds to wait before restarting `pw-dump` after it exited
+const PIPEWIRE_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Processes that open cameras on behalf of PipeWire clients
+const MEDIA_SERVERS: [&str; 2] = ["pipewire", "wireplumber"];
+
+/// Applications using each kind of device; an empty list means unused
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct PrivacyState {
+    microphone: Vec<String>,
+    camera: Vec<String>,
+    screen: Vec<String>,
+}
+
+/// Consumers of PipeWire video sources, split by the kind of source
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PipeWireUsers {
+    pub camera: Vec<String>,
+    pub screen: Vec<String>,
+}
+
+fn sorted(mut names: Vec<String>) -> Vec<String> {
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Names of the processes below `proc_root` that have a video device open.
+/// Only our own processes can be inspected, which covers the desktop
+/// session.
+pub fn camera_users(proc_root: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(proc_root) else {
+        return Vec::new();
+    };
+
+    let users = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .filter(|entry| {
+            let Ok(fds) = fs::read_dir(entry.path().join("fd")) else {
+                return false;
+            };
+            fds.filter_map(|fd| fd.ok())
+                .filter_map(|fd| fs::read_link(fd.path()).ok())
+                .any(|target| target.to_string_lossy().starts_with("/dev/video"))
+        })
+        .filter_map(|entry| fs::read_to_string(entry.path().join("comm")).ok())
+        .map(|comm| comm.trim().to_string())
+        .collect();
+    sorted(users)
+}
+
+/// PipeWire objects by id, as `pw-dump` reports them
+#[derive(Debug, Default)]
+pub struct PipeWireGraph {
+    objects: HashMap<u64, Value>,
+}
+
+impl PipeWireGraph {
+    /// Apply one array of objects from `pw-dump`. With `--monitor` the
+    /// first array holds every object and later ones the objects that
+    /// changed, where a removed object has no `info`.
+    pub fn apply(&mut self, objects: Vec<Value>) {
+        for object in objects {
+            let Some(id) = object["id"].as_u64() else {
+                continue;
+            };
+            if object["info"].is_null() {
+                self.objects.remove(&id);
+            } else {
+                self.objects.insert(id, object);
+            }
+        }
+    }
+
+    /// Find the applications reading from video sources. Sources backed
+    /// by a device are cameras; any other video source, such as the
+    /// portal's screen cast streams, is a shared screen.
+    pub fn users(&self) -> PipeWireUsers {
+        let object_type = |object: &Value, name: &str| object["type"] == name;
+        let props = |id: &Value| {
+            let object = self.objects.get(&id.as_u64()?)?;
+            object_type(object, "PipeWire:Interface:Node").then(|| &object["info"]["props"])
+        };
+
+        let mut users = PipeWireUsers::default();
+        for link in self
+            .objects
+            .values()
+            .filter(|object| object_type(object, "PipeWire:Interface:Link"))
+            .map(|object| &object["info"])
+            .filter(|info| info["state"] == "active")
+        {
+            let (Some(output), Some(input)) = (
+                props(&link["output-node-id"]),
+                props(&link["input-node-id"]),
+            ) else {
+                continue;
+            };
+            if output["media.class"] != "Video/Source" {
+                continue;
+            }
+            let Some(application) = ["application.name", "node.name"]
+                .iter()
+                .find_map(|key| input[*key].as_str())
+            else {
+                continue;
+            };
+            let list = if output["device.api"].is_string() {
+                &mut users.camera
+            } else {
+                &mut users.screen
+            };
+            list.push(application.to_string());
+        }
+
+        users.camera = sorted(users.camera);
+        users.screen = sorted(users.screen);
+        users
+    }
+}
+
+/// Find the applications reading from video sources in one `pw-dump`
+/// array
+pub fn parse_pw_dump(json: &str) -> Option<PipeWireUsers> {
+    let mut graph = PipeWireGraph::default();
+    graph.apply(serde_json::from_str(json).ok()?);
+    Some(graph.users())
+}
+
+/// Applications recording from a real source, leaving out paused streams
+/// and monitors of outputs (such as level meters)
+fn recording_applications(conn: &mut Connection) -> io::Result<Vec<String>> {
+    let mut monitors: HashMap<u32, bool> = HashMap::new();
+    let mut applications = Vec::new();
+    for output in conn.source_outputs()? {
+        if output.corked {
+            continue;
+        }
+        let is_monitor = match monitors.get(&output.source) {
+            Some(is_monitor) => *is_monitor,
+            None => {
+                let is_monitor = conn.is_monitor(output.source)?;
+                monitors.insert(output.source, is_monitor);
+                is_monitor
+            }
+        };
+        if !is_monitor {
+            applications.push(output.application);
+        }
+    }
+    Ok(sorted(applications))
+}
+
+fn connect_pulse() -> io::Result<Connection> {
+    let mut conn = Connection::connect("hypr-notch")?;
+    conn.subscribe(SUBSCRIBE_SOURCE_OUTPUT)?;
+    Ok(conn)
+}
+
+/// Keep `users` up to date from one `pw-dump --monitor` until it exits
+fn follow_pipewire(users: &Mutex<PipeWireUsers>) -> io::Result<()> {
+    let mut child = Command::new("pw-dump")
+        .arg("--monitor")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+
+    let mut graph = PipeWireGraph::default();
+    let mut result = Ok(());
+    for objects in serde_json::Deserializer::from_reader(BufReader::new(stdout)).into_iter() {
+        match objects {
+            Ok(objects) => {
+                graph.apply(objects);
+                *users.lock().unwrap() = graph.users();
+            }
+            Err(e) => {
+                result = Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                break;
+            }
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    result
+}
+
+/// Follow the PipeWire graph for as long as `pw-dump` is installed,
+/// restarting it when it exits, as it does when PipeWire restarts
+fn run_pipewire(users: Arc<Mutex<PipeWireUsers>>) {
+    loop {
+        match follow_pipewire(&users) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                info!("PrivacyModule: not using pw-dump: {}", e);
+                return;
+            }
+            Err(e) => warn!("PrivacyModule: pw-dump failed: {}", e),
+            Ok(()) => debug!("PrivacyModule: pw-dump exited"),
+        }
+        *users.lock().unwrap() = PipeWireUsers::default();
+        thread::sleep(PIPEWIRE_RETRY_INTERVAL);
+    }
+}
+
+/// Store `current` and redraw if it differs from what was shown
+fn publish(state: &Mutex<PrivacyState>, current: &PrivacyState) {
+    let mut state = state.lock().unwrap();
+    if *state != *current {
+        *state = current.clone();
+        module::request_update();
+    }
+}
+
+/// Scan cameras and screen casts every `interval`, and follow recording
+/// streams as the sound server reports them in between
+fn run_monitor(
+    state: Arc<Mutex<PrivacyState>>,
+    pipewire: Arc<Mutex<PipeWireUsers>>,
+    proc_path: PathBuf,
+    interval: Duration,
+) {
+    let mut pulse: Option<Connection> = None;
+    let mut next_connect = Instant::now();
+    let mut current = PrivacyState::default();
+
+    loop {
+        if pulse.is_none() && Instant::now() >= next_connect {
+            next_connect = Instant::now() + PULSE_RETRY_INTERVAL;
+            match connect_pulse() {
+                Ok(conn) => pulse = Some(conn),
+                Err(e) => debug!("PrivacyModule: no sound server: {}", e),
+            }
+        }
+        current.microphone = match pulse.as_mut().map(recording_applications) {
+            Some(Ok(applications)) => applications,
+            Some(Err(e)) => {
+                warn!("PrivacyModule: lost the sound server: {}", e);
+                pulse = None;
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+
+        let pipewire = pipewire.lock().unwrap().clone();
+        let mut camera = camera_users(&proc_path);
+        camera.extend(pipewire.camera);
+        // The media server holds the device for its clients, which are
+        // named instead when known
+        if camera
+            .iter()
+            .any(|name| !MEDIA_SERVERS.contains(&name.as_str()))
+        {
+            camera.retain(|name| !MEDIA_SERVERS.contains(&name.as_str()));
+        }
+        current.camera = sorted(camera);
+        current.screen = pipewire.screen;
+        publish(&state, &current);
+
+        // Wait for the next scan, following recording streams meanwhile
+        let deadline = Instant::now() + interval;
+        while let Some(conn) = pulse.as_mut() {
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            let result = match conn.next_event(left) {
+                Ok(Some(event)) if event.facility == Facility::SourceOutput => {
+                    recording_applications(conn).map(Some)
+                }
+                Ok(_) => Ok(None),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(Some(applications)) => {
+                    current.microphone = applications;
+                    publish(&state, &current);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("PrivacyModule: lost the sound server: {}", e);
+                    pulse = None;
+                    current.microphone.clear();
+                    publish(&state, &current);
+                }
+            }
+        }
+        if let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            thread::sleep(left);
+        }
+    }
+}
+
+/// A kind of device the module watches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Indicator {
+    Microphone,
+    Camera,
+    Screen,
+}
+
+impl Indicator {
+    const ALL: [Self; 3] = [Self::Microphone, Self::Camera, Self::Screen];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Microphone => "Microphone",
+            Self::Camera => "Camera",
+            Self::Screen => "Screen sharing",
+        }
+    }
+}
+
+pub struct PrivacyModule {
+    id: String,
+    name: String,
+    state: Arc<Mutex<PrivacyState>>,
+    /// The state as of the last update, which is what is drawn
+    shown: PrivacyState,
+    /// Screen sharing as reported by Hyprland, which knows about it even
+    /// when `pw-dump` isn't available
+    hyprland_screencast: bool,
+    proc_path: PathBuf,
+    interval: Duration,
+    pw_dump: bool,
+    color: ThemeColor,
+    secondary_color: ThemeColor,
+    microphone_color: ThemeColor,
+    camera_color: ThemeColor,
+    screen_color: ThemeColor,
+    font_size: f32,
+}
+
+impl Default for PrivacyModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PrivacyModule {
+    pub fn new() -> Self {
+        Self {
+            id: "privacy".to_string(),
+            name: "Privacy".to_string(),
+            state: Arc::new(Mutex::new(PrivacyState::default())),
+            shown: PrivacyState::default(),
+            hyprland_screencast: false,
+            proc_path: PathBuf::from(DEFAULT_PROC_PATH),
+            interval: Duration::from_secs(2),
+            pw_dump: true,
+            color: ThemeColor::named("foreground"),
+            secondary_color: ThemeColor::named("muted"),
+            microphone_color: ThemeColor::named("warning"),
+            camera_color: ThemeColor::named("success"),
+            screen_color: ThemeColor::named("accent"),
+            font_size: 14.0,
+        }
+    }
+
+    /// Devices in use, with the applications using them
+    fn active(&self) -> Vec<(Indicator, Vec<String>)> {
+        let state = &self.shown;
+        Indicator::ALL
+            .into_iter()
+            .filter_map(|indicator| {
+                let applications = match indicator {
+                    Indicator::Microphone => state.microphone.clone(),
+                    Indicator::Camera => state.camera.clone(),
+                    Indicator::Screen => state.screen.clone(),
+                };
+                let in_use = !applications.is_empty()
+                    || (indicator == Indicator::Screen && self.hyprland_screencast);
+                in_use.then_some((indicator, applications))
+            })
+            .collect()
+    }
+
+    fn indicator_color(&self, canvas: &Canvas, indicator: Indicator) -> [u8; 4] {
+        let color = match indicator {
+            Indicator::Microphone => &self.microphone_color,
+            Indicator::Camera => &self.camera_color,
+            Indicator::Screen => &self.screen_color,
+        };
+        color.resolve(canvas.theme())
+    }
+
+    fn line_height(&self) -> u32 {
+        self.font_size as u32 + 8
+    }
+}
+
+impl Module for PrivacyModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = config.get("proc_path").and_then(|v| v.as_str()) {
+            self.proc_path = PathBuf::from(path);
+        }
+        if let Some(secs) = config.get("interval").and_then(|v| v.as_integer()) {
+            self.interval = Duration::from_secs(secs.max(1) as u64);
+        }
+        if let Some(pw_dump) = config.get("pw_dump").and_then(|v| v.as_bool()) {
+            self.pw_dump = pw_dump;
+        }
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "secondary_color") {
+            self.secondary_color = color;
+        }
+        if let Some(color) = color_from_config(config, "microphone_color") {
+            self.microphone_color = color;
+        }
+        if let Some(color) = color_from_config(config, "camera_color") {
+            self.camera_color = color;
+        }
+        if let Some(color) = color_from_config(config, "screen_color") {
+            self.screen_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+
+        let pipewire = Arc::new(Mutex::new(PipeWireUsers::default()));
+        if self.pw_dump {
+            let pipewire = pipewire.clone();
+            thread::Builder::new()
+                .name("privacy-pipewire".to_string())
+                .spawn(move || run_pipewire(pipewire))?;
+        }
+
+        let state = self.state.clone();
+        let proc_path = self.proc_path.clone();
+        let interval = self.interval;
+        thread::Builder::new()
+            .name("privacy".to_string())
+            .spawn(move || run_monitor(state, pipewire, proc_path, interval))?;
+
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let secondary_color = self.secondary_color.resolve(canvas.theme());
+        let active = self.active();
+        if active.is_empty() {
+            canvas.draw_text(
+                area.x + 10,
+                area.y,
+                "Microphone, camera and screen are not in use",
+                secondary_color,
+                self.font_size,
+            );
+            return Ok(());
+        }
+
+        let line_height = self.line_height() as i32;
+        for (i, (indicator, applications)) in active.iter().enumerate() {
+            let y = area.y + i as i32 * line_height;
+            let dot_color = self.indicator_color(canvas, *indicator);
+            canvas.fill_circle(
+                area.x as f32 + 14.0,
+                y as f32 + self.font_size / 2.0 + 2.0,
+                4.0,
+                dot_color,
+            );
+
+            let label = indicator.label();
+            canvas.draw_text(area.x + 26, y, label, color, self.font_size);
+            let names_x = area.x + 26 + draw::text_width(label, self.font_size) as i32 + 10;
+            let max_width = (area.x + area.width as i32 - names_x).max(0) as u32;
+            let names = draw::truncate_text(&applications.join(", "), self.font_size, max_width);
+            canvas.draw_text(names_x, y, &names, secondary_color, self.font_size);
+        }
+
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let cy = area.y as f32 + area.height as f32 / 2.0;
+        for (i, (indicator, _)) in self.active().into_iter().enumerate() {
+            let color = self.indicator_color(canvas, indicator);
+            canvas.fill_circle(area.x as f32 + 6.0 + i as f32 * 12.0, cy, 4.0, color);
+        }
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, _area: Rect) -> bool {
+        match event {
+            ModuleEvent::Hyprland(HyprEvent::Screencast(active)) => {
+                self.hyprland_screencast = *active;
+                true
+            }
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                let state = self.state.lock().unwrap().clone();
+                let changed = state != self.shown;
+                self.shown = state;
+                changed
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        let lines = self.active().len().max(1) as u32;
+        (400, lines * self.line_height())
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        match self.active().len() as u32 {
+            0 => None,
+            dots => Some((dots * 12, 20)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    /// A camera and a screen cast, each read by an application, trimmed
+    /// to the fields that matter
+    const PW_DUMP: &str = r#"[
+  { "id": 30, "type": "PipeWire:Interface:Node",
+    "info": { "props": { "media.class": "Video/Source", "device.api": "v4l2",
+                         "node.name": "v4l2_input.pci-0000_00_14.0" } } },
+  { "id": 31, "type": "PipeWire:Interface:Node",
+    "info": { "props": { "media.class": "Video/Source", "node.name": "xdph-streaming-0" } } },
+  { "id": 32, "type": "PipeWire:Interface:Node",
+    "info": { "props": { "media.class": "Stream/Input/Video", "application.name": "Firefox" } } },
+  { "id": 33, "type": "PipeWire:Interface:Node",
+    "info": { "props": { "media.class": "Stream/Input/Video", "node.name": "obs" } } },
+  { "id": 34, "type": "PipeWire:Interface:Node",
+    "info": { "props": { "media.class": "Audio/Source", "device.api": "alsa" } } },
+  { "id": 35, "type": "PipeWire:Interface:Node",
+    "info": { "props": { "media.class": "Stream/Input/Audio", "application.name": "Recorder" } } },
+  { "id": 40, "type": "PipeWire:Interface:Link",
+    "info": { "output-node-id": 30, "input-node-id": 32, "state": "active" } },
+  { "id": 41, "type": "PipeWire:Interface:Link",
+    "info": { "output-node-id": 31, "input-node-id": 33, "state": "active" } },
+  { "id": 42, "type": "PipeWire:Interface:Link",
+    "info": { "output-node-id": 34, "input-node-id": 35, "state": "active" } },
+  { "id": 43, "type": "PipeWire:Interface:Link",
+    "info": { "output-node-id": 30, "input-node-id": 33, "state": "paused" } },
+  { "id": 44, "type": "PipeWire:Interface:Link",
+    "info": { "output-node-id": 99, "input-node-id": 32, "state": "active" } },
+  { "id": 0, "type": "PipeWire:Interface:Core", "info": { "name": "pipewire-0" } }
+]"#;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn pw_dump() {
+        assert_eq!(
+            parse_pw_dump(PW_DUMP),
+            Some(PipeWireUsers {
+                camera: names(&["Firefox"]),
+                screen: names(&["obs"]),
+            })
+        );
+        assert_eq!(parse_pw_dump("[]"), Some(PipeWireUsers::default()));
+        assert_eq!(parse_pw_dump("not json"), None);
+        assert_eq!(parse_pw_dump("{}"), None);
+    }
+
+    #[test]
+    fn monitor_updates_change_the_graph() {
+        let mut graph = PipeWireGraph::default();
+        graph.apply(serde_json::from_str(PW_DUMP).unwrap());
+
+        // The paused link to the camera becomes active
+        graph.apply(
+            serde_json::from_str(
+                r#"[{ "id": 43, "type": "PipeWire:Interface:Link",
+                      "info": { "output-node-id": 30, "input-node-id": 33, "state": "active" } }]"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(graph.users().camera, names(&["Firefox", "obs"]));
+
+        // The screen cast stops and its node goes away
+        graph.apply(serde_json::from_str(r#"[{ "id": 41, "info": null }, { "id": 31 }]"#).unwrap());
+        assert_eq!(
+            graph.users(),
+            PipeWireUsers {
+                camera: names(&["Firefox", "obs"]),
+                screen: Vec::new(),
+            }
+        );
+    }
+
+    /// A process in a fake `/proc` with file descriptors pointing at `fds`
+    fn process(proc: &Path, pid: &str, comm: &str, fds: &[&str]) {
+        let dir = proc.join(pid);
+        fs::create_dir_all(dir.join("fd")).unwrap();
+        fs::write(dir.join("comm"), format!("{}\n", comm)).unwrap();
+        for (fd, target) in fds.iter().enumerate() {
+            symlink(target, dir.join("fd").join(fd.to_string())).unwrap();
+        }
+    }
+
+    #[test]
+    fn camera_users_are_found_in_proc() {
+        let proc = tempfile::tempdir().unwrap();
+        process(proc.path(), "100", "zoom", &["/dev/null", "/dev/video0"]);
+        process(
+            proc.path(),
+            "200",
+            "firefox",
+            &["/dev/null", "socket:[1234]"],
+        );
+        process(proc.path(), "300", "cheese", &["/dev/video2"]);
+        process(proc.path(), "301", "cheese", &["/dev/video2"]);
+        // Not a process
+        process(proc.path(), "self", "self", &["/dev/video0"]);
+        // A process whose descriptors we can't see
+        fs::create_dir(proc.path().join("400")).unwrap();
+        fs::write(proc.path().join("400/comm"), "secret\n").unwrap();
+
+        assert_eq!(camera_users(proc.path()), names(&["cheese", "zoom"]));
+        assert!(camera_users(&proc.path().join("missing")).is_empty());
+    }
+}
@@ -0,0 +1,647 @@
+//! System monitor module for hypr-notch
+//!
+//! Samples CPU usage from `/proc/stat`, memory from `/proc/meminfo` and
+//! load averages from `/proc/loadavg`, keeping a short history of each
+//! for sparkline graphs in the expanded notch.
+
+use crate::config::color_from_config;
+use crate::draw::Canvas;
+use crate::module::{Module, ModuleEvent, Rect, UPDATE_INTERVAL};
+use crate::theme::ThemeColor;
+use log::warn;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Width of the usage ring in the collapsed notch
+const RING_SIZE: u32 = 20;
+
+/// Cumulative CPU time counters from one `cpu` line of `/proc/stat`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuTimes {
+    pub idle: u64,
+    pub total: u64,
+}
+
+/// Parse `/proc/stat`, returning the aggregate `cpu` line first and
+/// then one entry per core (`cpu0`, `cpu1`, ...)
+pub fn parse_proc_stat(content: &str) -> Vec<CpuTimes> {
+    content
+        .lines()
+        .filter(|line| line.starts_with("cpu"))
+        .filter_map(|line| {
+            let fields: Vec<u64> = line
+                .split_whitespace()
+                .skip(1)
+                .filter_map(|f| f.parse().ok())
+                .collect();
+            if fields.len() < 4 {
+                return None;
+            }
+            // user nice system idle iowait irq softirq steal; guest time is
+            // already accounted for in user/nice
+            let total = fields.iter().take(8).sum();
+            let idle = fields[3] + fields.get(4).copied().unwrap_or(0);
+            Some(CpuTimes { idle, total })
+        })
+        .collect()
+}
+
+/// CPU usage in percent between two samples of the same counter
+pub fn cpu_usage(prev: CpuTimes, cur: CpuTimes) -> f32 {
+    let total = cur.total.saturating_sub(prev.total);
+    if total == 0 {
+        return 0.0;
+    }
+    let idle = cur.idle.saturating_sub(prev.idle).min(total);
+    (total - idle) as f32 / total as f32 * 100.0
+}
+
+/// Memory figures from `/proc/meminfo`, in KiB
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemInfo {
+    pub total: u64,
+    pub available: u64,
+    pub swap_total: u64,
+    pub swap_free: u64,
+}
+
+impl MemInfo {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.available)
+    }
+
+    pub fn used_percent(&self) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.used() as f32 / self.total as f32 * 100.0
+    }
+}
+
+/// Parse `/proc/meminfo`
+pub fn parse_meminfo(content: &str) -> Option<MemInfo> {
+    let mut info = MemInfo::default();
+    let mut free = None;
+    let mut buffers_cached = 0;
+    let mut has_available = false;
+
+    for line in content.lines() {
+        let mut parts = line.split_whitespace();
+        let (Some(key), Some(value)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let Ok(value) = value.parse::<u64>() else {
+            continue;
+        };
+        match key {
+            "MemTotal:" => info.total = value,
+            "MemAvailable:" => {
+                info.available = value;
+                has_available = true;
+            }
+            "MemFree:" => free = Some(value),
+            "Buffers:" | "Cached:" => buffers_cached += value,
+            "SwapTotal:" => info.swap_total = value,
+            "SwapFree:" => info.swap_free = value,
+            _ => {}
+        }
+    }
+
+    if info.total == 0 {
+        return None;
+    }
+    // Kernels before 3.14 have no MemAvailable
+    if !has_available {
+        info.available = free? + buffers_cached;
+    }
+    Some(info)
+}
+
+/// Load averages and task counts from `/proc/loadavg`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadAvg {
+    pub one: f32,
+    pub five: f32,
+    pub fifteen: f32,
+    pub running: u32,
+    pub total: u32,
+}
+
+/// Parse `/proc/loadavg`
+pub fn parse_loadavg(content: &str) -> Option<LoadAvg> {
+    let mut parts = content.split_whitespace();
+    let one = parts.next()?.parse().ok()?;
+    let five = parts.next()?.parse().ok()?;
+    let fifteen = parts.next()?.parse().ok()?;
+    let (running, total) = parts.next()?.split_once('/')?;
+
+    Some(LoadAvg {
+        one,
+        five,
+        fifteen,
+        running: running.parse().ok()?,
+        total: total.parse().ok()?,
+    })
+}
+
+/// Fixed-size ring buffer of samples, oldest first
+#[derive(Debug, Clone)]
+pub struct History {
+    values: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            values: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Add a sample, dropping the oldest one if the buffer is full
+    pub fn push(&mut self, value: f32) {
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    pub fn latest(&self) -> Option<f32> {
+        self.values.back().copied()
+    }
+
+    /// Largest sample currently in the buffer
+    pub fn max(&self) -> f32 {
+        self.values.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Samples in chronological order
+    pub fn to_vec(&self) -> Vec<f32> {
+        self.values.iter().copied().collect()
+    }
+}
+
+fn format_gib(kib: u64) -> String {
+    format!("{:.1}", kib as f64 / (1024.0 * 1024.0))
+}
+
+/// Points of a line graph through the tops of the sparkline columns
+/// `Canvas::draw_sparkline` draws for the same values
+fn graph_points(area: Rect, values: &[f32], max: f32) -> Vec<(f32, f32)> {
+    if values.is_empty() || area.width == 0 {
+        return Vec::new();
+    }
+    let column_width = (area.width / values.len() as u32).max(1);
+    let visible = (area.width / column_width) as usize;
+    let start = values.len().saturating_sub(visible);
+    let right = (area.x + area.width as i32) as f32;
+    let bottom = (area.y + area.height as i32) as f32;
+
+    values[start..]
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, value)| {
+            let x = right - (i as f32 + 0.5) * column_width as f32;
+            (
+                x,
+                bottom - (value / max).clamp(0.0, 1.0) * area.height as f32,
+            )
+        })
+        .collect()
+}
+
+pub struct SysmonModule {
+    id: String,
+    name: String,
+    proc_path: PathBuf,
+    interval: Duration,
+    last_sample: Option<Instant>,
+    prev_cpu: Vec<CpuTimes>,
+    core_usage: Vec<f32>,
+    cpu_history: History,
+    mem_history: History,
+    mem: Option<MemInfo>,
+    load: Option<LoadAvg>,
+    color: ThemeColor,
+    cpu_color: ThemeColor,
+    mem_color: ThemeColor,
+    graph_background: ThemeColor,
+    font_size: f32,
+}
+
+impl Default for SysmonModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SysmonModule {
+    pub fn new() -> Self {
+        Self {
+            id: "sysmon".to_string(),
+            name: "System Monitor".to_string(),
+            proc_path: PathBuf::from("/proc"),
+            interval: UPDATE_INTERVAL,
+            last_sample: None,
+            prev_cpu: Vec::new(),
+            core_usage: Vec::new(),
+            cpu_history: History::new(60),
+            mem_history: History::new(60),
+            mem: None,
+            load: None,
+            color: ThemeColor::named("foreground"),
+            cpu_color: ThemeColor::named("accent"),
+            mem_color: ThemeColor::named("success"),
+            graph_background: ThemeColor::named("surface"),
+            font_size: 14.0,
+        }
+    }
+
+    /// Take a new sample if the sampling interval has elapsed
+    fn sample(&mut self) -> bool {
+        let now = Instant::now();
+        if let Some(last_sample) = self.last_sample {
+            // Allow for jitter in the update tick so a 1s interval doesn't skip ticks
+            if now.duration_since(last_sample) + Duration::from_millis(50) < self.interval {
+                return false;
+            }
+        }
+        self.last_sample = Some(now);
+
+        match fs::read_to_string(self.proc_path.join("stat")) {
+            Ok(content) => {
+                let cpu = parse_proc_stat(&content);
+                // The first sample only primes the counters
+                if cpu.len() == self.prev_cpu.len() {
+                    let usage: Vec<f32> = self
+                        .prev_cpu
+                        .iter()
+                        .zip(&cpu)
+                        .map(|(prev, cur)| cpu_usage(*prev, *cur))
+                        .collect();
+                    if let Some((total, cores)) = usage.split_first() {
+                        self.cpu_history.push(*total);
+                        self.core_usage = cores.to_vec();
+                    }
+                }
+                self.prev_cpu = cpu;
+            }
+            Err(e) => warn!("SysmonModule: failed to read /proc/stat: {}", e),
+        }
+
+        self.mem = fs::read_to_string(self.proc_path.join("meminfo"))
+            .ok()
+            .and_then(|content| parse_meminfo(&content));
+        if let Some(mem) = self.mem {
+            self.mem_history.push(mem.used_percent());
+        }
+
+        self.load = fs::read_to_string(self.proc_path.join("loadavg"))
+            .ok()
+            .and_then(|content| parse_loadavg(&content));
+
+        true
+    }
+
+    fn draw_graph(&self, canvas: &mut Canvas, area: Rect, history: &History, color: [u8; 4]) {
+        let graph_background = self.graph_background.resolve(canvas.theme());
+        canvas.fill_rect(area.x, area.y, area.width, area.height, graph_background);
+        // Faint columns under a solid line tracing the samples
+        let values = history.to_vec();
+        let mut faint = color;
+        faint[3] /= 3;
+        canvas.draw_sparkline(area, &values, 100.0, faint);
+        canvas.draw_polyline(&graph_points(area, &values, 100.0), 1.5, color);
+    }
+}
+
+impl Module for SysmonModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = config.get("proc_path").and_then(|v| v.as_str()) {
+            self.proc_path = PathBuf::from(path);
+        }
+        if let Some(ms) = config.get("interval_ms").and_then(|v| v.as_integer()) {
+            // Samples are taken on the update tick, so no faster than that
+            self.interval = Duration::from_millis(ms.max(0) as u64).max(UPDATE_INTERVAL);
+        }
+        if let Some(len) = config.get("history").and_then(|v| v.as_integer()) {
+            self.cpu_history = History::new(len.max(2) as usize);
+            self.mem_history = History::new(len.max(2) as usize);
+        }
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "cpu_color") {
+            self.cpu_color = color;
+        }
+        if let Some(color) = color_from_config(config, "mem_color") {
+            self.mem_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+
+        self.sample();
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let cpu_color = self.cpu_color.resolve(canvas.theme());
+        let mem_color = self.mem_color.resolve(canvas.theme());
+        let graph_background = self.graph_background.resolve(canvas.theme());
+        let margin = 10;
+        let line_height = self.font_size as i32 + 6;
+
+        // Summary line
+        let mut summary = format!("CPU {:.0}%", self.cpu_history.latest().unwrap_or(0.0));
+        if let Some(mem) = self.mem {
+            summary.push_str(&format!(
+                "   Mem {}/{} GiB",
+                format_gib(mem.used()),
+                format_gib(mem.total)
+            ));
+        }
+        if let Some(load) = self.load {
+            summary.push_str(&format!(
+                "   Load {:.2} {:.2} {:.2}",
+                load.one, load.five, load.fifteen
+            ));
+        }
+        canvas.draw_text(area.x + margin, area.y, &summary, color, self.font_size);
+
+        // CPU and memory sparklines side by side
+        let graph_y = area.y + line_height;
+        let graph_height = 50;
+        let graph_width = (area.width as i32 - 3 * margin).max(0) as u32 / 2;
+        self.draw_graph(
+            canvas,
+            Rect {
+                x: area.x + margin,
+                y: graph_y,
+                width: graph_width,
+                height: graph_height,
+            },
+            &self.cpu_history,
+            cpu_color,
+        );
+        self.draw_graph(
+            canvas,
+            Rect {
+                x: area.x + 2 * margin + graph_width as i32,
+                y: graph_y,
+                width: graph_width,
+                height: graph_height,
+            },
+            &self.mem_history,
+            mem_color,
+        );
+
+        // Per-core usage bars
+        if !self.core_usage.is_empty() {
+            let bars_y = graph_y + graph_height as i32 + margin;
+            let bars_height = 40u32;
+            let bars_width = area.width.saturating_sub(2 * margin as u32);
+            let slot = (bars_width / self.core_usage.len() as u32).max(2);
+
+            for (i, usage) in self.core_usage.iter().enumerate() {
+                let x = area.x + margin + (i as u32 * slot) as i32;
+                let bar_width = slot.saturating_sub(2).max(1);
+                let bar_height = (usage / 100.0 * bars_height as f32).round() as u32;
+                canvas.fill_rect(x, bars_y, bar_width, bars_height, graph_background);
+                canvas.fill_rect(
+                    x,
+                    bars_y + (bars_height - bar_height.min(bars_height)) as i32,
+                    bar_width,
+                    bar_height.min(bars_height),
+                    cpu_color,
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let cpu_color = self.cpu_color.resolve(canvas.theme());
+        let graph_background = self.graph_background.resolve(canvas.theme());
+        let usage = self.cpu_history.latest().unwrap_or(0.0);
+
+        // Usage ring filling clockwise from the top
+        let radius = area.height as f32 / 2.0 - 2.5;
+        let (cx, cy) = (
+            area.x as f32 + RING_SIZE as f32 / 2.0,
+            area.y as f32 + area.height as f32 / 2.0,
+        );
+        canvas.draw_circle(cx, cy, radius, 3.0, graph_background);
+        canvas.draw_arc(cx, cy, radius, -90.0, usage / 100.0 * 360.0, 3.0, cpu_color);
+
+        let text = format!("CPU {:.0}%", usage);
+        let y_pos = area.y + ((area.height as i32 - self.font_size as i32) / 2);
+        canvas.draw_text(
+            area.x + RING_SIZE as i32 + 4,
+            y_pos,
+            &text,
+            color,
+            self.font_size,
+        );
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, _area: Rect) -> bool {
+        match event {
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                self.sample()
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        (500, self.font_size as u32 + 6 + 50 + 10 + 40)
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        Some((RING_SIZE + 4 + 70, 20))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROC_STAT: &str = "\
+cpu  4705 356 584 3699176 23060 0 277 0 0 0
+cpu0 1393280 32966 572056 13343292 6130 0 17875 0 23933 0
+cpu1 1335 9 97 1096 4 0 3 0 0 0
+intr 114930548 113199788 3 0 5 263 0 4 [... lots more numbers ...]
+ctxt 1990473
+btime 1062191376
+processes 2915
+procs_running 1
+procs_blocked 0
+";
+
+    const MEMINFO: &str = "\
+MemTotal:       16303428 kB
+MemFree:         1021152 kB
+MemAvailable:    8151712 kB
+Buffers:          330672 kB
+Cached:          6563712 kB
+SwapCached:            0 kB
+SwapTotal:       8388604 kB
+SwapFree:        8388092 kB
+HugePages_Total:       0
+Hugepagesize:       2048 kB
+";
+
+    #[test]
+    fn proc_stat() {
+        let cpus = parse_proc_stat(PROC_STAT);
+        assert_eq!(cpus.len(), 3);
+        // user nice system idle iowait irq softirq steal; guest excluded
+        assert_eq!(
+            cpus[0],
+            CpuTimes {
+                idle: 3699176 + 23060,
+                total: 4705 + 356 + 584 + 3699176 + 23060 + 277,
+            }
+        );
+        assert_eq!(
+            cpus[2],
+            CpuTimes {
+                idle: 1100,
+                total: 2544
+            }
+        );
+
+        // Lines too short to read are skipped
+        assert_eq!(parse_proc_stat("cpu 1 2 3\nctxt 5\n"), []);
+        assert_eq!(parse_proc_stat(""), []);
+    }
+
+    #[test]
+    fn cpu_usage_between_samples() {
+        let prev = CpuTimes {
+            idle: 100,
+            total: 200,
+        };
+        assert_eq!(
+            cpu_usage(
+                prev,
+                CpuTimes {
+                    idle: 150,
+                    total: 400
+                }
+            ),
+            75.0
+        );
+        assert_eq!(cpu_usage(prev, prev), 0.0);
+        // Counters that went backwards, as after a CPU comes back online
+        assert_eq!(
+            cpu_usage(
+                prev,
+                CpuTimes {
+                    idle: 50,
+                    total: 300
+                }
+            ),
+            100.0
+        );
+    }
+
+    #[test]
+    fn meminfo() {
+        let mem = parse_meminfo(MEMINFO).unwrap();
+        assert_eq!(
+            mem,
+            MemInfo {
+                total: 16303428,
+                available: 8151712,
+                swap_total: 8388604,
+                swap_free: 8388092,
+            }
+        );
+        assert_eq!(mem.used(), 16303428 - 8151712);
+        assert_eq!(mem.used_percent().round(), 50.0);
+    }
+
+    #[test]
+    fn meminfo_of_old_kernels() {
+        // No MemAvailable before Linux 3.14
+        let old = "MemTotal: 1000 kB\nMemFree: 100 kB\nBuffers: 50 kB\nCached: 250 kB\n";
+        assert_eq!(parse_meminfo(old).unwrap().available, 400);
+
+        assert_eq!(parse_meminfo("MemTotal: 1000 kB\n"), None);
+        assert_eq!(parse_meminfo("MemFree: 100 kB\n"), None);
+        assert_eq!(parse_meminfo(""), None);
+    }
+
+    #[test]
+    fn loadavg() {
+        assert_eq!(
+            parse_loadavg("0.52 1.04 0.98 3/1024 123456\n"),
+            Some(LoadAvg {
+                one: 0.52,
+                five: 1.04,
+                fifteen: 0.98,
+                running: 3,
+                total: 1024,
+            })
+        );
+        assert_eq!(parse_loadavg("0.52 1.04 0.98\n"), None);
+        assert_eq!(parse_loadavg("0.52 1.04 0.98 3 1\n"), None);
+        assert_eq!(parse_loadavg("high 1.04 0.98 3/1024 1\n"), None);
+    }
+
+    #[test]
+    fn samples_are_read_from_proc() {
+        let proc = tempfile::tempdir().unwrap();
+        let write_stat = |idle: u64, total_busy: u64| {
+            fs::write(
+                proc.path().join("stat"),
+                format!("cpu  {} 0 0 {} 0 0 0 0\n", total_busy, idle),
+            )
+            .unwrap();
+        };
+        write_stat(100, 100);
+        fs::write(proc.path().join("meminfo"), MEMINFO).unwrap();
+        fs::write(proc.path().join("loadavg"), "0.52 1.04 0.98 3/1024 1\n").unwrap();
+
+        let mut module = SysmonModule::new();
+        let config: toml::Table =
+            toml::from_str(&format!("proc_path = {:?}\ninterval_ms = 100", proc.path())).unwrap();
+        module.init(&config).unwrap();
+        // Faster than the update tick isn't possible
+        assert_eq!(module.interval, UPDATE_INTERVAL);
+
+        // The first sample, taken by init, only primes the CPU counters
+        assert_eq!(module.cpu_history.latest(), None);
+        assert_eq!(module.mem_history.latest().map(f32::round), Some(50.0));
+        assert_eq!(module.load.map(|load| load.running), Some(3));
+
+        // Too soon for another sample
+        write_stat(150, 250);
+        assert!(!module.sample());
+        module.last_sample = None;
+        assert!(module.sample());
+        assert_eq!(module.cpu_history.latest(), Some(75.0));
+    }
+}
@@ -0,0 +1,939 @@
+//! Timer module for hypr-notch
+//!
+//! A countdown timer, a stopwatch and a Pomodoro timer. The collapsed
+//! notch shows a progress ring with the time while one is running; when a
+//! countdown or Pomodoro phase ends the notch expands and a desktop
+//! notification is sent. Click to start or pause, right-click to reset and
+//! scroll to change the countdown length, or drive it with
+//! `hypr-notch msg timer ...`. Running timers are saved to
+//! `$XDG_STATE_HOME/hypr-notch/timer.toml`, so they carry on across
+//! restarts.
+
+use crate::config::{color_from_config, resolve_path};
+use crate::draw::{self, Canvas};
+use crate::ipc::Reply;
+use crate::module::{Module, ModuleEvent, Rect};
+use crate::theme::ThemeColor;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::Value;
+
+/// Linux input event codes for the mouse buttons
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+
+/// Pomodoro work sessions before a long break
+const SESSIONS_PER_LONG_BREAK: u32 = 4;
+
+/// Parse a duration such as `25m`, `90s`, `1h30m`, `5:00` or `10` (minutes)
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    if let Ok(minutes) = text.parse::<u64>() {
+        return Some(Duration::from_secs(minutes.checked_mul(60)?));
+    }
+    if text.contains(':') {
+        // [h:]mm:ss
+        let parts: Vec<u64> = text
+            .split(':')
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()?;
+        let secs = match parts.as_slice() {
+            [m, s] => m.checked_mul(60)?.checked_add(*s)?,
+            [h, m, s] => h
+                .checked_mul(3600)?
+                .checked_add(m.checked_mul(60)?)?
+                .checked_add(*s)?,
+            _ => return None,
+        };
+        return Some(Duration::from_secs(secs));
+    }
+
+    let mut total: u64 = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value: u64 = number.parse().ok()?;
+        number.clear();
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        total = total.checked_add(value.checked_mul(unit)?)?;
+    }
+    number.is_empty().then_some(Duration::from_secs(total))
+}
+
+/// `m:ss`, or `h:mm:ss` from an hour up
+pub fn format_time(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Countdown,
+    Stopwatch,
+    Pomodoro,
+}
+
+impl Mode {
+    const ALL: [Self; 3] = [Self::Countdown, Self::Stopwatch, Self::Pomodoro];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Countdown => "Timer",
+            Self::Stopwatch => "Stopwatch",
+            Self::Pomodoro => "Pomodoro",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Work,
+    Break,
+    LongBreak,
+}
+
+impl Phase {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Work => "Focus",
+            Self::Break => "Break",
+            Self::LongBreak => "Long break",
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Everything needed to carry on after a restart. Times are wall-clock
+/// milliseconds so they stay meaningful in another process.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimerState {
+    pub mode: Mode,
+    /// Length of the countdown or current Pomodoro phase, in seconds
+    pub duration: u64,
+    /// Milliseconds counted before the current run started
+    pub elapsed: u64,
+    /// Unix time in milliseconds the current run started; None when paused
+    pub started_at: Option<u64>,
+    pub phase: Phase,
+    /// Pomodoro work sessions finished
+    pub sessions: u32,
+}
+
+impl TimerState {
+    fn new(mode: Mode, duration: Duration) -> Self {
+        Self {
+            mode,
+            duration: duration.as_secs(),
+            elapsed: 0,
+            started_at: None,
+            phase: Phase::Work,
+            sessions: 0,
+        }
+    }
+
+    fn running(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    /// Whether there is anything to show: running, or paused part-way
+    fn active(&self) -> bool {
+        self.running() || self.elapsed > 0
+    }
+
+    fn elapsed_at(&self, now: u64) -> Duration {
+        let running = self
+            .started_at
+            .map_or(0, |started| now.saturating_sub(started));
+        Duration::from_millis(self.elapsed.saturating_add(running))
+    }
+
+    fn counts_down(&self) -> bool {
+        self.mode != Mode::Stopwatch
+    }
+
+    /// Time left of a countdown, or time counted by the stopwatch
+    fn display_at(&self, now: u64) -> Duration {
+        let elapsed = self.elapsed_at(now);
+        if self.counts_down() {
+            Duration::from_secs(self.duration).saturating_sub(elapsed)
+        } else {
+            elapsed
+        }
+    }
+
+    /// How far the ring is filled, from 0 to 1. The stopwatch goes round
+    /// once a minute.
+    fn progress_at(&self, now: u64) -> f32 {
+        let elapsed = self.elapsed_at(now).as_secs_f32();
+        if self.counts_down() {
+            (elapsed / self.duration.max(1) as f32).min(1.0)
+        } else {
+            elapsed % 60.0 / 60.0
+        }
+    }
+
+    fn finished_at(&self, now: u64) -> bool {
+        self.counts_down() && self.running() && self.display_at(now).is_zero()
+    }
+
+    fn start(&mut self, now: u64) {
+        if self.started_at.is_none() && (!self.counts_down() || self.duration > 0) {
+            self.started_at = Some(now);
+        }
+    }
+
+    fn pause(&mut self, now: u64) {
+        if let Some(started) = self.started_at.take() {
+            self.elapsed = self.elapsed.saturating_add(now.saturating_sub(started));
+        }
+    }
+
+    fn reset(&mut self) {
+        self.elapsed = 0;
+        self.started_at = None;
+    }
+}
+
+/// Read saved timer state; None when there is none or it can't be read
+pub fn load_state(path: &Path) -> Option<TimerState> {
+    let text = fs::read_to_string(path).ok()?;
+    match toml::from_str(&text) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            warn!("TimerModule: ignoring {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+pub fn save_state(path: &Path, state: &TimerState) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, toml::to_string(state)?)?;
+    Ok(())
+}
+
+/// Send a desktop notification without holding up the caller
+fn notify(summary: String, body: String) {
+    let spawned = thread::Builder::new()
+        .name("timer-notify".to_string())
+        .spawn(move || {
+            let result = Connection::session().and_then(|conn| {
+                let proxy = Proxy::new(
+                    &conn,
+                    "org.freedesktop.Notifications",
+                    "/org/freedesktop/Notifications",
+                    "org.freedesktop.Notifications",
+                )?;
+                let actions: Vec<&str> = Vec::new();
+                let hints: HashMap<&str, Value> = HashMap::new();
+                proxy.call::<_, _, u32>(
+                    "Notify",
+                    &(
+                        "hypr-notch",
+                        0u32,
+                        "alarm-symbolic",
+                        summary.as_str(),
+                        body.as_str(),
+                        actions,
+                        hints,
+                        -1i32,
+                    ),
+                )
+            });
+            if let Err(e) = result {
+                warn!("TimerModule: failed to send notification: {}", e);
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("TimerModule: failed to start notification thread: {}", e);
+    }
+}
+
+pub struct TimerModule {
+    id: String,
+    name: String,
+    state: TimerState,
+    state_path: Option<PathBuf>,
+    countdown: Duration,
+    work: Duration,
+    short_break: Duration,
+    long_break: Duration,
+    step: Duration,
+    color: ThemeColor,
+    secondary_color: ThemeColor,
+    track_color: ThemeColor,
+    ring_color: ThemeColor,
+    font_size: f32,
+    notify: bool,
+    auto_expand: bool,
+    expand_duration: Duration,
+    expand_until: Option<Instant>,
+}
+
+impl Default for TimerModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimerModule {
+    pub fn new() -> Self {
+        let countdown = Duration::from_secs(5 * 60);
+        Self {
+            id: "timer".to_string(),
+            name: "Timer".to_string(),
+            state: TimerState::new(Mode::Countdown, countdown),
+            state_path: dirs::state_dir().map(|dir| dir.join("hypr-notch").join("timer.toml")),
+            countdown,
+            work: Duration::from_secs(25 * 60),
+            short_break: Duration::from_secs(5 * 60),
+            long_break: Duration::from_secs(15 * 60),
+            step: Duration::from_secs(60),
+            color: ThemeColor::named("foreground"),
+            secondary_color: ThemeColor::named("muted"),
+            track_color: ThemeColor::named("surface"),
+            ring_color: ThemeColor::named("accent"),
+            font_size: 16.0,
+            notify: true,
+            auto_expand: true,
+            expand_duration: Duration::from_secs(10),
+            expand_until: None,
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.state_path else {
+            return;
+        };
+        if let Err(e) = save_state(path, &self.state) {
+            warn!("TimerModule: failed to save {}: {}", path.display(), e);
+        }
+    }
+
+    fn phase_duration(&self, phase: Phase) -> Duration {
+        match phase {
+            Phase::Work => self.work,
+            Phase::Break => self.short_break,
+            Phase::LongBreak => self.long_break,
+        }
+    }
+
+    /// Switch to `mode`, stopping whatever was running
+    fn set_mode(&mut self, mode: Mode) {
+        let duration = match mode {
+            Mode::Countdown => self.countdown,
+            Mode::Stopwatch => Duration::ZERO,
+            Mode::Pomodoro => self.work,
+        };
+        self.state = TimerState::new(mode, duration);
+    }
+
+    fn toggle(&mut self) {
+        let now = now_millis();
+        if self.state.running() {
+            self.state.pause(now);
+        } else {
+            self.state.start(now);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state.reset();
+        if self.state.mode == Mode::Pomodoro {
+            self.state.phase = Phase::Work;
+            self.state.sessions = 0;
+            self.state.duration = self.work.as_secs();
+        }
+    }
+
+    /// Lengthen or shorten the countdown while it isn't running
+    fn adjust(&mut self, clicks: f64) -> bool {
+        if self.state.mode != Mode::Countdown || self.state.running() {
+            return false;
+        }
+        let change = self.step.as_secs_f64() * clicks;
+        let duration = (self.state.duration as f64 + change).max(0.0);
+        // Snap to whole steps so touchpad scrolling lands on round numbers
+        let step = self.step.as_secs().max(1);
+        self.state.duration = (duration as u64 + step / 2) / step * step;
+        self.countdown = Duration::from_secs(self.state.duration);
+        true
+    }
+
+    /// End a finished countdown or Pomodoro phase
+    fn finish(&mut self) {
+        let (summary, body) = match self.state.mode {
+            Mode::Pomodoro => {
+                let finished = self.state.phase;
+                let next = match finished {
+                    Phase::Work => {
+                        self.state.sessions += 1;
+                        if self.state.sessions.is_multiple_of(SESSIONS_PER_LONG_BREAK) {
+                            Phase::LongBreak
+                        } else {
+                            Phase::Break
+                        }
+                    }
+                    Phase::Break | Phase::LongBreak => Phase::Work,
+                };
+                self.state.phase = next;
+                self.state.duration = self.phase_duration(next).as_secs();
+                self.state.reset();
+                let summary = match finished {
+                    Phase::Work => "Time for a break",
+                    Phase::Break | Phase::LongBreak => "Break is over",
+                };
+                let body = format!(
+                    "{} of {} is next",
+                    next.label(),
+                    format_time(self.phase_duration(next))
+                );
+                (summary.to_string(), body)
+            }
+            _ => {
+                let length = format_time(Duration::from_secs(self.state.duration));
+                self.state.reset();
+                ("Timer finished".to_string(), format!("{} is up", length))
+            }
+        };
+
+        info!("TimerModule: {}", summary);
+        if self.notify {
+            notify(summary, body);
+        }
+        if self.auto_expand {
+            self.expand_until = Some(Instant::now() + self.expand_duration);
+        }
+        self.save();
+    }
+
+    /// Handle `hypr-notch msg timer ...`; returns false for unknown commands
+    fn command(&mut self, args: &[String], reply: &Reply) -> bool {
+        let now = now_millis();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match args.as_slice() {
+            ["status"] => {
+                reply.send(self.status_line(now));
+                return true;
+            }
+            ["start"] => {
+                self.state.start(now);
+            }
+            ["start", duration] => {
+                let Some(duration) = parse_duration(duration) else {
+                    return false;
+                };
+                self.countdown = duration;
+                self.set_mode(Mode::Countdown);
+                self.state.start(now);
+            }
+            ["stopwatch"] => {
+                self.set_mode(Mode::Stopwatch);
+                self.state.start(now);
+            }
+            ["pomodoro"] => {
+                self.set_mode(Mode::Pomodoro);
+                self.state.start(now);
+            }
+            ["pause"] => self.state.pause(now),
+            ["toggle"] => self.toggle(),
+            ["reset"] => self.reset(),
+            ["add", duration] => {
+                let Some(duration) = parse_duration(duration) else {
+                    return false;
+                };
+                if self.state.mode != Mode::Countdown {
+                    return false;
+                }
+                self.state.duration = self.state.duration.saturating_add(duration.as_secs());
+            }
+            _ => return false,
+        }
+        self.save();
+        true
+    }
+
+    /// Mode tabs along the top of the expanded layout
+    fn tab_rects(&self, area: Rect) -> Vec<(Mode, Rect)> {
+        let small_font = self.font_size * 0.8;
+        let mut x = area.x + 90;
+        Mode::ALL
+            .into_iter()
+            .map(|mode| {
+                let width = draw::text_width(mode.label(), small_font) + 16;
+                let rect = Rect {
+                    x,
+                    y: area.y + 4,
+                    width,
+                    height: small_font as u32 + 8,
+                };
+                x += width as i32 + 6;
+                (mode, rect)
+            })
+            .collect()
+    }
+
+    fn draw_ring(&self, canvas: &mut Canvas, cx: f32, cy: f32, radius: f32, width: f32) {
+        let track_color = self.track_color.resolve(canvas.theme());
+        let ring_color = self.ring_color.resolve(canvas.theme());
+        canvas.draw_circle(cx, cy, radius, width, track_color);
+        let progress = self.state.progress_at(now_millis());
+        if progress > 0.0 {
+            canvas.draw_arc(cx, cy, radius, -90.0, progress * 360.0, width, ring_color);
+        }
+    }
+
+    /// Status and time for `hypr-notch msg timer status`, such as
+    /// `Focus · Running · 1 done · 12:34 left`
+    fn status_line(&self, now: u64) -> String {
+        let time = format_time(self.state.display_at(now));
+        if self.state.counts_down() {
+            format!("{} · {} left", self.status(), time)
+        } else {
+            format!("{} · {}", self.status(), time)
+        }
+    }
+
+    fn status(&self) -> String {
+        let state = &self.state;
+        let activity = if state.running() {
+            "Running"
+        } else if state.active() {
+            "Paused"
+        } else {
+            "Ready"
+        };
+        match state.mode {
+            Mode::Pomodoro => format!(
+                "{} · {} · {} done",
+                state.phase.label(),
+                activity,
+                state.sessions
+            ),
+            _ => activity.to_string(),
+        }
+    }
+}
+
+impl Module for TimerModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        let duration = |key: &str| {
+            let value = config.get(key)?;
+            let duration = match value.as_integer() {
+                Some(minutes) => Duration::from_secs(minutes.max(0) as u64 * 60),
+                None => parse_duration(value.as_str()?)?,
+            };
+            Some(duration)
+        };
+        if let Some(countdown) = duration("countdown") {
+            self.countdown = countdown;
+        }
+        if let Some(work) = duration("work") {
+            self.work = work;
+        }
+        if let Some(short_break) = duration("short_break") {
+            self.short_break = short_break;
+        }
+        if let Some(long_break) = duration("long_break") {
+            self.long_break = long_break;
+        }
+        if let Some(step) = duration("step") {
+            self.step = step.max(Duration::from_secs(1));
+        }
+        if let Some(path) = config.get("state_file").and_then(|v| v.as_str()) {
+            self.state_path = Some(resolve_path(Path::new(path)));
+        }
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "secondary_color") {
+            self.secondary_color = color;
+        }
+        if let Some(color) = color_from_config(config, "track_color") {
+            self.track_color = color;
+        }
+        if let Some(color) = color_from_config(config, "ring_color") {
+            self.ring_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+        if let Some(notify) = config.get("notify").and_then(|v| v.as_bool()) {
+            self.notify = notify;
+        }
+        if let Some(auto_expand) = config.get("auto_expand").and_then(|v| v.as_bool()) {
+            self.auto_expand = auto_expand;
+        }
+        if let Some(secs) = config.get("expand_seconds").and_then(|v| v.as_integer()) {
+            self.expand_duration = Duration::from_secs(secs.max(0) as u64);
+        }
+
+        self.set_mode(Mode::Countdown);
+        if let Some(state) = self.state_path.as_deref().and_then(load_state) {
+            debug!("TimerModule: restored {:?}", state);
+            self.state = state;
+        }
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        let color = self.color.resolve(canvas.theme());
+        let secondary_color = self.secondary_color.resolve(canvas.theme());
+        let track_color = self.track_color.resolve(canvas.theme());
+        let small_font = self.font_size * 0.8;
+
+        let radius = (area.height as f32 / 2.0 - 6.0).clamp(8.0, 32.0);
+        let (cx, cy) = (
+            area.x as f32 + 10.0 + radius,
+            area.y as f32 + area.height as f32 / 2.0,
+        );
+        self.draw_ring(canvas, cx, cy, radius, 4.0);
+
+        for (mode, rect) in self.tab_rects(area) {
+            let selected = mode == self.state.mode;
+            if selected {
+                canvas.fill_rounded_rect(rect, rect.height as f32 / 2.0, track_color);
+            }
+            canvas.draw_text(
+                rect.x + 8,
+                rect.y + 4,
+                mode.label(),
+                if selected { color } else { secondary_color },
+                small_font,
+            );
+        }
+
+        let time = format_time(self.state.display_at(now_millis()));
+        let text_x = area.x + 90;
+        let text_y = area.y + area.height as i32 - self.font_size as i32 * 2 - 14;
+        canvas.draw_text(text_x, text_y, &time, color, self.font_size * 1.4);
+        canvas.draw_text(
+            text_x,
+            area.y + area.height as i32 - small_font as i32 - 6,
+            &self.status(),
+            secondary_color,
+            small_font,
+        );
+
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.state.active() {
+            return Ok(());
+        }
+        let color = if self.state.running() {
+            self.color.resolve(canvas.theme())
+        } else {
+            self.secondary_color.resolve(canvas.theme())
+        };
+
+        let cy = area.y as f32 + area.height as f32 / 2.0;
+        self.draw_ring(canvas, area.x as f32 + 8.0, cy, 6.5, 2.5);
+
+        let time = format_time(self.state.display_at(now_millis()));
+        let text_y = area.y + (area.height as i32 - self.font_size as i32) / 2;
+        canvas.draw_text(area.x + 22, text_y, &time, color, self.font_size * 0.9);
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, area: Rect) -> bool {
+        match event {
+            ModuleEvent::Press { button, x, y } if *button == BTN_LEFT => {
+                let tab = self
+                    .tab_rects(area)
+                    .into_iter()
+                    .find(|(_, rect)| rect.contains(*x, *y));
+                match tab {
+                    Some((mode, _)) if mode != self.state.mode => self.set_mode(mode),
+                    Some(_) => return false,
+                    None => self.toggle(),
+                }
+                self.save();
+                true
+            }
+            ModuleEvent::Press { button, .. } if *button == BTN_RIGHT => {
+                self.reset();
+                self.save();
+                true
+            }
+            ModuleEvent::Scroll { delta, .. } => {
+                // Scrolling up lengthens the countdown
+                let changed = self.adjust(-delta);
+                if changed {
+                    self.save();
+                }
+                changed
+            }
+            ModuleEvent::Command {
+                module,
+                args,
+                reply,
+            } if *module == self.id => self.command(args, reply),
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                if self.state.finished_at(now_millis()) {
+                    self.finish();
+                    return true;
+                }
+                // Redraw every tick while the time is changing
+                self.state.running()
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        (400, 80)
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        self.state.active().then_some((80, 20))
+    }
+
+    fn wants_expand(&self) -> bool {
+        self.expand_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Option<Duration> {
+        Some(Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("10"), secs(600));
+        assert_eq!(parse_duration(" 25m "), secs(1500));
+        assert_eq!(parse_duration("90s"), secs(90));
+        assert_eq!(parse_duration("1h30m"), secs(5400));
+        assert_eq!(parse_duration("5:00"), secs(300));
+        assert_eq!(parse_duration("1:02:03"), secs(3723));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("5x"), None);
+        assert_eq!(parse_duration("5m3"), None);
+        assert_eq!(parse_duration("1:2:3:4"), None);
+    }
+
+    #[test]
+    fn overflowing_durations_are_rejected() {
+        assert_eq!(parse_duration("18446744073709551615"), None);
+        assert_eq!(parse_duration("18446744073709551615h"), None);
+        assert_eq!(parse_duration("18446744073709551615s1s"), None);
+        assert_eq!(parse_duration("18446744073709551615:00"), None);
+        assert_eq!(parse_duration("1:18446744073709551615:00"), None);
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(format_time(Duration::ZERO), "0:00");
+        assert_eq!(format_time(Duration::from_millis(59_999)), "0:59");
+        assert_eq!(format_time(Duration::from_secs(25 * 60)), "25:00");
+        assert_eq!(format_time(Duration::from_secs(3600)), "1:00:00");
+        assert_eq!(format_time(Duration::from_secs(3723)), "1:02:03");
+    }
+
+    /// A module that neither notifies nor saves
+    fn pomodoro() -> TimerModule {
+        let mut module = TimerModule::new();
+        module.notify = false;
+        module.state_path = None;
+        module.set_mode(Mode::Pomodoro);
+        module
+    }
+
+    #[test]
+    fn pomodoro_phases_alternate_with_a_long_break() {
+        let mut module = pomodoro();
+        let mut phases = Vec::new();
+        for _ in 0..8 {
+            module.state.start(0);
+            module.finish();
+            assert!(!module.state.active());
+            phases.push((module.state.phase, module.state.duration));
+        }
+        let work = (Phase::Work, 25 * 60);
+        let short_break = (Phase::Break, 5 * 60);
+        let long_break = (Phase::LongBreak, 15 * 60);
+        assert_eq!(
+            phases,
+            [
+                short_break,
+                work,
+                short_break,
+                work,
+                short_break,
+                work,
+                long_break,
+                work
+            ]
+        );
+        assert_eq!(module.state.sessions, 4);
+        assert!(module.wants_expand());
+
+        module.reset();
+        assert_eq!(module.state.phase, Phase::Work);
+        assert_eq!(module.state.sessions, 0);
+    }
+
+    #[test]
+    fn countdowns_finish_at_zero() {
+        let mut module = TimerModule::new();
+        module.notify = false;
+        module.state_path = None;
+        module.state.start(1_000);
+        assert!(!module.state.finished_at(1_000 + 299_999));
+        assert!(module.state.finished_at(1_000 + 300_000));
+        module.finish();
+        assert_eq!(module.state.mode, Mode::Countdown);
+        assert_eq!(module.state.duration, 300);
+        assert!(!module.state.active());
+    }
+
+    #[test]
+    fn status_reports_the_time_left() {
+        let mut module = pomodoro();
+        module.state.sessions = 1;
+        module.state.start(0);
+        assert_eq!(
+            module.status_line(5 * 60 * 1000),
+            "Focus · Running · 1 done · 20:00 left"
+        );
+        module.set_mode(Mode::Stopwatch);
+        module.state.start(0);
+        assert_eq!(module.status_line(65 * 1000), "Running · 1:05");
+    }
+
+    /// Tests that move XDG_STATE_HOME take turns
+    static STATE_HOME: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    /// Run `test` with XDG_STATE_HOME in a new directory, passing it where
+    /// the timer state is saved
+    fn in_state_home(test: impl FnOnce(&Path)) {
+        let _turn = STATE_HOME.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        std::env::set_var("XDG_STATE_HOME", dir.path());
+        test(&dir.path().join("hypr-notch").join("timer.toml"));
+    }
+
+    /// A module as it starts up, restoring whatever was saved
+    fn restart() -> TimerModule {
+        let mut module = TimerModule::new();
+        module
+            .init(&toml::from_str("notify = false").unwrap())
+            .unwrap();
+        module
+    }
+
+    #[test]
+    fn timers_carry_on_after_a_restart() {
+        in_state_home(|path| {
+            // A countdown that has been running for a minute
+            let mut module = restart();
+            let now = now_millis();
+            module.countdown = Duration::from_secs(10 * 60);
+            module.set_mode(Mode::Countdown);
+            module.state.start(now - 60_000);
+            module.save();
+            assert!(path.is_file());
+            let module = restart();
+            assert!(module.state.running());
+            assert_eq!(module.state.display_at(now), Duration::from_secs(9 * 60));
+            // and keeps counting down while hypr-notch isn't running
+            assert_eq!(
+                module.state.display_at(now + 60_000),
+                Duration::from_secs(8 * 60)
+            );
+
+            // A stopwatch paused at 1:05
+            let mut module = restart();
+            module.set_mode(Mode::Stopwatch);
+            module.state.start(now - 65_000);
+            module.state.pause(now);
+            module.save();
+            let module = restart();
+            assert_eq!(module.state.mode, Mode::Stopwatch);
+            assert_eq!(module.status_line(now + 600_000), "Paused · 1:05");
+
+            // A Pomodoro on its first break
+            let mut module = restart();
+            module.set_mode(Mode::Pomodoro);
+            module.state.start(now);
+            module.finish();
+            module.state.start(now);
+            module.save();
+            let module = restart();
+            assert_eq!(module.state.phase, Phase::Break);
+            assert_eq!(module.state.sessions, 1);
+            assert_eq!(
+                module.status_line(now + 60_000),
+                "Break · Running · 1 done · 4:00 left"
+            );
+        });
+    }
+
+    #[test]
+    fn unreadable_state_starts_idle() {
+        in_state_home(|path| {
+            let idle = TimerState::new(Mode::Countdown, Duration::from_secs(5 * 60));
+            // Nothing saved yet
+            assert_eq!(restart().state, idle);
+
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            for corrupt in ["not toml at all", "mode = \"egg timer\"", ""] {
+                fs::write(path, corrupt).unwrap();
+                assert_eq!(restart().state, idle, "{corrupt:?}");
+            }
+        });
+    }
+}
@@ -0,0 +1,687 @@
+//! System tray module for hypr-notch
+//!
+//! Shows StatusNotifierItem tray icons in the expanded notch, and the icons
+//! of items asking for attention in the collapsed notch. Left click
+//! activates an item (or opens its menu if that is all it has), middle
+//! click is the secondary action, right click opens the item's menu inside
+//! the notch and scrolling is passed on to the item.
+
+use crate::config::color_from_config;
+use crate::draw::{self, Canvas};
+use crate::icons;
+use crate::image::{load_image, Filter, RgbaImage};
+use crate::module::{self, Module, ModuleEvent, Rect};
+use crate::sni::{Host, Item, MenuItem, Status, Toggle};
+use crate::theme::ThemeColor;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Linux input event codes for the mouse buttons
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+
+/// Space between icons
+const SPACING: u32 = 8;
+/// Size of the icons in the collapsed notch
+const COLLAPSED_ICON_SIZE: u32 = 16;
+/// Height of a separator in a menu
+const SEPARATOR_HEIGHT: u32 = 7;
+
+/// Requests for the command thread, which makes the D-Bus calls so the
+/// notch never waits on an application
+enum TrayCommand {
+    Activate(Item),
+    SecondaryActivate(Item),
+    Scroll(Item, i32),
+    /// Fetch the item's menu, after telling it the entry is about to show
+    OpenMenu(Item, i32),
+    MenuClicked(Item, i32),
+}
+
+/// The menu shown in the notch
+struct OpenMenu {
+    service: String,
+    /// The item's `menu_revision` when the menu was read
+    revision: u32,
+    root: MenuItem,
+}
+
+#[derive(Default)]
+struct MenuState {
+    open: Option<OpenMenu>,
+    /// Bumped on every change, so the module knows to redraw
+    version: u64,
+}
+
+impl MenuState {
+    fn set(&mut self, open: Option<OpenMenu>) {
+        self.open = open;
+        self.version += 1;
+    }
+}
+
+fn run_commands(host: Arc<Host>, commands: Receiver<TrayCommand>, menu: Arc<Mutex<MenuState>>) {
+    for command in commands {
+        let result = match command {
+            TrayCommand::Activate(item) => host.activate(&item, 0, 0),
+            TrayCommand::SecondaryActivate(item) => host.secondary_activate(&item, 0, 0),
+            TrayCommand::Scroll(item, delta) => host.scroll(&item, delta, "vertical"),
+            TrayCommand::OpenMenu(item, parent) => host.menu(&item, parent).map(|root| {
+                menu.lock().unwrap().set(Some(OpenMenu {
+                    service: item.service.clone(),
+                    revision: item.menu_revision,
+                    root,
+                }));
+                module::request_update();
+            }),
+            TrayCommand::MenuClicked(item, id) => host.menu_clicked(&item, id),
+        };
+        if let Err(e) = result {
+            warn!("TrayModule: {}", e);
+        }
+    }
+}
+
+/// Icons read from items' own theme paths, by file and size
+type ThemeIcons = HashMap<(PathBuf, u32), Option<Arc<RgbaImage>>>;
+
+/// Decode a file from an item's theme path, taking it from `previous` when
+/// the last update already had it. Every file looked up ends up in
+/// `cache`, so icons no item uses any more are let go.
+fn load_theme_icon(
+    path: PathBuf,
+    size: u32,
+    previous: &mut ThemeIcons,
+    cache: &mut ThemeIcons,
+) -> Option<Arc<RgbaImage>> {
+    let key = (path, size);
+    if let Some(icon) = cache.get(&key) {
+        return icon.clone();
+    }
+    let icon = previous
+        .remove(&key)
+        .unwrap_or_else(|| load_image(&key.0, size).ok().map(Arc::new));
+    cache.insert(key, icon.clone());
+    icon
+}
+
+/// Load an item's icon: its pixmap, an icon from its own theme path, or a
+/// themed icon
+fn load_icon(
+    item: &Item,
+    size: u32,
+    previous: &mut ThemeIcons,
+    cache: &mut ThemeIcons,
+) -> Option<Arc<RgbaImage>> {
+    if let Some(image) = item.current_pixmap(size) {
+        return Some(Arc::new(image));
+    }
+    let name = item.current_icon_name();
+    if name.is_empty() {
+        return None;
+    }
+    if !item.icon_theme_path.is_empty() {
+        for extension in ["png", "svg"] {
+            let path = PathBuf::from(&item.icon_theme_path).join(format!("{}.{}", name, extension));
+            if let Some(icon) = load_theme_icon(path, size, previous, cache) {
+                return Some(icon);
+            }
+        }
+    }
+    icons::load(name, size)
+}
+
+/// A row of the menu shown in the notch
+enum MenuRow {
+    /// Return to the enclosing menu
+    Back,
+    Entry(MenuItem),
+}
+
+pub struct TrayModule {
+    id: String,
+    name: String,
+    host: Option<Arc<Host>>,
+    commands: Option<Sender<TrayCommand>>,
+    menu: Arc<Mutex<MenuState>>,
+    /// Items as of the last update, which is what is drawn
+    items: Vec<Item>,
+    icons: Vec<Option<Arc<RgbaImage>>>,
+    theme_icons: ThemeIcons,
+    generation: u64,
+    menu_version: u64,
+    /// Submenus opened within the menu, innermost last
+    submenu: Vec<i32>,
+    icon_size: u32,
+    show_passive: bool,
+    color: ThemeColor,
+    secondary_color: ThemeColor,
+    highlight_color: ThemeColor,
+    attention_color: ThemeColor,
+    font_size: f32,
+}
+
+impl Default for TrayModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrayModule {
+    pub fn new() -> Self {
+        Self {
+            id: "tray".to_string(),
+            name: "Tray".to_string(),
+            host: None,
+            commands: None,
+            menu: Arc::new(Mutex::new(MenuState::default())),
+            items: Vec::new(),
+            icons: Vec::new(),
+            theme_icons: HashMap::new(),
+            generation: 0,
+            menu_version: 0,
+            submenu: Vec::new(),
+            icon_size: 22,
+            show_passive: false,
+            color: ThemeColor::named("foreground"),
+            secondary_color: ThemeColor::named("muted"),
+            highlight_color: ThemeColor::named("surface"),
+            attention_color: ThemeColor::named("warning"),
+            font_size: 14.0,
+        }
+    }
+
+    fn send(&self, command: TrayCommand) {
+        let Some(commands) = &self.commands else {
+            return;
+        };
+        if commands.send(command).is_err() {
+            warn!("TrayModule: command thread is gone");
+        }
+    }
+
+    /// Take the latest items from the host; returns true if they changed
+    fn refresh_items(&mut self) -> bool {
+        let Some(host) = &self.host else {
+            return false;
+        };
+        let generation = host.generation();
+        if generation == self.generation {
+            return false;
+        }
+        self.generation = generation;
+        self.items = host
+            .items()
+            .into_iter()
+            .filter(|item| self.show_passive || item.status != Status::Passive)
+            .collect();
+        let mut previous = std::mem::take(&mut self.theme_icons);
+        self.icons = self
+            .items
+            .iter()
+            .map(|item| load_icon(item, self.icon_size, &mut previous, &mut self.theme_icons))
+            .collect();
+
+        // Reread an open menu whose layout changed, or close it if its
+        // item went away
+        let mut menu = self.menu.lock().unwrap();
+        let Some(open) = menu.open.as_mut() else {
+            return true;
+        };
+        match self.items.iter().find(|item| item.service == open.service) {
+            Some(item) if item.menu_revision != open.revision => {
+                open.revision = item.menu_revision;
+                let item = item.clone();
+                drop(menu);
+                let parent = self.submenu.last().copied().unwrap_or(0);
+                self.send(TrayCommand::OpenMenu(item, parent));
+            }
+            Some(_) => {}
+            None => {
+                menu.set(None);
+                drop(menu);
+                self.submenu.clear();
+            }
+        }
+        true
+    }
+
+    fn close_menu(&mut self) {
+        self.menu.lock().unwrap().set(None);
+        self.submenu.clear();
+    }
+
+    fn row_height(&self) -> u32 {
+        self.icon_size + 8
+    }
+
+    fn icon_rect(&self, area: Rect, index: usize) -> Rect {
+        Rect {
+            x: area.x + (index as u32 * (self.icon_size + SPACING)) as i32,
+            y: area.y + 4,
+            width: self.icon_size,
+            height: self.icon_size,
+        }
+    }
+
+    /// The open menu's item and the entries of the submenu being shown
+    fn current_menu(&self) -> Option<(String, MenuItem)> {
+        let menu = self.menu.lock().unwrap();
+        let open = menu.open.as_ref()?;
+        let node = self
+            .submenu
+            .last()
+            .and_then(|&id| open.root.find(id))
+            .unwrap_or(&open.root);
+        Some((open.service.clone(), node.clone()))
+    }
+
+    fn menu_rows(&self, area: Rect) -> Vec<(MenuRow, Rect)> {
+        let Some((_, node)) = self.current_menu() else {
+            return Vec::new();
+        };
+        let entry_height = self.font_size as u32 + 8;
+        let mut y = area.y + self.row_height() as i32 + 4;
+        let mut rows = Vec::new();
+        let back = (!self.submenu.is_empty()).then_some(MenuRow::Back);
+        let entries = node
+            .children
+            .into_iter()
+            .filter(|entry| entry.visible)
+            .map(MenuRow::Entry);
+        for row in back.into_iter().chain(entries) {
+            let height = match &row {
+                MenuRow::Entry(entry) if entry.separator => SEPARATOR_HEIGHT,
+                _ => entry_height,
+            };
+            let rect = Rect {
+                x: area.x,
+                y,
+                width: area.width,
+                height,
+            };
+            y += height as i32;
+            rows.push((row, rect));
+        }
+        rows
+    }
+
+    fn draw_menu(&self, canvas: &mut Canvas, area: Rect) {
+        let color = self.color.resolve(canvas.theme());
+        let secondary_color = self.secondary_color.resolve(canvas.theme());
+        let highlight_color = self.highlight_color.resolve(canvas.theme());
+        let rows = self.menu_rows(area);
+        if rows.is_empty() {
+            return;
+        }
+
+        let top = rows[0].1.y;
+        let bottom = rows
+            .last()
+            .map_or(top, |(_, rect)| rect.y + rect.height as i32);
+        let background = Rect {
+            x: area.x,
+            y: top - 2,
+            width: area.width,
+            height: (bottom - top + 4) as u32,
+        };
+        canvas.fill_rounded_rect(background, 6.0, highlight_color);
+
+        for (row, rect) in rows {
+            let text_y = rect.y + 4;
+            let (label, text_color) = match &row {
+                MenuRow::Back => ("‹ Back".to_string(), secondary_color),
+                MenuRow::Entry(entry) if entry.separator => {
+                    let y = rect.y as f32 + rect.height as f32 / 2.0;
+                    let (x0, x1) = (
+                        rect.x as f32 + 8.0,
+                        (rect.x + rect.width as i32) as f32 - 8.0,
+                    );
+                    canvas.draw_line(x0, y, x1, y, 1.0, secondary_color);
+                    continue;
+                }
+                MenuRow::Entry(entry) => {
+                    let mark = match (entry.toggle, entry.checked) {
+                        (Toggle::Checkmark, true) => "✓ ",
+                        (Toggle::Radio, true) => "• ",
+                        (Toggle::None, _) => "",
+                        _ => "  ",
+                    };
+                    let label = format!("{}{}", mark, entry.label);
+                    let text_color = if entry.enabled {
+                        color
+                    } else {
+                        secondary_color
+                    };
+                    if !entry.children.is_empty() {
+                        let arrow_x = rect.x + rect.width as i32 - 16;
+                        canvas.draw_text(arrow_x, text_y, "›", text_color, self.font_size);
+                    }
+                    (label, text_color)
+                }
+            };
+            let label = draw::truncate_text(&label, self.font_size, rect.width.saturating_sub(30));
+            canvas.draw_text(rect.x + 10, text_y, &label, text_color, self.font_size);
+        }
+    }
+
+    fn press_menu(&mut self, x: f64, y: f64, area: Rect) -> Option<bool> {
+        let (service, _) = self.current_menu()?;
+        let item = self
+            .items
+            .iter()
+            .find(|item| item.service == service)?
+            .clone();
+        let (row, _) = self
+            .menu_rows(area)
+            .into_iter()
+            .find(|(_, rect)| rect.contains(x, y))?;
+        Some(match row {
+            MenuRow::Back => {
+                self.submenu.pop();
+                true
+            }
+            MenuRow::Entry(entry) if entry.separator || !entry.enabled => false,
+            MenuRow::Entry(entry) if !entry.children.is_empty() => {
+                self.submenu.push(entry.id);
+                self.send(TrayCommand::OpenMenu(item, entry.id));
+                true
+            }
+            MenuRow::Entry(entry) => {
+                self.send(TrayCommand::MenuClicked(item, entry.id));
+                self.close_menu();
+                true
+            }
+        })
+    }
+
+    fn attention_items(&self) -> impl Iterator<Item = (&Item, &Option<Arc<RgbaImage>>)> {
+        self.items
+            .iter()
+            .zip(&self.icons)
+            .filter(|(item, _)| item.status == Status::NeedsAttention)
+    }
+
+    fn draw_icon(
+        &self,
+        canvas: &mut Canvas,
+        item: &Item,
+        icon: &Option<Arc<RgbaImage>>,
+        rect: Rect,
+    ) {
+        match icon {
+            Some(image) => canvas.draw_image(image, rect, Filter::Smooth),
+            // Without an icon, show the first letter of the item's name
+            None => {
+                let color = self.secondary_color.resolve(canvas.theme());
+                let radius = rect.width as f32 / 2.0;
+                canvas.draw_circle(
+                    rect.x as f32 + radius,
+                    rect.y as f32 + radius,
+                    radius - 1.0,
+                    1.5,
+                    color,
+                );
+                let letter: String = item.label().chars().take(1).collect();
+                let size = rect.height as f32 * 0.6;
+                let x = rect.x + (rect.width as i32 - draw::text_width(&letter, size) as i32) / 2;
+                let y = rect.y + (rect.height as i32 - size as i32) / 2;
+                canvas.draw_text(x, y, &letter, color, size);
+            }
+        }
+    }
+}
+
+impl Module for TrayModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(size) = config.get("icon_size").and_then(|v| v.as_integer()) {
+            self.icon_size = size.clamp(8, 64) as u32;
+        }
+        if let Some(show) = config.get("show_passive").and_then(|v| v.as_bool()) {
+            self.show_passive = show;
+        }
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "secondary_color") {
+            self.secondary_color = color;
+        }
+        if let Some(color) = color_from_config(config, "highlight_color") {
+            self.highlight_color = color;
+        }
+        if let Some(color) = color_from_config(config, "attention_color") {
+            self.attention_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+
+        // Applications that hang must not hold up menus and clicks for long
+        let connection = zbus::blocking::connection::Builder::session()?
+            .method_timeout(Duration::from_secs(2))
+            .build()?;
+        let host = Arc::new(Host::start(connection, module::request_update)?);
+        info!(
+            "TrayModule: hosting tray items{}",
+            if host.is_watcher() {
+                " and serving the watcher"
+            } else {
+                ""
+            }
+        );
+
+        let (sender, receiver) = mpsc::channel();
+        let menu = self.menu.clone();
+        let command_host = host.clone();
+        thread::Builder::new()
+            .name("tray-commands".to_string())
+            .spawn(move || run_commands(command_host, receiver, menu))?;
+        self.commands = Some(sender);
+        self.host = Some(host);
+        self.refresh_items();
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        if self.items.is_empty() {
+            let color = self.secondary_color.resolve(canvas.theme());
+            canvas.draw_text(area.x, area.y + 8, "No tray icons", color, self.font_size);
+            return Ok(());
+        }
+
+        let open = self.current_menu().map(|(service, _)| service);
+        let highlight_color = self.highlight_color.resolve(canvas.theme());
+        let attention_color = self.attention_color.resolve(canvas.theme());
+        for (index, (item, icon)) in self.items.iter().zip(&self.icons).enumerate() {
+            let rect = self.icon_rect(area, index);
+            if open.as_deref() == Some(item.service.as_str()) {
+                let padded = Rect {
+                    x: rect.x - 3,
+                    y: rect.y - 3,
+                    width: rect.width + 6,
+                    height: rect.height + 6,
+                };
+                canvas.fill_rounded_rect(padded, 6.0, highlight_color);
+            }
+            self.draw_icon(canvas, item, icon, rect);
+            if item.status == Status::NeedsAttention {
+                let (cx, cy) = ((rect.x + rect.width as i32) as f32, rect.y as f32);
+                canvas.fill_circle(cx, cy, 3.0, attention_color);
+            }
+        }
+
+        self.draw_menu(canvas, area);
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let y = area.y + (area.height as i32 - COLLAPSED_ICON_SIZE as i32) / 2;
+        for (index, (item, icon)) in self.attention_items().enumerate() {
+            let rect = Rect {
+                x: area.x + (index as u32 * (COLLAPSED_ICON_SIZE + 4)) as i32,
+                y,
+                width: COLLAPSED_ICON_SIZE,
+                height: COLLAPSED_ICON_SIZE,
+            };
+            self.draw_icon(canvas, item, icon, rect);
+        }
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, area: Rect) -> bool {
+        match event {
+            ModuleEvent::Press { button, x, y } => {
+                if let Some(handled) = self.press_menu(*x, *y, area) {
+                    return handled;
+                }
+                let Some(item) = (0..self.items.len())
+                    .find(|&index| self.icon_rect(area, index).contains(*x, *y))
+                    .map(|index| self.items[index].clone())
+                else {
+                    return false;
+                };
+
+                let open = self.current_menu().map(|(service, _)| service);
+                if open.is_some() {
+                    self.close_menu();
+                    // A second click on the same icon just closes its menu
+                    if open.as_deref() == Some(item.service.as_str()) {
+                        return true;
+                    }
+                }
+                let wants_menu = item.menu.is_some()
+                    && (*button == BTN_RIGHT || (*button == BTN_LEFT && item.item_is_menu));
+                match *button {
+                    _ if wants_menu => self.send(TrayCommand::OpenMenu(item, 0)),
+                    BTN_LEFT => self.send(TrayCommand::Activate(item)),
+                    BTN_MIDDLE => self.send(TrayCommand::SecondaryActivate(item)),
+                    _ => return open.is_some(),
+                }
+                true
+            }
+            ModuleEvent::Scroll { x, y, delta } => {
+                let Some(index) = (0..self.items.len())
+                    .find(|&index| self.icon_rect(area, index).contains(*x, *y))
+                else {
+                    return false;
+                };
+                // Items expect wheel angles: 120 per click, positive upwards
+                let delta = (-delta * 120.0).round() as i32;
+                self.send(TrayCommand::Scroll(self.items[index].clone(), delta));
+                false
+            }
+            ModuleEvent::Leave => {
+                let open = self.current_menu().is_some();
+                if open {
+                    self.close_menu();
+                }
+                open
+            }
+            ModuleEvent::Update | ModuleEvent::UpdateExpanded | ModuleEvent::UpdateCollapsed => {
+                let changed = self.refresh_items();
+                let version = self.menu.lock().unwrap().version;
+                let menu_changed = version != self.menu_version;
+                self.menu_version = version;
+                changed || menu_changed
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        if self.items.is_empty() {
+            return (
+                draw::text_width("No tray icons", self.font_size),
+                self.row_height(),
+            );
+        }
+        let icons_width = self.items.len() as u32 * (self.icon_size + SPACING);
+        let Some((_, node)) = self.current_menu() else {
+            return (icons_width, self.row_height());
+        };
+
+        let entry_height = self.font_size as u32 + 8;
+        let mut menu_width = 180;
+        let mut menu_height = if self.submenu.is_empty() {
+            0
+        } else {
+            entry_height
+        };
+        for entry in node.children.iter().filter(|entry| entry.visible) {
+            if entry.separator {
+                menu_height += SEPARATOR_HEIGHT;
+            } else {
+                menu_height += entry_height;
+                menu_width = menu_width.max(draw::text_width(&entry.label, self.font_size) + 50);
+            }
+        }
+        (
+            icons_width.max(menu_width.min(400)),
+            self.row_height() + menu_height + 8,
+        )
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        let count = self.attention_items().count() as u32;
+        (count > 0).then_some((count * (COLLAPSED_ICON_SIZE + 4), 20))
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const SQUARE: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="8">
+  <rect width="8" height="8" fill="red"/>
+</svg>"#;
+
+    #[test]
+    fn theme_path_icons_are_decoded_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.svg");
+        fs::write(&path, SQUARE).unwrap();
+
+        let mut cache = ThemeIcons::new();
+        let icon = load_theme_icon(path.clone(), 16, &mut ThemeIcons::new(), &mut cache).unwrap();
+        assert_eq!((icon.width, icon.height), (16, 16));
+        let missing = dir.path().join("missing.png");
+        assert!(load_theme_icon(missing.clone(), 16, &mut ThemeIcons::new(), &mut cache).is_none());
+
+        // The next update takes both from the last one, without reading
+        // the file again
+        fs::remove_file(&path).unwrap();
+        let mut previous = std::mem::take(&mut cache);
+        let again = load_theme_icon(path.clone(), 16, &mut previous, &mut cache).unwrap();
+        assert!(Arc::ptr_eq(&icon, &again));
+        assert!(load_theme_icon(path.clone(), 16, &mut previous, &mut cache).is_some());
+        // Other sizes are decoded of their own
+        assert!(load_theme_icon(path, 24, &mut previous, &mut cache).is_none());
+
+        // The missing icon wasn't asked for, so it's let go
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains_key(&(missing, 16)));
+    }
+}
@@ -0,0 +1,299 @@
+//! Hyprland workspaces module for hypr-notch
+//!
+//! Shows one dot per workspace, with the active workspace drawn as a wider
+//! pill. Clicking a dot in the expanded notch switches to that workspace.
+//! Requests to Hyprland are made on a thread of their own, so a slow
+//! compositor can't hold up drawing.
+
+use crate::config::color_from_config;
+use crate::draw::{text_width, Canvas};
+use crate::hyprland::{self, HyprEvent};
+use crate::module::{self, Module, ModuleEvent, Rect};
+use crate::theme::ThemeColor;
+use log::warn;
+use serde::Deserialize;
+use std::iter;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Linux input event code of the left mouse button
+const BTN_LEFT: u32 = 0x110;
+
+/// Workspace entry from `j/workspaces`
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorkspaceInfo {
+    pub id: i32,
+    #[serde(default)]
+    pub windows: u32,
+}
+
+/// Workspaces as last read from Hyprland
+#[derive(Debug)]
+struct WorkspacesState {
+    workspaces: Vec<WorkspaceInfo>,
+    active: i32,
+}
+
+enum WorkspacesCommand {
+    /// Re-read the workspace list and the active workspace
+    Refresh,
+    /// Switch to the workspace with this id
+    Switch(i32),
+}
+
+/// Re-read the workspace list and the active workspace from Hyprland
+fn refresh(state: &Mutex<WorkspacesState>) {
+    let workspaces = hyprland::request_json::<Vec<WorkspaceInfo>>("workspaces")
+        .map_err(|e| warn!("WorkspacesModule: failed to list workspaces: {}", e));
+    let active = hyprland::request_json::<WorkspaceInfo>("activeworkspace")
+        .map_err(|e| warn!("WorkspacesModule: failed to get active workspace: {}", e));
+
+    let mut state = state.lock().unwrap();
+    if let Ok(workspaces) = workspaces {
+        state.workspaces = workspaces;
+    }
+    if let Ok(active) = active {
+        state.active = active.id;
+    }
+}
+
+/// Run requests to Hyprland, reading the workspaces once for any number
+/// of refreshes queued up behind each other
+fn run_requests(state: Arc<Mutex<WorkspacesState>>, commands: Receiver<WorkspacesCommand>) {
+    while let Ok(command) = commands.recv() {
+        let mut stale = false;
+        for command in iter::once(command).chain(commands.try_iter()) {
+            match command {
+                WorkspacesCommand::Refresh => stale = true,
+                WorkspacesCommand::Switch(id) => {
+                    if let Err(e) = hyprland::dispatch("workspace", &id.to_string()) {
+                        warn!("WorkspacesModule: failed to switch to {}: {}", id, e);
+                    }
+                }
+            }
+        }
+        if stale {
+            refresh(&state);
+            module::request_update();
+        }
+    }
+}
+
+pub struct WorkspacesModule {
+    id: String,
+    name: String,
+    state: Arc<Mutex<WorkspacesState>>,
+    commands: Option<Sender<WorkspacesCommand>>,
+    /// Always show workspaces 1..=count, even when they don't exist yet
+    count: u32,
+    color: ThemeColor,
+    active_color: ThemeColor,
+    empty_color: ThemeColor,
+    font_size: f32,
+}
+
+impl Default for WorkspacesModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WorkspacesModule {
+    pub fn new() -> Self {
+        Self {
+            id: "workspaces".to_string(),
+            name: "Workspaces".to_string(),
+            state: Arc::new(Mutex::new(WorkspacesState {
+                workspaces: Vec::new(),
+                active: 1,
+            })),
+            commands: None,
+            count: 0,
+            color: ThemeColor::named("muted"),
+            active_color: ThemeColor::named("foreground"),
+            empty_color: ThemeColor::named("surface"),
+            font_size: 12.0,
+        }
+    }
+
+    fn send(&self, command: WorkspacesCommand) {
+        if let Some(commands) = &self.commands {
+            let _ = commands.send(command);
+        }
+    }
+
+    fn active(&self) -> i32 {
+        self.state.lock().unwrap().active
+    }
+
+    /// Workspace ids to show, in order, with their window counts
+    fn visible(&self) -> Vec<(i32, u32)> {
+        let mut ids: Vec<(i32, u32)> = (1..=self.count as i32).map(|id| (id, 0)).collect();
+        let state = self.state.lock().unwrap();
+        // Special workspaces have negative ids and are left out
+        for workspace in state.workspaces.iter().filter(|w| w.id > 0) {
+            match ids.iter_mut().find(|(id, _)| *id == workspace.id) {
+                Some(entry) => entry.1 = workspace.windows,
+                None => ids.push((workspace.id, workspace.windows)),
+            }
+        }
+        ids.sort_by_key(|(id, _)| *id);
+        ids
+    }
+
+    /// Dot areas for each visible workspace, centered vertically in `area`
+    fn dot_rects(&self, area: Rect, dot_size: u32) -> Vec<(i32, Rect)> {
+        let spacing = dot_size as i32 / 2 + 2;
+        let active = self.active();
+        let y = area.y + (area.height as i32 - dot_size as i32) / 2;
+        let mut x = area.x;
+
+        self.visible()
+            .into_iter()
+            .map(|(id, _)| {
+                let width = if id == active { dot_size * 2 } else { dot_size };
+                let rect = Rect {
+                    x,
+                    y,
+                    width,
+                    height: dot_size,
+                };
+                x += width as i32 + spacing;
+                (id, rect)
+            })
+            .collect()
+    }
+
+    fn row_width(&self, dot_size: u32) -> u32 {
+        self.dot_rects(Rect::default(), dot_size)
+            .last()
+            .map(|(_, rect)| (rect.x + rect.width as i32) as u32)
+            .unwrap_or(0)
+    }
+
+    fn draw_dots(&self, canvas: &mut Canvas, area: Rect, dot_size: u32, numbered: bool) {
+        let color = self.color.resolve(canvas.theme());
+        let active_color = self.active_color.resolve(canvas.theme());
+        let empty_color = self.empty_color.resolve(canvas.theme());
+        let visible = self.visible();
+        let active = self.active();
+        for ((id, rect), (_, windows)) in self.dot_rects(area, dot_size).into_iter().zip(visible) {
+            let color = if id == active {
+                active_color
+            } else if windows > 0 {
+                color
+            } else {
+                empty_color
+            };
+            canvas.fill_rounded_rect(rect, rect.height as f32 / 2.0, color);
+
+            if numbered {
+                let label = id.to_string();
+                let label_x =
+                    rect.x + (rect.width as i32 - text_width(&label, self.font_size) as i32) / 2;
+                // Numbers are cut out of the dot in the notch's own colour
+                let label_color = canvas.theme().background;
+                canvas.draw_text(label_x, rect.y + 2, &label, label_color, self.font_size);
+            }
+        }
+    }
+}
+
+impl Module for WorkspacesModule {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn init(&mut self, config: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(count) = config.get("count").and_then(|v| v.as_integer()) {
+            self.count = count.clamp(0, 20) as u32;
+        }
+        if let Some(color) = color_from_config(config, "color") {
+            self.color = color;
+        }
+        if let Some(color) = color_from_config(config, "active_color") {
+            self.active_color = color;
+        }
+        if let Some(color) = color_from_config(config, "empty_color") {
+            self.empty_color = color;
+        }
+        if let Some(size) = config.get("font_size").and_then(|v| v.as_float()) {
+            self.font_size = size as f32;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let state = self.state.clone();
+        thread::Builder::new()
+            .name("workspaces".to_string())
+            .spawn(move || run_requests(state, receiver))?;
+        let _ = sender.send(WorkspacesCommand::Refresh);
+        self.commands = Some(sender);
+
+        Ok(())
+    }
+
+    fn draw(&self, canvas: &mut Canvas, area: Rect) -> Result<(), Box<dyn std::error::Error>> {
+        self.draw_dots(canvas, area, 18, true);
+        Ok(())
+    }
+
+    fn draw_collapsed(
+        &self,
+        canvas: &mut Canvas,
+        area: Rect,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.draw_dots(canvas, area, 8, false);
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &ModuleEvent, area: Rect) -> bool {
+        match event {
+            ModuleEvent::Press { button, x, y } if *button == BTN_LEFT => {
+                let clicked = self
+                    .dot_rects(area, 18)
+                    .into_iter()
+                    .find(|(_, rect)| rect.contains(*x, *y));
+                let Some((id, _)) = clicked else {
+                    return false;
+                };
+                self.send(WorkspacesCommand::Switch(id));
+                true
+            }
+            ModuleEvent::Hyprland(HyprEvent::Workspace { id, .. }) => {
+                self.state.lock().unwrap().active = *id;
+                true
+            }
+            ModuleEvent::Hyprland(
+                HyprEvent::CreateWorkspace { .. } | HyprEvent::DestroyWorkspace { .. },
+            ) => {
+                self.send(WorkspacesCommand::Refresh);
+                true
+            }
+            // Window counts change the look of the dots
+            ModuleEvent::Hyprland(HyprEvent::Other { name, .. })
+                if matches!(name.as_str(), "openwindow" | "closewindow" | "movewindowv2") =>
+            {
+                self.send(WorkspacesCommand::Refresh);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn preferred_size(&self) -> (u32, u32) {
+        (self.row_width(18), 24)
+    }
+
+    fn collapsed_size(&self) -> Option<(u32, u32)> {
+        Some((self.row_width(8), 12))
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
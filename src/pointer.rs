@@ -17,14 +17,20 @@ pub fn handle_pointer_events(events: &[PointerEvent], app: &mut AppData) {
                     event.position.0, event.position.1
                 );
                 info!("Expanding notch due to mouse enter");
+                app.hovered = true;
                 app.resize(true);
                 let _ = app.draw();
             }
             PointerEventKind::Leave { .. } => {
                 info!("Mouse left notch area");
-                info!("Collapsing notch due to mouse leave");
-                app.resize(false);
-                let _ = app.draw();
+                app.hovered = false;
+                if app.modules_want_expand() {
+                    info!("Keeping notch expanded on module request");
+                } else {
+                    info!("Collapsing notch due to mouse leave");
+                    app.resize(false);
+                    let _ = app.draw();
+                }
             }
             PointerEventKind::Motion { .. } => {
                 debug!(
@@ -36,8 +42,10 @@ pub fn handle_pointer_events(events: &[PointerEvent], app: &mut AppData) {
         }
 
         if app.expanded {
-            if let Some(_module_event) = convert_pointer_event(event) {
-                app.update_modules();
+            if let Some(module_event) = convert_pointer_event(event) {
+                if app.handle_module_event(&module_event) {
+                    let _ = app.draw();
+                }
             }
         }
     }
@@ -0,0 +1,912 @@
+//! PulseAudio client for hypr-notch
+//!
+//! A small, blocking implementation of the PulseAudio native protocol,
+//! which both PulseAudio and `pipewire-pulse` serve on
+//! `$XDG_RUNTIME_DIR/pulse/native`. It covers just enough to follow the
+//! default sink and source and the streams recording from them:
+//! introspection, volume and mute, and change subscriptions.
+//!
+//! Every packet starts with a 20-byte descriptor (length, channel and
+//! flags, big-endian) followed by a "tagstruct": a sequence of values,
+//! each prefixed with a one-byte type tag. Commands carry the command
+//! number and a tag the reply echoes back.
+
+use log::debug;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Newest protocol version whose replies we know how to read
+const PROTOCOL_VERSION: u32 = 32;
+/// Oldest server we can talk to; earlier versions have no property lists
+const MIN_PROTOCOL_VERSION: u32 = 13;
+
+const COOKIE_LENGTH: usize = 256;
+/// Channel of control packets, as opposed to audio data
+const CONTROL_CHANNEL: u32 = u32::MAX;
+/// Packets larger than this are treated as a protocol error
+const MAX_PACKET_LENGTH: u32 = 16 * 1024 * 1024;
+/// Index meaning "look the object up by name instead"
+const INVALID_INDEX: u32 = u32::MAX;
+
+const COMMAND_ERROR: u32 = 0;
+const COMMAND_REPLY: u32 = 2;
+const COMMAND_AUTH: u32 = 8;
+const COMMAND_SET_CLIENT_NAME: u32 = 9;
+const COMMAND_GET_SINK_INFO: u32 = 21;
+const COMMAND_GET_SOURCE_INFO: u32 = 23;
+const COMMAND_GET_SOURCE_OUTPUT_INFO_LIST: u32 = 32;
+const COMMAND_SUBSCRIBE: u32 = 35;
+const COMMAND_SET_SINK_VOLUME: u32 = 36;
+const COMMAND_SET_SOURCE_VOLUME: u32 = 38;
+const COMMAND_SET_SINK_MUTE: u32 = 39;
+const COMMAND_SET_SOURCE_MUTE: u32 = 40;
+const COMMAND_SUBSCRIBE_EVENT: u32 = 66;
+
+const TAG_STRING: u8 = b't';
+const TAG_STRING_NULL: u8 = b'N';
+const TAG_U32: u8 = b'L';
+const TAG_U8: u8 = b'B';
+const TAG_SAMPLE_SPEC: u8 = b'a';
+const TAG_ARBITRARY: u8 = b'x';
+const TAG_BOOLEAN_TRUE: u8 = b'1';
+const TAG_BOOLEAN_FALSE: u8 = b'0';
+const TAG_CHANNEL_MAP: u8 = b'm';
+const TAG_CVOLUME: u8 = b'v';
+const TAG_PROPLIST: u8 = b'P';
+const TAG_USEC: u8 = b'U';
+const TAG_FORMAT_INFO: u8 = b'f';
+
+/// Volume of a channel at 100%
+pub const VOLUME_NORM: u32 = 0x10000;
+/// Largest volume the server accepts
+pub const VOLUME_MAX: u32 = u32::MAX / 2;
+
+/// Subscription mask bits for [`Connection::subscribe`]
+pub const SUBSCRIBE_SINK: u32 = 0x0001;
+pub const SUBSCRIBE_SOURCE: u32 = 0x0002;
+pub const SUBSCRIBE_SOURCE_OUTPUT: u32 = 0x0008;
+pub const SUBSCRIBE_SERVER: u32 = 0x0080;
+
+fn protocol_error(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Readable name of a server error code
+fn error_name(code: u32) -> &'static str {
+    match code {
+        1 => "access denied",
+        2 => "unknown command",
+        3 => "invalid argument",
+        4 => "entity exists",
+        5 => "no such entity",
+        6 => "connection refused",
+        7 => "protocol error",
+        8 => "timeout",
+        9 => "no authentication key",
+        _ => "unknown error",
+    }
+}
+
+/// Builds the tagstruct of an outgoing packet
+#[derive(Debug, Default)]
+pub struct TagWriter {
+    data: Vec<u8>,
+}
+
+impl TagWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.data.push(TAG_U32);
+        self.data.extend(value.to_be_bytes());
+        self
+    }
+
+    /// A string, or the null string for `None`
+    pub fn string(&mut self, value: Option<&str>) -> &mut Self {
+        match value {
+            Some(value) => {
+                self.data.push(TAG_STRING);
+                self.data.extend(value.as_bytes());
+                self.data.push(0);
+            }
+            None => self.data.push(TAG_STRING_NULL),
+        }
+        self
+    }
+
+    pub fn bool(&mut self, value: bool) -> &mut Self {
+        self.data.push(if value {
+            TAG_BOOLEAN_TRUE
+        } else {
+            TAG_BOOLEAN_FALSE
+        });
+        self
+    }
+
+    pub fn arbitrary(&mut self, value: &[u8]) -> &mut Self {
+        self.data.push(TAG_ARBITRARY);
+        self.data.extend((value.len() as u32).to_be_bytes());
+        self.data.extend(value);
+        self
+    }
+
+    /// Per-channel volumes
+    pub fn cvolume(&mut self, volumes: &[u32]) -> &mut Self {
+        self.data.push(TAG_CVOLUME);
+        self.data.push(volumes.len() as u8);
+        for volume in volumes {
+            self.data.extend(volume.to_be_bytes());
+        }
+        self
+    }
+
+    /// A property list of string values
+    pub fn proplist(&mut self, properties: &[(&str, &str)]) -> &mut Self {
+        self.data.push(TAG_PROPLIST);
+        for (key, value) in properties {
+            // Values are stored with their terminating NUL
+            let mut value = value.as_bytes().to_vec();
+            value.push(0);
+            self.string(Some(key))
+                .u32(value.len() as u32)
+                .arbitrary(&value);
+        }
+        self.string(None)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Reads the tagstruct of an incoming packet
+#[derive(Debug)]
+pub struct TagReader {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl TagReader {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<&[u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| protocol_error("truncated packet"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn tag(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn expect(&mut self, expected: u8) -> io::Result<()> {
+        let tag = self.tag()?;
+        if tag != expected {
+            return Err(protocol_error(format!(
+                "expected tag '{}', found '{}'",
+                expected as char, tag as char
+            )));
+        }
+        Ok(())
+    }
+
+    fn raw_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        self.expect(TAG_U32)?;
+        self.raw_u32()
+    }
+
+    pub fn u8(&mut self) -> io::Result<u8> {
+        self.expect(TAG_U8)?;
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// A duration in microseconds
+    pub fn usec(&mut self) -> io::Result<u64> {
+        self.expect(TAG_USEC)?;
+        Ok(u64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    /// A string, or `None` for the null string
+    pub fn string(&mut self) -> io::Result<Option<String>> {
+        match self.tag()? {
+            TAG_STRING_NULL => Ok(None),
+            TAG_STRING => {
+                let rest = &self.data[self.pos..];
+                let len = rest
+                    .iter()
+                    .position(|&b| b == 0)
+                    .ok_or_else(|| protocol_error("unterminated string"))?;
+                let value = String::from_utf8_lossy(&rest[..len]).into_owned();
+                self.pos += len + 1;
+                Ok(Some(value))
+            }
+            tag => Err(protocol_error(format!(
+                "expected a string, found '{}'",
+                tag as char
+            ))),
+        }
+    }
+
+    pub fn bool(&mut self) -> io::Result<bool> {
+        match self.tag()? {
+            TAG_BOOLEAN_TRUE => Ok(true),
+            TAG_BOOLEAN_FALSE => Ok(false),
+            tag => Err(protocol_error(format!(
+                "expected a boolean, found '{}'",
+                tag as char
+            ))),
+        }
+    }
+
+    /// Sample format, channel count and rate; only the channel count is kept
+    pub fn sample_spec(&mut self) -> io::Result<u8> {
+        self.expect(TAG_SAMPLE_SPEC)?;
+        let spec = self.bytes(6)?;
+        Ok(spec[1])
+    }
+
+    /// Position of each channel
+    pub fn channel_map(&mut self) -> io::Result<Vec<u8>> {
+        self.expect(TAG_CHANNEL_MAP)?;
+        let channels = self.bytes(1)?[0] as usize;
+        Ok(self.bytes(channels)?.to_vec())
+    }
+
+    /// Per-channel volumes
+    pub fn cvolume(&mut self) -> io::Result<Vec<u32>> {
+        self.expect(TAG_CVOLUME)?;
+        let channels = self.bytes(1)?[0];
+        (0..channels).map(|_| self.raw_u32()).collect()
+    }
+
+    fn arbitrary(&mut self) -> io::Result<&[u8]> {
+        self.expect(TAG_ARBITRARY)?;
+        let len = self.raw_u32()? as usize;
+        self.bytes(len)
+    }
+
+    /// A property list; values are read as text
+    pub fn proplist(&mut self) -> io::Result<HashMap<String, String>> {
+        self.expect(TAG_PROPLIST)?;
+        let mut properties = HashMap::new();
+        while let Some(key) = self.string()? {
+            let len = self.u32()? as usize;
+            let value = self.arbitrary()?;
+            if value.len() != len {
+                return Err(protocol_error(format!(
+                    "property {} has the wrong length",
+                    key
+                )));
+            }
+            let value = value.strip_suffix(&[0]).unwrap_or(value);
+            properties.insert(key, String::from_utf8_lossy(value).into_owned());
+        }
+        Ok(properties)
+    }
+
+    /// A stream format; only its encoding and properties are in it, and
+    /// neither is kept
+    pub fn format_info(&mut self) -> io::Result<()> {
+        self.expect(TAG_FORMAT_INFO)?;
+        self.u8()?;
+        self.proplist()?;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+/// Kind of object a subscription event is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Facility {
+    Sink,
+    Source,
+    SinkInput,
+    SourceOutput,
+    Server,
+    Other(u32),
+}
+
+/// What happened to the object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    New,
+    Change,
+    Remove,
+}
+
+/// A change the server reported after [`Connection::subscribe`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriptionEvent {
+    pub facility: Facility,
+    pub kind: EventKind,
+    pub index: u32,
+}
+
+impl SubscriptionEvent {
+    /// Decode the event type word: the facility in the low four bits and
+    /// the kind in the next two
+    pub fn from_type(event_type: u32, index: u32) -> Self {
+        let facility = match event_type & 0x0f {
+            0 => Facility::Sink,
+            1 => Facility::Source,
+            2 => Facility::SinkInput,
+            3 => Facility::SourceOutput,
+            7 => Facility::Server,
+            other => Facility::Other(other),
+        };
+        let kind = match event_type & 0x30 {
+            0x00 => EventKind::New,
+            0x20 => EventKind::Remove,
+            _ => EventKind::Change,
+        };
+        Self {
+            facility,
+            kind,
+            index,
+        }
+    }
+}
+
+/// Which side of the default audio path a request is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Device {
+    /// The default output
+    Sink,
+    /// The default input
+    Source,
+}
+
+impl Device {
+    fn default_name(self) -> &'static str {
+        match self {
+            Self::Sink => "@DEFAULT_SINK@",
+            Self::Source => "@DEFAULT_SOURCE@",
+        }
+    }
+}
+
+/// The parts of a sink or source we show
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub index: u32,
+    pub name: String,
+    pub description: String,
+    /// Volume of each channel, with [`VOLUME_NORM`] as 100%
+    pub volume: Vec<u32>,
+    pub muted: bool,
+}
+
+impl DeviceInfo {
+    /// Read the start of a sink or source info reply; both begin with the
+    /// same fields, and the rest is left unread
+    pub fn read(reply: &mut TagReader) -> io::Result<Self> {
+        let index = reply.u32()?;
+        let name = reply.string()?.unwrap_or_default();
+        let description = reply.string()?.unwrap_or_else(|| name.clone());
+        reply.sample_spec()?;
+        reply.channel_map()?;
+        let _owner_module = reply.u32()?;
+        let volume = reply.cvolume()?;
+        let muted = reply.bool()?;
+        Ok(Self {
+            index,
+            name,
+            description,
+            volume,
+            muted,
+        })
+    }
+
+    /// Volume of the loudest channel, in percent
+    pub fn percent(&self) -> f64 {
+        let loudest = self.volume.iter().copied().max().unwrap_or(0);
+        loudest as f64 * 100.0 / VOLUME_NORM as f64
+    }
+
+    /// Channel volumes with the loudest at `percent`, keeping the balance
+    /// between channels
+    pub fn volume_at(&self, percent: f64) -> Vec<u32> {
+        let target = (percent.max(0.0) / 100.0 * VOLUME_NORM as f64).min(VOLUME_MAX as f64);
+        let loudest = self.volume.iter().copied().max().unwrap_or(0);
+        self.volume
+            .iter()
+            .map(|&volume| {
+                if loudest == 0 {
+                    target.round() as u32
+                } else {
+                    (volume as f64 * target / loudest as f64).round() as u32
+                }
+            })
+            .collect()
+    }
+}
+
+/// A stream recording from a source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceOutput {
+    pub index: u32,
+    /// Index of the source it records from
+    pub source: u32,
+    /// Name of the application that opened it
+    pub application: String,
+    /// Paused streams don't receive any audio
+    pub corked: bool,
+}
+
+impl SourceOutput {
+    /// Read one entry of a source output list; which fields are present
+    /// depends on the protocol `version`
+    pub fn read(reply: &mut TagReader, version: u32) -> io::Result<Self> {
+        let index = reply.u32()?;
+        let name = reply.string()?.unwrap_or_default();
+        let _owner_module = reply.u32()?;
+        let _client = reply.u32()?;
+        let source = reply.u32()?;
+        reply.sample_spec()?;
+        reply.channel_map()?;
+        let _buffer_usec = reply.usec()?;
+        let _source_usec = reply.usec()?;
+        let _resample_method = reply.string()?;
+        let _driver = reply.string()?;
+        let properties = reply.proplist()?;
+        let corked = if version >= 19 { reply.bool()? } else { false };
+        if version >= 22 {
+            reply.cvolume()?;
+            let _muted = reply.bool()?;
+            let _has_volume = reply.bool()?;
+            let _volume_writable = reply.bool()?;
+            reply.format_info()?;
+        }
+
+        let application = ["application.name", "application.process.binary"]
+            .iter()
+            .find_map(|key| properties.get(*key))
+            .cloned()
+            .unwrap_or(name);
+        Ok(Self {
+            index,
+            source,
+            application,
+            corked,
+        })
+    }
+}
+
+/// Path of the server socket: `$PULSE_SERVER` when it names a local
+/// socket, otherwise `native` in the PulseAudio runtime directory
+pub fn socket_path() -> Option<PathBuf> {
+    if let Ok(server) = env::var("PULSE_SERVER") {
+        // A list of addresses, of which only local sockets are supported
+        let local = server
+            .split_whitespace()
+            .filter_map(|address| address.strip_prefix("unix:").or(Some(address)))
+            .find(|address| address.starts_with('/'));
+        if let Some(path) = local {
+            return Some(PathBuf::from(path));
+        }
+    }
+    let runtime = match env::var_os("PULSE_RUNTIME_PATH") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(env::var_os("XDG_RUNTIME_DIR")?).join("pulse"),
+    };
+    Some(runtime.join("native"))
+}
+
+/// The authentication cookie, or zeros when there is none; PipeWire and
+/// servers that trust local users don't check it
+pub fn read_cookie() -> Vec<u8> {
+    let candidates = [
+        env::var_os("PULSE_COOKIE").map(PathBuf::from),
+        dirs::config_dir().map(|dir| dir.join("pulse").join("cookie")),
+        dirs::home_dir().map(|dir| dir.join(".pulse-cookie")),
+    ];
+    candidates
+        .into_iter()
+        .flatten()
+        .filter_map(|path| fs::read(path).ok())
+        .find(|cookie| cookie.len() >= COOKIE_LENGTH)
+        .map(|cookie| cookie[..COOKIE_LENGTH].to_vec())
+        .unwrap_or_else(|| vec![0; COOKIE_LENGTH])
+}
+
+/// An authenticated connection to the server
+pub struct Connection {
+    stream: UnixStream,
+    next_tag: u32,
+    /// Protocol version both sides speak
+    version: u32,
+    /// Events that arrived while waiting for a reply
+    events: VecDeque<SubscriptionEvent>,
+    /// Bytes read from the socket that don't make a whole packet yet
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    /// Connect to the user's server as `client_name`
+    pub fn connect(client_name: &str) -> io::Result<Self> {
+        let path = socket_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))?;
+        Self::connect_to(&path, &read_cookie(), client_name)
+    }
+
+    /// Connect to the server listening on `path`
+    pub fn connect_to(path: &Path, cookie: &[u8], client_name: &str) -> io::Result<Self> {
+        let mut conn = Self {
+            stream: UnixStream::connect(path)?,
+            next_tag: 0,
+            version: PROTOCOL_VERSION,
+            events: VecDeque::new(),
+            buffer: Vec::new(),
+        };
+
+        let mut args = TagWriter::new();
+        args.u32(PROTOCOL_VERSION).arbitrary(cookie);
+        let mut reply = conn.request(COMMAND_AUTH, args)?;
+        // The high bits advertise shared memory support, which we don't use
+        let server_version = reply.u32()? & 0xffff;
+        if server_version < MIN_PROTOCOL_VERSION {
+            return Err(protocol_error(format!(
+                "server protocol version {} is too old",
+                server_version
+            )));
+        }
+        conn.version = server_version.min(PROTOCOL_VERSION);
+        debug!(
+            "PulseAudio: connected to {} with protocol version {}",
+            path.display(),
+            conn.version
+        );
+
+        let mut args = TagWriter::new();
+        args.proplist(&[("application.name", client_name)]);
+        conn.request(COMMAND_SET_CLIENT_NAME, args)?;
+        Ok(conn)
+    }
+
+    fn write_packet(&mut self, payload: &[u8]) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(20 + payload.len());
+        for word in [payload.len() as u32, CONTROL_CHANNEL, 0, 0, 0] {
+            packet.extend(word.to_be_bytes());
+        }
+        packet.extend(payload);
+        self.stream.write_all(&packet)
+    }
+
+    /// Take the next complete control packet out of the read buffer,
+    /// dropping any audio data
+    fn buffered_packet(&mut self) -> io::Result<Option<TagReader>> {
+        loop {
+            let Some(descriptor) = self.buffer.get(..20) else {
+                return Ok(None);
+            };
+            let word =
+                |i: usize| u32::from_be_bytes(descriptor[i * 4..i * 4 + 4].try_into().unwrap());
+            let (length, channel) = (word(0), word(1));
+            if length > MAX_PACKET_LENGTH {
+                return Err(protocol_error(format!("packet of {} bytes", length)));
+            }
+            let end = 20 + length as usize;
+            if self.buffer.len() < end {
+                return Ok(None);
+            }
+            let payload = self.buffer[20..end].to_vec();
+            self.buffer.drain(..end);
+            if channel == CONTROL_CHANNEL {
+                return Ok(Some(TagReader::new(payload)));
+            }
+        }
+    }
+
+    /// Read the next control packet, waiting at most `timeout` for it
+    /// (forever for None). Partial packets stay buffered, so a timeout
+    /// never loses data.
+    fn read_packet(&mut self, timeout: Option<Duration>) -> io::Result<Option<TagReader>> {
+        self.stream.set_read_timeout(timeout)?;
+        let mut chunk = [0u8; 4096];
+        loop {
+            if let Some(packet) = self.buffered_packet()? {
+                return Ok(Some(packet));
+            }
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(len) => self.buffer.extend(&chunk[..len]),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None)
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Send a command and wait for its reply, queueing any events that
+    /// arrive in the meantime
+    pub fn request(&mut self, command: u32, args: TagWriter) -> io::Result<TagReader> {
+        let tag = self.next_tag;
+        self.next_tag = self.next_tag.wrapping_add(1) & 0x7fff_ffff;
+
+        let mut payload = TagWriter::new();
+        payload.u32(command).u32(tag);
+        let mut payload = payload.into_bytes();
+        payload.extend(args.into_bytes());
+        self.write_packet(&payload)?;
+
+        loop {
+            let Some(mut packet) = self.read_packet(None)? else {
+                continue;
+            };
+            let (reply_command, reply_tag) = (packet.u32()?, packet.u32()?);
+            match reply_command {
+                COMMAND_SUBSCRIBE_EVENT => self.queue_event(&mut packet)?,
+                _ if reply_tag != tag => {
+                    debug!("PulseAudio: ignoring command {}", reply_command);
+                }
+                COMMAND_REPLY => return Ok(packet),
+                COMMAND_ERROR => {
+                    let code = packet.u32()?;
+                    return Err(io::Error::other(format!(
+                        "PulseAudio error {} ({})",
+                        code,
+                        error_name(code)
+                    )));
+                }
+                _ => {
+                    return Err(protocol_error(format!(
+                        "unexpected command {}",
+                        reply_command
+                    )))
+                }
+            }
+        }
+    }
+
+    fn queue_event(&mut self, packet: &mut TagReader) -> io::Result<()> {
+        let event_type = packet.u32()?;
+        let index = packet.u32()?;
+        self.events
+            .push_back(SubscriptionEvent::from_type(event_type, index));
+        Ok(())
+    }
+
+    /// Ask the server to report changes to the objects in `mask`
+    pub fn subscribe(&mut self, mask: u32) -> io::Result<()> {
+        let mut args = TagWriter::new();
+        args.u32(mask);
+        self.request(COMMAND_SUBSCRIBE, args)?;
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for a subscription event. Returns None when
+    /// none arrived in time.
+    pub fn next_event(&mut self, timeout: Duration) -> io::Result<Option<SubscriptionEvent>> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(Some(event));
+        }
+
+        let Some(mut packet) = self.read_packet(Some(timeout))? else {
+            return Ok(None);
+        };
+        let (command, _tag) = (packet.u32()?, packet.u32()?);
+        if command == COMMAND_SUBSCRIBE_EVENT {
+            self.queue_event(&mut packet)?;
+        } else {
+            debug!("PulseAudio: ignoring command {}", command);
+        }
+        Ok(self.events.pop_front())
+    }
+
+    /// Current state of the default sink or source
+    pub fn device_info(&mut self, device: Device) -> io::Result<DeviceInfo> {
+        let command = match device {
+            Device::Sink => COMMAND_GET_SINK_INFO,
+            Device::Source => COMMAND_GET_SOURCE_INFO,
+        };
+        let mut args = TagWriter::new();
+        args.u32(INVALID_INDEX).string(Some(device.default_name()));
+        DeviceInfo::read(&mut self.request(command, args)?)
+    }
+
+    /// Every stream recording from a source, including monitors of sinks
+    pub fn source_outputs(&mut self) -> io::Result<Vec<SourceOutput>> {
+        let mut reply = self.request(COMMAND_GET_SOURCE_OUTPUT_INFO_LIST, TagWriter::new())?;
+        let mut outputs = Vec::new();
+        while !reply.is_empty() {
+            outputs.push(SourceOutput::read(&mut reply, self.version)?);
+        }
+        Ok(outputs)
+    }
+
+    /// Whether a source is the monitor of a sink, which records what is
+    /// played rather than a microphone
+    pub fn is_monitor(&mut self, source: u32) -> io::Result<bool> {
+        let mut args = TagWriter::new();
+        args.u32(source).string(None);
+        let mut reply = self.request(COMMAND_GET_SOURCE_INFO, args)?;
+        DeviceInfo::read(&mut reply)?;
+        let monitor_of_sink = reply.u32()?;
+        Ok(monitor_of_sink != INVALID_INDEX)
+    }
+
+    /// Set the volume of each channel of a sink or source
+    pub fn set_volume(&mut self, device: Device, index: u32, volume: &[u32]) -> io::Result<()> {
+        let command = match device {
+            Device::Sink => COMMAND_SET_SINK_VOLUME,
+            Device::Source => COMMAND_SET_SOURCE_VOLUME,
+        };
+        let mut args = TagWriter::new();
+        args.u32(index).string(None).cvolume(volume);
+        self.request(command, args)?;
+        Ok(())
+    }
+
+    /// Mute or unmute a sink or source
+    pub fn set_mute(&mut self, device: Device, index: u32, muted: bool) -> io::Result<()> {
+        let command = match device {
+            Device::Sink => COMMAND_SET_SINK_MUTE,
+            Device::Source => COMMAND_SET_SOURCE_MUTE,
+        };
+        let mut args = TagWriter::new();
+        args.u32(index).string(None).bool(muted);
+        self.request(command, args)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_round_trip() {
+        let mut writer = TagWriter::new();
+        writer
+            .u32(0xdead_beef)
+            .string(Some("Built-in Audio"))
+            .string(Some(""))
+            .string(None)
+            .bool(true)
+            .bool(false)
+            .cvolume(&[VOLUME_NORM, VOLUME_NORM / 2])
+            .cvolume(&[])
+            .proplist(&[("application.name", "hypr-notch"), ("media.role", "")])
+            .proplist(&[]);
+        let mut reader = TagReader::new(writer.into_bytes());
+
+        assert_eq!(reader.u32().unwrap(), 0xdead_beef);
+        assert_eq!(reader.string().unwrap().as_deref(), Some("Built-in Audio"));
+        assert_eq!(reader.string().unwrap().as_deref(), Some(""));
+        assert_eq!(reader.string().unwrap(), None);
+        assert!(reader.bool().unwrap());
+        assert!(!reader.bool().unwrap());
+        assert_eq!(reader.cvolume().unwrap(), [VOLUME_NORM, VOLUME_NORM / 2]);
+        assert!(reader.cvolume().unwrap().is_empty());
+        let properties = reader.proplist().unwrap();
+        assert_eq!(properties.len(), 2);
+        assert_eq!(properties["application.name"], "hypr-notch");
+        assert_eq!(properties["media.role"], "");
+        assert!(reader.proplist().unwrap().is_empty());
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn malformed_tags_are_errors() {
+        let mut writer = TagWriter::new();
+        writer.u32(1);
+        let mut reader = TagReader::new(writer.into_bytes());
+        assert!(reader.string().is_err());
+
+        let mut writer = TagWriter::new();
+        writer.bool(true);
+        assert!(TagReader::new(writer.into_bytes()).u32().is_err());
+
+        // Cut short in the middle of a value
+        let mut writer = TagWriter::new();
+        writer.u32(1);
+        let mut bytes = writer.into_bytes();
+        bytes.pop();
+        assert!(TagReader::new(bytes).u32().is_err());
+        assert!(TagReader::new(vec![TAG_STRING, b'a']).string().is_err());
+        assert!(TagReader::new(vec![TAG_CVOLUME, 2, 0, 0, 0, 1])
+            .cvolume()
+            .is_err());
+
+        // A property whose stated length doesn't match its value
+        let mut entry = TagWriter::new();
+        entry
+            .string(Some("key"))
+            .u32(5)
+            .arbitrary(b"ab\0")
+            .string(None);
+        let mut bytes = vec![TAG_PROPLIST];
+        bytes.extend(entry.into_bytes());
+        assert!(TagReader::new(bytes).proplist().is_err());
+    }
+
+    fn device(volume: &[u32]) -> DeviceInfo {
+        DeviceInfo {
+            index: 0,
+            name: "sink".to_string(),
+            description: "Sink".to_string(),
+            volume: volume.to_vec(),
+            muted: false,
+        }
+    }
+
+    #[test]
+    fn volume_keeps_the_balance() {
+        let sink = device(&[VOLUME_NORM, VOLUME_NORM / 2]);
+        assert_eq!(sink.percent(), 100.0);
+        assert_eq!(sink.volume_at(50.0), [VOLUME_NORM / 2, VOLUME_NORM / 4]);
+        assert_eq!(
+            sink.volume_at(150.0),
+            [VOLUME_NORM * 3 / 2, VOLUME_NORM * 3 / 4]
+        );
+        assert_eq!(sink.volume_at(-10.0), [0, 0]);
+
+        // The quieter channel may be either one
+        let sink = device(&[VOLUME_NORM / 4, VOLUME_NORM / 2]);
+        assert_eq!(sink.percent(), 50.0);
+        assert_eq!(sink.volume_at(100.0), [VOLUME_NORM / 2, VOLUME_NORM]);
+    }
+
+    #[test]
+    fn silent_devices_get_even_volume() {
+        let sink = device(&[0, 0]);
+        assert_eq!(sink.percent(), 0.0);
+        assert_eq!(sink.volume_at(25.0), [VOLUME_NORM / 4, VOLUME_NORM / 4]);
+        assert_eq!(device(&[]).volume_at(50.0), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn volume_is_capped() {
+        let sink = device(&[VOLUME_NORM, 0]);
+        assert_eq!(sink.volume_at(f64::MAX), [VOLUME_MAX, 0]);
+    }
+
+    #[test]
+    fn subscription_event_types() {
+        let event = SubscriptionEvent::from_type(0x10, 3);
+        assert_eq!(event.facility, Facility::Sink);
+        assert_eq!(event.kind, EventKind::Change);
+        assert_eq!(event.index, 3);
+        let event = SubscriptionEvent::from_type(0x23, 7);
+        assert_eq!(
+            (event.facility, event.kind),
+            (Facility::SourceOutput, EventKind::Remove)
+        );
+        let event = SubscriptionEvent::from_type(0x07, 0);
+        assert_eq!(
+            (event.facility, event.kind),
+            (Facility::Server, EventKind::New)
+        );
+        assert_eq!(
+            SubscriptionEvent::from_type(0x05, 0).facility,
+            Facility::Other(5)
+        );
+    }
+}
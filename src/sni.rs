@@ -0,0 +1,732 @@
+//! StatusNotifierItem tray support for hypr-notch
+//!
+//! Tray icons are D-Bus objects (`org.kde.StatusNotifierItem`) that
+//! applications register with a StatusNotifierWatcher. [`Host`] serves
+//! the watcher itself when nothing else does, registers as a host, and
+//! keeps the items and their properties current as they change. Item menus
+//! are read and driven through `com.canonical.dbusmenu`.
+
+use crate::image::RgbaImage;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use zbus::blocking::fdo::DBusProxy;
+use zbus::blocking::{Connection, MessageIterator};
+use zbus::fdo::RequestNameFlags;
+use zbus::fdo::RequestNameReply;
+use zbus::message::{Header, Type as MessageType};
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::MatchRule;
+
+pub const WATCHER_NAME: &str = "org.kde.StatusNotifierWatcher";
+pub const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+pub const WATCHER_INTERFACE: &str = "org.kde.StatusNotifierWatcher";
+pub const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+pub const DEFAULT_ITEM_PATH: &str = "/StatusNotifierItem";
+pub const MENU_INTERFACE: &str = "com.canonical.dbusmenu";
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// Distinguishes the host names of several hosts in one process
+static HOST_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// Split a registered item into its bus name and object path. Items
+/// register a bus name (served at the default path), a bus name followed by
+/// a path, or just a path on their own connection (as libappindicator
+/// does), in which case `sender` is the bus name.
+pub fn item_address(service: &str, sender: &str) -> (String, String) {
+    if service.starts_with('/') {
+        return (sender.to_string(), service.to_string());
+    }
+    match service.find('/') {
+        Some(slash) => (service[..slash].to_string(), service[slash..].to_string()),
+        None => (service.to_string(), DEFAULT_ITEM_PATH.to_string()),
+    }
+}
+
+/// The `org.kde.StatusNotifierWatcher` D-Bus interface
+#[derive(Default)]
+struct Watcher {
+    /// Registered items as `BUS/PATH`
+    items: Vec<String>,
+    hosts: Vec<String>,
+}
+
+#[zbus::interface(name = "org.kde.StatusNotifierWatcher")]
+impl Watcher {
+    async fn register_status_notifier_item(
+        &mut self,
+        service: &str,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let sender = header.sender().map(|s| s.to_string()).unwrap_or_default();
+        let (bus, path) = item_address(service, &sender);
+        let item = format!("{}{}", bus, path);
+        if !self.items.contains(&item) {
+            debug!("Tray item registered: {}", item);
+            self.items.push(item.clone());
+            Self::status_notifier_item_registered(&emitter, &item).await?;
+        }
+        Ok(())
+    }
+
+    async fn register_status_notifier_host(
+        &mut self,
+        service: &str,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        if !self.hosts.iter().any(|host| host == service) {
+            self.hosts.push(service.to_string());
+            Self::status_notifier_host_registered(&emitter).await?;
+        }
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn registered_status_notifier_items(&self) -> Vec<String> {
+        self.items.clone()
+    }
+
+    #[zbus(property)]
+    fn is_status_notifier_host_registered(&self) -> bool {
+        !self.hosts.is_empty()
+    }
+
+    #[zbus(property)]
+    fn protocol_version(&self) -> i32 {
+        0
+    }
+
+    #[zbus(signal)]
+    async fn status_notifier_item_registered(
+        emitter: &SignalEmitter<'_>,
+        service: &str,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn status_notifier_item_unregistered(
+        emitter: &SignalEmitter<'_>,
+        service: &str,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn status_notifier_host_registered(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Status {
+    /// Not important; trays may hide it
+    Passive,
+    #[default]
+    Active,
+    NeedsAttention,
+}
+
+impl Status {
+    fn parse(status: &str) -> Self {
+        match status {
+            "Passive" => Self::Passive,
+            "NeedsAttention" => Self::NeedsAttention,
+            _ => Self::Active,
+        }
+    }
+}
+
+/// One size of an `IconPixmap`: ARGB32 pixels in network byte order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pixmap {
+    pub width: i32,
+    pub height: i32,
+    pub data: Vec<u8>,
+}
+
+/// The pixmap nearest `size` pixels, preferring larger ones to scale down
+pub fn best_pixmap(pixmaps: &[Pixmap], size: u32) -> Option<RgbaImage> {
+    let size = size as i32;
+    let pixmap = pixmaps
+        .iter()
+        .filter(|pixmap| pixmap.width > 0 && pixmap.height > 0)
+        .min_by_key(|pixmap| {
+            let side = pixmap.width.max(pixmap.height);
+            (side < size, (side - size).abs())
+        })?;
+    RgbaImage::from_argb32(pixmap.width as u32, pixmap.height as u32, &pixmap.data)
+}
+
+/// A tray icon and the properties needed to draw and use it
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Item {
+    /// `BUS/PATH` as registered with the watcher
+    pub service: String,
+    pub bus: String,
+    pub path: String,
+    /// Unique name of the connection serving the item, which sends its
+    /// signals
+    pub owner: String,
+    pub id: String,
+    pub title: String,
+    pub status: Status,
+    pub icon_name: String,
+    pub icon_pixmap: Vec<Pixmap>,
+    pub attention_icon_name: String,
+    pub attention_icon_pixmap: Vec<Pixmap>,
+    pub icon_theme_path: String,
+    /// Object path of the item's dbusmenu, if it has one
+    pub menu: Option<String>,
+    /// The item only has a menu, so activating it should show the menu
+    pub item_is_menu: bool,
+    /// Bumped whenever the menu layout changes
+    pub menu_revision: u32,
+}
+
+fn property<T: TryFrom<OwnedValue>>(
+    properties: &HashMap<String, OwnedValue>,
+    name: &str,
+) -> Option<T> {
+    let value = properties.get(name)?.try_clone().ok()?;
+    T::try_from(value).ok()
+}
+
+fn pixmaps(properties: &HashMap<String, OwnedValue>, name: &str) -> Vec<Pixmap> {
+    property::<Vec<(i32, i32, Vec<u8>)>>(properties, name)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(width, height, data)| Pixmap {
+            width,
+            height,
+            data,
+        })
+        .collect()
+}
+
+impl Item {
+    fn update(&mut self, properties: &HashMap<String, OwnedValue>) {
+        let string = |name| property::<String>(properties, name).unwrap_or_default();
+        self.id = string("Id");
+        self.title = string("Title");
+        self.status = Status::parse(&string("Status"));
+        self.icon_name = string("IconName");
+        self.icon_pixmap = pixmaps(properties, "IconPixmap");
+        self.attention_icon_name = string("AttentionIconName");
+        self.attention_icon_pixmap = pixmaps(properties, "AttentionIconPixmap");
+        self.icon_theme_path = string("IconThemePath");
+        self.menu = property::<zbus::zvariant::OwnedObjectPath>(properties, "Menu")
+            .map(|path| path.to_string())
+            .filter(|path| path != "/");
+        self.item_is_menu = property(properties, "ItemIsMenu").unwrap_or(false);
+    }
+
+    fn needs_attention(&self) -> bool {
+        self.status == Status::NeedsAttention
+    }
+
+    /// The icon name to show, which changes while the item needs attention
+    pub fn current_icon_name(&self) -> &str {
+        if self.needs_attention() && !self.attention_icon_name.is_empty() {
+            &self.attention_icon_name
+        } else {
+            &self.icon_name
+        }
+    }
+
+    /// The pixmap to show at about `size` pixels, if the item sends any
+    pub fn current_pixmap(&self, size: u32) -> Option<RgbaImage> {
+        if self.needs_attention() && !self.attention_icon_pixmap.is_empty() {
+            best_pixmap(&self.attention_icon_pixmap, size)
+        } else {
+            best_pixmap(&self.icon_pixmap, size)
+        }
+    }
+
+    /// Name to show for the item
+    pub fn label(&self) -> &str {
+        if self.title.is_empty() {
+            &self.id
+        } else {
+            &self.title
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Toggle {
+    #[default]
+    None,
+    Checkmark,
+    Radio,
+}
+
+/// An entry of a dbusmenu
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MenuItem {
+    pub id: i32,
+    pub label: String,
+    pub enabled: bool,
+    pub visible: bool,
+    pub separator: bool,
+    pub toggle: Toggle,
+    pub checked: bool,
+    pub children: Vec<MenuItem>,
+}
+
+/// Drop the `_` that marks a mnemonic; `__` stands for an underscore
+pub fn strip_mnemonic(label: &str) -> String {
+    let mut out = String::with_capacity(label.len());
+    let mut chars = label.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '_' {
+            out.push(c);
+        } else if chars.peek() == Some(&'_') {
+            out.push('_');
+            chars.next();
+        }
+    }
+    out
+}
+
+/// A node of `GetLayout`: `(ia{sv}av)`
+type Layout = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+impl MenuItem {
+    fn from_layout((id, properties, children): Layout) -> Self {
+        let string = |name| property::<String>(&properties, name).unwrap_or_default();
+        let toggle = match string("toggle-type").as_str() {
+            "checkmark" => Toggle::Checkmark,
+            "radio" => Toggle::Radio,
+            _ => Toggle::None,
+        };
+        Self {
+            id,
+            label: strip_mnemonic(&string("label")),
+            enabled: property(&properties, "enabled").unwrap_or(true),
+            visible: property(&properties, "visible").unwrap_or(true),
+            separator: string("type") == "separator",
+            toggle,
+            checked: property::<i32>(&properties, "toggle-state") == Some(1),
+            children: children
+                .into_iter()
+                .filter_map(|child| Layout::try_from(child).ok())
+                .map(Self::from_layout)
+                .collect(),
+        }
+    }
+
+    /// The entry with `id` anywhere in this menu
+    pub fn find(&self, id: i32) -> Option<&MenuItem> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(id))
+    }
+}
+
+struct Shared {
+    connection: Connection,
+    items: Mutex<Vec<Item>>,
+    /// Bumped on every change to `items`
+    generation: AtomicU64,
+    /// Whether this connection serves the watcher
+    watcher: AtomicBool,
+    host_name: String,
+    on_change: Box<dyn Fn() + Send + Sync>,
+}
+
+impl Shared {
+    fn changed(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        (self.on_change)();
+    }
+
+    /// Serve the watcher if no one else does. Returns true if we now do.
+    fn take_watcher(&self) -> bool {
+        let object_server = self.connection.object_server();
+        if let Err(e) = object_server.at(WATCHER_PATH, Watcher::default()) {
+            warn!("Failed to serve {}: {}", WATCHER_PATH, e);
+            return false;
+        }
+        let reply = self
+            .connection
+            .request_name_with_flags(WATCHER_NAME, RequestNameFlags::DoNotQueue.into());
+        let serving = matches!(
+            reply,
+            Ok(RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner)
+        );
+        if serving {
+            info!("Serving {}", WATCHER_NAME);
+        } else {
+            let _ = object_server.remove::<Watcher, _>(WATCHER_PATH);
+        }
+        self.watcher.store(serving, Ordering::SeqCst);
+        serving
+    }
+
+    /// Register as a host with the watcher and load the items it knows
+    fn register_host(&self) -> zbus::Result<()> {
+        self.connection.call_method(
+            Some(WATCHER_NAME),
+            WATCHER_PATH,
+            Some(WATCHER_INTERFACE),
+            "RegisterStatusNotifierHost",
+            &(self.host_name.as_str(),),
+        )?;
+        let reply = self.connection.call_method(
+            Some(WATCHER_NAME),
+            WATCHER_PATH,
+            Some(PROPERTIES_INTERFACE),
+            "Get",
+            &(WATCHER_INTERFACE, "RegisteredStatusNotifierItems"),
+        )?;
+        let services: Vec<String> = reply
+            .body()
+            .deserialize::<OwnedValue>()
+            .ok()
+            .and_then(|value| Vec::try_from(value).ok())
+            .unwrap_or_default();
+        for service in services {
+            self.add_item(&service);
+        }
+        Ok(())
+    }
+
+    fn properties(&self, bus: &str, path: &str) -> zbus::Result<HashMap<String, OwnedValue>> {
+        let reply = self.connection.call_method(
+            Some(bus),
+            path,
+            Some(PROPERTIES_INTERFACE),
+            "GetAll",
+            &(ITEM_INTERFACE,),
+        )?;
+        reply.body().deserialize()
+    }
+
+    fn add_item(&self, service: &str) {
+        let (bus, path) = item_address(service, "");
+        let properties = match self.properties(&bus, &path) {
+            Ok(properties) => properties,
+            Err(e) => {
+                warn!("Failed to read tray item {}: {}", service, e);
+                return;
+            }
+        };
+        let owner = DBusProxy::new(&self.connection)
+            .and_then(|proxy| Ok(proxy.get_name_owner(bus.as_str().try_into()?)?))
+            .map(|owner| owner.to_string())
+            .unwrap_or_else(|_| bus.clone());
+
+        let mut item = Item {
+            service: service.to_string(),
+            bus,
+            path,
+            owner,
+            ..Item::default()
+        };
+        item.update(&properties);
+        debug!("Tray item {} ({})", item.service, item.label());
+
+        let mut items = self.items.lock().unwrap();
+        match items
+            .iter_mut()
+            .find(|existing| existing.service == item.service)
+        {
+            Some(existing) => *existing = item,
+            None => items.push(item),
+        }
+        drop(items);
+        self.changed();
+    }
+
+    fn remove_items(&self, matches: impl Fn(&Item) -> bool) {
+        let mut items = self.items.lock().unwrap();
+        let before = items.len();
+        items.retain(|item| !matches(item));
+        let removed = items.len() != before;
+        drop(items);
+        if removed {
+            self.changed();
+        }
+    }
+
+    /// Reread the properties of the items at `path` served by `owner`
+    fn refresh_item(&self, owner: &str, path: &str) {
+        let targets: Vec<(String, String)> = self
+            .items
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|item| item.owner == owner && item.path == path)
+            .map(|item| (item.service.clone(), item.bus.clone()))
+            .collect();
+        for (service, bus) in targets {
+            let properties = match self.properties(&bus, path) {
+                Ok(properties) => properties,
+                Err(e) => {
+                    debug!("Failed to refresh tray item {}: {}", service, e);
+                    continue;
+                }
+            };
+            let mut items = self.items.lock().unwrap();
+            if let Some(item) = items.iter_mut().find(|item| item.service == service) {
+                item.update(&properties);
+            }
+            drop(items);
+            self.changed();
+        }
+    }
+
+    /// Note a new layout of the menu at `path` served by `owner`
+    fn menu_changed(&self, owner: &str, path: &str) {
+        let mut changed = false;
+        for item in self.items.lock().unwrap().iter_mut() {
+            if item.owner == owner && item.menu.as_deref() == Some(path) {
+                item.menu_revision = item.menu_revision.wrapping_add(1);
+                changed = true;
+            }
+        }
+        if changed {
+            self.changed();
+        }
+    }
+
+    /// Forget the items of a connection that left the bus
+    fn name_lost(&self, name: &str) {
+        self.remove_items(|item| item.bus == name || item.owner == name);
+        if !self.watcher.load(Ordering::SeqCst) {
+            return;
+        }
+        let Ok(watcher) = self
+            .connection
+            .object_server()
+            .interface::<_, Watcher>(WATCHER_PATH)
+        else {
+            return;
+        };
+        let mut watcher = watcher.get_mut();
+        watcher.hosts.retain(|host| host != name);
+        let (gone, kept): (Vec<String>, Vec<String>) = watcher
+            .items
+            .drain(..)
+            .partition(|item| item_address(item, "").0 == name);
+        watcher.items = kept;
+        drop(watcher);
+        for item in gone {
+            debug!("Tray item unregistered: {}", item);
+            let result = self.connection.emit_signal(
+                None::<()>,
+                WATCHER_PATH,
+                WATCHER_INTERFACE,
+                "StatusNotifierItemUnregistered",
+                &(item.as_str(),),
+            );
+            if let Err(e) = result {
+                warn!("Failed to emit StatusNotifierItemUnregistered: {}", e);
+            }
+        }
+    }
+
+    fn handle_signal(&self, message: &zbus::Message) {
+        let header = message.header();
+        let (Some(interface), Some(member)) = (header.interface(), header.member()) else {
+            return;
+        };
+        let sender = header.sender().map(|s| s.to_string()).unwrap_or_default();
+        let path = header.path().map(|p| p.to_string()).unwrap_or_default();
+        let body = message.body();
+
+        match (interface.as_str(), member.as_str()) {
+            (WATCHER_INTERFACE, "StatusNotifierItemRegistered") => {
+                if let Ok(service) = body.deserialize::<String>() {
+                    self.add_item(&service);
+                }
+            }
+            (WATCHER_INTERFACE, "StatusNotifierItemUnregistered") => {
+                if let Ok(service) = body.deserialize::<String>() {
+                    self.remove_items(|item| item.service == service);
+                }
+            }
+            (ITEM_INTERFACE, member) if member.starts_with("New") => {
+                self.refresh_item(&sender, &path)
+            }
+            (MENU_INTERFACE, "LayoutUpdated" | "ItemsPropertiesUpdated") => {
+                self.menu_changed(&sender, &path)
+            }
+            ("org.freedesktop.DBus", "NameOwnerChanged") => {
+                let Ok((name, _, new_owner)) = body.deserialize::<(String, String, String)>()
+                else {
+                    return;
+                };
+                if name == WATCHER_NAME && !self.watcher.load(Ordering::SeqCst) {
+                    // Another watcher came or went; follow it or take over
+                    if new_owner.is_empty() {
+                        self.remove_items(|_| true);
+                        self.take_watcher();
+                    }
+                    if let Err(e) = self.register_host() {
+                        warn!("Failed to register with {}: {}", WATCHER_NAME, e);
+                    }
+                } else if new_owner.is_empty() {
+                    self.name_lost(&name);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A StatusNotifierHost, with the tray items kept current by a background
+/// thread
+pub struct Host {
+    shared: Arc<Shared>,
+}
+
+impl Host {
+    /// Start hosting on `connection`, serving the watcher if there is none.
+    /// `on_change` is called from the background thread whenever the items
+    /// change.
+    pub fn start(
+        connection: Connection,
+        on_change: impl Fn() + Send + Sync + 'static,
+    ) -> zbus::Result<Self> {
+        let host_name = format!(
+            "org.kde.StatusNotifierHost-{}-{}",
+            std::process::id(),
+            HOST_COUNT.fetch_add(1, Ordering::SeqCst) + 1
+        );
+        connection.request_name(host_name.as_str())?;
+        let shared = Arc::new(Shared {
+            connection,
+            items: Mutex::new(Vec::new()),
+            generation: AtomicU64::new(0),
+            watcher: AtomicBool::new(false),
+            host_name,
+            on_change: Box::new(on_change),
+        });
+
+        // Listen before registering so no item slips through
+        let proxy = DBusProxy::new(&shared.connection)?;
+        let rules = [
+            MatchRule::builder()
+                .msg_type(MessageType::Signal)
+                .interface(WATCHER_INTERFACE)?
+                .build(),
+            MatchRule::builder()
+                .msg_type(MessageType::Signal)
+                .interface(ITEM_INTERFACE)?
+                .build(),
+            MatchRule::builder()
+                .msg_type(MessageType::Signal)
+                .interface(MENU_INTERFACE)?
+                .build(),
+            MatchRule::builder()
+                .msg_type(MessageType::Signal)
+                .sender("org.freedesktop.DBus")?
+                .interface("org.freedesktop.DBus")?
+                .member("NameOwnerChanged")?
+                .build(),
+        ];
+        for rule in rules {
+            proxy.add_match_rule(rule)?;
+        }
+        let messages = MessageIterator::from(&shared.connection);
+        let listener = shared.clone();
+        thread::Builder::new()
+            .name("tray".to_string())
+            .spawn(move || {
+                for message in messages {
+                    match message {
+                        Ok(message) if message.message_type() == MessageType::Signal => {
+                            listener.handle_signal(&message)
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!("Tray connection error: {}", e),
+                    }
+                }
+            })
+            .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+
+        shared.take_watcher();
+        shared.register_host()?;
+        Ok(Self { shared })
+    }
+
+    /// Incremented whenever the items change
+    pub fn generation(&self) -> u64 {
+        self.shared.generation.load(Ordering::SeqCst)
+    }
+
+    pub fn items(&self) -> Vec<Item> {
+        self.shared.items.lock().unwrap().clone()
+    }
+
+    /// Whether this host also serves the watcher
+    pub fn is_watcher(&self) -> bool {
+        self.shared.watcher.load(Ordering::SeqCst)
+    }
+
+    fn call_item<B>(&self, item: &Item, method: &str, body: &B) -> zbus::Result<()>
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        self.shared.connection.call_method(
+            Some(item.bus.as_str()),
+            item.path.as_str(),
+            Some(ITEM_INTERFACE),
+            method,
+            body,
+        )?;
+        Ok(())
+    }
+
+    /// The primary action, usually showing the application's window.
+    /// `x` and `y` are where on screen it was clicked.
+    pub fn activate(&self, item: &Item, x: i32, y: i32) -> zbus::Result<()> {
+        self.call_item(item, "Activate", &(x, y))
+    }
+
+    /// The secondary action, usually on a middle click
+    pub fn secondary_activate(&self, item: &Item, x: i32, y: i32) -> zbus::Result<()> {
+        self.call_item(item, "SecondaryActivate", &(x, y))
+    }
+
+    /// Scroll over the item, `orientation` being `vertical` or `horizontal`
+    pub fn scroll(&self, item: &Item, delta: i32, orientation: &str) -> zbus::Result<()> {
+        self.call_item(item, "Scroll", &(delta, orientation))
+    }
+
+    fn call_menu<B>(&self, item: &Item, method: &str, body: &B) -> zbus::Result<zbus::Message>
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        let path = item
+            .menu
+            .as_deref()
+            .ok_or_else(|| zbus::Error::Failure(format!("{} has no menu", item.service)))?;
+        self.shared.connection.call_method(
+            Some(item.bus.as_str()),
+            path,
+            Some(MENU_INTERFACE),
+            method,
+            body,
+        )
+    }
+
+    /// Read the item's menu, first telling it the entry `parent` is about
+    /// to be shown (0 for the menu itself) so it can fill it in
+    pub fn menu(&self, item: &Item, parent: i32) -> zbus::Result<MenuItem> {
+        // Not every menu implements this, and it changes nothing if not
+        if let Err(e) = self.call_menu(item, "AboutToShow", &(parent,)) {
+            debug!("AboutToShow failed on {}: {}", item.service, e);
+        }
+        let reply = self.call_menu(item, "GetLayout", &(0i32, -1i32, Vec::<&str>::new()))?;
+        let (_revision, layout): (u32, Layout) = reply.body().deserialize()?;
+        Ok(MenuItem::from_layout(layout))
+    }
+
+    /// Click the menu entry `id`
+    pub fn menu_clicked(&self, item: &Item, id: i32) -> zbus::Result<()> {
+        self.call_menu(item, "Event", &(id, "clicked", Value::from(0i32), 0u32))?;
+        Ok(())
+    }
+}
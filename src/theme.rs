@@ -0,0 +1,483 @@
+//! Theme support for hypr-notch
+//!
+//! A theme maps semantic roles (background, foreground, accent, warning,
+//! critical) and free-form palette names to colours. Configuration values
+//! may name a role or palette entry instead of a literal colour, and are
+//! resolved against the current theme every time the notch is drawn, so a
+//! theme switch recolours everything that isn't pinned to a literal.
+//!
+//! All resolved colours are in buffer order (`[b, g, r, a]`), ready to be
+//! written to the `Argb8888` surface.
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// The theme modules draw with
+static CURRENT: RwLock<Option<Arc<Theme>>> = RwLock::new(None);
+
+/// Colour names already reported as unknown
+static UNKNOWN_NAMES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Theme currently in use; the built-in dark theme until one is set
+pub fn current() -> Arc<Theme> {
+    CURRENT
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| Arc::new(Theme::dark()))
+}
+
+/// Replace the current theme; takes effect with the next draw
+pub fn set_current(theme: Theme) {
+    *CURRENT.write().unwrap() = Some(Arc::new(theme));
+}
+
+/// Parse a colour string into buffer order.
+///
+/// Accepts `#RGB`, `#RRGGBB`, `#RRGGBBAA`, `rgb(r, g, b)` and
+/// `rgba(r, g, b, a)`, where the alpha of `rgba()` is a number between 0
+/// and 1 or a percentage, as in CSS.
+pub fn parse_color(text: &str) -> Option<[u8; 4]> {
+    let text = text.trim();
+
+    if let Some(hex) = text.strip_prefix('#') {
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        let (r, g, b, a) = match hex.len() {
+            3 => {
+                let short = |i: usize| {
+                    u8::from_str_radix(hex.get(i..i + 1)?, 16)
+                        .ok()
+                        .map(|v| v * 17)
+                };
+                (short(0)?, short(1)?, short(2)?, 255)
+            }
+            6 => (channel(0)?, channel(2)?, channel(4)?, 255),
+            8 => (channel(0)?, channel(2)?, channel(4)?, channel(6)?),
+            _ => return None,
+        };
+        return Some([b, g, r, a]);
+    }
+
+    let (function, args) = text.strip_suffix(')')?.split_once('(')?;
+    let args: Vec<&str> = args.split(',').map(str::trim).collect();
+    let component = |arg: &str| arg.parse::<u8>().ok();
+    match (function.trim(), args.as_slice()) {
+        ("rgb", [r, g, b]) => Some([component(b)?, component(g)?, component(r)?, 255]),
+        ("rgba", [r, g, b, a]) => {
+            let alpha = match a.strip_suffix('%') {
+                Some(percent) => percent.trim().parse::<f32>().ok()? / 100.0,
+                None => a.parse::<f32>().ok()?,
+            };
+            if !(0.0..=1.0).contains(&alpha) {
+                return None;
+            }
+            let alpha = (alpha * 255.0).round() as u8;
+            Some([component(b)?, component(g)?, component(r)?, alpha])
+        }
+        _ => None,
+    }
+}
+
+/// A colour as written in a configuration file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawColor", into = "RawColor")]
+pub enum ThemeColor {
+    /// A literal colour in buffer order
+    Fixed([u8; 4]),
+    /// A theme role or palette entry, looked up when drawing
+    Named(String),
+}
+
+/// Accepted spellings of a colour: a legacy `[b, g, r, a]` array or a string
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawColor {
+    Array([u8; 4]),
+    Text(String),
+}
+
+impl TryFrom<RawColor> for ThemeColor {
+    type Error = String;
+
+    fn try_from(raw: RawColor) -> Result<Self, Self::Error> {
+        match raw {
+            RawColor::Array(color) => Ok(Self::Fixed(color)),
+            RawColor::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl From<ThemeColor> for RawColor {
+    fn from(color: ThemeColor) -> Self {
+        match color {
+            ThemeColor::Fixed(color) => Self::Array(color),
+            ThemeColor::Named(name) => Self::Text(name),
+        }
+    }
+}
+
+impl std::str::FromStr for ThemeColor {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        if text.starts_with('#') || text.ends_with(')') {
+            return parse_color(text)
+                .map(Self::Fixed)
+                .ok_or_else(|| format!("invalid colour \"{}\"", text));
+        }
+        if text.is_empty()
+            || !text
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("invalid colour name \"{}\"", text));
+        }
+        Ok(Self::Named(text.to_ascii_lowercase()))
+    }
+}
+
+impl ThemeColor {
+    /// Shorthand for a role or palette reference
+    pub fn named(name: &str) -> Self {
+        Self::Named(name.to_string())
+    }
+
+    /// Look the colour up in `theme`, falling back to the foreground for
+    /// names the theme doesn't know
+    pub fn resolve(&self, theme: &Theme) -> [u8; 4] {
+        match self {
+            Self::Fixed(color) => *color,
+            Self::Named(name) => theme.get(name).unwrap_or_else(|| {
+                // Colours are resolved on every draw, so each unknown name
+                // is only reported the first time
+                if UNKNOWN_NAMES.lock().unwrap().insert(name.clone()) {
+                    warn!("Unknown theme colour \"{}\"", name);
+                }
+                theme.foreground
+            }),
+        }
+    }
+}
+
+/// The `[theme]` table of the configuration, and the contents of theme files
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// Theme to start from: `dark`, `light`, or the name of a file in
+    /// `~/.config/hypr-notch/themes/` (without `.toml`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// `system` switches between `light` and `dark` with the desktop's
+    /// colour scheme, falling back to `name` when it has no preference
+    pub mode: ThemeMode,
+    /// Theme used for a light system colour scheme, `light` by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub light: Option<String>,
+    /// Theme used for a dark system colour scheme, `dark` by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dark: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<ThemeColor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<ThemeColor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accent: Option<ThemeColor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<ThemeColor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub critical: Option<ThemeColor>,
+    /// Extra named colours, usable anywhere a colour is expected
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub palette: HashMap<String, ThemeColor>,
+}
+
+/// How the theme is chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeMode {
+    /// Always use the theme given by `name`
+    #[default]
+    Fixed,
+    /// Follow the desktop's light/dark preference
+    System,
+}
+
+/// Resolved colours of a theme, in buffer order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    pub background: [u8; 4],
+    pub foreground: [u8; 4],
+    pub accent: [u8; 4],
+    pub warning: [u8; 4],
+    pub critical: [u8; 4],
+    pub palette: HashMap<String, [u8; 4]>,
+}
+
+impl Theme {
+    /// Built-in dark theme, matching the notch's original look
+    pub fn dark() -> Self {
+        Self {
+            background: [0, 0, 0, 255],       // Black
+            foreground: [255, 255, 255, 255], // White
+            accent: [230, 160, 60, 255],      // Blue (BGRA)
+            warning: [40, 170, 250, 255],     // Orange (BGRA)
+            critical: [60, 60, 230, 255],     // Red (BGRA)
+            palette: HashMap::from([
+                ("muted".to_string(), [180, 180, 180, 255]), // Light gray
+                ("surface".to_string(), [60, 60, 60, 255]),  // Dark gray
+                ("success".to_string(), [80, 200, 80, 255]), // Green
+            ]),
+        }
+    }
+
+    /// Built-in light theme
+    pub fn light() -> Self {
+        Self {
+            background: [240, 240, 240, 255], // Off-white
+            foreground: [30, 30, 30, 255],    // Near black
+            accent: [200, 120, 20, 255],      // Blue (BGRA)
+            warning: [0, 120, 220, 255],      // Orange (BGRA)
+            critical: [40, 40, 200, 255],     // Red (BGRA)
+            palette: HashMap::from([
+                ("muted".to_string(), [100, 100, 100, 255]),   // Gray
+                ("surface".to_string(), [210, 210, 210, 255]), // Light gray
+                ("success".to_string(), [40, 150, 40, 255]),   // Green
+            ]),
+        }
+    }
+
+    /// Build a theme from the `[theme]` table: start from the named theme
+    /// and apply the overrides on top of it
+    pub fn from_config(config: &ThemeConfig) -> Self {
+        Self::with_base(&themes_dir(), config.name.as_deref(), config)
+    }
+
+    /// Build the light or dark theme of `config` for `mode = "system"`
+    pub fn for_dark_mode(config: &ThemeConfig, dark: bool) -> Self {
+        let name = if dark {
+            config.dark.as_deref().unwrap_or("dark")
+        } else {
+            config.light.as_deref().unwrap_or("light")
+        };
+        Self::with_base(&themes_dir(), Some(name), config)
+    }
+
+    /// Start from the theme called `name`, looking for theme files in `dir`
+    fn with_base(dir: &Path, name: Option<&str>, config: &ThemeConfig) -> Self {
+        let mut theme = match name {
+            None | Some("dark") => Self::dark(),
+            Some("light") => Self::light(),
+            Some(name) => {
+                let mut theme = Self::dark();
+                match load_theme_file_from(dir, name) {
+                    Ok(file) => theme.apply(&file),
+                    Err(e) => warn!("Failed to load theme {}: {}", name, e),
+                }
+                theme
+            }
+        };
+        theme.apply(config);
+        theme
+    }
+
+    /// Colour of a role or palette entry
+    pub fn get(&self, name: &str) -> Option<[u8; 4]> {
+        match name {
+            "background" => Some(self.background),
+            "foreground" => Some(self.foreground),
+            "accent" => Some(self.accent),
+            "warning" => Some(self.warning),
+            "critical" => Some(self.critical),
+            _ => self.palette.get(name).copied(),
+        }
+    }
+
+    fn role_mut(&mut self, role: &str) -> Option<&mut [u8; 4]> {
+        match role {
+            "background" => Some(&mut self.background),
+            "foreground" => Some(&mut self.foreground),
+            "accent" => Some(&mut self.accent),
+            "warning" => Some(&mut self.warning),
+            "critical" => Some(&mut self.critical),
+            _ => None,
+        }
+    }
+
+    /// Apply the colours set in `config`. Palette entries are added first so
+    /// roles can refer to them.
+    pub fn apply(&mut self, config: &ThemeConfig) {
+        for (name, color) in &config.palette {
+            let color = color.resolve(self);
+            self.palette.insert(name.to_ascii_lowercase(), color);
+        }
+
+        let roles = [
+            ("background", &config.background),
+            ("foreground", &config.foreground),
+            ("accent", &config.accent),
+            ("warning", &config.warning),
+            ("critical", &config.critical),
+        ];
+        for (role, color) in roles {
+            if let Some(color) = color {
+                let color = color.resolve(self);
+                if let Some(slot) = self.role_mut(role) {
+                    *slot = color;
+                }
+            }
+        }
+    }
+}
+
+/// Directory holding user theme files
+pub fn themes_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from(".config"))
+        .join("hypr-notch")
+        .join("themes")
+}
+
+/// Read `<name>.toml` from the themes directory
+pub fn load_theme_file(name: &str) -> Result<ThemeConfig, Box<dyn std::error::Error>> {
+    load_theme_file_from(&themes_dir(), name)
+}
+
+fn load_theme_file_from(dir: &Path, name: &str) -> Result<ThemeConfig, Box<dyn std::error::Error>> {
+    let path = dir.join(format!("{}.toml", name));
+    info!("Loading theme from {}", path.display());
+    let content = fs::read_to_string(&path)?;
+    Ok(toml::from_str(&content)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_colours() {
+        assert_eq!(parse_color("#f80"), Some([0, 136, 255, 255]));
+        assert_eq!(parse_color("#1e140a"), Some([10, 20, 30, 255]));
+        assert_eq!(parse_color(" #1E140A80 "), Some([10, 20, 30, 128]));
+        for bad in [
+            "#", "#12", "#1234", "#12345", "#1234567", "#ggg", "#12345g", "1e140a",
+        ] {
+            assert_eq!(parse_color(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn functional_colours() {
+        assert_eq!(parse_color("rgb(30, 20, 10)"), Some([10, 20, 30, 255]));
+        assert_eq!(parse_color("rgba(30,20,10,0.5)"), Some([10, 20, 30, 128]));
+        assert_eq!(parse_color("rgba(30, 20, 10, 25%)"), Some([10, 20, 30, 64]));
+        for bad in [
+            "rgb(30, 20)",
+            "rgb(300, 20, 10)",
+            "rgb(30, 20, 10, 1)",
+            "rgba(30, 20, 10)",
+            "rgba(30, 20, 10, 2)",
+            "rgba(30, 20, 10, 150%)",
+            "hsl(30, 20, 10)",
+            "rgb 30, 20, 10",
+        ] {
+            assert_eq!(parse_color(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn colour_names() {
+        let parse = |text: &str| text.parse::<ThemeColor>();
+        assert_eq!(parse("Accent"), Ok(ThemeColor::named("accent")));
+        assert_eq!(parse(" my-blue_2 "), Ok(ThemeColor::named("my-blue_2")));
+        assert_eq!(parse("#1e140a"), Ok(ThemeColor::Fixed([10, 20, 30, 255])));
+        assert!(parse("").is_err());
+        assert!(parse("light blue").is_err());
+        assert!(parse("#nothex").is_err());
+        assert!(parse("rgb(1, 2)").is_err());
+
+        let theme = Theme::dark();
+        assert_eq!(
+            ThemeColor::named("muted").resolve(&theme),
+            [180, 180, 180, 255]
+        );
+        // Unknown names fall back to the foreground
+        assert_eq!(ThemeColor::named("nope").resolve(&theme), theme.foreground);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Document {
+        color: ThemeColor,
+    }
+
+    #[test]
+    fn arrays_are_buffer_order_and_strings_are_rgb() {
+        let from = |text: &str| toml::from_str::<Document>(text).unwrap().color;
+        // The legacy array is taken as it is, blue first
+        let array = from("color = [10, 20, 30, 255]");
+        assert_eq!(array, ThemeColor::Fixed([10, 20, 30, 255]));
+        // while strings are written red first, like everywhere else
+        assert_eq!(from("color = \"#1e140a\""), array);
+        assert_eq!(from("color = \"rgb(30, 20, 10)\""), array);
+        assert_eq!(from("color = \"accent\""), ThemeColor::named("accent"));
+        assert!(toml::from_str::<Document>("color = \"#12\"").is_err());
+        assert!(toml::from_str::<Document>("color = [1, 2, 3]").is_err());
+
+        // Both forms survive a round trip
+        for color in [array, ThemeColor::named("accent")] {
+            let document = Document { color };
+            let text = toml::to_string(&document).unwrap();
+            assert_eq!(toml::from_str::<Document>(&text).unwrap(), document);
+        }
+    }
+
+    #[test]
+    fn theme_files_fill_in_from_the_dark_theme() {
+        let dir = tempfile::tempdir().unwrap();
+        let themes = dir.path().join("themes");
+        fs::create_dir(&themes).unwrap();
+        fs::write(
+            themes.join("ocean.toml"),
+            r##"
+                accent = "sea"
+                warning = "#ffcc00"
+
+                [palette]
+                sea = "#0077be"
+                Muted = "foreground"
+            "##,
+        )
+        .unwrap();
+
+        let dark = Theme::dark();
+        let theme = Theme::with_base(&themes, Some("ocean"), &ThemeConfig::default());
+        assert_eq!(theme.accent, [190, 119, 0, 255]);
+        assert_eq!(theme.warning, [0, 204, 255, 255]);
+        assert_eq!(theme.get("sea"), Some([190, 119, 0, 255]));
+        // Palette names are case-insensitive and can refer to roles
+        assert_eq!(theme.get("muted"), Some(dark.foreground));
+        // Roles the file leaves out come from the dark theme
+        assert_eq!(theme.background, dark.background);
+        assert_eq!(theme.foreground, dark.foreground);
+        assert_eq!(theme.critical, dark.critical);
+        assert_eq!(theme.get("surface"), dark.get("surface"));
+
+        // The configuration is applied on top of the file
+        let config = ThemeConfig {
+            background: Some(ThemeColor::named("sea")),
+            ..Default::default()
+        };
+        let theme = Theme::with_base(&themes, Some("ocean"), &config);
+        assert_eq!(theme.background, [190, 119, 0, 255]);
+
+        // A missing file leaves the dark theme
+        let missing = Theme::with_base(&themes, Some("nowhere"), &ThemeConfig::default());
+        assert_eq!(missing, dark);
+        let light = Theme::with_base(&themes, Some("light"), &ThemeConfig::default());
+        assert_eq!(light, Theme::light());
+    }
+}
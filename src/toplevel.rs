@@ -0,0 +1,311 @@
+//! Fullscreen detection for hypr-notch
+//!
+//! Tracks the toplevel windows announced through
+//! `zwlr_foreign_toplevel_manager_v1` together with their fullscreen state
+//! and the outputs they are shown on, so the notch can get out of the way of
+//! fullscreen videos and games on its own output. Hyprland's `fullscreen`
+//! events stand in for compositors without the protocol.
+
+use crate::app::AppData;
+use log::{debug, info};
+use std::collections::HashMap;
+use wayland_client::backend::ObjectId;
+use wayland_client::protocol::wl_output::WlOutput;
+use wayland_client::{event_created_child, Connection, Dispatch, Proxy, QueueHandle};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+#[derive(Debug, Default)]
+struct ToplevelInfo {
+    fullscreen: bool,
+    outputs: Vec<WlOutput>,
+}
+
+/// Known toplevels, keyed by their handle
+#[derive(Debug, Default)]
+pub struct ToplevelTracker {
+    toplevels: HashMap<ObjectId, ToplevelInfo>,
+}
+
+impl ToplevelTracker {
+    /// Whether a fullscreen toplevel is shown on `output`, or on any output
+    /// when the notch's output isn't known yet
+    pub fn fullscreen_on(&self, output: Option<&WlOutput>) -> bool {
+        self.toplevels.values().any(|toplevel| {
+            toplevel.fullscreen && output.is_none_or(|output| toplevel.outputs.contains(output))
+        })
+    }
+
+    fn entry(&mut self, id: ObjectId) -> &mut ToplevelInfo {
+        self.toplevels.entry(id).or_default()
+    }
+
+    /// Apply an event of the toplevel `id`, returning whether the fullscreen
+    /// state is due to be re-checked
+    fn handle_event(
+        &mut self,
+        id: ObjectId,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+    ) -> bool {
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::State { state } => {
+                self.entry(id).fullscreen = has_fullscreen_state(&state);
+                false
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputEnter { output } => {
+                self.entry(id).outputs.push(output);
+                false
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputLeave { output } => {
+                self.entry(id).outputs.retain(|known| *known != output);
+                false
+            }
+            // State changes are applied atomically once `done` arrives
+            zwlr_foreign_toplevel_handle_v1::Event::Done => true,
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                self.toplevels.remove(&id);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Fullscreen state from Hyprland's events, for when the compositor doesn't
+/// offer foreign toplevel management
+///
+/// The `fullscreen` event only covers the focused window, so each one is
+/// put down to the monitor that had focus at the time.
+#[derive(Debug, Default)]
+pub struct HyprlandFullscreen {
+    focused: Option<String>,
+    /// Keyed by monitor name, or `None` for events from before any
+    /// `focusedmon` event until one on a known monitor replaces them
+    monitors: HashMap<Option<String>, bool>,
+}
+
+impl HyprlandFullscreen {
+    pub fn focus(&mut self, monitor: &str) {
+        self.focused = Some(monitor.to_string());
+    }
+
+    pub fn set(&mut self, fullscreen: bool) {
+        if self.focused.is_some() {
+            self.monitors.remove(&None);
+        }
+        self.monitors.insert(self.focused.clone(), fullscreen);
+    }
+
+    /// Whether the monitor called `monitor` has a fullscreen window, or any
+    /// monitor when the notch's monitor or the focused one isn't known
+    pub fn fullscreen_on(&self, monitor: Option<&str>) -> bool {
+        self.monitors.iter().any(|(name, &fullscreen)| {
+            fullscreen
+                && match (name.as_deref(), monitor) {
+                    (Some(name), Some(monitor)) => name == monitor,
+                    _ => true,
+                }
+        })
+    }
+}
+
+/// Whether a `state` array from the protocol contains the fullscreen state
+fn has_fullscreen_state(states: &[u8]) -> bool {
+    states
+        .chunks_exact(4)
+        .map(|chunk| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .any(|state| state == zwlr_foreign_toplevel_handle_v1::State::Fullscreen as u32)
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for AppData {
+    fn event(
+        state: &mut Self,
+        _manager: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                debug!("New toplevel {:?}", toplevel.id());
+                state.toplevels.entry(toplevel.id());
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                info!("Foreign toplevel manager finished");
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(AppData, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ())
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for AppData {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let closed = matches!(event, zwlr_foreign_toplevel_handle_v1::Event::Closed);
+        if state.toplevels.handle_event(handle.id(), event) {
+            state.update_fullscreen();
+        }
+        if closed {
+            handle.destroy();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use wayland_client::protocol::wl_registry::WlRegistry;
+    use wayland_client::{delegate_noop, EventQueue};
+    use zwlr_foreign_toplevel_handle_v1::{Event, State};
+
+    struct Client;
+
+    delegate_noop!(Client: ignore WlRegistry);
+    delegate_noop!(Client: ignore WlOutput);
+    delegate_noop!(Client: ignore ZwlrForeignToplevelHandleV1);
+
+    /// Proxies for two outputs and two toplevels. Binding them only queues
+    /// requests that are never sent, so no compositor is needed.
+    fn proxies() -> (
+        UnixStream,
+        EventQueue<Client>,
+        [WlOutput; 2],
+        [ZwlrForeignToplevelHandleV1; 2],
+    ) {
+        let (client, server) = UnixStream::pair().unwrap();
+        let conn = Connection::from_socket(client).unwrap();
+        let queue = conn.new_event_queue();
+        let qh = queue.handle();
+        let registry = conn.display().get_registry(&qh, ());
+        let output = |name| registry.bind::<WlOutput, _, _>(name, 4, &qh, ());
+        let toplevel = |name| registry.bind::<ZwlrForeignToplevelHandleV1, _, _>(name, 3, &qh, ());
+        (
+            server,
+            queue,
+            [output(1), output(2)],
+            [toplevel(3), toplevel(4)],
+        )
+    }
+
+    fn states(states: &[State]) -> Event {
+        Event::State {
+            state: states
+                .iter()
+                .flat_map(|state| (*state as u32).to_ne_bytes())
+                .collect(),
+        }
+    }
+
+    /// Feed events of `toplevel`, returning whether any asked for a re-check
+    fn feed(
+        tracker: &mut ToplevelTracker,
+        toplevel: &ZwlrForeignToplevelHandleV1,
+        events: Vec<Event>,
+    ) -> bool {
+        let mut recheck = false;
+        for event in events {
+            recheck |= tracker.handle_event(toplevel.id(), event);
+        }
+        recheck
+    }
+
+    #[test]
+    fn fullscreen_windows_only_count_on_their_output() {
+        let (_server, _queue, [left, right], [video, editor]) = proxies();
+        let mut tracker = ToplevelTracker::default();
+        assert!(feed(
+            &mut tracker,
+            &editor,
+            vec![
+                states(&[State::Activated]),
+                Event::OutputEnter {
+                    output: left.clone()
+                },
+                Event::Done,
+            ]
+        ));
+        // Only `done` asks for a re-check
+        assert!(!feed(
+            &mut tracker,
+            &video,
+            vec![
+                states(&[State::Activated, State::Fullscreen]),
+                Event::OutputEnter {
+                    output: right.clone()
+                },
+            ]
+        ));
+        assert!(feed(&mut tracker, &video, vec![Event::Done]));
+        assert!(!tracker.fullscreen_on(Some(&left)));
+        assert!(tracker.fullscreen_on(Some(&right)));
+        assert!(tracker.fullscreen_on(None));
+
+        // Dragged across to the left output
+        feed(
+            &mut tracker,
+            &video,
+            vec![
+                Event::OutputEnter {
+                    output: left.clone(),
+                },
+                Event::OutputLeave {
+                    output: right.clone(),
+                },
+                Event::Done,
+            ],
+        );
+        assert!(tracker.fullscreen_on(Some(&left)));
+        assert!(!tracker.fullscreen_on(Some(&right)));
+
+        feed(&mut tracker, &video, vec![states(&[]), Event::Done]);
+        assert!(!tracker.fullscreen_on(Some(&left)));
+
+        feed(
+            &mut tracker,
+            &video,
+            vec![states(&[State::Fullscreen]), Event::Done],
+        );
+        assert!(feed(&mut tracker, &video, vec![Event::Closed]));
+        assert!(!tracker.fullscreen_on(None));
+    }
+
+    #[test]
+    fn hyprland_fullscreen_is_kept_per_monitor() {
+        let mut hyprland = HyprlandFullscreen::default();
+        assert!(!hyprland.fullscreen_on(Some("DP-1")));
+
+        // Before any focus event the monitor is unknown, so all count
+        hyprland.set(true);
+        assert!(hyprland.fullscreen_on(Some("DP-1")));
+        assert!(hyprland.fullscreen_on(Some("HDMI-A-1")));
+
+        hyprland.focus("HDMI-A-1");
+        hyprland.set(true);
+        assert!(!hyprland.fullscreen_on(Some("DP-1")));
+        assert!(hyprland.fullscreen_on(Some("HDMI-A-1")));
+        assert!(hyprland.fullscreen_on(None));
+
+        // Leaving fullscreen on another monitor keeps this one
+        hyprland.focus("DP-1");
+        hyprland.set(false);
+        assert!(hyprland.fullscreen_on(Some("HDMI-A-1")));
+        hyprland.focus("HDMI-A-1");
+        hyprland.set(false);
+        assert!(!hyprland.fullscreen_on(Some("HDMI-A-1")));
+        assert!(!hyprland.fullscreen_on(None));
+    }
+}
@@ -3,7 +3,7 @@
 
 use crate::app::AppData;
 use crate::pointer::handle_pointer_events;
-use log::{debug, info, warn};
+use log::{debug, info};
 use smithay_client_toolkit::{
     compositor::CompositorHandler,
     delegate_compositor, delegate_layer, delegate_output, delegate_pointer, delegate_registry,
@@ -29,9 +29,9 @@ impl CompositorHandler for AppData {
         _conn: &wayland_client::Connection,
         _qh: &wayland_client::QueueHandle<Self>,
         _surface: &wayland_client::protocol::wl_surface::WlSurface,
-        _output: &wayland_client::protocol::wl_output::WlOutput,
+        output: &wayland_client::protocol::wl_output::WlOutput,
     ) {
-        // No-op
+        self.set_output(output.clone());
     }
 
     fn surface_leave(
@@ -41,7 +41,8 @@ impl CompositorHandler for AppData {
         _surface: &wayland_client::protocol::wl_surface::WlSurface,
         _output: &wayland_client::protocol::wl_output::WlOutput,
     ) {
-        // No-op
+        // Keep the last output: the surface also leaves it when it is hidden
+        // for a fullscreen window, and must know when that window goes away
     }
 
     fn scale_factor_changed(
@@ -134,9 +135,8 @@ impl LayerShellHandler for AppData {
         self.update_size(width, height);
         self.set_configured(true);
 
-        // Only now: set input region and draw, and only once!
+        // Only now: draw (which also sets the input region), and only once!
         if !self.buffer_drawn {
-            self.set_full_input_region();
             let _ = self.draw();
             self.buffer_drawn = true;
         }
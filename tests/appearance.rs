@@ -0,0 +1,167 @@
+//! Colour scheme tests against a mock desktop portal
+//!
+//! Each test starts a private `dbus-daemon` and is skipped when there is
+//! none installed.
+
+mod common;
+
+use common::PrivateBus;
+use hypr_notch::appearance::{
+    read_color_scheme, watch, ColorScheme, APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY, PORTAL_NAME,
+    PORTAL_PATH, SETTINGS_INTERFACE,
+};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use zbus::blocking::{connection, Connection, Proxy};
+use zbus::fdo;
+use zbus::zvariant::Value;
+
+/// `org.freedesktop.portal.Settings` holding only the colour scheme
+struct FakeSettings {
+    color_scheme: u32,
+    /// Version 1 of the interface has no `ReadOne`
+    read_one: bool,
+}
+
+impl FakeSettings {
+    fn lookup(&self, namespace: &str, key: &str) -> fdo::Result<u32> {
+        if namespace == APPEARANCE_NAMESPACE && key == COLOR_SCHEME_KEY {
+            Ok(self.color_scheme)
+        } else {
+            Err(fdo::Error::Failed(
+                "Requested setting not found".to_string(),
+            ))
+        }
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.portal.Settings")]
+impl FakeSettings {
+    fn read_one(&self, namespace: &str, key: &str) -> fdo::Result<Value<'static>> {
+        if !self.read_one {
+            return Err(fdo::Error::UnknownMethod("ReadOne".to_string()));
+        }
+        Ok(Value::from(self.lookup(namespace, key)?))
+    }
+
+    /// The deprecated call, which wraps the value in an extra variant
+    fn read(&self, namespace: &str, key: &str) -> fdo::Result<Value<'static>> {
+        let value = Value::from(self.lookup(namespace, key)?);
+        Ok(Value::Value(Box::new(value)))
+    }
+}
+
+fn start_portal(bus: &PrivateBus, color_scheme: u32, read_one: bool) -> Connection {
+    connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name(PORTAL_NAME)
+        .unwrap()
+        .serve_at(
+            PORTAL_PATH,
+            FakeSettings {
+                color_scheme,
+                read_one,
+            },
+        )
+        .unwrap()
+        .build()
+        .unwrap()
+}
+
+fn settings(conn: &Connection) -> Proxy<'static> {
+    Proxy::new(conn, PORTAL_NAME, PORTAL_PATH, SETTINGS_INTERFACE).unwrap()
+}
+
+fn setting_changed(portal: &Connection, namespace: &str, key: &str, value: Value) {
+    portal
+        .emit_signal(
+            None::<()>,
+            PORTAL_PATH,
+            SETTINGS_INTERFACE,
+            "SettingChanged",
+            &(namespace, key, value),
+        )
+        .unwrap();
+}
+
+#[test]
+fn color_scheme_is_read() {
+    let bus = private_bus!();
+    let _portal = start_portal(&bus, 1, true);
+    let client = bus.connect();
+    assert_eq!(
+        read_color_scheme(&settings(&client)).unwrap(),
+        ColorScheme::Dark
+    );
+}
+
+#[test]
+fn older_portals_are_read_with_read() {
+    let bus = private_bus!();
+    let _portal = start_portal(&bus, 2, false);
+    let client = bus.connect();
+    assert_eq!(
+        read_color_scheme(&settings(&client)).unwrap(),
+        ColorScheme::Light
+    );
+}
+
+#[test]
+fn missing_portal_is_an_error() {
+    let bus = private_bus!();
+    let client = bus.connect();
+    assert!(read_color_scheme(&settings(&client)).is_err());
+    assert!(watch(&client, &|_| {}).is_err());
+}
+
+#[test]
+fn changes_are_followed() {
+    let bus = private_bus!();
+    let portal = start_portal(&bus, 0, true);
+    let client = bus.connect();
+    let (sender, schemes) = mpsc::channel();
+    thread::spawn(move || watch(&client, &|scheme| sender.send(scheme).unwrap()));
+
+    let wait = Duration::from_secs(5);
+    assert_eq!(
+        schemes.recv_timeout(wait).unwrap(),
+        ColorScheme::NoPreference
+    );
+
+    // Other settings and malformed values are skipped
+    setting_changed(
+        &portal,
+        "org.gnome.desktop.interface",
+        COLOR_SCHEME_KEY,
+        Value::from(1u32),
+    );
+    setting_changed(
+        &portal,
+        APPEARANCE_NAMESPACE,
+        "accent-color",
+        Value::from(1u32),
+    );
+    setting_changed(
+        &portal,
+        APPEARANCE_NAMESPACE,
+        COLOR_SCHEME_KEY,
+        Value::from("dark"),
+    );
+    setting_changed(
+        &portal,
+        APPEARANCE_NAMESPACE,
+        COLOR_SCHEME_KEY,
+        Value::from(1u32),
+    );
+    assert_eq!(schemes.recv_timeout(wait).unwrap(), ColorScheme::Dark);
+
+    setting_changed(
+        &portal,
+        APPEARANCE_NAMESPACE,
+        COLOR_SCHEME_KEY,
+        Value::from(2u32),
+    );
+    assert_eq!(schemes.recv_timeout(wait).unwrap(), ColorScheme::Light);
+    assert!(schemes.recv_timeout(Duration::from_millis(200)).is_err());
+}
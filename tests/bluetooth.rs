@@ -0,0 +1,311 @@
+//! BlueZ client tests against a mock `org.bluez` object tree
+//!
+//! Each test starts a private `dbus-daemon` and is skipped when there is
+//! none installed. The mock serves an adapter and devices under an
+//! ObjectManager at `/`, as bluetoothd does.
+
+mod common;
+
+use common::{wait_for, PrivateBus};
+use hypr_notch::bluez::{Client, Device, BATTERY_INTERFACE, BLUEZ_NAME};
+use std::sync::{Arc, Mutex};
+use zbus::blocking::{connection, Connection};
+use zbus::fdo::ObjectManager;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{OwnedObjectPath, Value};
+
+const ADAPTER_PATH: &str = "/org/bluez/hci0";
+const HEADSET_PATH: &str = "/org/bluez/hci0/dev_00_11_22_33_44_55";
+const MOUSE_PATH: &str = "/org/bluez/hci0/dev_66_77_88_99_AA_BB";
+const STRANGER_PATH: &str = "/org/bluez/hci0/dev_CC_DD_EE_FF_00_11";
+
+/// Calls the mock devices received, in order
+type Calls = Arc<Mutex<Vec<String>>>;
+
+struct FakeAdapter;
+
+#[zbus::interface(name = "org.bluez.Adapter1")]
+impl FakeAdapter {
+    #[zbus(property)]
+    fn alias(&self) -> String {
+        "laptop".to_string()
+    }
+
+    #[zbus(property)]
+    fn powered(&self) -> bool {
+        true
+    }
+}
+
+struct FakeDevice {
+    address: String,
+    alias: String,
+    icon: String,
+    paired: bool,
+    connected: bool,
+    calls: Calls,
+}
+
+#[zbus::interface(name = "org.bluez.Device1")]
+impl FakeDevice {
+    async fn connect(
+        &mut self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("Connect {}", self.alias));
+        self.connected = true;
+        self.connected_changed(&emitter).await?;
+        Ok(())
+    }
+
+    async fn disconnect(
+        &mut self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("Disconnect {}", self.alias));
+        self.connected = false;
+        self.connected_changed(&emitter).await?;
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+
+    #[zbus(property)]
+    fn alias(&self) -> String {
+        self.alias.clone()
+    }
+
+    #[zbus(property)]
+    fn icon(&self) -> String {
+        self.icon.clone()
+    }
+
+    #[zbus(property)]
+    fn adapter(&self) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(ADAPTER_PATH).unwrap()
+    }
+
+    #[zbus(property)]
+    fn paired(&self) -> bool {
+        self.paired
+    }
+
+    #[zbus(property)]
+    fn connected(&self) -> bool {
+        self.connected
+    }
+}
+
+struct FakeBattery {
+    percentage: u8,
+}
+
+#[zbus::interface(name = "org.bluez.Battery1")]
+impl FakeBattery {
+    #[zbus(property)]
+    fn percentage(&self) -> u8 {
+        self.percentage
+    }
+
+    #[zbus(property)]
+    fn set_percentage(&mut self, percentage: u8) {
+        self.percentage = percentage;
+    }
+}
+
+fn device(address: &str, alias: &str, icon: &str, paired: bool, calls: &Calls) -> FakeDevice {
+    FakeDevice {
+        address: address.to_string(),
+        alias: alias.to_string(),
+        icon: icon.to_string(),
+        paired,
+        connected: false,
+        calls: calls.clone(),
+    }
+}
+
+/// Serve a mock bluetoothd: an adapter with a headset that reports its
+/// battery, a mouse and a device that was seen but never paired
+fn start_bluez(bus: &PrivateBus) -> (Connection, Calls) {
+    let calls = Calls::default();
+    let connection = connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name(BLUEZ_NAME)
+        .unwrap()
+        .serve_at(ADAPTER_PATH, FakeAdapter)
+        .unwrap()
+        .serve_at(
+            HEADSET_PATH,
+            device(
+                "00:11:22:33:44:55",
+                "Headphones",
+                "audio-headset",
+                true,
+                &calls,
+            ),
+        )
+        .unwrap()
+        .serve_at(HEADSET_PATH, FakeBattery { percentage: 80 })
+        .unwrap()
+        .serve_at(
+            MOUSE_PATH,
+            device("66:77:88:99:AA:BB", "Mouse", "input-mouse", true, &calls),
+        )
+        .unwrap()
+        .serve_at(
+            STRANGER_PATH,
+            device("CC:DD:EE:FF:00:11", "Speaker", "audio-card", false, &calls),
+        )
+        .unwrap()
+        .serve_at("/", ObjectManager)
+        .unwrap()
+        .build()
+        .unwrap();
+    (connection, calls)
+}
+
+fn find(client: &Client, path: &str) -> Option<Device> {
+    client
+        .devices()
+        .into_iter()
+        .find(|device| device.path == path)
+}
+
+#[test]
+fn devices_are_read_with_their_battery() {
+    let bus = private_bus!();
+    let (_bluez, _calls) = start_bluez(&bus);
+    let client = Client::start(bus.connect(), || {}).unwrap();
+
+    let adapters = client.adapters();
+    assert_eq!(adapters.len(), 1);
+    assert_eq!(adapters[0].path, ADAPTER_PATH);
+    assert_eq!(adapters[0].name, "laptop");
+    assert!(adapters[0].powered);
+
+    // Sorted by name, paired or not
+    let devices = client.devices();
+    let names: Vec<&str> = devices.iter().map(|device| device.name.as_str()).collect();
+    assert_eq!(names, ["Headphones", "Mouse", "Speaker"]);
+
+    let headset = &devices[0];
+    assert_eq!(headset.path, HEADSET_PATH);
+    assert_eq!(headset.adapter, ADAPTER_PATH);
+    assert_eq!(headset.address, "00:11:22:33:44:55");
+    assert!(headset.paired);
+    assert!(!headset.connected);
+    assert!(headset.is_audio());
+    assert_eq!(headset.battery, Some(80));
+
+    assert!(!devices[1].is_audio());
+    assert_eq!(devices[1].battery, None);
+    assert!(!devices[2].paired);
+}
+
+#[test]
+fn connecting_and_disconnecting_reach_the_device() {
+    let bus = private_bus!();
+    let (_bluez, calls) = start_bluez(&bus);
+    let client = Client::start(bus.connect(), || {}).unwrap();
+
+    let headset = find(&client, HEADSET_PATH).unwrap();
+    let generation = client.generation();
+    client.connect(&headset).unwrap();
+    assert!(wait_for(|| find(&client, HEADSET_PATH).unwrap().connected));
+    assert!(client.generation() > generation);
+
+    client.disconnect(&headset).unwrap();
+    assert!(wait_for(|| !find(&client, HEADSET_PATH).unwrap().connected));
+    assert_eq!(
+        *calls.lock().unwrap(),
+        ["Connect Headphones", "Disconnect Headphones"]
+    );
+
+    // The other devices are left alone
+    assert!(!find(&client, MOUSE_PATH).unwrap().connected);
+}
+
+#[test]
+fn added_and_removed_objects_are_followed() {
+    let bus = private_bus!();
+    let (bluez, calls) = start_bluez(&bus);
+    let changes = Arc::new(Mutex::new(0));
+    let counter = changes.clone();
+    let client = Client::start(bus.connect(), move || *counter.lock().unwrap() += 1).unwrap();
+    assert!(*changes.lock().unwrap() > 0);
+
+    // A newly paired device
+    let path = "/org/bluez/hci0/dev_12_34_56_78_9A_BC";
+    let keyboard = FakeDevice {
+        connected: true,
+        ..device(
+            "12:34:56:78:9A:BC",
+            "Keyboard",
+            "input-keyboard",
+            true,
+            &calls,
+        )
+    };
+    bluez.object_server().at(path, keyboard).unwrap();
+    assert!(wait_for(
+        || find(&client, path).is_some_and(|device| device.connected)
+    ));
+    assert_eq!(find(&client, path).unwrap().battery, None);
+
+    // Its battery shows up later and then drains
+    bluez
+        .object_server()
+        .at(path, FakeBattery { percentage: 40 })
+        .unwrap();
+    assert!(wait_for(|| find(&client, path).unwrap().battery == Some(40)));
+    bluez
+        .call_method(
+            Some(BLUEZ_NAME),
+            path,
+            Some("org.freedesktop.DBus.Properties"),
+            "Set",
+            &(BATTERY_INTERFACE, "Percentage", Value::from(35u8)),
+        )
+        .unwrap();
+    assert!(wait_for(|| find(&client, path).unwrap().battery == Some(35)));
+
+    // Losing the battery interface keeps the device
+    bluez
+        .object_server()
+        .remove::<FakeBattery, _>(path)
+        .unwrap();
+    assert!(wait_for(|| find(&client, path).unwrap().battery.is_none()));
+
+    bluez.object_server().remove::<FakeDevice, _>(path).unwrap();
+    assert!(wait_for(|| find(&client, path).is_none()));
+    assert_eq!(client.devices().len(), 3);
+}
+
+#[test]
+fn bluez_restarting_is_followed() {
+    let bus = private_bus!();
+    // Starting before bluetoothd is fine
+    let client = Client::start(bus.connect(), || {}).unwrap();
+    assert!(client.devices().is_empty());
+
+    let (bluez, _calls) = start_bluez(&bus);
+    assert!(wait_for(|| client.devices().len() == 3));
+
+    drop(bluez);
+    assert!(wait_for(
+        || client.devices().is_empty() && client.adapters().is_empty()
+    ));
+
+    let (_bluez, _calls) = start_bluez(&bus);
+    assert!(wait_for(|| client.devices().len() == 3));
+    assert_eq!(client.adapters().len(), 1);
+}
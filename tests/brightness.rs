@@ -0,0 +1,128 @@
+//! Brightness setting tests against a mock logind session
+//!
+//! Each test starts a private `dbus-daemon` and is skipped when there is
+//! none installed.
+
+mod common;
+
+use hypr_notch::modules::brightness::{
+    set_brightness, Backlight, LogindSession, LOGIND_NAME, LOGIND_SESSION_PATH,
+};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use zbus::blocking::connection;
+
+/// Calls the mock session received, in order
+type Calls = Arc<Mutex<Vec<String>>>;
+
+struct FakeSession {
+    calls: Calls,
+    /// Refuse every call, as logind does for a session that isn't active
+    refuse: bool,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Session")]
+impl FakeSession {
+    fn set_brightness(&self, subsystem: &str, name: &str, value: u32) -> zbus::fdo::Result<()> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("SetBrightness {} {} {}", subsystem, name, value));
+        if self.refuse {
+            return Err(zbus::fdo::Error::AccessDenied("not active".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Serve a mock logind with a session at the usual path
+fn start_logind(bus: &common::PrivateBus, refuse: bool) -> (zbus::blocking::Connection, Calls) {
+    let calls = Calls::default();
+    let connection = connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name(LOGIND_NAME)
+        .unwrap()
+        .serve_at(
+            LOGIND_SESSION_PATH,
+            FakeSession {
+                calls: calls.clone(),
+                refuse,
+            },
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+    (connection, calls)
+}
+
+/// A fake sysfs backlight at 10 of 100
+fn backlight(dir: &tempfile::TempDir) -> Backlight {
+    let path = dir.path().join("intel_backlight");
+    fs::create_dir(&path).unwrap();
+    fs::write(path.join("brightness"), "10").unwrap();
+    Backlight {
+        name: "intel_backlight".to_string(),
+        path,
+        brightness: 10,
+        max_brightness: 100,
+    }
+}
+
+fn sysfs_value(backlight: &Backlight) -> String {
+    fs::read_to_string(backlight.path.join("brightness")).unwrap()
+}
+
+#[test]
+fn brightness_is_set_through_logind() {
+    let bus = private_bus!();
+    let (_logind, calls) = start_logind(&bus, false);
+    let dir = tempfile::tempdir().unwrap();
+    let backlight = backlight(&dir);
+    let session = LogindSession {
+        connection: bus.connect(),
+        path: LOGIND_SESSION_PATH.to_string(),
+    };
+
+    set_brightness(Some(&session), &backlight, 42).unwrap();
+    assert_eq!(
+        *calls.lock().unwrap(),
+        ["SetBrightness backlight intel_backlight 42"]
+    );
+    // logind writes sysfs itself
+    assert_eq!(sysfs_value(&backlight), "10");
+}
+
+#[test]
+fn sysfs_is_written_when_logind_refuses() {
+    let bus = private_bus!();
+    let (_logind, calls) = start_logind(&bus, true);
+    let dir = tempfile::tempdir().unwrap();
+    let backlight = backlight(&dir);
+    let session = LogindSession {
+        connection: bus.connect(),
+        path: LOGIND_SESSION_PATH.to_string(),
+    };
+
+    set_brightness(Some(&session), &backlight, 42).unwrap();
+    assert_eq!(calls.lock().unwrap().len(), 1);
+    assert_eq!(sysfs_value(&backlight), "42");
+
+    // A session logind doesn't have
+    let session = LogindSession {
+        path: "/org/freedesktop/login1/session/missing".to_string(),
+        ..session
+    };
+    set_brightness(Some(&session), &backlight, 43).unwrap();
+    assert_eq!(sysfs_value(&backlight), "43");
+}
+
+#[test]
+fn sysfs_is_written_without_a_bus() {
+    let dir = tempfile::tempdir().unwrap();
+    let backlight = backlight(&dir);
+    set_brightness(None, &backlight, 7).unwrap();
+    assert_eq!(sysfs_value(&backlight), "7");
+
+    fs::remove_dir_all(&backlight.path).unwrap();
+    assert!(set_brightness(None, &backlight, 8).is_err());
+}
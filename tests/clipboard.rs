@@ -0,0 +1,361 @@
+//! Clipboard tests against a fake compositor
+//!
+//! The compositor's end of the Wayland connection is played by hand,
+//! speaking just enough of the wire protocol to announce a seat and
+//! `zwlr_data_control_manager_v1`, offer selections and ask for the one
+//! hypr-notch serves.
+
+use hypr_notch::clipboard::{spawn_on, Command, Entry, History, Options, TEXT_MIME_TYPE};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use wayland_client::Connection;
+
+/// The display is always object 1
+const DISPLAY: u32 = 1;
+/// Objects the compositor creates get ids from here up
+const FIRST_SERVER_ID: u32 = 0xff00_0000;
+
+/// An argument of an event
+enum Arg<'a> {
+    Uint(u32),
+    Str(&'a str),
+}
+
+/// The arguments of a request, read in order
+struct Args {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl Args {
+    fn uint(&mut self) -> u32 {
+        let bytes = self.data[self.pos..self.pos + 4].try_into().unwrap();
+        self.pos += 4;
+        u32::from_ne_bytes(bytes)
+    }
+
+    fn string(&mut self) -> String {
+        let len = self.uint() as usize;
+        let text = &self.data[self.pos..self.pos + len - 1];
+        self.pos += len.next_multiple_of(4);
+        String::from_utf8(text.to_vec()).unwrap()
+    }
+}
+
+/// The compositor's end of the connection
+struct Compositor {
+    socket: UnixStream,
+    buffer: Vec<u8>,
+    fds: VecDeque<OwnedFd>,
+}
+
+impl Compositor {
+    fn new(socket: UnixStream) -> Self {
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        Self {
+            socket,
+            buffer: Vec::new(),
+            fds: VecDeque::new(),
+        }
+    }
+
+    /// Read more of the stream, keeping the file descriptors sent with it
+    fn fill(&mut self) {
+        let mut data = [0u8; 4096];
+        let mut control = [0u64; 32];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+        // SAFETY: msghdr is plain data, and every pointer set in it outlives
+        // the call
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+        let read =
+            unsafe { libc::recvmsg(self.socket.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) };
+        assert!(read > 0, "no request: {}", io::Error::last_os_error());
+        self.buffer.extend_from_slice(&data[..read as usize]);
+
+        // SAFETY: the control messages are the ones the kernel just wrote
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                    let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / 4;
+                    let fds = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                    for i in 0..count {
+                        let fd = fds.add(i).read_unaligned();
+                        self.fds.push_back(OwnedFd::from_raw_fd(fd));
+                    }
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+    }
+
+    /// The next request as its object, opcode and arguments
+    fn request(&mut self) -> (u32, u16, Args) {
+        loop {
+            if self.buffer.len() >= 8 {
+                let word =
+                    |at: usize| u32::from_ne_bytes(self.buffer[at..at + 4].try_into().unwrap());
+                let (object, size_opcode) = (word(0), word(4));
+                let size = (size_opcode >> 16) as usize;
+                if self.buffer.len() >= size {
+                    let message: Vec<u8> = self.buffer.drain(..size).collect();
+                    let args = Args {
+                        data: message[8..].to_vec(),
+                        pos: 0,
+                    };
+                    return (object, size_opcode as u16, args);
+                }
+            }
+            self.fill();
+        }
+    }
+
+    /// The next request, which must be `opcode` on `object`
+    fn expect(&mut self, object: u32, opcode: u16) -> Args {
+        let (got_object, got_opcode, args) = self.request();
+        assert_eq!((got_object, got_opcode), (object, opcode));
+        args
+    }
+
+    /// The file descriptor passed with the last request that had one
+    fn take_fd(&mut self) -> File {
+        File::from(self.fds.pop_front().expect("no file descriptor was sent"))
+    }
+
+    fn encode(object: u32, opcode: u16, args: &[Arg]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for arg in args {
+            match arg {
+                Arg::Uint(value) => body.extend(value.to_ne_bytes()),
+                Arg::Str(text) => {
+                    body.extend((text.len() as u32 + 1).to_ne_bytes());
+                    body.extend(text.as_bytes());
+                    body.push(0);
+                    body.resize(body.len().next_multiple_of(4), 0);
+                }
+            }
+        }
+        let mut message = Vec::new();
+        message.extend(object.to_ne_bytes());
+        message.extend(((body.len() as u32 + 8) << 16 | opcode as u32).to_ne_bytes());
+        message.extend(body);
+        message
+    }
+
+    fn event(&mut self, object: u32, opcode: u16, args: &[Arg]) {
+        self.socket
+            .write_all(&Self::encode(object, opcode, args))
+            .unwrap();
+    }
+
+    /// Send an event whose last argument is `fd`
+    fn event_with_fd(&mut self, object: u32, opcode: u16, args: &[Arg], fd: RawFd) {
+        let message = Self::encode(object, opcode, args);
+        let mut control = [0u64; 4];
+        let mut iov = libc::iovec {
+            iov_base: message.as_ptr() as *mut _,
+            iov_len: message.len(),
+        };
+        // SAFETY: as in `fill`, with room for one descriptor in `control`
+        unsafe {
+            let mut msg: libc::msghdr = mem::zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr().cast();
+            msg.msg_controllen = libc::CMSG_SPACE(4) as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(4) as _;
+            libc::CMSG_DATA(cmsg).cast::<RawFd>().write_unaligned(fd);
+            let sent = libc::sendmsg(self.socket.as_raw_fd(), &msg, 0);
+            assert_eq!(sent, message.len() as isize);
+        }
+    }
+
+    /// Answer the registry round trip with a seat and a data control
+    /// manager, then return the ids the client gave the manager and its
+    /// data device
+    fn handshake(&mut self) -> (u32, u32) {
+        let registry = self.expect(DISPLAY, 1).uint();
+        let callback = self.expect(DISPLAY, 0).uint();
+        let globals = [(1, "wl_seat"), (2, "zwlr_data_control_manager_v1")];
+        for (name, interface) in globals {
+            self.event(
+                registry,
+                0,
+                &[Arg::Uint(name), Arg::Str(interface), Arg::Uint(1)],
+            );
+        }
+        self.event(callback, 0, &[Arg::Uint(1)]);
+        self.event(DISPLAY, 1, &[Arg::Uint(callback)]);
+
+        let mut bound = HashMap::new();
+        for _ in globals {
+            let mut args = self.expect(registry, 0);
+            let (_name, interface, _version, id) =
+                (args.uint(), args.string(), args.uint(), args.uint());
+            bound.insert(interface, id);
+        }
+        let manager = bound["zwlr_data_control_manager_v1"];
+        let mut args = self.expect(manager, 1);
+        let device = args.uint();
+        assert_eq!(args.uint(), bound["wl_seat"]);
+        (manager, device)
+    }
+
+    /// Announce a new selection offering `mime_types`
+    fn select(&mut self, device: u32, offer: u32, mime_types: &[&str]) {
+        self.event(device, 0, &[Arg::Uint(offer)]);
+        for mime_type in mime_types {
+            self.event(offer, 0, &[Arg::Str(mime_type)]);
+        }
+        self.event(device, 1, &[Arg::Uint(offer)]);
+    }
+
+    /// Send `data` to the client as the contents of `offer`, which it must
+    /// ask for as `mime_type`
+    fn serve(&mut self, offer: u32, mime_type: &str, data: &str) {
+        assert_eq!(self.expect(offer, 0).string(), mime_type);
+        self.take_fd().write_all(data.as_bytes()).unwrap();
+        self.expect(offer, 1);
+    }
+}
+
+fn text(data: &str) -> Entry {
+    Entry::new(TEXT_MIME_TYPE.to_string(), data.as_bytes().to_vec(), 1)
+}
+
+fn previews(history: &Mutex<History>) -> Vec<String> {
+    history
+        .lock()
+        .unwrap()
+        .entries()
+        .filter_map(Entry::preview)
+        .collect()
+}
+
+fn changed(changes: &Receiver<()>) {
+    changes
+        .recv_timeout(Duration::from_secs(5))
+        .expect("the history didn't change");
+}
+
+#[test]
+fn selections_are_recorded_and_restored() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut saved = History::new(10);
+    saved.push(text("saved earlier"));
+    saved.save(dir.path()).unwrap();
+
+    let (client, server) = UnixStream::pair().unwrap();
+    let mut compositor = Compositor::new(server);
+    let history = Arc::new(Mutex::new(History::new(10)));
+    let (changes, change_events) = mpsc::channel();
+    let options = Options {
+        max_entry_size: 64,
+        ignore_mime_types: vec!["application/x-secret".to_string()],
+        history_dir: Some(dir.path().to_path_buf()),
+    };
+    let spawning = {
+        let history = history.clone();
+        std::thread::spawn(move || {
+            spawn_on(
+                Connection::from_socket(client).unwrap(),
+                history,
+                options,
+                move || {
+                    let _ = changes.send(());
+                },
+            )
+            .unwrap()
+        })
+    };
+    let (manager, device) = compositor.handshake();
+    let commands = spawning.join().unwrap();
+
+    // The saved history is loaded on the clipboard thread
+    changed(&change_events);
+    assert_eq!(previews(&history), ["saved earlier"]);
+
+    // Text is asked for in the preferred type
+    let offer = FIRST_SERVER_ID;
+    compositor.select(device, offer, &["STRING", TEXT_MIME_TYPE]);
+    compositor.serve(offer, TEXT_MIME_TYPE, "first\nsecond line");
+    changed(&change_events);
+    assert_eq!(previews(&history), ["first", "saved earlier"]);
+
+    // Password managers' selections and ignored types aren't even read
+    for (offer, hint) in [
+        (offer + 1, "x-kde-passwordManagerHint"),
+        (offer + 2, "application/x-secret"),
+    ] {
+        compositor.select(device, offer, &[TEXT_MIME_TYPE, hint]);
+        compositor.expect(offer, 1);
+    }
+    // Nor are blank or oversized ones recorded
+    compositor.select(device, offer + 3, &["text/plain"]);
+    compositor.serve(offer + 3, "text/plain", " \n\t");
+    compositor.select(device, offer + 4, &[TEXT_MIME_TYPE]);
+    compositor.serve(offer + 4, TEXT_MIME_TYPE, &"x".repeat(65));
+
+    compositor.select(device, offer + 5, &["image/png", "UTF8_STRING"]);
+    compositor.serve(offer + 5, "UTF8_STRING", "latest");
+    changed(&change_events);
+    assert_eq!(previews(&history), ["latest", "first", "saved earlier"]);
+
+    // Restoring an entry serves it as every text type
+    let first = text("first\nsecond line").id;
+    commands.send(Command::Restore(first)).unwrap();
+    let source = compositor.expect(manager, 0).uint();
+    let mut offered = Vec::new();
+    for _ in 0..5 {
+        offered.push(compositor.expect(source, 0).string());
+    }
+    assert_eq!(
+        offered,
+        [
+            TEXT_MIME_TYPE,
+            "text/plain",
+            "UTF8_STRING",
+            "STRING",
+            "TEXT"
+        ]
+    );
+    assert_eq!(compositor.expect(device, 0).uint(), source);
+    changed(&change_events);
+    assert_eq!(previews(&history), ["first", "latest", "saved earlier"]);
+
+    let (mut reader, writer) = io::pipe().unwrap();
+    compositor.event_with_fd(source, 0, &[Arg::Str("text/plain")], writer.as_raw_fd());
+    drop(writer);
+    let mut served = String::new();
+    reader.read_to_string(&mut served).unwrap();
+    assert_eq!(served, "first\nsecond line");
+
+    // Someone else copied something
+    compositor.event(source, 1, &[]);
+    compositor.expect(source, 1);
+
+    // Every change was saved
+    assert_eq!(
+        previews(&Mutex::new(History::load(dir.path(), 10))),
+        ["first", "latest", "saved earlier"]
+    );
+}
@@ -0,0 +1,79 @@
+//! Helpers shared by the integration tests
+//!
+//! D-Bus tests run against a private `dbus-daemon` and are skipped when
+//! there is none installed.
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use zbus::blocking::{connection, Connection};
+
+/// A bus of our own, standing in for the session or system bus, stopped
+/// when dropped
+pub struct PrivateBus {
+    daemon: Child,
+    pub address: String,
+}
+
+impl PrivateBus {
+    pub fn start() -> Option<Self> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        let stdout = daemon.stdout.take()?;
+        BufReader::new(stdout).read_line(&mut address).ok()?;
+        Some(Self {
+            daemon,
+            address: address.trim().to_string(),
+        })
+    }
+
+    pub fn connect(&self) -> Connection {
+        connection::Builder::address(self.address.as_str())
+            .unwrap()
+            .method_timeout(Duration::from_secs(2))
+            .build()
+            .unwrap()
+    }
+}
+
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+/// Start a [`PrivateBus`], or return from the test when there is no
+/// `dbus-daemon`
+#[macro_export]
+macro_rules! private_bus {
+    () => {
+        match $crate::common::PrivateBus::start() {
+            Some(bus) => bus,
+            None => {
+                eprintln!("dbus-daemon is not available, skipping");
+                return;
+            }
+        }
+    };
+}
+
+/// Wait up to a few seconds for `condition` to hold
+// Each test binary compiles this module, and not all of them wait
+#[allow(dead_code)]
+pub fn wait_for(mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
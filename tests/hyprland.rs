@@ -0,0 +1,120 @@
+//! Hyprland IPC tests against fake compositor sockets
+//!
+//! Each test binds `.socket.sock` or `.socket2.sock` in a directory of its
+//! own and plays the compositor's side of the conversation.
+
+use hypr_notch::hyprland::{read_events_at, request_at, HyprEvent};
+use std::io::{Read, Write};
+use std::os::unix::net::UnixListener;
+use std::thread;
+
+/// What Hyprland sends on `.socket2.sock` when switching to workspace 2,
+/// focusing a window and making it fullscreen
+const EVENTS: &str = "\
+workspace>>2
+workspacev2>>2,2
+focusedmon>>DP-1,2
+activewindow>>kitty,~/src: vim, main.rs
+activewindowv2>>55d4b7a0c6e0
+fullscreen>>1
+activewindow>>,
+fullscreen>>0
+";
+
+#[test]
+fn events_are_replayed_in_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let listener = UnixListener::bind(dir.path().join(".socket2.sock")).unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        // Split across writes, as a real stream may be
+        let (first, second) = EVENTS.split_at(EVENTS.find("vim").unwrap());
+        stream.write_all(first.as_bytes()).unwrap();
+        stream.flush().unwrap();
+        stream.write_all(second.as_bytes()).unwrap();
+    });
+
+    let mut events = Vec::new();
+    read_events_at(dir.path(), |event| {
+        events.push(event);
+        true
+    })
+    .unwrap();
+    server.join().unwrap();
+
+    let other = |name: &str, data: &str| HyprEvent::Other {
+        name: name.to_string(),
+        data: data.to_string(),
+    };
+    assert_eq!(
+        events,
+        [
+            // The v1 workspace event is passed on untouched, only v2 is used
+            other("workspace", "2"),
+            HyprEvent::Workspace {
+                id: 2,
+                name: "2".to_string()
+            },
+            HyprEvent::FocusedMonitor("DP-1".to_string()),
+            HyprEvent::ActiveWindow {
+                class: "kitty".to_string(),
+                title: "~/src: vim, main.rs".to_string()
+            },
+            other("activewindowv2", "55d4b7a0c6e0"),
+            HyprEvent::Fullscreen(true),
+            HyprEvent::ActiveWindow {
+                class: String::new(),
+                title: String::new()
+            },
+            HyprEvent::Fullscreen(false),
+        ]
+    );
+}
+
+#[test]
+fn reading_stops_when_asked() {
+    let dir = tempfile::tempdir().unwrap();
+    let listener = UnixListener::bind(dir.path().join(".socket2.sock")).unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(EVENTS.as_bytes()).unwrap();
+        // Keep the connection open; the reader has to stop by itself
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+    });
+
+    let mut events = Vec::new();
+    read_events_at(dir.path(), |event| {
+        let fullscreen = event == HyprEvent::Fullscreen(true);
+        events.push(event);
+        !fullscreen
+    })
+    .unwrap();
+    assert_eq!(events.len(), 6);
+    // Returning dropped the connection, which lets the server finish
+    server.join().unwrap();
+}
+
+#[test]
+fn requests_get_the_reply() {
+    let dir = tempfile::tempdir().unwrap();
+    let listener = UnixListener::bind(dir.path().join(".socket.sock")).unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut command = [0; 64];
+        let len = stream.read(&mut command).unwrap();
+        stream.write_all(b"ok").unwrap();
+        String::from_utf8(command[..len].to_vec()).unwrap()
+    });
+
+    let reply = request_at(dir.path(), "dispatch workspace 3").unwrap();
+    assert_eq!(reply, "ok");
+    assert_eq!(server.join().unwrap(), "dispatch workspace 3");
+}
+
+#[test]
+fn missing_sockets_are_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(request_at(dir.path(), "j/workspaces").is_err());
+    assert!(read_events_at(dir.path(), |_| true).is_err());
+}
@@ -0,0 +1,223 @@
+//! MPRIS client tests against mock players
+//!
+//! Each test starts a private `dbus-daemon` and is skipped when there is
+//! none installed.
+
+mod common;
+
+use common::{wait_for, PrivateBus};
+use hypr_notch::modules::mpris::{
+    poll_loop, PlaybackStatus, PlayerCommand, PlayerState, MPRIS_PATH, MPRIS_PREFIX,
+};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use zbus::blocking::{connection, Connection};
+use zbus::zvariant::Value;
+
+/// What a mock player is playing, and the methods it was called with
+#[derive(Debug, Default)]
+struct Track {
+    status: String,
+    title: String,
+    artists: Vec<String>,
+    length_us: i64,
+    calls: Vec<String>,
+}
+
+type SharedTrack = Arc<Mutex<Track>>;
+
+struct FakePlayer(SharedTrack);
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+impl FakePlayer {
+    fn previous(&self) {
+        self.0.lock().unwrap().calls.push("Previous".to_string());
+    }
+
+    fn play_pause(&self) {
+        let mut track = self.0.lock().unwrap();
+        track.calls.push("PlayPause".to_string());
+        track.status = match track.status.as_str() {
+            "Playing" => "Paused",
+            _ => "Playing",
+        }
+        .to_string();
+    }
+
+    fn next(&self) {
+        self.0.lock().unwrap().calls.push("Next".to_string());
+    }
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        self.0.lock().unwrap().status.clone()
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, Value<'static>> {
+        let track = self.0.lock().unwrap();
+        HashMap::from([
+            ("xesam:title".to_string(), Value::from(track.title.clone())),
+            (
+                "xesam:artist".to_string(),
+                Value::from(track.artists.clone()),
+            ),
+            ("xesam:album".to_string(), Value::from("Album")),
+            ("mpris:length".to_string(), Value::from(track.length_us)),
+        ])
+    }
+
+    #[zbus(property)]
+    fn position(&self) -> i64 {
+        30_000_000
+    }
+}
+
+/// Serve a mock player as `org.mpris.MediaPlayer2.<name>`
+fn start_player(
+    bus: &PrivateBus,
+    name: &str,
+    status: &str,
+    title: &str,
+) -> (Connection, SharedTrack) {
+    let track = SharedTrack::new(Mutex::new(Track {
+        status: status.to_string(),
+        title: title.to_string(),
+        artists: vec!["Artist".to_string()],
+        length_us: 180_000_000,
+        calls: Vec::new(),
+    }));
+    let connection = connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name(format!("{}{}", MPRIS_PREFIX, name))
+        .unwrap()
+        .serve_at(MPRIS_PATH, FakePlayer(track.clone()))
+        .unwrap()
+        .build()
+        .unwrap();
+    (connection, track)
+}
+
+/// The module's side: the polling loop on a thread of its own
+struct Client {
+    state: Arc<Mutex<Option<PlayerState>>>,
+    commands: Sender<PlayerCommand>,
+    thread: JoinHandle<zbus::Result<()>>,
+}
+
+impl Client {
+    fn start(bus: &PrivateBus, preferred: Option<&str>) -> Self {
+        let connection = bus.connect();
+        let state = Arc::new(Mutex::new(None));
+        let (commands, receiver) = mpsc::channel();
+        let preferred = preferred.map(|name| name.to_string());
+        let thread = thread::spawn({
+            let state = state.clone();
+            move || {
+                poll_loop(
+                    &connection,
+                    &state,
+                    &receiver,
+                    preferred.as_deref(),
+                    Duration::from_millis(50),
+                )
+            }
+        });
+        Self {
+            state,
+            commands,
+            thread,
+        }
+    }
+
+    fn player(&self) -> Option<PlayerState> {
+        self.state.lock().unwrap().clone()
+    }
+
+    fn showing(&self, title: &str) -> bool {
+        self.player().is_some_and(|player| player.title == title)
+    }
+
+    /// Drop the command sender, which ends the loop
+    fn stop(self) {
+        drop(self.commands);
+        self.thread.join().unwrap().unwrap();
+    }
+}
+
+#[test]
+fn playback_and_metadata_changes_are_seen() {
+    let bus = private_bus!();
+    let (player, track) = start_player(&bus, "fake", "Paused", "First");
+    let client = Client::start(&bus, None);
+
+    assert!(wait_for(|| client.showing("First")));
+    let state = client.player().unwrap();
+    assert_eq!(state.bus_name, "org.mpris.MediaPlayer2.fake");
+    assert_eq!(state.status, PlaybackStatus::Paused);
+    assert_eq!(state.artist, "Artist");
+    assert_eq!(state.album, "Album");
+    assert_eq!(state.length, Some(Duration::from_secs(180)));
+    assert_eq!(state.position, Duration::from_secs(30));
+    assert!(state.art.is_none());
+
+    // Next track, with two artists and no known length
+    {
+        let mut track = track.lock().unwrap();
+        track.status = "Playing".to_string();
+        track.title = "Second".to_string();
+        track.artists = vec!["A".to_string(), "B".to_string()];
+        track.length_us = 0;
+    }
+    assert!(wait_for(|| client.showing("Second")));
+    let state = client.player().unwrap();
+    assert_eq!(state.status, PlaybackStatus::Playing);
+    assert_eq!(state.artist, "A, B");
+    assert_eq!(state.length, None);
+
+    // The player quits
+    drop(player);
+    assert!(wait_for(|| client.player().is_none()));
+    client.stop();
+}
+
+#[test]
+fn transport_buttons_call_the_player() {
+    let bus = private_bus!();
+    let (_player, track) = start_player(&bus, "fake", "Paused", "Song");
+    let client = Client::start(&bus, None);
+    assert!(wait_for(|| client.showing("Song")));
+
+    for command in [
+        PlayerCommand::Previous,
+        PlayerCommand::PlayPause,
+        PlayerCommand::Next,
+    ] {
+        client.commands.send(command).unwrap();
+    }
+    assert!(wait_for(|| track.lock().unwrap().calls.len() == 3));
+    assert_eq!(
+        track.lock().unwrap().calls,
+        ["Previous", "PlayPause", "Next"]
+    );
+    assert!(wait_for(|| client
+        .player()
+        .is_some_and(|player| player.status == PlaybackStatus::Playing)));
+    client.stop();
+}
+
+#[test]
+fn playing_players_win_over_the_preferred_one() {
+    let bus = private_bus!();
+    let (_vlc, _) = start_player(&bus, "vlc", "Paused", "Film");
+    let (_spotify, spotify) = start_player(&bus, "spotify", "Paused", "Song");
+    let client = Client::start(&bus, Some("vlc"));
+    assert!(wait_for(|| client.showing("Film")));
+
+    spotify.lock().unwrap().status = "Playing".to_string();
+    assert!(wait_for(|| client.showing("Song")));
+    client.stop();
+}
@@ -0,0 +1,176 @@
+//! Notification server tests on a private session bus
+//!
+//! Each test starts a private `dbus-daemon` and is skipped when there is
+//! none installed.
+
+mod common;
+
+use common::PrivateBus;
+use hypr_notch::module::{Module, ModuleEvent, Rect};
+use hypr_notch::modules::notifications::{NotificationsModule, BUS_NAME, OBJECT_PATH};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::Value;
+
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+
+const AREA: Rect = Rect {
+    x: 0,
+    y: 0,
+    width: 600,
+    height: 100,
+};
+
+/// A module serving notifications on the bus, and a client connection
+fn start(bus: &PrivateBus) -> (NotificationsModule, Connection) {
+    let mut module = NotificationsModule::new();
+    module.serve(bus.connect()).unwrap();
+    (module, bus.connect())
+}
+
+fn proxy(client: &Connection) -> Proxy<'static> {
+    Proxy::new(client, BUS_NAME, OBJECT_PATH, BUS_NAME).unwrap()
+}
+
+/// Arguments of the signals named `name`, as they arrive
+fn signals<T>(client: &Connection, name: &'static str) -> Receiver<T>
+where
+    T: for<'d> zbus::zvariant::DynamicDeserialize<'d> + Send + 'static,
+{
+    let proxy = proxy(client);
+    let (sender, receiver) = mpsc::channel();
+    let signals = proxy.receive_signal(name).unwrap();
+    thread::spawn(move || {
+        for message in signals {
+            if sender.send(message.body().deserialize().unwrap()).is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+fn notify(client: &Connection, summary: &str, actions: &[&str], timeout: i32) -> u32 {
+    proxy(client)
+        .call(
+            "Notify",
+            &(
+                "test",
+                0u32,
+                "",
+                summary,
+                "a < b",
+                actions,
+                HashMap::<&str, Value>::new(),
+                timeout,
+            ),
+        )
+        .unwrap()
+}
+
+const WAIT: Duration = Duration::from_secs(5);
+
+#[test]
+fn notify_queues_and_expands() {
+    let bus = private_bus!();
+    let (mut module, client) = start(&bus);
+    assert!(!module.wants_expand());
+
+    let capabilities: Vec<String> = proxy(&client).call("GetCapabilities", &()).unwrap();
+    assert!(capabilities.contains(&"actions".to_string()));
+    let (name, ..): (String, String, String, String) =
+        proxy(&client).call("GetServerInformation", &()).unwrap();
+    assert_eq!(name, "hypr-notch");
+
+    let first = notify(&client, "first", &[], -1);
+    let second = notify(&client, "second", &[], -1);
+    assert_eq!((first, second), (1, 2));
+    assert!(module.wants_expand());
+
+    // Replacing keeps the id
+    let replaced: u32 = proxy(&client)
+        .call(
+            "Notify",
+            &(
+                "test",
+                first,
+                "",
+                "first again",
+                "",
+                Vec::<&str>::new(),
+                HashMap::<&str, Value>::new(),
+                -1,
+            ),
+        )
+        .unwrap();
+    assert_eq!(replaced, first);
+
+    // Right-clicking dismisses the current notification, then the next
+    let closed = signals::<(u32, u32)>(&client, "NotificationClosed");
+    for id in [first, second] {
+        let press = ModuleEvent::Press {
+            button: BTN_RIGHT,
+            x: 10.0,
+            y: 10.0,
+        };
+        assert!(module.handle_event(&press, AREA));
+        assert_eq!(closed.recv_timeout(WAIT).unwrap(), (id, 2));
+    }
+    assert!(!module.wants_expand());
+}
+
+#[test]
+fn close_notification_signals_closed() {
+    let bus = private_bus!();
+    let (module, client) = start(&bus);
+    let closed = signals::<(u32, u32)>(&client, "NotificationClosed");
+
+    let id = notify(&client, "closing", &[], 0);
+    let () = proxy(&client).call("CloseNotification", &(id,)).unwrap();
+    assert_eq!(closed.recv_timeout(WAIT).unwrap(), (id, 3));
+    assert!(!module.wants_expand());
+
+    // Closing it again is allowed but says nothing
+    let () = proxy(&client).call("CloseNotification", &(id,)).unwrap();
+    assert!(closed.recv_timeout(Duration::from_millis(200)).is_err());
+}
+
+#[test]
+fn clicking_invokes_the_default_action() {
+    let bus = private_bus!();
+    let (mut module, client) = start(&bus);
+    let invoked = signals::<(u32, String)>(&client, "ActionInvoked");
+    let closed = signals::<(u32, u32)>(&client, "NotificationClosed");
+
+    let id = notify(&client, "with action", &["default", "Open"], 0);
+    let press = ModuleEvent::Press {
+        button: BTN_LEFT,
+        x: 300.0,
+        y: 10.0,
+    };
+    assert!(module.handle_event(&press, AREA));
+    assert_eq!(
+        invoked.recv_timeout(WAIT).unwrap(),
+        (id, "default".to_string())
+    );
+    assert_eq!(closed.recv_timeout(WAIT).unwrap(), (id, 2));
+}
+
+#[test]
+fn timed_out_notifications_expire() {
+    let bus = private_bus!();
+    let (mut module, client) = start(&bus);
+    let closed = signals::<(u32, u32)>(&client, "NotificationClosed");
+
+    let id = notify(&client, "short", &[], 50);
+    // The first update shows it and starts the timeout
+    assert!(!module.handle_event(&ModuleEvent::Update, AREA));
+    thread::sleep(Duration::from_millis(60));
+    assert!(module.handle_event(&ModuleEvent::Update, AREA));
+    assert_eq!(closed.recv_timeout(WAIT).unwrap(), (id, 1));
+    assert!(!module.wants_expand());
+}
@@ -0,0 +1,141 @@
+//! PulseAudio client tests against a real server
+//!
+//! Each test starts `pulseaudio` with a null sink and nothing else, in a
+//! directory of its own, and is skipped when PulseAudio isn't installed
+//! or won't start.
+
+use hypr_notch::pulse::{Connection, Device, EventKind, Facility, SUBSCRIBE_SINK};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// A PulseAudio server of our own, stopped when dropped
+struct Server {
+    daemon: Child,
+    socket: PathBuf,
+    /// Runtime, state and config directory of the server
+    _dir: TempDir,
+}
+
+impl Server {
+    fn start() -> Option<Self> {
+        let dir = tempfile::tempdir().ok()?;
+        let socket = dir.path().join("native");
+        let daemon = Command::new("pulseaudio")
+            .args([
+                "--daemonize=no",
+                "-n",
+                "--exit-idle-time=-1",
+                "--use-pid-file=no",
+                "--disable-shm=yes",
+                "--load=module-null-sink",
+            ])
+            .arg(format!(
+                "--load=module-native-protocol-unix auth-anonymous=1 socket={}",
+                socket.display()
+            ))
+            .env("HOME", dir.path())
+            .env("XDG_RUNTIME_DIR", dir.path())
+            .env("XDG_CONFIG_HOME", dir.path())
+            .env("PULSE_RUNTIME_PATH", dir.path())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut server = Self {
+            daemon,
+            socket,
+            _dir: dir,
+        };
+
+        // Wait for it to take connections, unless it gives up
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if server.daemon.try_wait().ok()?.is_some() {
+                return None;
+            }
+            if server.try_connect().is_some() {
+                return Some(server);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        None
+    }
+
+    fn try_connect(&self) -> Option<Connection> {
+        Connection::connect_to(&self.socket, &[0; 256], "hypr-notch-test").ok()
+    }
+
+    fn connect(&self) -> Connection {
+        self.try_connect()
+            .expect("pulseaudio stopped taking connections")
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+macro_rules! server {
+    () => {
+        match Server::start() {
+            Some(server) => server,
+            None => {
+                eprintln!("pulseaudio is not available, skipping");
+                return;
+            }
+        }
+    };
+}
+
+#[test]
+fn default_devices_are_read() {
+    let server = server!();
+    let mut conn = server.connect();
+
+    let sink = conn.device_info(Device::Sink).unwrap();
+    assert_eq!(sink.name, "null");
+    assert!(!sink.volume.is_empty());
+
+    // The null sink's monitor is the only source
+    let source = conn.device_info(Device::Source).unwrap();
+    assert_eq!(source.name, "null.monitor");
+    assert!(conn.is_monitor(source.index).unwrap());
+    assert!(conn.source_outputs().unwrap().is_empty());
+}
+
+#[test]
+fn volume_and_mute_changes_are_reported() {
+    let server = server!();
+    let mut conn = server.connect();
+    let mut events = server.connect();
+    events.subscribe(SUBSCRIBE_SINK).unwrap();
+
+    let sink = conn.device_info(Device::Sink).unwrap();
+    conn.set_volume(Device::Sink, sink.index, &sink.volume_at(40.0))
+        .unwrap();
+    let event = events.next_event(Duration::from_secs(5)).unwrap().unwrap();
+    assert_eq!(event.facility, Facility::Sink);
+    assert_eq!(event.kind, EventKind::Change);
+    assert_eq!(event.index, sink.index);
+
+    let sink = conn.device_info(Device::Sink).unwrap();
+    assert_eq!(sink.percent().round(), 40.0);
+    assert!(!sink.muted);
+
+    conn.set_mute(Device::Sink, sink.index, true).unwrap();
+    assert!(conn.device_info(Device::Sink).unwrap().muted);
+}
+
+#[test]
+fn errors_are_reported() {
+    let server = server!();
+    let mut conn = server.connect();
+    let error = conn.set_mute(Device::Sink, 12345, true).unwrap_err();
+    assert!(error.to_string().contains("no such entity"), "{}", error);
+}
@@ -0,0 +1,369 @@
+//! Tray host tests against a fake StatusNotifierItem
+//!
+//! Each test starts a private `dbus-daemon` and is skipped when there is
+//! none installed.
+
+mod common;
+
+use common::{wait_for, PrivateBus};
+use hypr_notch::sni::{
+    Host, Status, Toggle, ITEM_INTERFACE, WATCHER_INTERFACE, WATCHER_NAME, WATCHER_PATH,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use zbus::blocking::{connection, Connection};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Structure, Value};
+
+/// Calls the fake item received, in order
+type Calls = Arc<Mutex<Vec<String>>>;
+
+struct FakeItem {
+    title: String,
+    calls: Calls,
+}
+
+#[zbus::interface(name = "org.kde.StatusNotifierItem")]
+impl FakeItem {
+    fn activate(&self, x: i32, y: i32) {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("Activate {} {}", x, y));
+    }
+
+    fn secondary_activate(&self, x: i32, y: i32) {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("SecondaryActivate {} {}", x, y));
+    }
+
+    fn scroll(&self, delta: i32, orientation: &str) {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("Scroll {} {}", delta, orientation));
+    }
+
+    #[zbus(property)]
+    fn id(&self) -> String {
+        "fake".to_string()
+    }
+
+    #[zbus(property)]
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> String {
+        "NeedsAttention".to_string()
+    }
+
+    #[zbus(property)]
+    fn icon_name(&self) -> String {
+        "fake-icon".to_string()
+    }
+
+    #[zbus(property)]
+    fn icon_pixmap(&self) -> Vec<(i32, i32, Vec<u8>)> {
+        vec![
+            (1, 1, vec![255, 1, 2, 3]),
+            // Opaque red, then half-transparent green, blue and white
+            (
+                2,
+                2,
+                vec![
+                    255, 255, 0, 0, 128, 0, 255, 0, 128, 0, 0, 255, 128, 255, 255, 255,
+                ],
+            ),
+        ]
+    }
+
+    #[zbus(property)]
+    fn menu(&self) -> OwnedObjectPath {
+        OwnedObjectPath::try_from("/Menu").unwrap()
+    }
+
+    #[zbus(property)]
+    fn item_is_menu(&self) -> bool {
+        false
+    }
+}
+
+/// A dbusmenu node: `(ia{sv}av)`
+type Node = (i32, HashMap<String, Value<'static>>, Vec<Value<'static>>);
+
+fn node(id: i32, properties: &[(&str, Value<'static>)], children: Vec<Node>) -> Node {
+    let properties = properties
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.clone()))
+        .collect();
+    let children = children
+        .into_iter()
+        .map(|child| Value::from(Structure::from(child)))
+        .collect();
+    (id, properties, children)
+}
+
+struct FakeMenu {
+    calls: Calls,
+}
+
+#[zbus::interface(name = "com.canonical.dbusmenu")]
+impl FakeMenu {
+    fn get_layout(
+        &self,
+        _parent_id: i32,
+        _recursion_depth: i32,
+        _property_names: Vec<String>,
+    ) -> (u32, Node) {
+        let options = node(
+            3,
+            &[
+                ("label", Value::from("Op__tions")),
+                ("children-display", Value::from("submenu")),
+            ],
+            vec![
+                node(
+                    4,
+                    &[
+                        ("label", Value::from("Check")),
+                        ("toggle-type", Value::from("checkmark")),
+                        ("toggle-state", Value::from(1i32)),
+                    ],
+                    Vec::new(),
+                ),
+                node(
+                    5,
+                    &[
+                        ("label", Value::from("Hidden")),
+                        ("visible", Value::from(false)),
+                    ],
+                    Vec::new(),
+                ),
+            ],
+        );
+        let root = node(
+            0,
+            &[("children-display", Value::from("submenu"))],
+            vec![
+                node(1, &[("label", Value::from("_Open"))], Vec::new()),
+                node(2, &[("type", Value::from("separator"))], Vec::new()),
+                options,
+                node(
+                    6,
+                    &[
+                        ("label", Value::from("Disabled")),
+                        ("enabled", Value::from(false)),
+                    ],
+                    Vec::new(),
+                ),
+            ],
+        );
+        (1, root)
+    }
+
+    fn event(&self, id: i32, event_id: &str, _data: OwnedValue, _timestamp: u32) {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("Event {} {}", id, event_id));
+    }
+
+    fn about_to_show(&self, id: i32) -> bool {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("AboutToShow {}", id));
+        false
+    }
+}
+
+/// Serve a fake item on its own connection and register it with the
+/// watcher as `service`, or by its bus name when None
+fn register_item(
+    bus: &PrivateBus,
+    name: &str,
+    path: &str,
+    service: Option<&str>,
+) -> (Connection, Calls) {
+    let calls = Calls::default();
+    let item = FakeItem {
+        title: "Fake item".to_string(),
+        calls: calls.clone(),
+    };
+    let menu = FakeMenu {
+        calls: calls.clone(),
+    };
+    let connection = connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .name(name)
+        .unwrap()
+        .serve_at(path, item)
+        .unwrap()
+        .serve_at("/Menu", menu)
+        .unwrap()
+        .build()
+        .unwrap();
+    connection
+        .call_method(
+            Some(WATCHER_NAME),
+            WATCHER_PATH,
+            Some(WATCHER_INTERFACE),
+            "RegisterStatusNotifierItem",
+            &(service.unwrap_or(name),),
+        )
+        .unwrap();
+    (connection, calls)
+}
+
+#[test]
+fn host_follows_registered_items() {
+    let bus = private_bus!();
+    let host = Host::start(bus.connect(), || {}).unwrap();
+    assert!(host.is_watcher());
+
+    let name = "org.kde.StatusNotifierItem-test-1";
+    let (connection, _calls) = register_item(&bus, name, "/StatusNotifierItem", None);
+    assert!(wait_for(|| host.items().len() == 1));
+
+    let item = &host.items()[0];
+    assert_eq!(item.service, format!("{}/StatusNotifierItem", name));
+    assert_eq!(item.bus, name);
+    assert_eq!(item.owner, connection.unique_name().unwrap().to_string());
+    assert_eq!(item.id, "fake");
+    assert_eq!(item.label(), "Fake item");
+    assert_eq!(item.status, Status::NeedsAttention);
+    assert_eq!(item.current_icon_name(), "fake-icon");
+    assert_eq!(item.menu.as_deref(), Some("/Menu"));
+    assert_eq!(item.icon_pixmap.len(), 2);
+
+    // The 2x2 pixmap is nearest, converted from ARGB to RGBA
+    let image = item.current_pixmap(2).unwrap();
+    assert_eq!((image.width, image.height), (2, 2));
+    assert_eq!(image.pixel(0, 0), [255, 0, 0, 255]);
+    assert_eq!(image.pixel(1, 0), [0, 255, 0, 128]);
+    assert_eq!(image.pixel(1, 1), [255, 255, 255, 128]);
+
+    // Property changes are announced with signals
+    let generation = host.generation();
+    connection
+        .object_server()
+        .interface::<_, FakeItem>("/StatusNotifierItem")
+        .unwrap()
+        .get_mut()
+        .title = "Renamed".to_string();
+    connection
+        .emit_signal(
+            None::<()>,
+            "/StatusNotifierItem",
+            ITEM_INTERFACE,
+            "NewTitle",
+            &(),
+        )
+        .unwrap();
+    assert!(wait_for(|| host.items()[0].title == "Renamed"));
+    assert!(host.generation() > generation);
+
+    // Items go away with their connection
+    drop(connection);
+    assert!(wait_for(|| host.items().is_empty()));
+}
+
+#[test]
+fn items_registered_by_path_use_the_sender() {
+    let bus = private_bus!();
+    let host = Host::start(bus.connect(), || {}).unwrap();
+
+    let path = "/org/ayatana/NotificationItem/fake";
+    let (connection, _calls) = register_item(&bus, "org.example.Fake", path, Some(path));
+    assert!(wait_for(|| host.items().len() == 1));
+
+    let unique_name = connection.unique_name().unwrap().to_string();
+    let item = &host.items()[0];
+    assert_eq!(item.service, format!("{}{}", unique_name, path));
+    assert_eq!(item.path, path);
+    assert_eq!(item.label(), "Fake item");
+}
+
+#[test]
+fn clicks_and_scrolling_reach_the_item() {
+    let bus = private_bus!();
+    let host = Host::start(bus.connect(), || {}).unwrap();
+    let (_connection, calls) = register_item(
+        &bus,
+        "org.kde.StatusNotifierItem-test-2",
+        "/StatusNotifierItem",
+        None,
+    );
+    assert!(wait_for(|| host.items().len() == 1));
+    let item = host.items().remove(0);
+
+    host.activate(&item, 10, 20).unwrap();
+    host.secondary_activate(&item, 0, 0).unwrap();
+    host.scroll(&item, -120, "vertical").unwrap();
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [
+            "Activate 10 20",
+            "SecondaryActivate 0 0",
+            "Scroll -120 vertical"
+        ]
+    );
+}
+
+#[test]
+fn menus_are_read_and_clicked() {
+    let bus = private_bus!();
+    let host = Host::start(bus.connect(), || {}).unwrap();
+    let (_connection, calls) = register_item(
+        &bus,
+        "org.kde.StatusNotifierItem-test-3",
+        "/StatusNotifierItem",
+        None,
+    );
+    assert!(wait_for(|| host.items().len() == 1));
+    let item = host.items().remove(0);
+
+    let menu = host.menu(&item, 0).unwrap();
+    let labels: Vec<&str> = menu
+        .children
+        .iter()
+        .map(|entry| entry.label.as_str())
+        .collect();
+    assert_eq!(labels, ["Open", "", "Op_tions", "Disabled"]);
+    assert!(menu.children[1].separator);
+    assert!(!menu.children[3].enabled);
+
+    let options = menu.find(3).unwrap();
+    assert_eq!(options.children.len(), 2);
+    assert_eq!(options.children[0].toggle, Toggle::Checkmark);
+    assert!(options.children[0].checked);
+    assert!(!options.children[1].visible);
+
+    host.menu(&item, 3).unwrap();
+    host.menu_clicked(&item, 1).unwrap();
+    assert_eq!(
+        *calls.lock().unwrap(),
+        ["AboutToShow 0", "AboutToShow 3", "Event 1 clicked"]
+    );
+}
+
+#[test]
+fn a_second_host_uses_the_existing_watcher() {
+    let bus = private_bus!();
+    let first = Host::start(bus.connect(), || {}).unwrap();
+    let (_connection, _calls) = register_item(
+        &bus,
+        "org.kde.StatusNotifierItem-test-4",
+        "/StatusNotifierItem",
+        None,
+    );
+    assert!(wait_for(|| first.items().len() == 1));
+
+    let second = Host::start(bus.connect(), || {}).unwrap();
+    assert!(first.is_watcher());
+    assert!(!second.is_watcher());
+    assert_eq!(second.items().len(), 1);
+}